@@ -0,0 +1,71 @@
+/**
+ * Framed read benchmark for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Reads a long stream of small newline-delimited messages, as an echo
+ * session would, once with one reused `GrowableBuffer` and once with a fresh
+ * buffer per message. Run with `cargo bench --bench framing`.
+ */
+use std::hint::black_box;
+use std::io::{Cursor, Read, Result};
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{GrowableBuffer, read_until_delimiter};
+
+const MESSAGES: usize = 200_000;
+
+/**
+ * Hands out one message per read, so a fresh buffer never drops pipelined bytes
+ */
+struct OneMessagePerRead {
+  messages: Cursor<Vec<u8>>,
+}
+
+impl Read for OneMessagePerRead {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    let start = self.messages.position() as usize;
+    let rest = &self.messages.get_ref()[start..];
+    let end = rest
+      .iter()
+      .position(|&byte| byte == b'\n')
+      .map_or(0, |end| end + 1);
+    let len = end.min(buf.len());
+    buf[..len].copy_from_slice(&rest[..len]);
+    self.messages.set_position((start + len) as u64);
+    Ok(len)
+  }
+}
+
+fn session_stream() -> OneMessagePerRead {
+  let messages = (0..MESSAGES)
+    .flat_map(|seq| format!("HELLO {seq}\n").into_bytes())
+    .collect();
+  OneMessagePerRead {
+    messages: Cursor::new(messages),
+  }
+}
+
+fn run(name: &str, mut read_one: impl FnMut(&mut OneMessagePerRead) -> Vec<u8>) -> Duration {
+  let mut stream = session_stream();
+  let started = Instant::now();
+  for _ in 0..MESSAGES {
+    black_box(read_one(&mut stream));
+  }
+  let elapsed = started.elapsed();
+  println!(
+    "{name:<16} {MESSAGES} messages in {elapsed:?} ({:.0} ns/message)",
+    elapsed.as_nanos() as f64 / MESSAGES as f64
+  );
+  elapsed
+}
+
+fn main() {
+  let mut reused = GrowableBuffer::default();
+  run("reused buffer", |stream| {
+    read_until_delimiter(stream, b"\n", &mut reused).unwrap()
+  });
+  run("fresh buffer", |stream| {
+    read_until_delimiter(stream, b"\n", &mut GrowableBuffer::default()).unwrap()
+  });
+}
@@ -0,0 +1,40 @@
+/**
+ * HELLO parsing benchmark for 3-way Handshake Protocol
+ *
+ * Parses the same batch of HELLO messages once with `parse_hello_message`,
+ * which decodes the bytes into a `String` first as the default path does,
+ * and once in place with `parse_hello_message_bytes`, as the throughput
+ * profile does. Run with `cargo bench --bench parse`.
+ */
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{parse_hello_message, parse_hello_message_bytes};
+
+const MESSAGES: usize = 200_000;
+
+fn run(name: &str, messages: &[Vec<u8>], mut parse: impl FnMut(&[u8]) -> i32) -> Duration {
+  let started = Instant::now();
+  for message in messages {
+    black_box(parse(black_box(message)));
+  }
+  let elapsed = started.elapsed();
+  println!(
+    "{name:<16} {MESSAGES} messages in {elapsed:?} ({:.0} ns/message)",
+    elapsed.as_nanos() as f64 / MESSAGES as f64
+  );
+  elapsed
+}
+
+fn main() {
+  let messages: Vec<Vec<u8>> = (0..MESSAGES)
+    .map(|seq| format!("HELLO {seq}").into_bytes())
+    .collect();
+  run("string", &messages, |message| {
+    let decoded = String::from_utf8_lossy(message).to_string();
+    parse_hello_message(black_box(&decoded)).unwrap()
+  });
+  run("bytes in place", &messages, |message| {
+    parse_hello_message_bytes(message).unwrap()
+  });
+}
@@ -0,0 +1,75 @@
+/**
+ * async-std shim for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The protocol logic lives in the runtime-neutral `_with_config` handshakes.
+ * This module only supplies the two runtime-specific pieces: an adapter from
+ * async-std's futures-io streams to the tokio I/O traits, and a clock built on
+ * async-std timers. Enabled with the `async-std` feature.
+ */
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ::async_std::net::TcpStream;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::HandshakeConfig;
+use crate::error::Result;
+use crate::outcome::HandshakeOutcome;
+use crate::protocol::{
+  perform_async_client_handshake_with_config, perform_async_server_handshake_with_config,
+};
+use crate::time::{Clock, Sleep};
+
+/**
+ * Real clock backed by async-std timers
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdClock;
+
+impl Clock for AsyncStdClock {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+
+  fn sleep(&self, duration: Duration) -> Sleep {
+    Box::pin(::async_std::task::sleep(duration))
+  }
+}
+
+/**
+ * Default config with timeouts driven by async-std instead of tokio
+ */
+pub fn default_config() -> HandshakeConfig {
+  HandshakeConfig {
+    clock: Arc::new(AsyncStdClock),
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * async-std version: Performs client-side 3-way handshake
+ */
+pub async fn perform_async_client_handshake(
+  stream: TcpStream,
+  initial_seq: i32,
+) -> Result<HandshakeOutcome> {
+  let mut stream = stream.compat();
+  perform_async_client_handshake_with_config(&mut stream, initial_seq, &default_config()).await
+}
+
+/**
+ * async-std version: Performs server-side 3-way handshake
+ */
+pub async fn perform_async_server_handshake(
+  stream: TcpStream,
+  peer_addr: SocketAddr,
+  cancel: Option<&CancellationToken>,
+) -> Result<HandshakeOutcome> {
+  let mut stream = stream.compat();
+  perform_async_server_handshake_with_config(&mut stream, peer_addr, cancel, &default_config())
+    .await
+}
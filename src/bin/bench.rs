@@ -0,0 +1,184 @@
+/**
+ * Benchmark for the four server models of the 3-way Handshake Protocol
+ * Starts each model in-process, once per server profile, and drives the same
+ * client workload against it
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The servers and clients log every handshake to stdout, so the report goes
+ * to stderr. Run with `> /dev/null` to see only the table. The async model
+ * runs a second time per profile with batched replies.
+ */
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  BenchArgs, HandshakeConfig, ReplyStrategy, ServerModel, ServerProfile, exit_with_error,
+  parse_bench_args, perform_client_handshake_with_config, spawn_server,
+};
+
+/**
+ * What one model achieved under the workload
+ */
+struct BenchReport {
+  model: ServerModel,
+  profile: ServerProfile,
+  // How the server scheduled its replies
+  replies: String,
+  succeeded: usize,
+  failed: usize,
+  elapsed: Duration,
+  // Sorted connect-to-completion times of successful handshakes
+  latencies: Vec<Duration>,
+  // Replies the server wrote in each burst; 1 unless replies were batched
+  replies_per_flush: f64,
+}
+
+impl BenchReport {
+  fn throughput(&self) -> f64 {
+    self.succeeded as f64 / self.elapsed.as_secs_f64()
+  }
+
+  fn percentile(&self, pct: usize) -> Duration {
+    if self.latencies.is_empty() {
+      return Duration::ZERO;
+    }
+    let index = (self.latencies.len() * pct / 100).min(self.latencies.len() - 1);
+    self.latencies[index]
+  }
+}
+
+/**
+ * Runs `args.connections` handshakes from `args.concurrency` client threads
+ */
+fn drive_workload(
+  model: ServerModel,
+  profile: ServerProfile,
+  replies: String,
+  addr: SocketAddr,
+  args: BenchArgs,
+) -> BenchReport {
+  let next = Arc::new(AtomicUsize::new(0));
+  let started = Instant::now();
+
+  let clients: Vec<_> = (0..args.concurrency)
+    .map(|_| {
+      let next = Arc::clone(&next);
+      thread::spawn(move || {
+        let config = HandshakeConfig::default();
+        let mut latencies = Vec::new();
+        let mut failed = 0;
+        loop {
+          let run = next.fetch_add(1, Ordering::Relaxed);
+          if run >= args.connections {
+            break;
+          }
+          let attempt = Instant::now();
+          let result = TcpStream::connect(addr)
+            .map_err(Into::into)
+            .and_then(|mut stream| {
+              perform_client_handshake_with_config(&mut stream, run as i32, &config)
+            });
+          match result {
+            Ok(_) => latencies.push(attempt.elapsed()),
+            Err(_) => failed += 1,
+          }
+        }
+        (latencies, failed)
+      })
+    })
+    .collect();
+
+  let mut latencies = Vec::with_capacity(args.connections);
+  let mut failed = 0;
+  for client in clients {
+    let (client_latencies, client_failed) = client.join().unwrap_or_default();
+    latencies.extend(client_latencies);
+    failed += client_failed;
+  }
+  let elapsed = started.elapsed();
+  latencies.sort();
+
+  BenchReport {
+    model,
+    profile,
+    replies,
+    succeeded: latencies.len(),
+    failed,
+    elapsed,
+    latencies,
+    replies_per_flush: 1.0,
+  }
+}
+
+fn main() {
+  // Parse command line arguments
+  let args = match parse_bench_args() {
+    Ok(args) => args,
+    Err(e) => exit_with_error(&e),
+  };
+
+  let reply_window = Duration::from_millis(args.reply_window_ms as u64);
+  let mut reports = Vec::new();
+  for model in ServerModel::ALL {
+    for profile in ServerProfile::ALL {
+      let mut strategies = vec![ReplyStrategy::Immediate];
+      if model == ServerModel::Async {
+        strategies.push(ReplyStrategy::batched(reply_window));
+      }
+      for reply_strategy in strategies {
+        let replies = match &reply_strategy {
+          ReplyStrategy::Immediate => "immediate".to_string(),
+          ReplyStrategy::Batched(queue) => format!("batched {:?}", queue.window()),
+        };
+        let mut config = HandshakeConfig {
+          reply_strategy: reply_strategy.clone(),
+          ..HandshakeConfig::default()
+        };
+        config.apply_profile(profile);
+        let server = match spawn_server(model, config) {
+          Ok(server) => server,
+          Err(e) => exit_with_error(&e),
+        };
+        eprintln!(
+          "Benchmarking {model} server ({profile} profile, {replies} replies) on {}...",
+          server.addr
+        );
+        let mut report = drive_workload(model, profile, replies, server.addr, args);
+        if let ReplyStrategy::Batched(queue) = &reply_strategy {
+          report.replies_per_flush = queue.replies() as f64 / queue.batches().max(1) as f64;
+        }
+        reports.push(report);
+        server.stop();
+      }
+    }
+  }
+
+  eprintln!();
+  eprintln!(
+    "{} handshakes per model and profile, {} concurrent clients",
+    args.connections, args.concurrency
+  );
+  eprintln!(
+    "{:<12} {:<10} {:<14} {:>8} {:>8} {:>12} {:>10} {:>10} {:>10} {:>14}",
+    "model", "profile", "replies", "ok", "failed", "hs/sec", "p50", "p99", "max", "replies/flush"
+  );
+  for report in &reports {
+    eprintln!(
+      "{:<12} {:<10} {:<14} {:>8} {:>8} {:>12.0} {:>10.2?} {:>10.2?} {:>10.2?} {:>14.1}",
+      report.model.to_string(),
+      report.profile.to_string(),
+      report.replies,
+      report.succeeded,
+      report.failed,
+      report.throughput(),
+      report.percentile(50),
+      report.percentile(99),
+      report.latencies.last().copied().unwrap_or_default(),
+      report.replies_per_flush,
+    );
+  }
+}
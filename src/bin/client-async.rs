@@ -1,7 +1,7 @@
 use tcp_handshake::{
-  exit_with_error, format_server_address, parse_client_args, perform_async_client_handshake,
+  HandshakeConfig, HandshakeError, Preamble, connect_async_timed, exit_with_error,
+  format_server_address, parse_client_args, perform_async_client_handshake_with_config,
 };
-use tokio::net::TcpStream;
 
 /**
  * Event-Driven Client for 3-way Handshake Protocol
@@ -14,31 +14,90 @@ use tokio::net::TcpStream;
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
   // Parse command line arguments
-  let (server_ip, port, initial_seq) = match parse_client_args() {
+  let args = match parse_client_args() {
     Ok(args) => args,
     Err(e) => exit_with_error(&e),
   };
+  if args.seq_file.is_some() {
+    exit_with_error(&HandshakeError::InvalidArguments(
+      "--seq-file is only supported by client-sync".to_string(),
+    ));
+  }
+  if args.confirm_final {
+    exit_with_error(&HandshakeError::InvalidArguments(
+      "--confirm-final is only supported by client-sync".to_string(),
+    ));
+  }
 
-  // Connect to the server asynchronously
-  let server_addr = format_server_address(&server_ip, port);
-  println!("Connecting to {server_addr}...");
+  let config = HandshakeConfig {
+    raw_first_message: args.raw_first.clone(),
+    bind_addr: args.bind,
+    source_port_range: args.source_port_range.clone(),
+    socks5_proxy: args.socks5.clone(),
+    namespace: args.namespace.clone(),
+    claimed_ip: args.claim_ip,
+    preamble: args.preamble.then(Preamble::default),
+    capabilities: args.capabilities,
+    required_capabilities: args.required_capabilities,
+    // Losing a log line to a closed stdout is reported, not fatal
+    tolerate_log_failures: true,
+    ..HandshakeConfig::default()
+  };
 
-  let stream = match TcpStream::connect(&server_addr).await {
-    Ok(stream) => {
+  // Resolve and connect to the server asynchronously, each step under its own timeout
+  let server_addr = format_server_address(&args.server_ip, args.port);
+  match &config.socks5_proxy {
+    Some(proxy) => println!("Connecting to {server_addr} via SOCKS5 proxy {proxy}..."),
+    None => println!("Connecting to {server_addr}..."),
+  }
+
+  let (mut stream, phases) = match connect_async_timed(&args.server_ip, args.port, &config).await {
+    Ok((stream, phases)) => {
       println!("Connected to {server_addr}");
-      stream
+      if let (Some(_), Ok(local)) = (&config.source_port_range, stream.local_addr()) {
+        eprintln!("Using source port {}", local.port());
+      }
+      (stream, phases)
     }
     Err(e) => {
       eprintln!("ERROR: Failed to connect to {server_addr}: {e}");
-      std::process::exit(1);
+      std::process::exit(e.exit_code());
     }
   };
 
   // Perform the 3-way handshake asynchronously
-  if let Err(e) = perform_async_client_handshake(stream, initial_seq).await {
-    exit_with_error(&e);
-  }
+  let outcome = match perform_async_client_handshake_with_config(
+    &mut stream,
+    args.initial_seq,
+    &config,
+  )
+  .await
+  {
+    Ok(outcome) => outcome,
+    Err(e) => exit_with_error(&e),
+  };
 
+  if outcome.logging_degraded {
+    eprintln!("WARNING: Some handshake output could not be written to stdout");
+  }
+  eprintln!("Round-trip time: {:?}", outcome.rtt);
+  eprintln!(
+    "Phases: DNS {:?}, connect {:?}, handshake {:?}",
+    phases.dns, phases.connect, outcome.duration
+  );
+  if let Ok(local) = stream.local_addr() {
+    eprintln!("Local endpoint: {local}");
+  }
   println!("Client completed successfully!");
+
+  // Optionally keep the socket open until the hold elapses or Ctrl-C arrives
+  if let Some(hold) = args.hold {
+    println!("Holding connection open for {} ms", hold.as_millis());
+    tokio::select! {
+      _ = tokio::time::sleep(hold) => {}
+      _ = tokio::signal::ctrl_c() => println!("Hold interrupted, closing connection"),
+    }
+  }
+
   Ok(())
 }
@@ -0,0 +1,168 @@
+/**
+ * Conformance client for the 3-way Handshake Protocol
+ * Sends a fixed suite of malformed first messages and checks that the server refuses each
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Every case opens its own connection and sends one malformed HELLO X in
+ * place of the real one, through `raw_first_message`. A server passes a case
+ * by closing the connection or answering with anything other than HELLO, and
+ * fails it by replying HELLO Y or by neither replying nor closing within the
+ * timeout. The table goes to stdout and the process exits with 1 when any
+ * case fails, so it can gate a CI job for another server implementation.
+ */
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tcp_handshake::{
+  BUSY_MESSAGE, ConformanceArgs, Direction, EventSink, HELLO_VERB, HandshakeConfig, HandshakeError,
+  OVERLOADED_MESSAGE, connect_async, exit_with_error, format_server_address,
+  parse_conformance_args, perform_async_client_handshake_with_config,
+};
+
+/**
+ * One malformed first message and what makes it malformed
+ */
+struct Case {
+  name: &'static str,
+  message: &'static str,
+}
+
+const CASES: [Case; 6] = [
+  Case {
+    name: "non-numeric sequence",
+    message: "HELLO abc",
+  },
+  Case {
+    name: "extra tokens",
+    message: "HELLO 5 6 extra",
+  },
+  Case {
+    name: "missing keyword",
+    message: "5",
+  },
+  Case {
+    name: "wrong keyword",
+    message: "HI 5",
+  },
+  Case {
+    name: "empty message",
+    message: "\n",
+  },
+  Case {
+    name: "huge number",
+    message: "HELLO 99999999999999999999",
+  },
+];
+
+/**
+ * How the server answered one case
+ */
+enum Verdict {
+  // Closed or reset the connection without replying
+  Closed,
+  // Replied with something other than HELLO
+  ErrorReply(String),
+  // Replied with HELLO, i.e. took the malformed message as valid
+  Accepted(String),
+  // Refused with BUSY or OVERLOADED before looking at the message
+  Busy(String),
+  // Neither replied nor closed within the timeout
+  NoResponse(Duration),
+  // The case never reached the server
+  Unreachable(HandshakeError),
+}
+
+impl Verdict {
+  fn passed(&self) -> bool {
+    matches!(self, Self::Closed | Self::ErrorReply(_))
+  }
+
+  fn describe(&self) -> String {
+    match self {
+      Self::Closed => "closed the connection".to_string(),
+      Self::ErrorReply(reply) => format!("replied {reply:?}"),
+      Self::Accepted(reply) => format!("accepted it, replied {reply:?}"),
+      Self::Busy(reply) => format!("refused with {reply:?} before checking; retry later"),
+      Self::NoResponse(timeout) => format!("neither replied nor closed within {timeout:?}"),
+      Self::Unreachable(e) => format!("could not connect: {e}"),
+    }
+  }
+}
+
+/**
+ * Sends one malformed first message and classifies the server's reaction
+ */
+async fn run_case(args: &ConformanceArgs, case: &Case) -> Verdict {
+  let replies = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&replies);
+  let config = HandshakeConfig {
+    raw_first_message: Some(case.message.to_string()),
+    client_connection_timeout: args.timeout,
+    silent: true,
+    on_event: EventSink::new(move |event| {
+      if event.direction == Direction::Inbound {
+        sink.lock().unwrap().push(event.message.clone());
+      }
+    }),
+    ..HandshakeConfig::default()
+  };
+
+  let mut stream = match connect_async(&args.server_ip, args.port, &config).await {
+    Ok(stream) => stream,
+    Err(e) => return Verdict::Unreachable(e),
+  };
+  let result = perform_async_client_handshake_with_config(&mut stream, 0, &config).await;
+  let reply = replies.lock().unwrap().first().cloned();
+
+  // The reply decides the verdict; the handshake error only matters without one
+  match (result, reply) {
+    (_, Some(reply)) if reply == BUSY_MESSAGE || reply == OVERLOADED_MESSAGE => {
+      Verdict::Busy(reply)
+    }
+    (_, Some(reply)) if reply.split_whitespace().next() == Some(HELLO_VERB) => {
+      Verdict::Accepted(reply)
+    }
+    (_, Some(reply)) => Verdict::ErrorReply(reply),
+    (Err(HandshakeError::Timeout), None) => Verdict::NoResponse(args.timeout),
+    (_, None) => Verdict::Closed,
+  }
+}
+
+#[tokio::main]
+async fn main() {
+  // Parse command line arguments
+  let args = match parse_conformance_args() {
+    Ok(args) => args,
+    Err(e) => exit_with_error(&e),
+  };
+  println!(
+    "Running {} malformed-input cases against {}",
+    CASES.len(),
+    format_server_address(&args.server_ip, args.port)
+  );
+  println!();
+  println!("{:<22} {:<30} {:<6} server", "case", "sent", "result");
+
+  let mut failed = 0;
+  for case in &CASES {
+    let verdict = run_case(&args, case).await;
+    if !verdict.passed() {
+      failed += 1;
+    }
+    println!(
+      "{:<22} {:<30} {:<6} {}",
+      case.name,
+      format!("{:?}", case.message),
+      if verdict.passed() { "PASS" } else { "FAIL" },
+      verdict.describe()
+    );
+  }
+
+  println!();
+  println!("{}/{} cases passed", CASES.len() - failed, CASES.len());
+  if failed > 0 {
+    process::exit(1);
+  }
+}
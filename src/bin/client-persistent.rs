@@ -0,0 +1,65 @@
+/**
+ * Persistent Client for 3-way Handshake Protocol
+ * Runs several handshakes back to back over one connection
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Meant for a server started with `--persistent`. Each handshake starts over
+ * from its own HELLO X, with the `--initial-seq` given or a fresh random one,
+ * and the connection closes once the last one completes. Nagle's algorithm
+ * holds each HELLO X back until the server has acknowledged the HELLO Z
+ * before it, so the two never arrive in the same read.
+ */
+use tcp_handshake::{
+  HandshakeConfig, connect_sync, exit_with_error, format_server_address, parse_persistent_args,
+  perform_client_handshake_with_config, random_initial_seq,
+};
+
+fn main() {
+  // Parse command line arguments
+  let args = match parse_persistent_args() {
+    Ok(args) => args,
+    Err(e) => exit_with_error(&e),
+  };
+  let server_addr = format_server_address(&args.server_ip, args.port);
+  let config = HandshakeConfig {
+    // Losing a log line to a closed stdout is reported, not fatal
+    tolerate_log_failures: true,
+    ..HandshakeConfig::default()
+  };
+
+  // Connect to the server once
+  let mut stream = match connect_sync(&args.server_ip, args.port, &config) {
+    Ok(stream) => stream,
+    Err(e) => {
+      eprintln!("ERROR: Failed to connect to {server_addr}: {e}");
+      std::process::exit(e.exit_code());
+    }
+  };
+
+  // Every handshake resets the sequence state
+  let mut rng = rand::rng();
+  for handshake in 1..=args.handshakes {
+    let initial_seq = args
+      .initial_seq
+      .unwrap_or_else(|| random_initial_seq(&mut rng));
+    match perform_client_handshake_with_config(&mut stream, initial_seq, &config) {
+      Ok(outcome) => eprintln!(
+        "[handshake {handshake}/{}] Round-trip time: {:?}",
+        args.handshakes, outcome.rtt
+      ),
+      Err(e) => {
+        eprintln!(
+          "ERROR: [handshake {handshake}/{}] Handshake failed: {e}",
+          args.handshakes
+        );
+        std::process::exit(e.exit_code());
+      }
+    }
+  }
+
+  eprintln!(
+    "Completed {} handshakes over one connection to {server_addr}",
+    args.handshakes
+  );
+}
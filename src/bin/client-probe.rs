@@ -0,0 +1,133 @@
+/**
+ * Server model probe for the 3-way Handshake Protocol
+ * Guesses whether the server handles connections one at a time or concurrently
+ *
+ * Author: Sae-Hwan Park
+ *
+ * One connection is opened and left silent, which a sequential server blocks
+ * on until its read timeout. Several handshakes then run alongside it: a
+ * concurrent server completes them at about the baseline latency, while a
+ * sequential one only gets to them once the silent connection is closed.
+ * Handshakes log to stdout, so the report goes to stderr.
+ */
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinSet;
+
+use tcp_handshake::{
+  HandshakeConfig, ProbeArgs, Result, connect_async, exit_with_error, format_server_address,
+  parse_probe_args, perform_async_client_handshake_with_config,
+};
+
+// Head start for the silent connection so the server accepts it before the probes
+const SILENT_HEAD_START: Duration = Duration::from_millis(100);
+
+/**
+ * Connects and runs one handshake, returning how long both took
+ */
+async fn timed_handshake(
+  args: &ProbeArgs,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<Duration> {
+  let started = Instant::now();
+  let mut stream = connect_async(&args.server_ip, args.port, config).await?;
+  perform_async_client_handshake_with_config(&mut stream, initial_seq, config).await?;
+  Ok(started.elapsed())
+}
+
+/**
+ * The largest duration, or zero when there are none
+ */
+fn slowest(durations: &[Duration]) -> Duration {
+  durations.iter().copied().max().unwrap_or_default()
+}
+
+#[tokio::main]
+async fn main() {
+  // Parse command line arguments
+  let args = match parse_probe_args() {
+    Ok(args) => args,
+    Err(e) => exit_with_error(&e),
+  };
+  let config = HandshakeConfig::default();
+  let server_addr = format_server_address(&args.server_ip, args.port);
+  eprintln!("Probing {server_addr}...");
+
+  // Baseline: one handshake with nothing else going on
+  let baseline = match timed_handshake(&args, 0, &config).await {
+    Ok(baseline) => baseline,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Hold one connection open without ever sending HELLO
+  let silent = match connect_async(&args.server_ip, args.port, &config).await {
+    Ok(stream) => stream,
+    Err(e) => exit_with_error(&e),
+  };
+  tokio::time::sleep(SILENT_HEAD_START).await;
+
+  // Run the probe handshakes concurrently alongside the silent connection
+  let mut handshakes = JoinSet::new();
+  for seq in 1..=args.connections {
+    let args = args.clone();
+    let config = config.clone();
+    handshakes.spawn(async move { timed_handshake(&args, seq as i32, &config).await });
+  }
+
+  let mut while_silent = Vec::new();
+  let mut failed = 0;
+  let window = tokio::time::sleep(args.window);
+  tokio::pin!(window);
+  loop {
+    tokio::select! {
+      _ = &mut window => break,
+      joined = handshakes.join_next() => match joined {
+        Some(Ok(Ok(elapsed))) => while_silent.push(elapsed),
+        Some(_) => failed += 1,
+        None => break,
+      },
+    }
+  }
+
+  // Release the silent connection and see whether the rest follow
+  drop(silent);
+  let released = Instant::now();
+  let mut after_release = Vec::new();
+  while let Some(joined) = handshakes.join_next().await {
+    match joined {
+      Ok(Ok(_)) => after_release.push(released.elapsed()),
+      _ => failed += 1,
+    }
+  }
+
+  eprintln!();
+  eprintln!("Baseline handshake: {baseline:?}");
+  eprintln!(
+    "Completed while a connection was held silent for {:?}: {}/{} (slowest {:?})",
+    args.window,
+    while_silent.len(),
+    args.connections,
+    slowest(&while_silent)
+  );
+  eprintln!(
+    "Completed after the silent connection closed: {}/{} (last {:?} after release)",
+    after_release.len(),
+    args.connections,
+    slowest(&after_release)
+  );
+  if failed > 0 {
+    eprintln!("Failed: {failed}/{}", args.connections);
+  }
+
+  let verdict = if while_silent.len() == args.connections {
+    "concurrent (threaded, thread pool or async): handshakes did not wait for the silent connection"
+  } else if while_silent.is_empty() && !after_release.is_empty() {
+    "sequential: handshakes only completed once the silent connection was released"
+  } else if !while_silent.is_empty() {
+    "concurrent with limited workers (likely a thread pool): some handshakes waited for a free worker"
+  } else {
+    "unknown: no probe handshake completed"
+  };
+  eprintln!("Inferred server model: {verdict}");
+}
@@ -0,0 +1,87 @@
+/**
+ * Replay Client for 3-way Handshake Protocol
+ * Re-sends a recorded transcript and compares the server's replies
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{
+  Direction, HandshakeError, exit_with_error, format_server_address, parse_replay_args,
+  parse_transcript, read_message_from_stream,
+};
+
+fn main() {
+  // Parse command line arguments
+  let (server_ip, port, transcript_path) = match parse_replay_args() {
+    Ok(args) => args,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Load the transcript before connecting so format errors fail fast
+  let text = match std::fs::read_to_string(&transcript_path) {
+    Ok(text) => text,
+    Err(e) => exit_with_error(&HandshakeError::Io(e)),
+  };
+  let entries = match parse_transcript(&text) {
+    Ok(entries) => entries,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Connect to the server
+  let server_addr = format_server_address(&server_ip, port);
+  let mut stream = match TcpStream::connect(&server_addr) {
+    Ok(stream) => stream,
+    Err(e) => {
+      eprintln!("ERROR: Failed to connect to {server_addr}: {e}");
+      std::process::exit(HandshakeError::Io(e).exit_code());
+    }
+  };
+  if let Err(e) = stream.set_read_timeout(Some(Duration::from_secs(5))) {
+    exit_with_error(&HandshakeError::Io(e));
+  }
+
+  // Replay each entry in order, honoring the recorded delays
+  let mut mismatches = 0;
+  for (index, entry) in entries.iter().enumerate() {
+    let step = index + 1;
+    let payload = String::from_utf8_lossy(&entry.payload);
+    thread::sleep(entry.delay);
+
+    match entry.direction {
+      Direction::Outbound => {
+        if let Err(e) = std::io::Write::write_all(&mut stream, &entry.payload) {
+          eprintln!("[{step}] SEND FAILED {payload:?}: {e}");
+          mismatches += 1;
+          break;
+        }
+        println!("[{step}] SENT     {payload:?}");
+      }
+      Direction::Inbound => {
+        let expected = payload.trim_end_matches('\0');
+        match read_message_from_stream(&mut stream) {
+          Ok(actual) if actual == expected => println!("[{step}] MATCH    {actual:?}"),
+          Ok(actual) => {
+            println!("[{step}] MISMATCH expected {expected:?}, received {actual:?}");
+            mismatches += 1;
+          }
+          Err(e) => {
+            println!("[{step}] MISMATCH expected {expected:?}, server gave: {e}");
+            mismatches += 1;
+            break;
+          }
+        }
+      }
+    }
+  }
+
+  println!(
+    "Replay finished: {} entries, {mismatches} mismatches",
+    entries.len()
+  );
+  if mismatches > 0 {
+    std::process::exit(1);
+  }
+}
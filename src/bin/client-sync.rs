@@ -3,32 +3,184 @@
  *
  * Author: Sae-Hwan Park
  */
-use std::net::TcpStream;
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+#[cfg(unix)]
+use std::time::Instant;
+
+#[cfg(unix)]
+use signal_hook::consts::SIGINT;
+
+use tcp_handshake::config::DEFAULT_CONFIRM_FINAL_TIMEOUT;
 use tcp_handshake::{
-  exit_with_error, format_server_address, parse_client_args, perform_client_handshake,
+  ClientArgs, HandshakeConfig, HandshakeError, HandshakeOutcome, Preamble, Result, ResultsCsv,
+  connect_sync, exit_with_error, format_server_address, parse_client_args,
+  perform_client_handshake_with_config,
 };
 
+// How often the hold checks for Ctrl-C
+#[cfg(unix)]
+const HOLD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/**
+ * Connects and performs one handshake on a fresh connection
+ */
+fn run_handshake(
+  args: &ClientArgs,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut stream = connect_sync(&args.server_ip, args.port, config)?;
+  perform_client_handshake_with_config(&mut stream, initial_seq, config)
+}
+
+/**
+ * Runs one handshake per line of the sequence file, appending each outcome to the results CSV
+ * Malformed lines are reported and skipped
+ */
+fn run_seq_file(args: &ClientArgs, seq_path: &str, config: &HandshakeConfig) {
+  let text = match std::fs::read_to_string(seq_path) {
+    Ok(text) => text,
+    Err(e) => exit_with_error(&HandshakeError::Io(e)),
+  };
+  let mut results = match ResultsCsv::append(&args.results_path) {
+    Ok(results) => results,
+    Err(e) => exit_with_error(&e),
+  };
+
+  let (mut succeeded, mut failed, mut skipped) = (0, 0, 0);
+  for (index, line) in text.lines().enumerate() {
+    let line_number = index + 1;
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let Ok(initial_seq) = line.parse::<i32>() else {
+      eprintln!("ERROR: {seq_path}:{line_number}: invalid sequence number '{line}', skipping");
+      skipped += 1;
+      continue;
+    };
+
+    let result = run_handshake(args, initial_seq, config);
+    match &result {
+      Ok(outcome) => {
+        eprintln!("[line {line_number}] Round-trip time: {:?}", outcome.rtt);
+        succeeded += 1;
+      }
+      Err(e) => {
+        eprintln!("ERROR: [line {line_number}] Handshake with HELLO {initial_seq} failed: {e}");
+        failed += 1;
+      }
+    }
+    if let Err(e) = results.record(line_number, initial_seq, &result) {
+      exit_with_error(&e);
+    }
+  }
+
+  eprintln!(
+    "Completed {} runs: {succeeded} succeeded, {failed} failed, {skipped} malformed lines skipped \
+     (results appended to {})",
+    succeeded + failed,
+    args.results_path
+  );
+  if failed > 0 {
+    std::process::exit(1);
+  }
+}
+
 fn main() {
   // Parse command line arguments
-  let (server_ip, port, initial_seq) = match parse_client_args() {
+  let args = match parse_client_args() {
     Ok(args) => args,
     Err(e) => exit_with_error(&e),
   };
+  let server_addr = format_server_address(&args.server_ip, args.port);
+  let config = HandshakeConfig {
+    raw_first_message: args.raw_first.clone(),
+    bind_addr: args.bind,
+    source_port_range: args.source_port_range.clone(),
+    confirm_final: args.confirm_final.then_some(DEFAULT_CONFIRM_FINAL_TIMEOUT),
+    socks5_proxy: args.socks5.clone(),
+    namespace: args.namespace.clone(),
+    claimed_ip: args.claim_ip,
+    preamble: args.preamble.then(Preamble::default),
+    capabilities: args.capabilities,
+    required_capabilities: args.required_capabilities,
+    // Losing a log line to a closed stdout is reported, not fatal
+    tolerate_log_failures: true,
+    ..HandshakeConfig::default()
+  };
+
+  if let Some(seq_path) = &args.seq_file {
+    run_seq_file(&args, seq_path, &config);
+    return;
+  }
 
   // Connect to the server
-  let server_addr = format_server_address(&server_ip, port);
-  let stream = match TcpStream::connect(&server_addr) {
-    Ok(stream) => stream,
+  let mut stream = match connect_sync(&args.server_ip, args.port, &config) {
+    Ok(stream) => {
+      if let (Some(_), Ok(local)) = (&config.source_port_range, stream.local_addr()) {
+        eprintln!("Using source port {}", local.port());
+      }
+      stream
+    }
     Err(e) => {
       eprintln!("ERROR: Failed to connect to {server_addr}: {e}");
-      std::process::exit(1);
+      std::process::exit(e.exit_code());
     }
   };
 
   // Perform the 3-way handshake
-  if let Err(e) = perform_client_handshake(stream, initial_seq) {
-    exit_with_error(&e);
-  }
+  let outcome = match perform_client_handshake_with_config(&mut stream, args.initial_seq, &config) {
+    Ok(outcome) => outcome,
+    Err(e) => exit_with_error(&e),
+  };
 
   // Handshake completed successfully
+  if outcome.logging_degraded {
+    eprintln!("WARNING: Some handshake output could not be written to stdout");
+  }
+  eprintln!("Round-trip time: {:?}", outcome.rtt);
+  if let Ok(local) = stream.local_addr() {
+    eprintln!("Local endpoint: {local}");
+  }
+  // Optionally keep the socket open until the hold elapses or Ctrl-C arrives
+  if let Some(hold) = args.hold {
+    println!("Holding connection open for {} ms", hold.as_millis());
+    if hold_until_interrupted(hold) {
+      println!("Hold interrupted, closing connection");
+    }
+  }
+}
+
+/**
+ * Sleeps for `hold`, returning early with `true` once Ctrl-C arrives
+ */
+#[cfg(unix)]
+fn hold_until_interrupted(hold: Duration) -> bool {
+  let interrupted = Arc::new(AtomicBool::new(false));
+  // Without the flag Ctrl-C keeps its default action and ends the process
+  if let Err(e) = signal_hook::flag::register(SIGINT, Arc::clone(&interrupted)) {
+    eprintln!("WARNING: Could not watch for Ctrl-C during the hold: {e}");
+  }
+  let deadline = Instant::now() + hold;
+  while !interrupted.load(Ordering::Relaxed) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      return false;
+    }
+    thread::sleep(remaining.min(HOLD_POLL_INTERVAL));
+  }
+  true
+}
+
+#[cfg(not(unix))]
+fn hold_until_interrupted(hold: Duration) -> bool {
+  thread::sleep(hold);
+  false
 }
@@ -7,60 +7,76 @@
  * multiple client connections concurrently without creating explicit threads.
  * Each connection is handled as a lightweight async task.
  */
-use std::net::SocketAddr;
-use tokio::net::TcpStream;
+use std::sync::Arc;
 
+#[cfg(feature = "otel")]
+use tcp_handshake::install_otel;
 use tcp_handshake::{
-  create_async_listener, exit_with_error, parse_server_args, perform_async_server_handshake,
+  ReportOnExit, ServerMetrics, create_async_server_listener, dump_on_sigusr1, exit_with_error,
+  load_live_config, parse_server_args, run_timer, serve_async, serve_ready_socket, shutdown_signal,
 };
 
-/**
- * Async task wrapper to handle client connections
- * Ensures proper error handling and logging
- */
-async fn handle_client_task(stream: TcpStream, peer_addr: SocketAddr) {
-  match perform_async_server_handshake(stream, peer_addr).await {
-    Ok(_) => {
-      println!("Successfully handled connection from {peer_addr}");
-    }
-    Err(e) => {
-      eprintln!("ERROR handling {peer_addr}: {e}");
-    }
-  }
-}
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+  // The console feature serves task states to tokio-console on 127.0.0.1:6669
+  #[cfg(feature = "console")]
+  if cfg!(tokio_unstable) {
+    console_subscriber::init();
+  } else {
+    eprintln!("WARNING: tokio-console needs RUSTFLAGS=\"--cfg tokio_unstable\", not serving it");
+  }
+
   // Parse command line arguments
-  let port = match parse_server_args() {
-    Ok(port) => port,
+  let args = match parse_server_args() {
+    Ok(args) => args,
     Err(e) => exit_with_error(&e),
   };
 
-  // Create and bind async listener
-  let listener = match create_async_listener(port).await {
+  // --otel-endpoint exports connection and step spans until the guard is dropped on exit
+  #[cfg(feature = "otel")]
+  let _otel = match args.otel.as_ref().map(install_otel).transpose() {
+    Ok(guard) => guard,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Layer the --config file over the flags; SIGHUP reloads it
+  let config = match load_live_config(args.config, args.config_file) {
+    Ok(config) => config,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // SIGUSR1 dumps the current config and metrics to stderr
+  let metrics = Arc::new(ServerMetrics::new());
+  if let Err(e) = dump_on_sigusr1(Arc::clone(&config), Arc::clone(&metrics)) {
+    exit_with_error(&e);
+  }
+
+  // Watch for Ctrl-C and SIGTERM before the listener announces itself
+  let signalled = shutdown_signal(Arc::clone(&config));
+
+  // Create and bind async listener, or take over the inherited one
+  let listener = match create_async_server_listener(args.port, args.listen_fd).await {
     Ok(listener) => listener,
     Err(e) => exit_with_error(&e),
   };
 
-  // Main async event loop
-  // Accept connections and spawn async tasks to handle them
-  loop {
-    match listener.accept().await {
-      Ok((stream, peer_addr)) => {
-        println!("Accepted connection from {peer_addr}");
+  // --ready-socket answers supervisor checks with OK once the loop below is serving
+  if let Some(path) = &args.ready_socket
+    && let Err(e) = serve_ready_socket(path, Arc::clone(&config), Arc::clone(&metrics))
+  {
+    exit_with_error(&e);
+  }
 
-        // Spawn a new async task to handle this client concurrently
-        // The task will run independently and not block other connections
-        tokio::spawn(async move {
-          handle_client_task(stream, peer_addr).await;
-        });
-      }
-      Err(e) => {
-        eprintln!("ERROR accepting connection: {e}");
-        // Continue accepting other connections
-        continue;
-      }
+  // Serve until Ctrl-C, SIGTERM or --run-for, then drain in-flight handshakes
+  let timer = run_timer(args.run_for, Arc::clone(&config));
+  let shutdown = async {
+    tokio::select! {
+      _ = signalled => {}
+      _ = timer => {}
     }
-  }
+  };
+  // The run report is printed once on the way out, however the server stops
+  let _report = ReportOnExit::stdout(Arc::clone(&metrics));
+  serve_async(listener, config, metrics, args.options, shutdown).await;
+  Ok(())
 }
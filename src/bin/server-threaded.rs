@@ -4,58 +4,63 @@
  *
  * Author: Sae-Hwan Park
  */
-use std::net::TcpStream;
-use std::thread;
+use std::sync::Arc;
 
+#[cfg(feature = "otel")]
+use tcp_handshake::install_otel;
 use tcp_handshake::{
-  create_listener, exit_with_error, parse_server_args, perform_server_handshake,
+  ReportOnExit, ServerMetrics, create_server_listener, dump_on_sigusr1_blocking, exit_with_error,
+  load_live_config, parse_server_args, serve_ready_socket_blocking, serve_threaded,
+  stop_on_shutdown_signal,
 };
 
-/**
- * Thread wrapper function to handle client connections
- */
-fn handle_client_thread(stream: TcpStream) {
-  let peer_addr = stream
-    .peer_addr()
-    .map(|addr| addr.to_string())
-    .unwrap_or_else(|_| "unknown".to_string());
-
-  match perform_server_handshake(stream) {
-    Ok(_) => println!("Successfully handled connection from {peer_addr}"),
-    Err(e) => eprintln!("ERROR: Handshake failed with {peer_addr}: {e}"),
-  }
-  // Thread automatically cleans up when function returns
-}
-
 fn main() {
   // Parse command line arguments
-  let port = match parse_server_args() {
-    Ok(port) => port,
+  let args = match parse_server_args() {
+    Ok(args) => args,
     Err(e) => exit_with_error(&e),
   };
 
-  // Create and bind listener
-  let listener = match create_listener(port) {
+  // --otel-endpoint exports connection and step spans until the guard is dropped on exit
+  #[cfg(feature = "otel")]
+  let _otel = match args.otel.as_ref().map(install_otel).transpose() {
+    Ok(guard) => guard,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Layer the --config file over the flags; SIGHUP reloads it
+  let config = match load_live_config(args.config, args.config_file) {
+    Ok(config) => config,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // SIGUSR1 dumps the current config and metrics to stderr
+  let metrics = Arc::new(ServerMetrics::new());
+  if let Err(e) = dump_on_sigusr1_blocking(Arc::clone(&config), Arc::clone(&metrics)) {
+    exit_with_error(&e);
+  }
+
+  // Create and bind listener, or take over the inherited one
+  let listener = match create_server_listener(args.port, args.listen_fd) {
     Ok(listener) => listener,
     Err(e) => exit_with_error(&e),
   };
 
-  // Main server loop - spawn thread for each client
-  loop {
-    match listener.accept() {
-      Ok((stream, addr)) => {
-        println!("Accepted connection from {addr}");
-
-        // Create a new thread to handle this client
-        // Move the stream into the thread to transfer ownership
-        thread::spawn(move || {
-          handle_client_thread(stream);
-        });
-      }
-      Err(e) => {
-        eprintln!("ERROR: Failed to accept connection: {e}");
-        // Continue accepting other connections
-      }
-    }
+  // --ready-socket answers supervisor checks with OK once the loop below is serving
+  if let Some(path) = &args.ready_socket
+    && let Err(e) = serve_ready_socket_blocking(path, Arc::clone(&config), Arc::clone(&metrics))
+  {
+    exit_with_error(&e);
   }
+
+  // Ctrl-C, SIGTERM or --run-for stops accepting new clients
+  let stop = match stop_on_shutdown_signal(&listener, args.run_for, Arc::clone(&config)) {
+    Ok(stop) => stop,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Main server loop - spawn thread for each client
+  // The run report is printed once on the way out, however the server stops
+  let _report = ReportOnExit::stdout(Arc::clone(&metrics));
+  serve_threaded(listener, config, Arc::clone(&metrics), &stop);
 }
@@ -1,69 +1,85 @@
 /**
  * Thread Pool Server for 3-way Handshake Protocol
- * Uses a thread pool to efficiently handle multiple client connections
+ * Uses a fixed set of worker threads fed by a bounded queue of connections
  *
  * Author: Sae-Hwan Park
  */
-use std::net::TcpStream;
-use threadpool::ThreadPool;
+use std::sync::Arc;
 
+#[cfg(feature = "otel")]
+use tcp_handshake::install_otel;
 use tcp_handshake::{
-  calculate_optimal_thread_count, create_listener, exit_with_error, parse_server_args,
-  perform_server_handshake,
+  ReportOnExit, ServerMetrics, create_server_listener, dump_on_sigusr1_blocking, exit_with_error,
+  load_live_config, parse_server_args, serve_ready_socket_blocking, serve_threadpool,
+  stop_on_shutdown_signal,
 };
 
-/**
- * Worker function to handle client connection in thread pool
- * Ensures proper error handling and logging
- */
-fn handle_client_worker(stream: TcpStream) {
-  let peer_addr = stream
-    .peer_addr()
-    .map(|addr| addr.to_string())
-    .unwrap_or_else(|_| "unknown".to_string());
-
-  match perform_server_handshake(stream) {
-    Ok(_) => println!("Successfully handled connection from {peer_addr}"),
-    Err(e) => eprintln!("ERROR: Handshake failed with {peer_addr}: {e}"),
-  }
-}
-
 fn main() {
   // Parse command line arguments
-  let port = match parse_server_args() {
-    Ok(port) => port,
+  let args = match parse_server_args() {
+    Ok(args) => args,
     Err(e) => exit_with_error(&e),
   };
 
-  // Determine optimal thread pool size
-  // For I/O bound tasks like TCP handling, we can use more threads than CPU cores
-  let num_threads = calculate_optimal_thread_count();
-  println!("Starting server on port {port} with {num_threads} worker threads");
+  // --otel-endpoint exports connection and step spans until the guard is dropped on exit
+  #[cfg(feature = "otel")]
+  let _otel = match args.otel.as_ref().map(install_otel).transpose() {
+    Ok(guard) => guard,
+    Err(e) => exit_with_error(&e),
+  };
 
-  // Create thread pool
-  let pool = ThreadPool::new(num_threads);
+  // Layer the --config file over the flags; SIGHUP reloads it
+  let config = match load_live_config(args.config, args.config_file) {
+    Ok(config) => config,
+    Err(e) => exit_with_error(&e),
+  };
 
-  // Create and bind listener
-  let listener = match create_listener(port) {
+  // SIGUSR1 dumps the current config and metrics to stderr
+  let metrics = Arc::new(ServerMetrics::new());
+  if let Err(e) = dump_on_sigusr1_blocking(Arc::clone(&config), Arc::clone(&metrics)) {
+    exit_with_error(&e);
+  }
+
+  // Determine optimal thread pool size unless --workers fixes it
+  // For I/O bound tasks like TCP handling, we can use more threads than CPU cores
+  let num_threads = args.options.worker_count();
+  let source = match args.listen_fd {
+    Some(fd) => format!("inherited fd {fd}"),
+    None => format!("port {}", args.port),
+  };
+  println!(
+    "Starting server on {source} with {num_threads} worker threads and a queue of {}",
+    args.options.queue_capacity
+  );
+
+  // Create and bind listener, or take over the inherited one
+  let listener = match create_server_listener(args.port, args.listen_fd) {
     Ok(listener) => listener,
     Err(e) => exit_with_error(&e),
   };
 
-  // Main server loop - submit connections to thread pool
-  loop {
-    match listener.accept() {
-      Ok((stream, addr)) => {
-        println!("Accepted connection from {addr}");
+  // --ready-socket answers supervisor checks with OK once the loop below is serving
+  if let Some(path) = &args.ready_socket
+    && let Err(e) = serve_ready_socket_blocking(path, Arc::clone(&config), Arc::clone(&metrics))
+  {
+    exit_with_error(&e);
+  }
+
+  // Ctrl-C, SIGTERM or --run-for starts draining and wakes the blocking accept
+  let stop = match stop_on_shutdown_signal(&listener, args.run_for, Arc::clone(&config)) {
+    Ok(stop) => stop,
+    Err(e) => exit_with_error(&e),
+  };
+
+  // Hand connections to the workers through the queue until shutdown
+  // The run report is printed once on the way out, however the server stops
+  let _report = ReportOnExit::stdout(Arc::clone(&metrics));
+  let drained = serve_threadpool(listener, config, Arc::clone(&metrics), args.options, &stop);
 
-        // Submit client handling to thread pool
-        pool.execute(move || {
-          handle_client_worker(stream);
-        });
-      }
-      Err(e) => {
-        eprintln!("ERROR: Failed to accept connection: {e}");
-        // Continue accepting other connections
-      }
-    }
+  if !drained {
+    eprintln!(
+      "Drain timeout elapsed, abandoning busy workers ({})",
+      metrics.snapshot()
+    );
   }
 }
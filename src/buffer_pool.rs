@@ -0,0 +1,131 @@
+/**
+ * Reusable read buffers for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * A server handshake normally allocates a fresh `GrowableBuffer` for the
+ * bytes it has read but not yet parsed. With a `BufferPool` in the config the
+ * buffers are allocated once, up front, and each handshake borrows one and
+ * hands it back cleared when it ends, so steady traffic reads into memory
+ * that already exists.
+ */
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::config::HandshakeConfig;
+use crate::framing::{DEFAULT_RETAINED_CAPACITY, GrowableBuffer};
+
+/**
+ * A fixed set of preallocated buffers shared by the server's workers
+ *
+ * A handshake that finds the pool empty gets a fresh buffer, which joins the
+ * pool afterwards only if there is room, so the pool never holds more than
+ * it was created with.
+ */
+#[derive(Debug)]
+pub struct BufferPool {
+  idle: Mutex<Vec<GrowableBuffer>>,
+  size: usize,
+}
+
+impl BufferPool {
+  /**
+   * Allocates `size` buffers of `DEFAULT_RETAINED_CAPACITY` bytes each
+   */
+  pub fn new(size: usize) -> Self {
+    let idle = (0..size)
+      .map(|_| GrowableBuffer::with_capacity(DEFAULT_RETAINED_CAPACITY))
+      .collect();
+    Self {
+      idle: Mutex::new(idle),
+      size,
+    }
+  }
+
+  /**
+   * Borrows an idle buffer, or allocates one when every buffer is in use
+   */
+  pub fn take(&self) -> PooledBuffer<'_> {
+    let buffer = self
+      .idle
+      .lock()
+      .unwrap()
+      .pop()
+      .unwrap_or_else(|| GrowableBuffer::with_capacity(DEFAULT_RETAINED_CAPACITY));
+    PooledBuffer {
+      buffer: Some(buffer),
+      pool: Some(self),
+    }
+  }
+
+  /**
+   * Buffers waiting to be borrowed
+   */
+  pub fn idle(&self) -> usize {
+    self.idle.lock().unwrap().len()
+  }
+
+  fn give_back(&self, mut buffer: GrowableBuffer) {
+    buffer.clear();
+    let mut idle = self.idle.lock().unwrap();
+    if idle.len() < self.size {
+      idle.push(buffer);
+    }
+  }
+}
+
+/**
+ * A buffer borrowed from a `BufferPool`, returned to it on drop
+ */
+#[derive(Debug)]
+pub struct PooledBuffer<'p> {
+  buffer: Option<GrowableBuffer>,
+  // `None` for a buffer that belongs to no pool and is simply freed
+  pool: Option<&'p BufferPool>,
+}
+
+impl PooledBuffer<'_> {
+  fn unpooled(buffer: GrowableBuffer) -> Self {
+    Self {
+      buffer: Some(buffer),
+      pool: None,
+    }
+  }
+}
+
+impl Deref for PooledBuffer<'_> {
+  type Target = GrowableBuffer;
+
+  fn deref(&self) -> &GrowableBuffer {
+    self.buffer.as_ref().expect("buffer is only taken on drop")
+  }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+  fn deref_mut(&mut self) -> &mut GrowableBuffer {
+    self.buffer.as_mut().expect("buffer is only taken on drop")
+  }
+}
+
+impl Drop for PooledBuffer<'_> {
+  fn drop(&mut self) {
+    if let (Some(buffer), Some(pool)) = (self.buffer.take(), self.pool) {
+      pool.give_back(buffer);
+    }
+  }
+}
+
+/**
+ * The buffer a server handshake reads into, holding `prefix` to start with
+ * Comes from `config.buffer_pool` when one is set
+ */
+pub(crate) fn handshake_buffer<'c>(prefix: &[u8], config: &'c HandshakeConfig) -> PooledBuffer<'c> {
+  match &config.buffer_pool {
+    Some(pool) => {
+      let mut buffer = pool.take();
+      buffer.extend_from_slice(prefix);
+      buffer
+    }
+    None => PooledBuffer::unpooled(GrowableBuffer::from(prefix)),
+  }
+}
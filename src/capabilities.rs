@@ -0,0 +1,125 @@
+/**
+ * Capability negotiation for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With `capabilities` set in the config, the client lists the features it
+ * supports as a hex bitmask in HELLO X, e.g. `HELLO 5 CAP=3`. The server
+ * intersects that with its own set and answers with the common subset in
+ * HELLO Y, `HELLO 6 CAP=1`. Each side then checks that every feature in its
+ * `required_capabilities` made it into the common set, and fails with
+ * `CapabilityMismatch` otherwise. The common set is reported in the outcome.
+ *
+ * Negotiation only describes what both ends can do; each feature is still
+ * switched on by its own setting. Bits without a name are carried through
+ * untouched, so newer peers can add features without breaking older ones.
+ */
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+use std::str::FromStr;
+
+use crate::error::{HandshakeError, Result};
+
+/**
+ * A set of protocol features, sent on the wire as a hex bitmask
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+  pub const NONE: Self = Self(0);
+  // `N=` nonces against replay
+  pub const NONCE: Self = Self(1 << 0);
+  // `IP=` claimed source addresses
+  pub const CLAIMED_IP: Self = Self(1 << 1);
+  // `<namespace>:` message prefixes
+  pub const NAMESPACE: Self = Self(1 << 2);
+  // READY/START greeting before HELLO X
+  pub const PREAMBLE: Self = Self(1 << 3);
+  // Chunked payloads after the handshake
+  pub const CHUNKED: Self = Self(1 << 4);
+  // gzip and zstd compressed chunked payloads (`compression` feature)
+  pub const GZIP: Self = Self(1 << 5);
+  pub const ZSTD: Self = Self(1 << 6);
+
+  pub const fn from_bits(bits: u32) -> Self {
+    Self(bits)
+  }
+
+  pub const fn bits(self) -> u32 {
+    self.0
+  }
+
+  pub const fn is_empty(self) -> bool {
+    self.0 == 0
+  }
+
+  /**
+   * The features in either set, usable in constants
+   */
+  pub const fn union(self, other: Self) -> Self {
+    Self(self.0 | other.0)
+  }
+
+  /**
+   * Whether every feature in `other` is also in this set
+   */
+  pub const fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  /**
+   * The features in this set that `other` lacks
+   */
+  pub const fn difference(self, other: Self) -> Self {
+    Self(self.0 & !other.0)
+  }
+}
+
+impl BitOr for Capabilities {
+  type Output = Self;
+
+  fn bitor(self, other: Self) -> Self {
+    self.union(other)
+  }
+}
+
+impl BitAnd for Capabilities {
+  type Output = Self;
+
+  fn bitand(self, other: Self) -> Self {
+    Self(self.0 & other.0)
+  }
+}
+
+/**
+ * Formats the set as it appears after `CAP=`, in lowercase hex without a prefix
+ */
+impl fmt::Display for Capabilities {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:x}", self.0)
+  }
+}
+
+impl FromStr for Capabilities {
+  type Err = HandshakeError;
+
+  fn from_str(value: &str) -> Result<Self> {
+    u32::from_str_radix(value, 16)
+      .map(Self)
+      .map_err(|_| HandshakeError::InvalidArguments(format!("invalid capability mask '{value}'")))
+  }
+}
+
+/**
+ * Fails unless every feature in `required` is in `common`
+ */
+pub fn require_capabilities(required: Capabilities, common: Capabilities) -> Result<()> {
+  if common.contains(required) {
+    return Ok(());
+  }
+  Err(HandshakeError::CapabilityMismatch {
+    missing: required.difference(common),
+    common,
+  })
+}
@@ -0,0 +1,149 @@
+/**
+ * Chunked payloads for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * After the handshake, payloads larger than a handshake message are split into
+ * chunks, each preceded by a 5 byte header:
+ *
+ * ```text
+ * +-------+----------------------+------------------+
+ * | flags | length (u32, BE)     | length bytes ... |
+ * +-------+----------------------+------------------+
+ * ```
+ *
+ * Bit 0 of `flags` is the "more" flag: it is set on every chunk but the last,
+ * and all other bits must be zero. The receiver rejects a chunk longer than
+ * `max_chunk_size` and a payload growing past `max_chunked_payload`, so a peer
+ * cannot make it buffer without bound.
+ */
+use std::io::ErrorKind;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::protocol::write_async_bytes;
+use crate::time::timeout;
+
+// Bytes in front of every chunk: the flags byte and the big-endian length
+pub const CHUNK_HEADER_LEN: usize = 5;
+
+// Flag set on every chunk that is followed by another one
+pub const CHUNK_MORE: u8 = 0x01;
+
+/**
+ * Sends `data` as one chunked payload, split into chunks of at most `config.max_chunk_size` bytes
+ * An empty payload is a single empty chunk
+ */
+pub async fn send_chunked<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  data: &[u8],
+  config: &HandshakeConfig,
+) -> Result<()> {
+  check_payload_size(data.len(), config)?;
+
+  let mut chunks = data
+    .chunks(config.max_chunk_size.clamp(1, u32::MAX as usize))
+    .peekable();
+  if chunks.peek().is_none() {
+    return write_chunk(stream, &[], false, config).await;
+  }
+  while let Some(chunk) = chunks.next() {
+    write_chunk(stream, chunk, chunks.peek().is_some(), config).await?;
+  }
+  Ok(())
+}
+
+async fn write_chunk<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  chunk: &[u8],
+  more: bool,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  let mut header = [0u8; CHUNK_HEADER_LEN];
+  header[0] = if more { CHUNK_MORE } else { 0 };
+  header[1..].copy_from_slice(&(chunk.len() as u32).to_be_bytes());
+  if config.coalesce_writes {
+    let mut framed = Vec::with_capacity(CHUNK_HEADER_LEN + chunk.len());
+    framed.extend_from_slice(&header);
+    framed.extend_from_slice(chunk);
+    write_async_bytes(stream, &framed, config).await?;
+  } else {
+    write_async_bytes(stream, &header, config).await?;
+    write_async_bytes(stream, chunk, config).await?;
+  }
+  config.record_bytes_sent(CHUNK_HEADER_LEN + chunk.len());
+  Ok(())
+}
+
+/**
+ * Receives one chunked payload and returns it reassembled
+ * A clean close before the first chunk is reported as `ClientDisconnected`
+ */
+pub async fn recv_chunked<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  config: &HandshakeConfig,
+) -> Result<Vec<u8>> {
+  let clock = config.clock.as_ref();
+  let mut payload = Vec::new();
+  let mut first = true;
+
+  loop {
+    let mut header = [0u8; CHUNK_HEADER_LEN];
+    let bytes_read = timeout(clock, config.read_timeout, stream.read(&mut header)).await??;
+    if bytes_read == 0 {
+      return Err(if first {
+        HandshakeError::ClientDisconnected
+      } else {
+        std::io::Error::from(ErrorKind::UnexpectedEof).into()
+      });
+    }
+    timeout(
+      clock,
+      config.read_timeout,
+      stream.read_exact(&mut header[bytes_read..]),
+    )
+    .await??;
+
+    let flags = header[0];
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if flags & !CHUNK_MORE != 0 {
+      return Err(HandshakeError::ProtocolViolation(format!(
+        "unknown chunk flags {flags:#04x}"
+      )));
+    }
+    if len > config.max_chunk_size {
+      return Err(HandshakeError::ProtocolViolation(format!(
+        "chunk of {len} bytes exceeds the maximum of {}",
+        config.max_chunk_size
+      )));
+    }
+    check_payload_size(payload.len() + len, config)?;
+
+    let start = payload.len();
+    payload.resize(start + len, 0);
+    timeout(
+      clock,
+      config.read_timeout,
+      stream.read_exact(&mut payload[start..]),
+    )
+    .await??;
+    config.record_bytes_received(CHUNK_HEADER_LEN + len);
+
+    if flags & CHUNK_MORE == 0 {
+      return Ok(payload);
+    }
+    first = false;
+  }
+}
+
+fn check_payload_size(size: usize, config: &HandshakeConfig) -> Result<()> {
+  if size > config.max_chunked_payload {
+    return Err(HandshakeError::PayloadTooLarge {
+      size: size as u64,
+      max: config.max_chunked_payload as u64,
+    });
+  }
+  Ok(())
+}
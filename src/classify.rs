@@ -0,0 +1,123 @@
+/**
+ * Protocol classification for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Several protocols can share one port: PING health checks, PROXY headers in
+ * front of a handshake, plaintext HELLO and TLS. `peek_classify` looks at the
+ * first bytes of an accepted connection without consuming them, so whichever
+ * handler it picks still reads the connection from the start. Bytes that
+ * could still open more than one protocol, like a lone `P`, are peeked again
+ * until they tell the protocols apart, the peer closes or `READ_TIMEOUT`
+ * passes.
+ */
+use std::mem::MaybeUninit;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::SockRef;
+use tokio::net::TcpStream as AsyncTcpStream;
+
+use crate::error::Result;
+use crate::protocol::READ_TIMEOUT;
+
+// Bytes each protocol opens with
+pub const PING_PREFIX: &[u8] = b"PING";
+pub const PROXY_PREFIX: &[u8] = b"PROXY ";
+pub const HELLO_PREFIX: &[u8] = b"HELLO";
+// A handshake record (0x16) of TLS 1.x, as every ClientHello starts
+pub const TLS_PREFIX: &[u8] = &[0x16, 0x03];
+
+// Enough bytes to match the longest prefix
+pub const PEEK_LEN: usize = 6;
+
+// Pause before peeking again at bytes that do not decide the protocol yet
+const PEEK_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/**
+ * What a connection speaks, judged by its first bytes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+  // A `PING` health check
+  Ping,
+  // A PROXY protocol v1 header ahead of the handshake
+  Proxy,
+  // A plaintext handshake opening with HELLO X
+  Hello,
+  // A TLS ClientHello
+  Tls,
+  // Anything else, or a peer that closed before sending enough to tell
+  Unknown,
+}
+
+const SIGNATURES: [(&[u8], Protocol); 4] = [
+  (PING_PREFIX, Protocol::Ping),
+  (PROXY_PREFIX, Protocol::Proxy),
+  (HELLO_PREFIX, Protocol::Hello),
+  (TLS_PREFIX, Protocol::Tls),
+];
+
+/**
+ * Classifies a connection by the bytes it opened with
+ * `None` while `prefix` is too short to tell, e.g. a lone `P` could be PING or PROXY
+ */
+pub fn classify_prefix(prefix: &[u8]) -> Option<Protocol> {
+  let mut undecided = false;
+  for (signature, protocol) in SIGNATURES {
+    if prefix.starts_with(signature) {
+      return Some(protocol);
+    }
+    undecided |= signature.starts_with(prefix);
+  }
+  (!undecided).then_some(Protocol::Unknown)
+}
+
+/**
+ * Classifies a connection from its first bytes, peeked with `recv(MSG_PEEK)`
+ * Blocks until the peer sends something, or the stream's read timeout fails the peek
+ */
+pub fn peek_classify(stream: &TcpStream) -> Result<Protocol> {
+  let socket = SockRef::from(stream);
+  let deadline = Instant::now() + READ_TIMEOUT;
+  let mut buffer = [MaybeUninit::new(0u8); PEEK_LEN];
+  loop {
+    let peeked = socket.peek(&mut buffer)?;
+    // SAFETY: the buffer started out initialized and `peek` only writes bytes into it
+    let prefix: Vec<u8> = buffer[..peeked]
+      .iter()
+      .map(|byte| unsafe { byte.assume_init() })
+      .collect();
+    if let Some(protocol) = classified(&prefix, deadline) {
+      return Ok(protocol);
+    }
+    thread::sleep(PEEK_RETRY_INTERVAL);
+  }
+}
+
+/**
+ * Async version: Classifies a connection from its first bytes with `TcpStream::peek`
+ * Waits until the peer sends something; wrap it in a timeout to bound that
+ */
+pub async fn peek_classify_async(stream: &AsyncTcpStream) -> Result<Protocol> {
+  let deadline = Instant::now() + READ_TIMEOUT;
+  let mut buffer = [0u8; PEEK_LEN];
+  loop {
+    let peeked = stream.peek(&mut buffer).await?;
+    if let Some(protocol) = classified(&buffer[..peeked], deadline) {
+      return Ok(protocol);
+    }
+    tokio::time::sleep(PEEK_RETRY_INTERVAL).await;
+  }
+}
+
+/**
+ * The protocol once `prefix` decides it, `Unknown` once the peer closed or `deadline` passed
+ */
+fn classified(prefix: &[u8], deadline: Instant) -> Option<Protocol> {
+  if prefix.is_empty() || Instant::now() >= deadline {
+    return Some(classify_prefix(prefix).unwrap_or(Protocol::Unknown));
+  }
+  classify_prefix(prefix)
+}
@@ -0,0 +1,120 @@
+/**
+ * Compressed session payloads for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Compression is negotiated like any other feature: each side lists `GZIP`
+ * and/or `ZSTD` in its capabilities, and the common set picks the codec, zstd
+ * first. When neither made it into the common set, or a peer did not
+ * negotiate at all, payloads stay plaintext, so both ends always agree.
+ *
+ * A compressed payload travels as an ordinary chunked payload (see
+ * `chunked`); the chunk limits apply to the compressed bytes, and
+ * `max_chunked_payload` also caps what a payload may decompress to, so a
+ * small bomb cannot make the receiver buffer without bound.
+ */
+use std::io::{Read, Write};
+
+use flate2::Compression as GzipLevel;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::capabilities::Capabilities;
+use crate::chunked::{recv_chunked, send_chunked};
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+
+// zstd level used for session payloads; the library default
+const ZSTD_LEVEL: i32 = 3;
+
+/**
+ * How session payloads are encoded on the wire
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+  #[default]
+  None,
+  Gzip,
+  Zstd,
+}
+
+impl Compression {
+  // Every codec this build can offer
+  pub const SUPPORTED: Capabilities = Capabilities::GZIP.union(Capabilities::ZSTD);
+
+  /**
+   * Picks the codec for a negotiated common set, preferring zstd
+   * `None` (a peer that did not negotiate) means plaintext
+   */
+  pub fn negotiate(common: Option<Capabilities>) -> Self {
+    match common {
+      Some(common) if common.contains(Capabilities::ZSTD) => Self::Zstd,
+      Some(common) if common.contains(Capabilities::GZIP) => Self::Gzip,
+      _ => Self::None,
+    }
+  }
+}
+
+/**
+ * Encodes `data` with `compression`
+ */
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+  match compression {
+    Compression::None => Ok(data.to_vec()),
+    Compression::Gzip => {
+      let mut encoder = GzEncoder::new(Vec::new(), GzipLevel::default());
+      encoder.write_all(data)?;
+      Ok(encoder.finish()?)
+    }
+    Compression::Zstd => Ok(zstd::encode_all(data, ZSTD_LEVEL)?),
+  }
+}
+
+/**
+ * Decodes `data` with `compression`, failing with `PayloadTooLarge` past `max` bytes
+ */
+pub fn decompress(data: &[u8], compression: Compression, max: usize) -> Result<Vec<u8>> {
+  let decoder: Box<dyn Read + '_> = match compression {
+    Compression::None => Box::new(data),
+    Compression::Gzip => Box::new(GzDecoder::new(data)),
+    Compression::Zstd => Box::new(zstd::Decoder::new(data)?),
+  };
+  // Reading one byte past the limit tells a full payload from an oversized one
+  let mut payload = Vec::new();
+  decoder
+    .take(max as u64 + 1)
+    .read_to_end(&mut payload)
+    .map_err(|e| HandshakeError::ProtocolViolation(format!("undecodable payload: {e}")))?;
+  if payload.len() > max {
+    return Err(HandshakeError::PayloadTooLarge {
+      size: payload.len() as u64,
+      max: max as u64,
+    });
+  }
+  Ok(payload)
+}
+
+/**
+ * Compresses `data` and sends it as one chunked payload
+ */
+pub async fn send_compressed<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  data: &[u8],
+  compression: Compression,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  send_chunked(stream, &compress(data, compression)?, config).await
+}
+
+/**
+ * Receives one chunked payload and decompresses it
+ */
+pub async fn recv_compressed<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  compression: Compression,
+  config: &HandshakeConfig,
+) -> Result<Vec<u8>> {
+  let received = recv_chunked(stream, config).await?;
+  decompress(&received, compression, config.max_chunked_payload)
+}
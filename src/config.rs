@@ -0,0 +1,421 @@
+/**
+ * Tunable settings for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::MSG_SIZE;
+use crate::buffer_pool::BufferPool;
+use crate::capabilities::Capabilities;
+use crate::error::{HandshakeError, Result};
+use crate::events::EventSink;
+use crate::framing::Delimiter;
+use crate::limits::RateLimit;
+use crate::metrics::{ByteCounters, MessageSizeHistogram};
+use crate::peer::{DefaultPeerResolver, PeerResolver};
+use crate::pool::DEFAULT_QUEUE_CAPACITY;
+use crate::preamble::Preamble;
+use crate::protocol::{CLIENT_CONNECTION_TIMEOUT, CONNECTION_TIMEOUT, READ_TIMEOUT};
+use crate::reply::ReplyStrategy;
+use crate::sequence::{IncrementPolicy, SequencePolicy};
+use crate::socks5::Socks5Proxy;
+use crate::template::ResponseTemplate;
+use crate::time::{Clock, TokioClock};
+use crate::transcript::Direction;
+use crate::utils::calculate_optimal_thread_count;
+
+// Default budgets for resolving the server name and for connecting to it
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Default time the async server waits for in-flight handshakes on shutdown
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Default caps for a post-handshake echo session
+pub const DEFAULT_MAX_SESSION_MESSAGES: u64 = 10_000;
+pub const DEFAULT_MAX_SESSION_BYTES: u64 = 1024 * 1024;
+
+// Default largest chunk and largest reassembled payload for chunked sessions
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 64 * 1024;
+pub const DEFAULT_MAX_CHUNKED_PAYLOAD: usize = 16 * 1024 * 1024;
+
+// Default cap on logical handshakes multiplexed over one connection
+pub const DEFAULT_MAX_MUX_STREAMS: usize = 1024;
+
+// Default retries for transient async write errors and the pause between them
+pub const DEFAULT_WRITE_RETRIES: u32 = 3;
+pub const DEFAULT_WRITE_RETRY_DELAY: Duration = Duration::from_millis(5);
+
+// Default duration above which a completed handshake is logged as slow
+pub const DEFAULT_SLOW_THRESHOLD: Duration = Duration::from_secs(1);
+
+// Default wait for the server to close or object after the sync client's final HELLO
+pub const DEFAULT_CONFIRM_FINAL_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Default estimated accept queue wait above which the accept loop warns
+pub const DEFAULT_ACCEPT_LATENCY_THRESHOLD: Duration = Duration::from_millis(100);
+
+/**
+ * Settings shared by the handshake functions and the server loops
+ * `Default` matches the behavior of the plain (non `_with_config`) functions
+ */
+#[derive(Debug, Clone)]
+pub struct HandshakeConfig {
+  // Upper bound for a whole server-side handshake
+  pub connection_timeout: Duration,
+  // Upper bound for a single read
+  pub read_timeout: Duration,
+  // Server gives up on a peer that sends nothing this long after connecting (`None` waits `read_timeout`)
+  pub first_byte_timeout: Option<Duration>,
+  // Upper bound for a whole client-side handshake
+  pub client_connection_timeout: Duration,
+  // Null-pad outgoing messages to `MSG_SIZE` so each one fills the peer's read buffer
+  pub pad_to_buffer: bool,
+  // Upper bounds for the async client's name lookup and TCP connect, in that order
+  pub dns_timeout: Duration,
+  pub connect_timeout: Duration,
+  // Local address client sockets bind to before connecting (`None` lets the OS pick)
+  pub bind_addr: Option<SocketAddr>,
+  // Client sockets bind to the first free port in this range, on `bind_addr`'s IP if set
+  pub source_port_range: Option<RangeInclusive<u16>>,
+  // Client connections are tunneled through this SOCKS5 proxy when set
+  pub socks5_proxy: Option<Socks5Proxy>,
+  // Sync client waits this long after HELLO Z for a clean close or an `ERR` reply (`None` skips it)
+  pub confirm_final: Option<Duration>,
+  // Client claims this source address with an `IP=` field in HELLO X
+  pub claimed_ip: Option<IpAddr>,
+  // Testing only: client sends this verbatim instead of the formatted first HELLO
+  pub raw_first_message: Option<String>,
+  // Fail a read that fills the buffer while more data is already waiting
+  pub detect_read_overflow: bool,
+  // How handshake messages are separated on the wire
+  pub delimiter: Delimiter,
+  // Longest delimited message; a peer withholding the delimiter past it fails with `FrameTooLarge`
+  pub max_line_len: usize,
+  // Write a message and its delimiter, or a chunk header and its bytes, in one call instead of one per part
+  pub coalesce_writes: bool,
+  // Byte lengths of received messages are recorded here; see `ServerMetrics::instrument`
+  pub message_sizes: Option<Arc<MessageSizeHistogram>>,
+  // Bytes received and sent are added here; see `ServerMetrics::instrument`
+  pub byte_counters: Option<Arc<ByteCounters>>,
+  // Connections cut off by `max_connection_messages` are counted here; see `ServerMetrics::instrument`
+  pub excess_messages: Option<Arc<AtomicU64>>,
+  // Transient async write errors are retried this many times, pausing in between
+  pub write_retries: u32,
+  pub write_retry_delay: Duration,
+  // Time source used for every async timeout
+  pub clock: Arc<dyn Clock>,
+  // How Y follows X and Z follows Y; both sides need the same policy, see `sequence`
+  pub sequence_policy: Arc<dyn SequencePolicy>,
+  // Picks the client address servers log, limit and verify connections by; see `peer`
+  pub peer_resolver: Arc<dyn PeerResolver>,
+  // Receives every message the handshake functions send or receive
+  pub on_event: EventSink,
+  // Library code prints nothing to stdout or stderr; use `on_event` and the metrics instead
+  pub silent: bool,
+  // Drop stdout lines that cannot be written instead of panicking; handshakes report them as `logging_degraded`
+  pub tolerate_log_failures: bool,
+  // Check protocol invariants at runtime, panicking on a violation in debug builds; see `invariants`
+  pub strict_debug: bool,
+  // Grace period for in-flight handshakes once shutdown starts
+  pub drain_timeout: Duration,
+  // Expect a PROXY protocol v1 header before the first HELLO
+  pub proxy_protocol: bool,
+  // Server issues a random nonce in HELLO Y that HELLO Z must echo
+  pub require_nonce: bool,
+  // Server accepts an `IP=` claim in HELLO X and warns when it differs from the peer address
+  pub verify_client_ip: bool,
+  // A claimed address that differs from the peer address fails the handshake instead
+  pub strict_client_ip: bool,
+  // Server replies are formatted from this instead of `HELLO Y`
+  pub response_template: Option<ResponseTemplate>,
+  // Every handshake message is prefixed with `<namespace>:`; both ends must agree
+  pub namespace: Option<String>,
+  // Server greeting and client reply exchanged before HELLO X; both ends must agree
+  pub preamble: Option<Preamble>,
+  // Features offered in a `CAP=` field and intersected with the peer's (`None` does not negotiate)
+  pub capabilities: Option<Capabilities>,
+  // Features that must end up in the common set when negotiating, or the handshake fails
+  pub required_capabilities: Capabilities,
+  // Run the permissive reflect handshake instead of the strict one
+  pub reflect: bool,
+  // Run stream-ID tagged handshakes instead of a single handshake
+  pub multiplex: bool,
+  pub max_mux_streams: usize,
+  // Keep the connection open after the handshake and echo messages back
+  pub echo_session: bool,
+  // Run one handshake after another on each connection until the client closes it;
+  // `multiplex` takes precedence, and `reflect` and `echo_session` do not apply
+  pub persistent: bool,
+  // Echo session ends with an error once either limit is exceeded
+  pub max_session_messages: u64,
+  pub max_session_bytes: u64,
+  // Echo session exchanges chunked payloads instead of single messages; see `chunked`
+  pub chunked_session: bool,
+  // Chunks are at most this long, and reassembled payloads at most `max_chunked_payload`
+  pub max_chunk_size: usize,
+  pub max_chunked_payload: usize,
+  // Connections one source IP may hold open at once (`None` for no limit)
+  pub max_connections_per_source: Option<usize>,
+  // Connections accepted beyond this rate are refused (`None` for no limit)
+  pub accept_rate_limit: Option<RateLimit>,
+  // Handshake messages the server reads on one connection before failing it (`None` for no limit)
+  pub max_connection_messages: Option<u64>,
+  // Send `BUSY` to connections refused by a limit or a full queue before closing them
+  pub busy_response: bool,
+  // Refuse connections for this long after the accept loop starts
+  pub warmup: Option<Duration>,
+  // Completed handshakes slower than this are logged and counted
+  pub slow_threshold: Duration,
+  // Connections estimated to wait longer than this in the accept queue are logged and counted
+  pub accept_latency_threshold: Duration,
+  // SO_LINGER for handshake sockets (`None` keeps the OS default)
+  pub linger: Option<Duration>,
+  // TCP_NODELAY for handshake sockets, so each small HELLO goes out at once
+  pub tcp_nodelay: bool,
+  // TCP_CORK around each server reply so it leaves as one segment (Linux and Android only)
+  pub cork_replies: bool,
+  // Parse plain HELLO messages from their bytes as ASCII, skipping the UTF-8 check and allocations
+  pub parse_in_place: bool,
+  // Log protocol, local and remote address of each accepted connection instead of the peer alone
+  pub log_five_tuple: bool,
+  // Server handshakes borrow their read buffers from this pool (`None` allocates one per handshake)
+  pub buffer_pool: Option<Arc<BufferPool>>,
+  // When async server handshakes send HELLO Y: at once, or batched with other connections' replies
+  pub reply_strategy: ReplyStrategy,
+  // Opaque tag, e.g. a test or shard name, on this config's handshake log lines, events and outcomes
+  pub label: Option<String>,
+}
+
+impl Default for HandshakeConfig {
+  fn default() -> Self {
+    Self {
+      connection_timeout: CONNECTION_TIMEOUT,
+      read_timeout: READ_TIMEOUT,
+      first_byte_timeout: None,
+      client_connection_timeout: CLIENT_CONNECTION_TIMEOUT,
+      pad_to_buffer: false,
+      dns_timeout: DEFAULT_DNS_TIMEOUT,
+      connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+      bind_addr: None,
+      source_port_range: None,
+      socks5_proxy: None,
+      confirm_final: None,
+      claimed_ip: None,
+      raw_first_message: None,
+      detect_read_overflow: false,
+      delimiter: Delimiter::None,
+      max_line_len: MSG_SIZE,
+      coalesce_writes: true,
+      message_sizes: None,
+      byte_counters: None,
+      excess_messages: None,
+      write_retries: DEFAULT_WRITE_RETRIES,
+      write_retry_delay: DEFAULT_WRITE_RETRY_DELAY,
+      clock: Arc::new(TokioClock),
+      sequence_policy: Arc::new(IncrementPolicy),
+      peer_resolver: Arc::new(DefaultPeerResolver),
+      on_event: EventSink::default(),
+      silent: false,
+      tolerate_log_failures: false,
+      strict_debug: false,
+      drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+      proxy_protocol: false,
+      require_nonce: false,
+      verify_client_ip: false,
+      strict_client_ip: false,
+      response_template: None,
+      namespace: None,
+      preamble: None,
+      capabilities: None,
+      required_capabilities: Capabilities::NONE,
+      reflect: false,
+      multiplex: false,
+      max_mux_streams: DEFAULT_MAX_MUX_STREAMS,
+      echo_session: false,
+      persistent: false,
+      max_session_messages: DEFAULT_MAX_SESSION_MESSAGES,
+      max_session_bytes: DEFAULT_MAX_SESSION_BYTES,
+      chunked_session: false,
+      max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+      max_chunked_payload: DEFAULT_MAX_CHUNKED_PAYLOAD,
+      max_connections_per_source: None,
+      accept_rate_limit: None,
+      max_connection_messages: None,
+      busy_response: false,
+      warmup: None,
+      slow_threshold: DEFAULT_SLOW_THRESHOLD,
+      accept_latency_threshold: DEFAULT_ACCEPT_LATENCY_THRESHOLD,
+      linger: None,
+      tcp_nodelay: false,
+      cork_replies: false,
+      parse_in_place: false,
+      log_five_tuple: false,
+      buffer_pool: None,
+      reply_strategy: ReplyStrategy::Immediate,
+      label: None,
+    }
+  }
+}
+
+/**
+ * Settings for a whole server rather than for each handshake it runs
+ * Read once when the accept loop starts, so a config file reload leaves them alone
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerOptions {
+  // Thread pool workers (`None` sizes the pool from the CPU count); one handles connections in accept order
+  pub workers: Option<usize>,
+  // Connections allowed to wait for a worker (thread pool server only)
+  pub queue_capacity: usize,
+  // Connections handled at once across all clients; extra ones get `OVERLOADED` (`None` for no limit)
+  pub max_concurrent_handshakes: Option<usize>,
+}
+
+impl Default for ServerOptions {
+  fn default() -> Self {
+    Self {
+      workers: None,
+      queue_capacity: DEFAULT_QUEUE_CAPACITY,
+      max_concurrent_handshakes: None,
+    }
+  }
+}
+
+impl ServerOptions {
+  /**
+   * Worker threads for the thread pool server: `workers`, or a count sized from the CPUs
+   */
+  pub fn worker_count(&self) -> usize {
+    self.workers.unwrap_or_else(calculate_optimal_thread_count)
+  }
+}
+
+/**
+ * A named set of server settings, chosen with `--profile`
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ServerProfile {
+  // The plain `HandshakeConfig::default()` settings
+  #[default]
+  Default,
+  // Batch handshakes as fast as possible: no per-handshake logging,
+  // TCP_NODELAY on and read buffers from a preallocated pool, one per worker
+  Throughput,
+}
+
+impl ServerProfile {
+  pub const ALL: [Self; 2] = [Self::Default, Self::Throughput];
+}
+
+impl fmt::Display for ServerProfile {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::Default => "default",
+      Self::Throughput => "throughput",
+    })
+  }
+}
+
+impl FromStr for ServerProfile {
+  type Err = HandshakeError;
+
+  fn from_str(value: &str) -> Result<Self> {
+    match value {
+      "default" => Ok(Self::Default),
+      "throughput" => Ok(Self::Throughput),
+      _ => Err(HandshakeError::InvalidArguments(format!(
+        "unknown profile '{value}' (expected 'default' or 'throughput')"
+      ))),
+    }
+  }
+}
+
+impl HandshakeConfig {
+  /**
+   * Switches every setting the profile covers, leaving the rest alone
+   */
+  pub fn apply_profile(&mut self, profile: ServerProfile) {
+    match profile {
+      ServerProfile::Default => {
+        let defaults = Self::default();
+        self.silent = defaults.silent;
+        self.tcp_nodelay = defaults.tcp_nodelay;
+        self.cork_replies = defaults.cork_replies;
+        self.parse_in_place = defaults.parse_in_place;
+        self.buffer_pool = defaults.buffer_pool;
+      }
+      ServerProfile::Throughput => {
+        self.silent = true;
+        self.tcp_nodelay = true;
+        self.cork_replies = true;
+        self.parse_in_place = true;
+        self.buffer_pool = Some(Arc::new(BufferPool::new(calculate_optimal_thread_count())));
+      }
+    }
+  }
+
+  /**
+   * Reports one handshake message to `on_event`, timestamped by `clock`
+   */
+  pub(crate) fn emit_event(
+    &self,
+    step: u8,
+    direction: Direction,
+    message: &str,
+    elapsed: Duration,
+  ) {
+    self.on_event.emit(
+      step,
+      direction,
+      message,
+      self.clock.now(),
+      elapsed,
+      self.label.as_deref(),
+    );
+  }
+
+  /**
+   * Records the byte length of one received message when a histogram or byte counters are attached
+   */
+  pub(crate) fn record_message_size(&self, len: usize) {
+    if let Some(histogram) = &self.message_sizes {
+      histogram.record(len);
+    }
+    self.record_bytes_received(len);
+  }
+
+  /**
+   * Records bytes read from the peer when byte counters are attached
+   */
+  pub(crate) fn record_bytes_received(&self, len: usize) {
+    if let Some(bytes) = &self.byte_counters {
+      bytes.received.fetch_add(len as u64, Ordering::Relaxed);
+    }
+  }
+
+  /**
+   * Counts a connection cut off by `max_connection_messages` when a counter is attached
+   */
+  pub(crate) fn record_excess_message(&self) {
+    if let Some(excess) = &self.excess_messages {
+      excess.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  /**
+   * Records bytes written to the peer when byte counters are attached
+   */
+  pub(crate) fn record_bytes_sent(&self, len: usize) {
+    if let Some(bytes) = &self.byte_counters {
+      bytes.sent.fetch_add(len as u64, Ordering::Relaxed);
+    }
+  }
+}
@@ -0,0 +1,135 @@
+/**
+ * Client-side connection pool for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The strict protocol needs a fresh connection per handshake, but once a
+ * connection is in the post-handshake echo session it can carry many more
+ * messages. The pool keeps such connections per server address so repeated
+ * session traffic skips the TCP connect. Connections idle for longer than
+ * the idle timeout are closed instead of being handed out again.
+ */
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::time::{Clock, TokioClock};
+
+// Idle connections older than this are closed rather than reused
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+// Idle connections kept per server address; extra check-ins are closed
+pub const DEFAULT_MAX_IDLE_PER_ADDR: usize = 8;
+
+/**
+ * Idle connections keyed by server address, with checkout/checkin semantics
+ * Works with any stream type; share it behind an `Arc` between client tasks
+ */
+#[derive(Debug)]
+pub struct ConnectionPool<S> {
+  idle_timeout: Duration,
+  max_idle_per_addr: usize,
+  clock: Arc<dyn Clock>,
+  // Most recently checked in last, so checkout reuses the warmest connection
+  idle: Mutex<HashMap<SocketAddr, Vec<IdleConnection<S>>>>,
+}
+
+#[derive(Debug)]
+struct IdleConnection<S> {
+  stream: S,
+  since: Instant,
+}
+
+impl<S> Default for ConnectionPool<S> {
+  fn default() -> Self {
+    Self::new(DEFAULT_POOL_IDLE_TIMEOUT, DEFAULT_MAX_IDLE_PER_ADDR)
+  }
+}
+
+impl<S> ConnectionPool<S> {
+  pub fn new(idle_timeout: Duration, max_idle_per_addr: usize) -> Self {
+    Self::with_clock(idle_timeout, max_idle_per_addr, Arc::new(TokioClock))
+  }
+
+  /**
+   * Creates a pool that measures idle time on `clock`, e.g. a `MockClock` in tests
+   */
+  pub fn with_clock(
+    idle_timeout: Duration,
+    max_idle_per_addr: usize,
+    clock: Arc<dyn Clock>,
+  ) -> Self {
+    Self {
+      idle_timeout,
+      max_idle_per_addr,
+      clock,
+      idle: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /**
+   * Takes an idle connection to `addr`, closing any that sat idle too long
+   * Returns `None` when the caller has to connect itself
+   */
+  pub fn checkout(&self, addr: SocketAddr) -> Option<S> {
+    let now = self.clock.now();
+    let mut idle = self.idle.lock().unwrap();
+    let connections = idle.get_mut(&addr)?;
+    let found = loop {
+      match connections.pop() {
+        Some(connection) if self.is_fresh(&connection, now) => break Some(connection.stream),
+        // Expired; dropping it closes the connection
+        Some(_) => continue,
+        None => break None,
+      }
+    };
+    if connections.is_empty() {
+      idle.remove(&addr);
+    }
+    found
+  }
+
+  /**
+   * Returns a connection for reuse, closing it if `addr` already has the maximum idle
+   * Only check in connections that are between session messages
+   */
+  pub fn checkin(&self, addr: SocketAddr, stream: S) {
+    let mut idle = self.idle.lock().unwrap();
+    let connections = idle.entry(addr).or_default();
+    if connections.len() < self.max_idle_per_addr {
+      connections.push(IdleConnection {
+        stream,
+        since: self.clock.now(),
+      });
+    }
+  }
+
+  /**
+   * Closes every connection that has been idle longer than the idle timeout
+   * Returns how many were closed
+   */
+  pub fn evict_idle(&self) -> usize {
+    let now = self.clock.now();
+    let mut idle = self.idle.lock().unwrap();
+    let mut evicted = 0;
+    idle.retain(|_, connections| {
+      let before = connections.len();
+      connections.retain(|connection| self.is_fresh(connection, now));
+      evicted += before - connections.len();
+      !connections.is_empty()
+    });
+    evicted
+  }
+
+  /**
+   * Number of idle connections to `addr`, including any not yet evicted
+   */
+  pub fn idle_count(&self, addr: SocketAddr) -> usize {
+    let idle = self.idle.lock().unwrap();
+    idle.get(&addr).map_or(0, Vec::len)
+  }
+
+  fn is_fresh(&self, connection: &IdleConnection<S>, now: Instant) -> bool {
+    now.duration_since(connection.since) <= self.idle_timeout
+  }
+}
@@ -0,0 +1,130 @@
+/**
+ * Corked server replies for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With `cork_replies` set, the servers set TCP_CORK on a connection before
+ * writing a reply and clear it once the reply is written, so the kernel
+ * holds the bytes back until the whole reply is queued and then sends them
+ * as one segment. The blocking servers cork straight around the write. The
+ * async handshake works over any stream, so the async server wraps its
+ * socket in `CorkedReplies`, which corks on the first write and uncorks when
+ * the handshake turns to reading, flushes or shuts down. TCP_CORK exists on
+ * Linux and Android only; elsewhere both are plain pass-throughs.
+ */
+use std::io;
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use socket2::SockRef;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream as AsyncTcpStream;
+
+use crate::error::Result;
+
+/**
+ * Sets or clears TCP_CORK; does nothing where the OS has no such option
+ */
+pub(crate) fn set_cork<S>(socket: &S, corked: bool) -> io::Result<()>
+where
+  for<'s> SockRef<'s>: From<&'s S>,
+{
+  #[cfg(any(target_os = "android", target_os = "linux"))]
+  SockRef::from(socket).set_tcp_cork(corked)?;
+  #[cfg(not(any(target_os = "android", target_os = "linux")))]
+  let _ = (socket, corked);
+  Ok(())
+}
+
+/**
+ * Runs `write` on a blocking socket corked for its duration when `enabled`
+ * The cork is cleared even when the write fails
+ */
+pub(crate) fn corked<T>(
+  stream: &mut TcpStream,
+  enabled: bool,
+  write: impl FnOnce(&mut TcpStream) -> Result<T>,
+) -> Result<T> {
+  if !enabled {
+    return write(stream);
+  }
+  set_cork(&*stream, true)?;
+  let written = write(stream);
+  set_cork(&*stream, false)?;
+  written
+}
+
+/**
+ * An async socket that is corked while a reply is being written
+ * With `enabled` off every call goes straight through
+ */
+pub(crate) struct CorkedReplies<'a> {
+  stream: &'a mut AsyncTcpStream,
+  enabled: bool,
+  corked: bool,
+}
+
+impl<'a> CorkedReplies<'a> {
+  pub(crate) fn new(stream: &'a mut AsyncTcpStream, enabled: bool) -> Self {
+    Self {
+      stream,
+      enabled,
+      corked: false,
+    }
+  }
+
+  /**
+   * Sends whatever the cork is holding back
+   */
+  fn uncork(&mut self) -> io::Result<()> {
+    if self.corked {
+      set_cork(&*self.stream, false)?;
+      self.corked = false;
+    }
+    Ok(())
+  }
+}
+
+impl AsyncRead for CorkedReplies<'_> {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    // The reply is complete once the handshake waits for the peer again
+    self.uncork()?;
+    Pin::new(&mut *self.stream).poll_read(cx, buf)
+  }
+}
+
+impl AsyncWrite for CorkedReplies<'_> {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    if self.enabled && !self.corked {
+      set_cork(&*self.stream, true)?;
+      self.corked = true;
+    }
+    Pin::new(&mut *self.stream).poll_write(cx, buf)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    self.uncork()?;
+    Pin::new(&mut *self.stream).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    self.uncork()?;
+    Pin::new(&mut *self.stream).poll_shutdown(cx)
+  }
+}
+
+impl Drop for CorkedReplies<'_> {
+  fn drop(&mut self) {
+    // A handshake that ends on a write must not leave the socket corked
+    let _ = self.uncork();
+  }
+}
@@ -0,0 +1,83 @@
+/**
+ * On-demand state dumps for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Sending SIGUSR1 to a running server prints its current config and a metrics
+ * snapshot to stderr, without touching any connection. It is a quick look
+ * inside a live server when there is no metrics port to ask. The async server
+ * listens through `tokio::signal::unix`, the blocking servers through a
+ * background thread. On other platforms installing the handler does nothing.
+ */
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::metrics::ServerMetrics;
+use crate::reload::LiveConfig;
+
+/**
+ * Writes the current config and a metrics snapshot as one block
+ */
+pub fn write_state_dump(
+  out: &mut impl Write,
+  live: &LiveConfig,
+  metrics: &ServerMetrics,
+) -> std::io::Result<()> {
+  writeln!(out, "=== State dump ===")?;
+  writeln!(out, "config: {:#?}", live.load())?;
+  writeln!(out, "metrics: {}", metrics.snapshot())?;
+  writeln!(out, "=== End of state dump ===")
+}
+
+fn dump_to_stderr(live: &LiveConfig, metrics: &ServerMetrics) {
+  let stderr = std::io::stderr();
+  if let Err(e) = write_state_dump(&mut stderr.lock(), live, metrics) {
+    eprintln!("ERROR: Could not write state dump: {e}");
+  }
+}
+
+/**
+ * Async version: Dumps the server state to stderr on every SIGUSR1
+ * The handler is installed before this returns; must be called within a Tokio runtime
+ */
+pub fn dump_on_sigusr1(live: LiveConfig, metrics: Arc<ServerMetrics>) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut user1 = signal(SignalKind::user_defined1())?;
+    tokio::spawn(async move {
+      while user1.recv().await.is_some() {
+        dump_to_stderr(&live, &metrics);
+      }
+    });
+  }
+
+  #[cfg(not(unix))]
+  let _ = (live, metrics);
+  Ok(())
+}
+
+/**
+ * Dumps the server state to stderr on every SIGUSR1 from a background thread
+ * Blocking servers use this in place of `dump_on_sigusr1`
+ */
+pub fn dump_on_sigusr1_blocking(live: LiveConfig, metrics: Arc<ServerMetrics>) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use signal_hook::consts::SIGUSR1;
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGUSR1])?;
+    std::thread::spawn(move || {
+      for _ in signals.forever() {
+        dump_to_stderr(&live, &metrics);
+      }
+    });
+  }
+
+  #[cfg(not(unix))]
+  let _ = (live, metrics);
+  Ok(())
+}
@@ -4,33 +4,199 @@
  * Author: Sae-Hwan Park
  */
 use std::io;
+use std::net::{IpAddr, SocketAddr};
 use thiserror::Error;
 
+use crate::capabilities::Capabilities;
+
 #[derive(Error, Debug)]
 pub enum HandshakeError {
   #[error("IO error: {0}")]
   Io(#[from] io::Error),
 
-  #[error("Invalid message format: expected 'HELLO <number>', got '{message}'")]
-  InvalidMessageFormat { message: String },
+  #[error("Invalid message format: expected {expected} at offset {offset} in '{message}'")]
+  InvalidMessageFormat {
+    message: String,
+    // Byte offset in `message` where parsing failed
+    offset: usize,
+    // What the parser wanted to find there
+    expected: &'static str,
+  },
 
   #[error("Invalid sequence number: {0}")]
   InvalidSequenceNumber(String),
 
+  #[error("Malformed sequence number '{value}': expected {expected} at offset {offset}")]
+  MalformedSequence {
+    // The sequence token as received
+    value: String,
+    // Byte offset of the token in the message
+    offset: usize,
+    expected: &'static str,
+  },
+
   #[error("Sequence mismatch: expected {expected}, received {received}")]
   SequenceMismatch { expected: i32, received: i32 },
 
+  #[error("Sequence overflow: {seq} has no next sequence within a 32-bit integer")]
+  SequenceOverflow { seq: i32 },
+
+  #[error(
+    "Server echoed our own sequence {seq} instead of incrementing it; \
+     check for a loopback or misrouted connection"
+  )]
+  EchoDetected { seq: i32 },
+
+  #[error(
+    "Nonce mismatch: expected N={expected}, received {}",
+    .received.map_or("no nonce".to_string(), |nonce| format!("N={nonce}"))
+  )]
+  NonceMismatch {
+    expected: u64,
+    received: Option<u64>,
+  },
+
+  #[error("Address mismatch: client claimed IP={claimed} but connected from {actual}")]
+  AddressMismatch { claimed: IpAddr, actual: IpAddr },
+
+  #[error(
+    "Capability mismatch: required CAP={missing} is not supported by both sides (common CAP={common})"
+  )]
+  CapabilityMismatch {
+    // Required features that did not make it into the common set
+    missing: Capabilities,
+    common: Capabilities,
+  },
+
+  #[error("Server rejected the handshake: {0}")]
+  Rejected(String),
+
+  #[error("Server is busy and refused the connection; try again later")]
+  ServerBusy,
+
+  #[error(
+    "Server is overloaded with concurrent handshakes and refused the connection; try again later"
+  )]
+  ServerOverloaded,
+
+  #[error("Protocol violation: {0}")]
+  ProtocolViolation(String),
+
+  #[error("Session limit exceeded: {value} {limit} (max {max})")]
+  SessionLimitExceeded {
+    limit: &'static str,
+    value: u64,
+    max: u64,
+  },
+
+  #[error("Payload too large: {size} bytes (max {max})")]
+  PayloadTooLarge { size: u64, max: u64 },
+
+  #[error("Message exceeds {max} bytes without a delimiter")]
+  FrameTooLarge { max: usize },
+
   #[error("Client disconnected unexpectedly")]
   ClientDisconnected,
 
   #[error("Connection timeout")]
   Timeout,
 
+  #[error("Peer sent nothing within the first byte timeout")]
+  FirstByteTimeout,
+
+  #[error("DNS resolution of '{host}' timed out")]
+  DnsTimeout { host: String },
+
+  #[error("SOCKS5 proxy error: {0}")]
+  Socks5(String),
+
+  #[error("Local address {0} is already in use; pick another port or use port 0")]
+  LocalAddressInUse(SocketAddr),
+
+  #[error(
+    "Port {0} is already in use; stop the other listener or pick another port \
+     (SO_REUSEADDR, already set on Unix, only frees ports held by connections in TIME_WAIT)"
+  )]
+  PortInUse(u16),
+
+  #[error("No free source port in {first}-{last}; widen the range or free a port")]
+  SourcePortsExhausted { first: u16, last: u16 },
+
+  #[error("Handshake aborted by cancellation")]
+  Aborted,
+
   #[error("Invalid port number: {0}")]
   InvalidPort(String),
 
   #[error("Invalid command line arguments: {0}")]
   InvalidArguments(String),
+
+  #[error("Invalid config file: {0}")]
+  InvalidConfigFile(String),
+
+  #[error("Invalid response template '{template}': {reason}")]
+  InvalidResponseTemplate { template: String, reason: String },
+
+  #[error("Invalid transcript at line {line}: {reason}")]
+  InvalidTranscript { line: usize, reason: String },
+}
+
+impl HandshakeError {
+  /**
+   * Process exit code for this failure, so scripts can branch on the reason
+   *
+   * | Code | Failure |
+   * | ---- | ------- |
+   * | 1    | Any other I/O error |
+   * | 2    | Timed out: connect, read, first byte or DNS |
+   * | 3    | Sequence mismatch, including an echoed X |
+   * | 4    | Connection refused |
+   * | 5    | Server refused the handshake: busy, overloaded or rejected |
+   * | 6    | Malformed message, sequence overflow, protocol violation or size limit |
+   * | 7    | Nonce, claimed address or capability mismatch |
+   * | 8    | Peer disconnected mid-handshake |
+   * | 9    | SOCKS5 proxy error |
+   * | 10   | Local address or source port unavailable |
+   * | 11   | Aborted by cancellation |
+   * | 12   | Invalid arguments, config file, template or transcript |
+   */
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      Self::Io(e) => match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => 2,
+        io::ErrorKind::ConnectionRefused => 4,
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => 8,
+        io::ErrorKind::AddrInUse | io::ErrorKind::AddrNotAvailable => 10,
+        _ => 1,
+      },
+      Self::Timeout | Self::FirstByteTimeout | Self::DnsTimeout { .. } => 2,
+      Self::SequenceMismatch { .. } | Self::EchoDetected { .. } => 3,
+      Self::ServerBusy | Self::ServerOverloaded | Self::Rejected(_) => 5,
+      Self::InvalidMessageFormat { .. }
+      | Self::MalformedSequence { .. }
+      | Self::SequenceOverflow { .. }
+      | Self::ProtocolViolation(_)
+      | Self::SessionLimitExceeded { .. }
+      | Self::PayloadTooLarge { .. }
+      | Self::FrameTooLarge { .. } => 6,
+      Self::NonceMismatch { .. }
+      | Self::AddressMismatch { .. }
+      | Self::CapabilityMismatch { .. } => 7,
+      Self::ClientDisconnected => 8,
+      Self::Socks5(_) => 9,
+      Self::LocalAddressInUse(_) | Self::PortInUse(_) | Self::SourcePortsExhausted { .. } => 10,
+      Self::Aborted => 11,
+      Self::InvalidSequenceNumber(_)
+      | Self::InvalidPort(_)
+      | Self::InvalidArguments(_)
+      | Self::InvalidConfigFile(_)
+      | Self::InvalidResponseTemplate { .. }
+      | Self::InvalidTranscript { .. } => 12,
+    }
+  }
 }
 
 pub type Result<T> = std::result::Result<T, HandshakeError>;
@@ -0,0 +1,88 @@
+/**
+ * Per-step handshake events for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Every handshake function reports each message it sends or receives to
+ * `config.on_event`, so callers can route them to a ring buffer, a UI or a
+ * test collector. The default sink does nothing and costs nothing.
+ */
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::transcript::Direction;
+
+/**
+ * One message sent or received during a handshake
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeEvent {
+  // 1 for HELLO X, 2 for HELLO Y, 3 for HELLO Z
+  pub step: u8,
+  // Relative to the side reporting the event
+  pub direction: Direction,
+  pub message: String,
+  // Taken from `config.clock`
+  pub timestamp: Instant,
+  // How long the step took, as in `HandshakeOutcome::step_durations`
+  pub elapsed: Duration,
+  // `config.label` of the handshake that reported it
+  pub label: Option<String>,
+}
+
+/**
+ * Shared event callback
+ */
+pub type EventCallback = Arc<dyn Fn(&HandshakeEvent) + Send + Sync>;
+
+/**
+ * Callback invoked for every handshake event
+ */
+#[derive(Clone, Default)]
+pub struct EventSink {
+  callback: Option<EventCallback>,
+}
+
+impl EventSink {
+  pub fn new(callback: impl Fn(&HandshakeEvent) + Send + Sync + 'static) -> Self {
+    Self {
+      callback: Some(Arc::new(callback)),
+    }
+  }
+
+  /**
+   * Builds and delivers an event; does nothing for the default sink
+   */
+  pub(crate) fn emit(
+    &self,
+    step: u8,
+    direction: Direction,
+    message: &str,
+    timestamp: Instant,
+    elapsed: Duration,
+    label: Option<&str>,
+  ) {
+    if let Some(callback) = &self.callback {
+      callback(&HandshakeEvent {
+        step,
+        direction,
+        message: message.to_string(),
+        timestamp,
+        elapsed,
+        label: label.map(str::to_string),
+      });
+    }
+  }
+}
+
+impl fmt::Debug for EventSink {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = if self.callback.is_some() {
+      "callback"
+    } else {
+      "noop"
+    };
+    f.debug_tuple("EventSink").field(&kind).finish()
+  }
+}
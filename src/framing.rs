@@ -0,0 +1,288 @@
+/**
+ * Message framing for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * By default each read of up to `MSG_SIZE` bytes is taken as one message.
+ * Peers that terminate messages instead (`\n`, `\r\n`, `\0`, ...) are read
+ * until the delimiter, buffering across reads so that a message or its
+ * delimiter may be split over several segments. Bytes read past a delimiter
+ * are kept for the next message. A peer that never sends the delimiter can
+ * only make us buffer up to `config.max_line_len` bytes before the read fails
+ * with `FrameTooLarge`.
+ */
+use std::io::Read;
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::MSG_SIZE;
+use crate::error::{HandshakeError, Result};
+use crate::protocol::{READ_TIMEOUT, read_into_buffer};
+
+/**
+ * How messages are separated on the wire
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Delimiter {
+  // One read into a fixed `MSG_SIZE` buffer is one message
+  #[default]
+  None,
+  // Messages end with a single byte such as `\n` or `\0`
+  Byte(u8),
+  // Messages end with a byte sequence such as `\r\n`
+  Bytes(Vec<u8>),
+}
+
+impl Delimiter {
+  /**
+   * The delimiter bytes, empty for fixed buffer reads
+   */
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      Delimiter::None => &[],
+      Delimiter::Byte(byte) => std::slice::from_ref(byte),
+      Delimiter::Bytes(bytes) => bytes,
+    }
+  }
+
+  /**
+   * A message as sent on the wire, followed by the delimiter
+   */
+  pub fn frame(&self, message: &str) -> Vec<u8> {
+    let mut framed = message.as_bytes().to_vec();
+    framed.extend_from_slice(self.as_bytes());
+    framed
+  }
+}
+
+// Capacity a `GrowableBuffer` keeps once a spike has been read through
+pub const DEFAULT_RETAINED_CAPACITY: usize = 4 * MSG_SIZE;
+
+/**
+ * Reusable buffer for bytes read but not yet handed out as messages
+ *
+ * Taking a message only advances a read position; the bytes are compacted
+ * when the buffer needs room or runs empty, so a long session does not shift
+ * memory on every message. The buffer grows as needed, and once the unread
+ * bytes fit in `max_retained` again any capacity above it is released.
+ */
+#[derive(Debug, Clone)]
+pub struct GrowableBuffer {
+  bytes: Vec<u8>,
+  // Where the unread bytes start in `bytes`
+  start: usize,
+  max_retained: usize,
+}
+
+impl Default for GrowableBuffer {
+  fn default() -> Self {
+    Self::with_max_retained(DEFAULT_RETAINED_CAPACITY)
+  }
+}
+
+impl From<&[u8]> for GrowableBuffer {
+  fn from(bytes: &[u8]) -> Self {
+    let mut buffer = Self::default();
+    buffer.extend_from_slice(bytes);
+    buffer
+  }
+}
+
+impl GrowableBuffer {
+  pub fn with_max_retained(max_retained: usize) -> Self {
+    Self {
+      bytes: Vec::new(),
+      start: 0,
+      max_retained,
+    }
+  }
+
+  /**
+   * An empty buffer with `capacity` bytes allocated up front
+   * It keeps that much after a spike, or `DEFAULT_RETAINED_CAPACITY` if larger
+   */
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self {
+      bytes: Vec::with_capacity(capacity),
+      start: 0,
+      max_retained: DEFAULT_RETAINED_CAPACITY.max(capacity),
+    }
+  }
+
+  /**
+   * The unread bytes
+   */
+  pub fn as_slice(&self) -> &[u8] {
+    &self.bytes[self.start..]
+  }
+
+  pub fn len(&self) -> usize {
+    self.bytes.len() - self.start
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /**
+   * Bytes allocated, read or not
+   */
+  pub fn capacity(&self) -> usize {
+    self.bytes.capacity()
+  }
+
+  /**
+   * Appends newly read bytes, reusing the space of consumed ones before growing
+   */
+  pub fn extend_from_slice(&mut self, data: &[u8]) {
+    if self.start > 0 && self.bytes.capacity() - self.bytes.len() < data.len() {
+      self.bytes.drain(..self.start);
+      self.start = 0;
+    }
+    self.bytes.extend_from_slice(data);
+  }
+
+  /**
+   * Marks the first `count` unread bytes as handed out
+   */
+  pub fn consume(&mut self, count: usize) {
+    self.start = (self.start + count).min(self.bytes.len());
+    if self.start == self.bytes.len() {
+      self.bytes.clear();
+      self.start = 0;
+    }
+    self.release_spike();
+  }
+
+  /**
+   * Drops every unread byte but keeps the allocation
+   */
+  pub fn clear(&mut self) {
+    self.bytes.clear();
+    self.start = 0;
+  }
+
+  /**
+   * Removes and returns every unread byte
+   */
+  pub fn take_all(&mut self) -> Vec<u8> {
+    let unread = self.as_slice().to_vec();
+    self.consume(unread.len());
+    unread
+  }
+
+  /**
+   * Gives back capacity above `max_retained` once the unread bytes fit in it
+   */
+  fn release_spike(&mut self) {
+    if self.bytes.capacity() <= self.max_retained || self.len() > self.max_retained {
+      return;
+    }
+    self.bytes.drain(..self.start);
+    self.start = 0;
+    self.bytes.shrink_to(self.max_retained);
+  }
+}
+
+/**
+ * Reads until `delimiter` and returns the message before it
+ *
+ * `pending` holds bytes already read but not yet returned; it may start with
+ * bytes read elsewhere and keeps whatever followed the delimiter. A message
+ * longer than `MSG_SIZE` without a delimiter fails with `FrameTooLarge`; the
+ * handshakes use `config.max_line_len` instead. A non-blocking stream is
+ * polled for up to `READ_TIMEOUT`.
+ */
+pub fn read_until_delimiter<S: Read>(
+  stream: &mut S,
+  delimiter: &[u8],
+  pending: &mut GrowableBuffer,
+) -> Result<Vec<u8>> {
+  read_until_delimiter_before(
+    stream,
+    delimiter,
+    pending,
+    MSG_SIZE,
+    Instant::now() + READ_TIMEOUT,
+  )
+}
+
+/**
+ * Reads until `delimiter`, giving up with `Timeout` once `deadline` passes without data
+ */
+pub(crate) fn read_until_delimiter_before<S: Read>(
+  stream: &mut S,
+  delimiter: &[u8],
+  pending: &mut GrowableBuffer,
+  max_len: usize,
+  deadline: Instant,
+) -> Result<Vec<u8>> {
+  let mut buffer = [0u8; MSG_SIZE];
+  loop {
+    if let Some(message) = take_message(pending, delimiter, max_len)? {
+      return Ok(message);
+    }
+    let bytes_read = read_into_buffer(stream, &mut buffer, deadline)?;
+    pending.extend_from_slice(&buffer[..bytes_read]);
+  }
+}
+
+/**
+ * Async version: Reads until `delimiter` and returns the message before it
+ */
+pub async fn read_until_delimiter_async<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  delimiter: &[u8],
+  pending: &mut GrowableBuffer,
+) -> Result<Vec<u8>> {
+  read_until_delimiter_async_within(stream, delimiter, pending, MSG_SIZE).await
+}
+
+/**
+ * Async version: Reads until `delimiter`, failing with `FrameTooLarge` past `max_len` bytes
+ */
+pub(crate) async fn read_until_delimiter_async_within<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  delimiter: &[u8],
+  pending: &mut GrowableBuffer,
+  max_len: usize,
+) -> Result<Vec<u8>> {
+  let mut buffer = [0u8; MSG_SIZE];
+  loop {
+    if let Some(message) = take_message(pending, delimiter, max_len)? {
+      return Ok(message);
+    }
+    let bytes_read = stream.read(&mut buffer).await?;
+    if bytes_read == 0 {
+      return Err(HandshakeError::ClientDisconnected);
+    }
+    pending.extend_from_slice(&buffer[..bytes_read]);
+  }
+}
+
+/**
+ * Removes the first delimited message from `pending`, if it is complete
+ * The whole buffer is searched so a delimiter split across reads is still found
+ */
+pub(crate) fn take_message(
+  pending: &mut GrowableBuffer,
+  delimiter: &[u8],
+  max_len: usize,
+) -> Result<Option<Vec<u8>>> {
+  let unread = pending.as_slice();
+  let found = unread
+    .windows(delimiter.len())
+    .position(|window| window == delimiter);
+
+  match found {
+    Some(end) if end <= max_len => {
+      let message = unread[..end].to_vec();
+      pending.consume(end + delimiter.len());
+      Ok(Some(message))
+    }
+    // Room is left for a delimiter that has only partly arrived
+    None if unread.len() < max_len + delimiter.len() => Ok(None),
+    _ => Err(HandshakeError::FrameTooLarge { max: max_len }),
+  }
+}
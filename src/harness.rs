@@ -0,0 +1,143 @@
+/**
+ * In-process server harness for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Starts any of the four server models on an ephemeral localhost port so
+ * benchmarks and tests can drive them without spawning processes. The server
+ * is ready as soon as `spawn_server` returns, since the listener is already
+ * bound by then.
+ */
+use std::fmt;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{HandshakeConfig, ServerOptions};
+use crate::error::Result;
+use crate::metrics::ServerMetrics;
+use crate::reload::live_config;
+use crate::server::serve_async;
+use crate::sync_server::{
+  serve_sequential, serve_threaded, serve_threadpool, stop_blocking_server,
+};
+
+/**
+ * The four ways this crate can serve handshakes
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerModel {
+  Sequential,
+  Threaded,
+  ThreadPool,
+  Async,
+}
+
+impl ServerModel {
+  pub const ALL: [ServerModel; 4] = [
+    ServerModel::Sequential,
+    ServerModel::Threaded,
+    ServerModel::ThreadPool,
+    ServerModel::Async,
+  ];
+}
+
+impl fmt::Display for ServerModel {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      ServerModel::Sequential => "sequential",
+      ServerModel::Threaded => "threaded",
+      ServerModel::ThreadPool => "threadpool",
+      ServerModel::Async => "async",
+    };
+    f.write_str(name)
+  }
+}
+
+/**
+ * A server running on a background thread
+ */
+pub struct RunningServer {
+  pub model: ServerModel,
+  pub addr: SocketAddr,
+  pub metrics: Arc<ServerMetrics>,
+  stop: CancellationToken,
+  thread: JoinHandle<()>,
+}
+
+impl RunningServer {
+  /**
+   * Stops accepting, lets the model finish its shutdown and waits for it
+   */
+  pub fn stop(self) {
+    match self.model {
+      ServerModel::Async => self.stop.cancel(),
+      _ => stop_blocking_server(&self.stop, self.addr),
+    }
+    let _ = self.thread.join();
+  }
+}
+
+/**
+ * Binds an ephemeral localhost port and serves it with `model` on a background thread
+ */
+pub fn spawn_server(model: ServerModel, config: HandshakeConfig) -> Result<RunningServer> {
+  spawn_server_with_options(model, config, ServerOptions::default())
+}
+
+/**
+ * Like `spawn_server`, with server-wide settings such as the worker count
+ */
+pub fn spawn_server_with_options(
+  model: ServerModel,
+  config: HandshakeConfig,
+  options: ServerOptions,
+) -> Result<RunningServer> {
+  let listener = TcpListener::bind("127.0.0.1:0")?;
+  let addr = listener.local_addr()?;
+  let config = live_config(config);
+  let metrics = Arc::new(ServerMetrics::new());
+  let stop = CancellationToken::new();
+
+  let thread = {
+    let metrics = Arc::clone(&metrics);
+    let stop = stop.clone();
+    match model {
+      ServerModel::Sequential => {
+        thread::spawn(move || serve_sequential(listener, &config, &metrics, &stop))
+      }
+      ServerModel::Threaded => {
+        thread::spawn(move || serve_threaded(listener, config, metrics, &stop))
+      }
+      ServerModel::ThreadPool => thread::spawn(move || {
+        serve_threadpool(listener, config, metrics, options, &stop);
+      }),
+      ServerModel::Async => {
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Runtime::new()?;
+        thread::spawn(move || {
+          runtime.block_on(async move {
+            let listener = match tokio::net::TcpListener::from_std(listener) {
+              Ok(listener) => listener,
+              Err(e) => {
+                errln!(config.load(), "ERROR: Could not start async server: {e}");
+                return;
+              }
+            };
+            serve_async(listener, config, metrics, options, stop.cancelled_owned()).await;
+          });
+        })
+      }
+    }
+  };
+
+  Ok(RunningServer {
+    model,
+    addr,
+    metrics,
+    stop,
+    thread,
+  })
+}
@@ -0,0 +1,62 @@
+/**
+ * Runtime protocol invariants for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With `strict_debug` set in the config, the handshake functions check at a
+ * few key points that what they are about to do still makes sense: every
+ * message they send parses back to the sequence number they meant, and reads
+ * stay inside their buffer. This catches bugs in the format extensions early.
+ * A violation is logged loudly and, in builds with debug assertions (tests
+ * included), panics. The checks cost nothing when `strict_debug` is off.
+ * They cover the handshake's own state only: a peer's sequence too close to
+ * `i32::MAX` is a protocol error, `SequenceOverflow`, and never panics.
+ */
+use crate::config::HandshakeConfig;
+use crate::message::{HELLO_VERB, tokens};
+use crate::protocol::strip_namespace;
+
+/**
+ * Reports a violation unless `holds` is true; neither closure runs when `strict_debug` is off
+ */
+fn check(
+  config: &HandshakeConfig,
+  holds: impl FnOnce() -> bool,
+  violation: impl FnOnce() -> String,
+) {
+  if !config.strict_debug || holds() {
+    return;
+  }
+  let violation = violation();
+  errln!(config, "!!! PROTOCOL INVARIANT VIOLATED: {violation} !!!");
+  if cfg!(debug_assertions) {
+    panic!("Protocol invariant violated: {violation}");
+  }
+}
+
+/**
+ * A message about to be sent must read back as `HELLO <seq>`; trailing fields are not checked
+ */
+pub(crate) fn check_sent(config: &HandshakeConfig, message: &str, seq: i32) {
+  check(
+    config,
+    || {
+      strip_namespace(message, config).is_ok_and(|hello| {
+        let mut parts = tokens(hello).map(|(_, token)| token);
+        parts.next() == Some(HELLO_VERB) && parts.next().and_then(|s| s.parse().ok()) == Some(seq)
+      })
+    },
+    || format!("sent '{message}' does not parse back as HELLO {seq}"),
+  );
+}
+
+/**
+ * A read of `len` bytes must fit the buffer it went into
+ */
+pub(crate) fn check_read_len(config: &HandshakeConfig, len: usize, capacity: usize) {
+  check(
+    config,
+    || len <= capacity,
+    || format!("read {len} bytes into a {capacity} byte buffer"),
+  );
+}
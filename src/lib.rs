@@ -3,37 +3,219 @@
  *
  * Author: Sae-Hwan Park
  */
+// Declared first so every module can use its printing macros
+#[macro_use]
+mod output;
+
+#[cfg(feature = "async-std")]
+pub mod async_std_rt;
+pub mod buffer_pool;
+pub mod capabilities;
+pub mod chunked;
+pub mod classify;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod config;
+pub mod connection_pool;
+mod cork;
+pub mod dump;
 pub mod error;
+pub mod events;
+pub mod framing;
+pub mod harness;
+mod invariants;
+pub mod limits;
+pub mod machine;
+pub mod message;
+pub mod metrics;
+#[cfg(feature = "testing")]
+pub mod mock_peer;
+pub mod mux;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod outcome;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod peer;
+mod persistent;
+pub mod pool;
+pub mod preamble;
 pub mod protocol;
+pub mod proxy;
+pub mod ready;
+pub mod reflect;
+pub mod reload;
+pub mod reply;
+pub mod report;
+pub mod results;
+pub mod sequence;
+pub mod server;
+pub mod session;
+pub mod socks5;
+pub mod step;
+pub mod sync_server;
+pub mod template;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod time;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transcript;
 pub mod utils;
 
 // Re-export commonly used items
+pub use buffer_pool::{BufferPool, PooledBuffer};
+pub use capabilities::{Capabilities, require_capabilities};
+pub use chunked::{CHUNK_HEADER_LEN, CHUNK_MORE, recv_chunked, send_chunked};
+pub use classify::{
+  HELLO_PREFIX, PEEK_LEN, PING_PREFIX, PROXY_PREFIX, Protocol, TLS_PREFIX, classify_prefix,
+  peek_classify, peek_classify_async,
+};
+#[cfg(feature = "compression")]
+pub use compression::{Compression, compress, decompress, recv_compressed, send_compressed};
+pub use config::{HandshakeConfig, ServerOptions, ServerProfile};
+pub use connection_pool::{ConnectionPool, DEFAULT_MAX_IDLE_PER_ADDR, DEFAULT_POOL_IDLE_TIMEOUT};
+pub use dump::{dump_on_sigusr1, dump_on_sigusr1_blocking, write_state_dump};
 pub use error::{HandshakeError, Result};
+pub use events::{EventCallback, EventSink, HandshakeEvent};
+pub use framing::{
+  DEFAULT_RETAINED_CAPACITY, Delimiter, GrowableBuffer, read_until_delimiter,
+  read_until_delimiter_async,
+};
+pub use harness::{RunningServer, ServerModel, spawn_server, spawn_server_with_options};
+pub use limits::{
+  ConcurrencyLimit, ConcurrencyPermit, MessageCounter, RateLimit, SourceGuard, SourceLimiter,
+};
+pub use machine::{HandshakeMachine, MachineOutput, Role};
+pub use message::{HELLO_VERB, ValidatedMessage, validate_hello_message};
+pub use metrics::{
+  ActiveConnection, ByteCounters, HANDSHAKES_PER_CONNECTION_BOUNDS,
+  HANDSHAKES_PER_CONNECTION_BUCKETS, HandshakeCountHistogram, MESSAGE_SIZE_BOUNDS,
+  MESSAGE_SIZE_BUCKETS, MessageSizeHistogram, MetricsSnapshot, STEP_DURATION_BOUNDS_MS,
+  STEP_DURATION_BUCKETS, ServerMetrics, StepDurationHistogram,
+};
+#[cfg(feature = "testing")]
+pub use mock_peer::{MockPeer, MockPeerHandle, ScriptStep};
+pub use mux::{HandshakeState, MuxStats, run_async_mux_session};
+#[cfg(feature = "otel")]
+pub use otel::{DEFAULT_OTEL_ENDPOINT, OtelConfig, OtelGuard, install_otel, install_otel_provider};
+pub use outcome::{ConnectPhases, HANDSHAKE_STEPS, HandshakeOutcome, Negotiated};
+#[cfg(feature = "pcap")]
+pub use pcap::{PCAP_LINKTYPE_RAW, PCAP_MAGIC, PcapWriter};
+pub use peer::{DefaultPeerResolver, PeerInfo, PeerResolver};
+pub use pool::{BoundedWorkerPool, DEFAULT_QUEUE_CAPACITY};
+pub use preamble::{DEFAULT_PREAMBLE_GREETING, DEFAULT_PREAMBLE_REPLY, Preamble};
 pub use protocol::{
+  BUSY_MESSAGE,
   CLIENT_CONNECTION_TIMEOUT,
   CONNECTION_TIMEOUT,
+  MAX_INTERRUPTED_RETRIES,
+  MAX_RANDOM_INITIAL_SEQ,
+  OVERLOADED_MESSAGE,
   READ_TIMEOUT,
+  WOULD_BLOCK_POLL_INTERVAL,
   format_hello_message,
+  format_hello_with_capabilities,
+  format_hello_with_claimed_ip,
+  format_hello_with_nonce,
+  format_hello_with_stream_id,
+  is_transient,
   parse_hello_message,
+  parse_hello_message_bytes,
+  parse_hello_with_capabilities,
+  parse_hello_with_claimed_ip,
+  parse_hello_with_nonce,
+  parse_hello_with_stream_id,
   perform_async_client_handshake,
+  perform_async_client_handshake_on,
+  perform_async_client_handshake_to,
+  perform_async_client_handshake_with_config,
   perform_async_server_handshake,
+  perform_async_server_handshake_with_config,
+  perform_async_server_handshake_with_prefix,
   perform_client_handshake,
+  perform_client_handshake_with_config,
   perform_server_handshake,
+  perform_server_handshake_with_config,
+  perform_server_handshake_with_prefix,
+  random_initial_seq,
   // Async versions
   read_message_from_async_stream,
   read_message_from_stream,
+  validate_nonce,
+  validate_server_reply,
+  verify_claimed_ip,
   write_message_to_async_stream,
   write_message_to_stream,
+  write_message_to_stream_with_config,
+};
+pub use proxy::{
+  ProxyHeader, parse_proxy_header, read_proxy_header_from_async_stream,
+  read_proxy_header_from_stream, resolve_async_client_addr, resolve_client_addr,
+};
+pub use ready::{NOT_READY_REPLY, READY_REPLY, serve_ready_socket, serve_ready_socket_blocking};
+pub use reflect::{perform_async_server_handshake_reflect, perform_server_handshake_reflect};
+pub use reload::{ConfigFile, LiveConfig, live_config, load_live_config};
+pub use reply::{ReplyQueue, ReplyStrategy};
+pub use report::{ReportOnExit, RunReport};
+pub use results::{DEFAULT_RESULTS_PATH, ResultsCsv};
+pub use sequence::{IncrementPolicy, SequencePolicy};
+pub use server::{
+  ServeSummary, on_shutdown_signal, record_slow_handshake, record_step_durations, run_timer,
+  serve_async, shutdown_signal,
+};
+#[cfg(feature = "compression")]
+pub use session::run_async_compressed_echo_session;
+pub use session::{SessionStats, run_async_echo_session};
+pub use socks5::{Socks5Credentials, Socks5Proxy, socks5_handshake, socks5_handshake_async};
+pub use step::{Handshake, StepStatus};
+pub use sync_server::{
+  handle_sync_connection, serve_sequential, serve_threaded, serve_threadpool, stop_blocking_server,
+  stop_on_shutdown_signal,
 };
+pub use template::ResponseTemplate;
+#[cfg(feature = "testing")]
+pub use testing::TestServer;
+pub use time::{Clock, MockClock, TokioClock};
+#[cfg(feature = "tls")]
+pub use tls::{DualAcceptor, MaybeTlsStream, TLS_HANDSHAKE_RECORD, looks_like_tls};
+pub use transcript::{Direction, TranscriptEntry, parse_transcript};
 pub use utils::{
+  Backoff,
+  BenchArgs,
+  ClientArgs,
+  ConformanceArgs,
+  DEFAULT_FANOUT_CONCURRENCY,
+  FanoutArgs,
+  PersistentArgs,
+  ProbeArgs,
+  ServerArgs,
+  apply_linger,
+  apply_nodelay,
   calculate_optimal_thread_count,
+  connect_async,
+  connect_async_timed,
+  connect_sync,
   // Async versions
   create_async_listener,
+  create_async_server_listener,
   create_listener,
+  create_server_listener,
   exit_with_error,
   format_server_address,
+  parse_bench_args,
   parse_client_args,
+  parse_conformance_args,
+  parse_duration,
+  parse_fanout_args,
+  parse_persistent_args,
+  parse_probe_args,
+  parse_replay_args,
   parse_server_args,
 };
+#[cfg(target_os = "linux")]
+pub use utils::{connect_abstract_unix, create_abstract_unix_listener};
+#[cfg(unix)]
+pub use utils::{create_async_listener_from_fd, create_listener_from_fd};
 
 pub const MSG_SIZE: usize = 64;
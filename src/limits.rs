@@ -0,0 +1,217 @@
+/**
+ * Connection limits for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::utils::parse_duration;
+
+/**
+ * At most `max_connections` accepted in each `window`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+  pub max_connections: u32,
+  pub window: Duration,
+}
+
+impl RateLimit {
+  /**
+   * Parses `<n>/<duration>`, e.g. `100/1s` or `5/500ms`
+   */
+  pub fn parse(spec: &str) -> Result<Self> {
+    let invalid = || {
+      HandshakeError::InvalidArguments(format!(
+        "invalid rate limit '{spec}', expected <connections>/<duration>"
+      ))
+    };
+    let (max_connections, window) = spec.split_once('/').ok_or_else(invalid)?;
+    let max_connections = max_connections
+      .parse()
+      .ok()
+      .filter(|&max| max > 0)
+      .ok_or_else(invalid)?;
+    let window = parse_duration(window).map_err(|_| invalid())?;
+    if window.is_zero() {
+      return Err(invalid());
+    }
+    Ok(Self {
+      max_connections,
+      window,
+    })
+  }
+}
+
+impl fmt::Display for RateLimit {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} per {:?}", self.max_connections, self.window)
+  }
+}
+
+/**
+ * Gauge of open connections per source IP with an optional cap
+ * Share it behind an `Arc`; each accepted connection holds a `SourceGuard`
+ */
+#[derive(Debug, Default)]
+pub struct SourceLimiter {
+  max_per_source: Option<usize>,
+  active: Mutex<HashMap<IpAddr, usize>>,
+}
+
+/**
+ * Counts one open connection until dropped
+ */
+#[derive(Debug)]
+pub struct SourceGuard {
+  limiter: Arc<SourceLimiter>,
+  source: IpAddr,
+}
+
+impl SourceLimiter {
+  /**
+   * Creates a limiter; `None` tracks connections without refusing any
+   */
+  pub fn new(max_per_source: Option<usize>) -> Self {
+    Self {
+      max_per_source,
+      active: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /**
+   * Registers a new connection from `source`
+   * Returns `None` when the source already has the maximum number open
+   */
+  pub fn try_acquire(self: &Arc<Self>, source: IpAddr) -> Option<SourceGuard> {
+    let mut active = self.active.lock().unwrap();
+    let count = active.get(&source).copied().unwrap_or(0);
+    if self.max_per_source.is_some_and(|max| count >= max) {
+      return None;
+    }
+    active.insert(source, count + 1);
+
+    Some(SourceGuard {
+      limiter: Arc::clone(self),
+      source,
+    })
+  }
+
+  /**
+   * Number of connections currently open from `source`
+   */
+  pub fn active(&self, source: IpAddr) -> usize {
+    let active = self.active.lock().unwrap();
+    active.get(&source).copied().unwrap_or(0)
+  }
+}
+
+impl Drop for SourceGuard {
+  fn drop(&mut self) {
+    let mut active = self.limiter.active.lock().unwrap();
+    if let Some(count) = active.get_mut(&self.source) {
+      *count -= 1;
+      // Forget idle sources so the map does not grow with every client ever seen
+      if *count == 0 {
+        active.remove(&self.source);
+      }
+    }
+  }
+}
+
+/**
+ * Fixed number of permits for connections handled at once, across all sources
+ * Share it behind an `Arc`; each admitted connection holds a `ConcurrencyPermit`
+ */
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+  max: usize,
+  active: AtomicUsize,
+}
+
+/**
+ * One of the permits of a `ConcurrencyLimit`, returned when dropped
+ */
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+  limit: Arc<ConcurrencyLimit>,
+}
+
+impl ConcurrencyLimit {
+  pub fn new(max: usize) -> Self {
+    Self {
+      max,
+      active: AtomicUsize::new(0),
+    }
+  }
+
+  /**
+   * Takes a permit without waiting
+   * Returns `None` when all of them are held
+   */
+  pub fn try_acquire(self: &Arc<Self>) -> Option<ConcurrencyPermit> {
+    self
+      .active
+      .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+        (active < self.max).then_some(active + 1)
+      })
+      .ok()?;
+    Some(ConcurrencyPermit {
+      limit: Arc::clone(self),
+    })
+  }
+
+  /**
+   * Number of permits currently held
+   */
+  pub fn active(&self) -> usize {
+    self.active.load(Ordering::Acquire)
+  }
+}
+
+impl Drop for ConcurrencyPermit {
+  fn drop(&mut self) {
+    self.limit.active.fetch_sub(1, Ordering::AcqRel);
+  }
+}
+
+/**
+ * Counts the messages a server reads on one connection against `config.max_connection_messages`
+ * Create one per connection and call `count` after every message read
+ */
+#[derive(Debug, Default)]
+pub struct MessageCounter {
+  read: u64,
+}
+
+impl MessageCounter {
+  /**
+   * Counts one more message, failing with `ProtocolViolation` once past the cap
+   */
+  pub fn count(&mut self, config: &HandshakeConfig) -> Result<()> {
+    self.read += 1;
+    match config.max_connection_messages {
+      Some(max) if self.read > max => {
+        config.record_excess_message();
+        Err(HandshakeError::ProtocolViolation(format!(
+          "more than {max} messages on one connection"
+        )))
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /**
+   * Messages counted so far
+   */
+  pub fn read(&self) -> u64 {
+    self.read
+  }
+}
@@ -0,0 +1,338 @@
+/**
+ * Sans-io handshake core for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * `HandshakeMachine` holds the whole protocol, for either role, without
+ * touching a socket or a clock. Bytes that arrive go in through
+ * `handle_input`, and `poll_output` says what to do next: send some bytes,
+ * wait for more input, or stop because the handshake is complete. Errors come
+ * out of `poll_output` as well, and the machine refuses to go on after one.
+ * Since nothing here does I/O, the same logic runs over TCP, UDP, QUIC or an
+ * in-memory buffer, and a test can drive it with plain byte slices.
+ *
+ * Transports that frame and read messages themselves, like the TCP
+ * handshakes with their timeouts and overflow checks, use the message layer
+ * underneath instead: `poll_message` and `handle_message`. The byte layer is
+ * that plus `config.delimiter` framing; without a delimiter each
+ * `handle_input` call is one message, as each read is for the TCP handshakes.
+ *
+ * The machine covers HELLO X, Y and Z. The READY/START preamble and the
+ * client's `confirm_final` wait depend on the transport and stay with it, and
+ * the outcome's timing fields are left zero for the transport to fill in.
+ */
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+use crate::MSG_SIZE;
+use crate::capabilities::Capabilities;
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::framing::{GrowableBuffer, take_message};
+use crate::outcome::{HANDSHAKE_STEPS, HandshakeOutcome, Negotiated};
+use crate::protocol::{
+  client_final_message, decode_sync_message, first_client_message, pad_message, parse_client_hello,
+  parse_final_message, parse_server_reply, read_overflow, server_capabilities, server_reply,
+};
+use crate::sequence::next_seq;
+
+/**
+ * Which end of the handshake to run
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+  // Sends HELLO X with this sequence, checks HELLO Y and sends HELLO Z
+  Client { initial_seq: i32 },
+  // Answers HELLO X with HELLO Y and checks HELLO Z
+  Server,
+}
+
+/**
+ * What the caller should do next, from `HandshakeMachine::poll_output`
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineOutput {
+  // Write these bytes to the peer, then poll again
+  Send(Vec<u8>),
+  // Feed the peer's next bytes to `handle_input`, or report the end with `handle_eof`
+  NeedInput,
+  // The handshake is done; the timing fields are zero
+  Complete(HandshakeOutcome),
+}
+
+/**
+ * The protocol state of one handshake, driven by the caller
+ */
+#[derive(Debug)]
+pub struct HandshakeMachine<'a> {
+  config: Cow<'a, HandshakeConfig>,
+  role: Role,
+  // The step in progress (1 for HELLO X), or past the last once complete
+  step: u8,
+  // The message for the step in progress, when this side sends it
+  outgoing: Option<String>,
+  failed: bool,
+  // Delimited input not yet taken as a message
+  pending: GrowableBuffer,
+  // Undelimited input, one message per `handle_input` call
+  chunks: VecDeque<Vec<u8>>,
+  eof: bool,
+  // Checked against an `IP=` claim in HELLO X
+  peer_ip: Option<IpAddr>,
+  initial_seq: i32,
+  // Y as sent or received
+  server_seq: i32,
+  final_seq: i32,
+  nonce: Option<u64>,
+  capabilities: Option<Capabilities>,
+}
+
+impl<'a> HandshakeMachine<'a> {
+  /**
+   * Starts a handshake; a client's HELLO X is ready for the first poll
+   * Pass `Cow::Borrowed` to share a config, or `Cow::Owned` for a machine that outlives it
+   */
+  pub fn new(role: Role, config: Cow<'a, HandshakeConfig>) -> Self {
+    let (initial_seq, outgoing) = match role {
+      Role::Client { initial_seq } => (
+        initial_seq,
+        Some(first_client_message(initial_seq, &config)),
+      ),
+      Role::Server => (0, None),
+    };
+    let mut machine = Self {
+      config,
+      role,
+      step: 1,
+      outgoing,
+      failed: false,
+      pending: GrowableBuffer::default(),
+      chunks: VecDeque::new(),
+      eof: false,
+      peer_ip: None,
+      initial_seq,
+      server_seq: 0,
+      final_seq: 0,
+      nonce: None,
+      capabilities: None,
+    };
+    // An empty override sends nothing, for a server that was handed HELLO X as a prefix
+    if machine.outgoing.as_ref().is_some_and(String::is_empty) {
+      machine.outgoing = None;
+      machine.step = 2;
+    }
+    machine
+  }
+
+  /**
+   * Server side: the peer's address, for checking an `IP=` claim under `config.verify_client_ip`
+   */
+  pub fn with_peer_ip(mut self, peer_ip: IpAddr) -> Self {
+    self.peer_ip = Some(peer_ip);
+    self
+  }
+
+  pub fn config(&self) -> &HandshakeConfig {
+    &self.config
+  }
+
+  /**
+   * The step in progress (1 for HELLO X), or `None` once complete or failed
+   */
+  pub fn next_step(&self) -> Option<u8> {
+    let finished = self.failed || usize::from(self.step) > HANDSHAKE_STEPS;
+    (!finished).then_some(self.step)
+  }
+
+  /**
+   * Whether the step in progress waits for the peer
+   */
+  pub fn wants_input(&self) -> bool {
+    self.next_step().is_some() && self.outgoing.is_none()
+  }
+
+  /**
+   * The outcome, once the handshake is complete; its timing fields are zero
+   */
+  pub fn outcome(&self) -> Option<HandshakeOutcome> {
+    if self.failed || usize::from(self.step) <= HANDSHAKE_STEPS {
+      return None;
+    }
+    Some(HandshakeOutcome {
+      initial_seq: self.initial_seq,
+      final_seq: self.final_seq,
+      capabilities: self.capabilities,
+      label: self.config.label.clone(),
+      negotiated: Negotiated::new(self.capabilities, self.nonce, &self.config),
+      ..HandshakeOutcome::default()
+    })
+  }
+
+  /**
+   * Hands out the message this side sends next, unframed, and moves past its step
+   * `None` while waiting for the peer
+   */
+  pub fn poll_message(&mut self) -> Option<String> {
+    if self.failed {
+      return None;
+    }
+    let message = self.outgoing.take()?;
+    self.step += 1;
+    Some(message)
+  }
+
+  /**
+   * Takes the peer's next message, already unframed
+   * Fails when the message is wrong or none was expected; the machine is finished after any error
+   */
+  pub fn handle_message(&mut self, message: &str) -> Result<()> {
+    let peer_ip = self.peer_ip;
+    self.handle_message_from(message, || peer_ip)
+  }
+
+  /**
+   * `handle_message`, asking `peer_ip` for the address only when HELLO X makes a claim
+   */
+  pub(crate) fn handle_message_from(
+    &mut self,
+    message: &str,
+    peer_ip: impl FnOnce() -> Option<IpAddr>,
+  ) -> Result<()> {
+    let handled = self.handle(message, peer_ip);
+    self.failed |= handled.is_err();
+    handled
+  }
+
+  fn handle(&mut self, message: &str, peer_ip: impl FnOnce() -> Option<IpAddr>) -> Result<()> {
+    if !self.wants_input() {
+      return Err(self.not_expected(message));
+    }
+    let config = self.config.as_ref();
+    match (self.role, self.step) {
+      (Role::Client { initial_seq }, 2) => {
+        let (received_seq, nonce, capabilities) = parse_server_reply(message, initial_seq, config)?;
+        self.server_seq = received_seq;
+        self.final_seq = next_seq(config.sequence_policy.as_ref(), received_seq)?;
+        self.nonce = nonce;
+        self.capabilities = capabilities;
+        self.outgoing = Some(client_final_message(self.final_seq, nonce, config));
+      }
+      (Role::Server, 1) => {
+        let (client_seq, offered) = parse_client_hello(message, peer_ip, config)?;
+        let capabilities = server_capabilities(offered, config)?;
+        let server_seq = next_seq(config.sequence_policy.as_ref(), client_seq)?;
+        // Z must exist too, or the client could never finish
+        next_seq(config.sequence_policy.as_ref(), server_seq)?;
+        let (response, nonce) = server_reply(
+          server_seq,
+          capabilities.filter(|_| offered.is_some()),
+          config,
+        );
+        self.initial_seq = client_seq;
+        self.server_seq = server_seq;
+        self.nonce = nonce;
+        self.capabilities = capabilities;
+        self.outgoing = Some(response);
+      }
+      (Role::Server, 3) => {
+        let final_seq = parse_final_message(message, self.nonce, config)?;
+        let expected_final = next_seq(config.sequence_policy.as_ref(), self.server_seq)?;
+        if !config.sequence_policy.validate(expected_final, final_seq) {
+          errln!(
+            config,
+            "ERROR: Expected HELLO {expected_final}, received HELLO {final_seq}"
+          );
+        }
+        self.final_seq = final_seq;
+      }
+      _ => return Err(self.not_expected(message)),
+    }
+    self.step += 1;
+    Ok(())
+  }
+
+  fn not_expected(&self, message: &str) -> HandshakeError {
+    let reason = match self.next_step() {
+      Some(step) => format!("step {step} sends rather than receives"),
+      None => "the handshake is over".to_string(),
+    };
+    HandshakeError::ProtocolViolation(format!("unexpected message '{message}': {reason}"))
+  }
+
+  /**
+   * Takes bytes from the peer; they are parsed on the next `poll_output`
+   * Empty input is ignored; a closed connection is reported with `handle_eof`
+   */
+  pub fn handle_input(&mut self, bytes: &[u8]) {
+    if bytes.is_empty() {
+      return;
+    }
+    if self.config.delimiter.as_bytes().is_empty() {
+      self.chunks.push_back(bytes.to_vec());
+    } else {
+      self.pending.extend_from_slice(bytes);
+    }
+  }
+
+  /**
+   * Notes that the peer closed; a handshake still waiting for it then fails with `ClientDisconnected`
+   */
+  pub fn handle_eof(&mut self) {
+    self.eof = true;
+  }
+
+  /**
+   * Works through the input so far and says what to do next
+   */
+  pub fn poll_output(&mut self) -> Result<MachineOutput> {
+    if self.failed {
+      return Err(HandshakeError::ProtocolViolation(
+        "the handshake has already failed".to_string(),
+      ));
+    }
+    loop {
+      if let Some(message) = self.poll_message() {
+        let message = pad_message(&message, &self.config);
+        return Ok(MachineOutput::Send(self.config.delimiter.frame(&message)));
+      }
+      if let Some(outcome) = self.outcome() {
+        return Ok(MachineOutput::Complete(outcome));
+      }
+      let Some(message) = self.next_input()? else {
+        if self.eof {
+          self.failed = true;
+          return Err(HandshakeError::ClientDisconnected);
+        }
+        return Ok(MachineOutput::NeedInput);
+      };
+      self.handle_message(&message)?;
+    }
+  }
+
+  /**
+   * The next whole message in the input, decoded as the sync handshakes do
+   */
+  fn next_input(&mut self) -> Result<Option<String>> {
+    let delimiter = self.config.delimiter.as_bytes();
+    let message = if delimiter.is_empty() {
+      match self.chunks.pop_front() {
+        Some(chunk) if chunk.len() > MSG_SIZE => Err(read_overflow()),
+        chunk => Ok(chunk),
+      }
+    } else {
+      take_message(&mut self.pending, delimiter, self.config.max_line_len)
+    };
+    match message {
+      Ok(Some(message)) => {
+        self.config.record_message_size(message.len());
+        Ok(Some(decode_sync_message(&message)))
+      }
+      Ok(None) => Ok(None),
+      Err(e) => {
+        self.failed = true;
+        Err(e)
+      }
+    }
+  }
+}
@@ -0,0 +1,142 @@
+/**
+ * HELLO message grammar for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * A message is a verb and a sequence number followed by optional
+ * `KEY=value` fields, separated by whitespace:
+ *
+ * ```text
+ * HELLO <seq> [S=<stream_id>] [N=<nonce>] [IP=<address>] [CAP=<hex>]
+ * ```
+ *
+ * Each field may appear at most once and unknown fields are rejected.
+ */
+use std::net::IpAddr;
+
+use crate::capabilities::Capabilities;
+use crate::error::{HandshakeError, Result};
+
+// The only verb the protocol defines
+pub const HELLO_VERB: &str = "HELLO";
+
+/**
+ * Every part of a well-formed HELLO message
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatedMessage {
+  pub verb: String,
+  pub seq: i32,
+  // `S=` field used to multiplex handshakes over one connection
+  pub stream_id: Option<u32>,
+  // `N=` field carrying a server-issued nonce against replay
+  pub nonce: Option<u64>,
+  // `IP=` field with the source address the client claims to connect from
+  pub claimed_ip: Option<IpAddr>,
+  // `CAP=` field with a hex bitmask of supported features
+  pub capabilities: Option<Capabilities>,
+}
+
+impl ValidatedMessage {
+  /**
+   * Whether any optional field was present
+   */
+  pub fn has_optional_fields(&self) -> bool {
+    self.stream_id.is_some()
+      || self.nonce.is_some()
+      || self.claimed_ip.is_some()
+      || self.capabilities.is_some()
+  }
+}
+
+/**
+ * Splits a message on whitespace, keeping each token's byte offset
+ */
+pub(crate) fn tokens(message: &str) -> impl Iterator<Item = (usize, &str)> {
+  message
+    .split(char::is_whitespace)
+    .filter(|token| !token.is_empty())
+    .map(move |token| (token.as_ptr() as usize - message.as_ptr() as usize, token))
+}
+
+/**
+ * Validates a message against the full grammar without touching a stream
+ * Failures report the byte offset where parsing stopped and what was expected there;
+ * a sequence that is not a 32-bit integer fails with `MalformedSequence` naming the token
+ */
+pub fn validate_hello_message(message: &str) -> Result<ValidatedMessage> {
+  let invalid_at = |offset: usize, expected: &'static str| HandshakeError::InvalidMessageFormat {
+    message: message.to_string(),
+    offset,
+    expected,
+  };
+
+  let mut parts = tokens(message);
+  let end = message.trim_end().len();
+
+  let verb = match parts.next() {
+    Some((_, verb)) if verb == HELLO_VERB => verb,
+    Some((offset, _)) => return Err(invalid_at(offset, "'HELLO'")),
+    None => return Err(invalid_at(end, "'HELLO'")),
+  };
+
+  let seq = match parts.next() {
+    Some((offset, seq)) => seq
+      .parse::<i32>()
+      .map_err(|_| HandshakeError::MalformedSequence {
+        value: seq.to_string(),
+        offset,
+        expected: "32-bit integer",
+      })?,
+    None => return Err(invalid_at(end, "sequence number")),
+  };
+
+  let mut validated = ValidatedMessage {
+    verb: verb.to_string(),
+    seq,
+    stream_id: None,
+    nonce: None,
+    claimed_ip: None,
+    capabilities: None,
+  };
+
+  for (offset, field) in parts {
+    let Some((key, value)) = field.split_once('=') else {
+      return Err(invalid_at(offset, "KEY=value field"));
+    };
+    let value_offset = offset + key.len() + 1;
+    match key {
+      "S" if validated.stream_id.is_none() => {
+        let id = value
+          .parse::<u32>()
+          .map_err(|_| invalid_at(value_offset, "unsigned stream ID"))?;
+        validated.stream_id = Some(id);
+      }
+      "S" => return Err(invalid_at(offset, "no repeated 'S' field")),
+      "N" if validated.nonce.is_none() => {
+        let nonce = value
+          .parse::<u64>()
+          .map_err(|_| invalid_at(value_offset, "unsigned nonce"))?;
+        validated.nonce = Some(nonce);
+      }
+      "N" => return Err(invalid_at(offset, "no repeated 'N' field")),
+      "IP" if validated.claimed_ip.is_none() => {
+        let ip = value
+          .parse::<IpAddr>()
+          .map_err(|_| invalid_at(value_offset, "IP address"))?;
+        validated.claimed_ip = Some(ip);
+      }
+      "IP" => return Err(invalid_at(offset, "no repeated 'IP' field")),
+      "CAP" if validated.capabilities.is_none() => {
+        let capabilities = value
+          .parse::<Capabilities>()
+          .map_err(|_| invalid_at(value_offset, "hex capability mask"))?;
+        validated.capabilities = Some(capabilities);
+      }
+      "CAP" => return Err(invalid_at(offset, "no repeated 'CAP' field")),
+      _ => return Err(invalid_at(offset, "known field 'S', 'N', 'IP' or 'CAP'")),
+    }
+  }
+
+  Ok(validated)
+}
@@ -0,0 +1,364 @@
+/**
+ * Shared server counters for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::outcome::{HANDSHAKE_STEPS, HandshakeOutcome};
+
+// Exclusive upper bounds of the message size buckets; one more bucket holds the rest
+pub const MESSAGE_SIZE_BOUNDS: [usize; 4] = [8, 16, 32, 64];
+pub const MESSAGE_SIZE_BUCKETS: usize = MESSAGE_SIZE_BOUNDS.len() + 1;
+// Exclusive upper bounds of the step duration buckets in milliseconds; one more bucket holds the rest
+pub const STEP_DURATION_BOUNDS_MS: [u64; 4] = [1, 10, 100, 1000];
+pub const STEP_DURATION_BUCKETS: usize = STEP_DURATION_BOUNDS_MS.len() + 1;
+// Exclusive upper bounds of the handshakes-per-connection buckets; one more bucket holds the rest
+pub const HANDSHAKES_PER_CONNECTION_BOUNDS: [u64; 4] = [2, 5, 10, 100];
+pub const HANDSHAKES_PER_CONNECTION_BUCKETS: usize = HANDSHAKES_PER_CONNECTION_BOUNDS.len() + 1;
+
+/**
+ * Counts received messages by byte length
+ * Unusually short or full-buffer messages point at misconfigured or probing clients
+ */
+#[derive(Debug, Default)]
+pub struct MessageSizeHistogram {
+  buckets: [AtomicU64; MESSAGE_SIZE_BUCKETS],
+}
+
+impl MessageSizeHistogram {
+  pub fn record(&self, len: usize) {
+    let bucket = MESSAGE_SIZE_BOUNDS
+      .iter()
+      .position(|&bound| len < bound)
+      .unwrap_or(MESSAGE_SIZE_BOUNDS.len());
+    self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+  }
+
+  /**
+   * Count per bucket, in the order of `MESSAGE_SIZE_BOUNDS` followed by the overflow bucket
+   */
+  pub fn counts(&self) -> [u64; MESSAGE_SIZE_BUCKETS] {
+    std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed))
+  }
+}
+
+/**
+ * Counts how long one handshake step took across handshakes
+ * Comparing the send and receive steps shows which side the latency is on
+ */
+#[derive(Debug, Default)]
+pub struct StepDurationHistogram {
+  buckets: [AtomicU64; STEP_DURATION_BUCKETS],
+}
+
+impl StepDurationHistogram {
+  pub fn record(&self, elapsed: Duration) {
+    let bucket = STEP_DURATION_BOUNDS_MS
+      .iter()
+      .position(|&bound| elapsed < Duration::from_millis(bound))
+      .unwrap_or(STEP_DURATION_BOUNDS_MS.len());
+    self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+  }
+
+  /**
+   * Count per bucket, in the order of `STEP_DURATION_BOUNDS_MS` followed by the overflow bucket
+   */
+  pub fn counts(&self) -> [u64; STEP_DURATION_BUCKETS] {
+    std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed))
+  }
+}
+
+/**
+ * Counts persistent connections by how many handshakes each completed
+ * Shows whether clients really reuse their connections or close after one
+ */
+#[derive(Debug, Default)]
+pub struct HandshakeCountHistogram {
+  buckets: [AtomicU64; HANDSHAKES_PER_CONNECTION_BUCKETS],
+}
+
+impl HandshakeCountHistogram {
+  pub fn record(&self, handshakes: u64) {
+    let bucket = HANDSHAKES_PER_CONNECTION_BOUNDS
+      .iter()
+      .position(|&bound| handshakes < bound)
+      .unwrap_or(HANDSHAKES_PER_CONNECTION_BOUNDS.len());
+    self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+  }
+
+  /**
+   * Count per bucket, in the order of `HANDSHAKES_PER_CONNECTION_BOUNDS` followed by the overflow bucket
+   */
+  pub fn counts(&self) -> [u64; HANDSHAKES_PER_CONNECTION_BUCKETS] {
+    std::array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed))
+  }
+}
+
+/**
+ * Bytes of handshake and session messages moved, counted by `instrument`ed configs
+ */
+#[derive(Debug, Default)]
+pub struct ByteCounters {
+  pub received: AtomicU64,
+  pub sent: AtomicU64,
+}
+
+/**
+ * Live counters updated by the server loops
+ * Share it behind an `Arc` and call `snapshot` to read a consistent-enough view
+ */
+#[derive(Debug)]
+pub struct ServerMetrics {
+  // When the metrics, and so the server run, started
+  pub started: Instant,
+  pub accepted: AtomicU64,
+  pub rejected_queue_full: AtomicU64,
+  pub per_source_rejected: AtomicU64,
+  pub rejected_warmup: AtomicU64,
+  pub rate_limited: AtomicU64,
+  pub overloaded: AtomicU64,
+  pub queue_depth: AtomicUsize,
+  pub slow_handshakes: AtomicU64,
+  // Estimated accept queue wait of the latest connection, in microseconds
+  pub accept_latency_us: AtomicU64,
+  pub accept_latency_spikes: AtomicU64,
+  // Byte lengths of messages received by handshakes run through `instrument`ed configs
+  pub message_sizes: Arc<MessageSizeHistogram>,
+  pub bytes: Arc<ByteCounters>,
+  // Connections failed for reading more than `max_connection_messages` messages
+  pub excess_messages: Arc<AtomicU64>,
+  // One histogram per handshake step, from completed handshakes
+  pub step_durations: [StepDurationHistogram; HANDSHAKE_STEPS],
+  // Handshakes completed on each connection served with `persistent`
+  pub handshakes_per_connection: HandshakeCountHistogram,
+  // Outcomes of handled connections; timed out ones are also counted as failed
+  pub succeeded: AtomicU64,
+  pub failed: AtomicU64,
+  pub timed_out: AtomicU64,
+  // Connections being handled right now, and the most at any one time
+  pub active_connections: AtomicUsize,
+  pub peak_connections: AtomicUsize,
+  // Connections cancelled when the drain timeout elapsed
+  pub force_closed: AtomicU64,
+  // Set once shutdown starts; new connections are refused from then on
+  pub draining: AtomicBool,
+  // When the accept loop started serving plus any warmup; unset until it starts
+  pub ready_at: OnceLock<Instant>,
+}
+
+/**
+ * Counts one connection as being handled until dropped
+ */
+#[derive(Debug)]
+pub struct ActiveConnection<'a> {
+  metrics: &'a ServerMetrics,
+}
+
+impl Drop for ActiveConnection<'_> {
+  fn drop(&mut self) {
+    self
+      .metrics
+      .active_connections
+      .fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+impl Default for ServerMetrics {
+  fn default() -> Self {
+    Self {
+      started: Instant::now(),
+      accepted: AtomicU64::default(),
+      rejected_queue_full: AtomicU64::default(),
+      per_source_rejected: AtomicU64::default(),
+      rejected_warmup: AtomicU64::default(),
+      rate_limited: AtomicU64::default(),
+      overloaded: AtomicU64::default(),
+      queue_depth: AtomicUsize::default(),
+      slow_handshakes: AtomicU64::default(),
+      accept_latency_us: AtomicU64::default(),
+      accept_latency_spikes: AtomicU64::default(),
+      message_sizes: Arc::default(),
+      bytes: Arc::default(),
+      excess_messages: Arc::default(),
+      step_durations: Default::default(),
+      handshakes_per_connection: HandshakeCountHistogram::default(),
+      succeeded: AtomicU64::default(),
+      failed: AtomicU64::default(),
+      timed_out: AtomicU64::default(),
+      active_connections: AtomicUsize::default(),
+      peak_connections: AtomicUsize::default(),
+      force_closed: AtomicU64::default(),
+      draining: AtomicBool::default(),
+      ready_at: OnceLock::new(),
+    }
+  }
+}
+
+/**
+ * Point-in-time copy of `ServerMetrics`
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+  pub accepted: u64,
+  pub rejected_queue_full: u64,
+  pub per_source_rejected: u64,
+  pub rejected_warmup: u64,
+  pub rate_limited: u64,
+  pub overloaded: u64,
+  pub queue_depth: usize,
+  pub slow_handshakes: u64,
+  pub accept_latency_us: u64,
+  pub accept_latency_spikes: u64,
+  pub excess_messages: u64,
+  pub message_sizes: [u64; MESSAGE_SIZE_BUCKETS],
+  pub step_durations: [[u64; STEP_DURATION_BUCKETS]; HANDSHAKE_STEPS],
+  pub handshakes_per_connection: [u64; HANDSHAKES_PER_CONNECTION_BUCKETS],
+  pub draining: bool,
+}
+
+impl ServerMetrics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * True once the accept loop is serving and warmed up at `now`, until shutdown starts draining
+   */
+  pub fn is_ready(&self, now: Instant) -> bool {
+    self.ready_at.get().is_some_and(|&at| now >= at) && !self.draining.load(Ordering::Relaxed)
+  }
+
+  pub fn snapshot(&self) -> MetricsSnapshot {
+    MetricsSnapshot {
+      accepted: self.accepted.load(Ordering::Relaxed),
+      rejected_queue_full: self.rejected_queue_full.load(Ordering::Relaxed),
+      per_source_rejected: self.per_source_rejected.load(Ordering::Relaxed),
+      rejected_warmup: self.rejected_warmup.load(Ordering::Relaxed),
+      rate_limited: self.rate_limited.load(Ordering::Relaxed),
+      overloaded: self.overloaded.load(Ordering::Relaxed),
+      queue_depth: self.queue_depth.load(Ordering::Relaxed),
+      slow_handshakes: self.slow_handshakes.load(Ordering::Relaxed),
+      accept_latency_us: self.accept_latency_us.load(Ordering::Relaxed),
+      accept_latency_spikes: self.accept_latency_spikes.load(Ordering::Relaxed),
+      excess_messages: self.excess_messages.load(Ordering::Relaxed),
+      message_sizes: self.message_sizes.counts(),
+      step_durations: std::array::from_fn(|step| self.step_durations[step].counts()),
+      handshakes_per_connection: self.handshakes_per_connection.counts(),
+      draining: self.draining.load(Ordering::Relaxed),
+    }
+  }
+
+  /**
+   * A copy of `config` whose reads and writes record message sizes, bytes and excess messages into these metrics
+   */
+  pub fn instrument(&self, config: &HandshakeConfig) -> HandshakeConfig {
+    HandshakeConfig {
+      message_sizes: Some(Arc::clone(&self.message_sizes)),
+      byte_counters: Some(Arc::clone(&self.bytes)),
+      excess_messages: Some(Arc::clone(&self.excess_messages)),
+      ..config.clone()
+    }
+  }
+
+  /**
+   * Counts a connection as being handled, raising the peak if needed, until the guard is dropped
+   */
+  pub fn track_connection(&self) -> ActiveConnection<'_> {
+    let active = self.active_connections.fetch_add(1, Ordering::Relaxed) + 1;
+    self.peak_connections.fetch_max(active, Ordering::Relaxed);
+    ActiveConnection { metrics: self }
+  }
+
+  /**
+   * Records how long each step of a completed handshake took
+   */
+  pub fn record_step_durations(&self, outcome: &HandshakeOutcome) {
+    for (histogram, elapsed) in self.step_durations.iter().zip(outcome.step_durations) {
+      histogram.record(elapsed);
+    }
+  }
+
+  /**
+   * Counts how a handled connection ended
+   */
+  pub fn record_result(&self, result: &Result<()>) {
+    match result {
+      Ok(()) => self.succeeded.fetch_add(1, Ordering::Relaxed),
+      Err(e) => {
+        if is_timeout(e) {
+          self.timed_out.fetch_add(1, Ordering::Relaxed);
+        }
+        self.failed.fetch_add(1, Ordering::Relaxed)
+      }
+    };
+  }
+}
+
+impl fmt::Display for MetricsSnapshot {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "accepted={} rejected_queue_full={} per_source_rejected={} rejected_warmup={} \
+       rate_limited={} overloaded={} queue_depth={} slow_handshakes={} accept_latency_us={} accept_latency_spikes={} \
+       excess_messages={} message_sizes=",
+      self.accepted,
+      self.rejected_queue_full,
+      self.per_source_rejected,
+      self.rejected_warmup,
+      self.rate_limited,
+      self.overloaded,
+      self.queue_depth,
+      self.slow_handshakes,
+      self.accept_latency_us,
+      self.accept_latency_spikes,
+      self.excess_messages,
+    )?;
+    write_buckets(f, &self.message_sizes, &MESSAGE_SIZE_BOUNDS)?;
+    for (step, counts) in self.step_durations.iter().enumerate() {
+      write!(f, " step{}_ms=", step + 1)?;
+      write_buckets(f, counts, &STEP_DURATION_BOUNDS_MS)?;
+    }
+    write!(f, " handshakes_per_connection=")?;
+    write_buckets(
+      f,
+      &self.handshakes_per_connection,
+      &HANDSHAKES_PER_CONNECTION_BOUNDS,
+    )?;
+    write!(f, " draining={}", self.draining)
+  }
+}
+
+/**
+ * Writes histogram counts as `<bound:count` pairs, ending with the `>=` overflow bucket
+ */
+fn write_buckets<T: fmt::Display>(
+  f: &mut fmt::Formatter<'_>,
+  counts: &[u64],
+  bounds: &[T],
+) -> fmt::Result {
+  for (bucket, count) in counts.iter().enumerate() {
+    let separator = if bucket == 0 { "" } else { "," };
+    match bounds.get(bucket) {
+      Some(bound) => write!(f, "{separator}<{bound}:{count}")?,
+      None => write!(f, "{separator}>={}:{count}", bounds[bucket - 1])?,
+    }
+  }
+  Ok(())
+}
+
+fn is_timeout(error: &HandshakeError) -> bool {
+  match error {
+    HandshakeError::Timeout | HandshakeError::FirstByteTimeout => true,
+    HandshakeError::Io(e) => matches!(
+      e.kind(),
+      std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+    ),
+    _ => false,
+  }
+}
@@ -0,0 +1,175 @@
+/**
+ * Scriptable mock peer for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * `MockPeer` plays one side of a connection from a script of expected reads
+ * and scripted writes, so a test can pin down the exact wire behavior of the
+ * real client or server on the other side:
+ *
+ * ```text
+ * MockPeer::new().expect("HELLO 5").send("HELLO 99")
+ * ```
+ *
+ * Messages are framed with the peer's config exactly like the handshake
+ * functions frame them. The first read or write that does not match the
+ * script stops the peer with a `ProtocolViolation` naming the step.
+ * Enabled with the `testing` feature.
+ */
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, duplex};
+use tokio::task::JoinHandle;
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::framing::GrowableBuffer;
+use crate::protocol::{read_async_message, write_async_framed};
+
+// Bytes the in-memory stream buffers in each direction
+const MOCK_STREAM_CAPACITY: usize = 64 * 1024;
+
+/**
+ * One step of a mock peer's script
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptStep {
+  // Read one message that must equal this text
+  Expect(String),
+  // Write this message
+  Send(String),
+  // The other side must close the connection without sending anything more
+  ExpectClose,
+}
+
+/**
+ * A scripted peer, built step by step and then run against a stream
+ */
+#[derive(Debug, Clone, Default)]
+pub struct MockPeer {
+  script: Vec<ScriptStep>,
+  config: HandshakeConfig,
+}
+
+impl MockPeer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /**
+   * Frames messages and bounds each read with `config`, e.g. to match the delimiter of the side under test
+   */
+  pub fn with_config(config: HandshakeConfig) -> Self {
+    Self {
+      script: Vec::new(),
+      config,
+    }
+  }
+
+  /**
+   * Next, read one message and require it to be `message`
+   */
+  pub fn expect(mut self, message: impl Into<String>) -> Self {
+    self.script.push(ScriptStep::Expect(message.into()));
+    self
+  }
+
+  /**
+   * Next, write `message`
+   */
+  pub fn send(mut self, message: impl Into<String>) -> Self {
+    self.script.push(ScriptStep::Send(message.into()));
+    self
+  }
+
+  /**
+   * Next, require the other side to close the connection
+   */
+  pub fn expect_close(mut self) -> Self {
+    self.script.push(ScriptStep::ExpectClose);
+    self
+  }
+
+  pub fn script(&self) -> &[ScriptStep] {
+    &self.script
+  }
+
+  /**
+   * Plays the script over `stream`, stopping at the first deviation
+   */
+  pub async fn run<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: &mut S) -> Result<()> {
+    let mut pending = GrowableBuffer::default();
+
+    for (index, step) in self.script.iter().enumerate() {
+      let deviation = |what: String| {
+        HandshakeError::ProtocolViolation(format!("mock peer step {}: {what}", index + 1))
+      };
+      match step {
+        ScriptStep::Send(message) => write_async_framed(stream, message, &self.config)
+          .await
+          .map_err(|e| deviation(format!("could not send '{message}': {e}")))?,
+        ScriptStep::Expect(expected) => {
+          match read_async_message(stream, &mut pending, &self.config).await {
+            Ok(received) if received == *expected => {}
+            Ok(received) => {
+              return Err(deviation(format!(
+                "expected '{expected}', received '{received}'"
+              )));
+            }
+            Err(e) => return Err(deviation(format!("expected '{expected}', {e}"))),
+          }
+        }
+        ScriptStep::ExpectClose => {
+          match read_async_message(stream, &mut pending, &self.config).await {
+            Err(HandshakeError::ClientDisconnected) => {}
+            Ok(received) => {
+              return Err(deviation(format!(
+                "expected the connection to close, received '{received}'"
+              )));
+            }
+            Err(e) => return Err(deviation(format!("expected the connection to close, {e}"))),
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /**
+   * Runs the script on a background task over an in-memory stream
+   * Returns the other end of the stream, for the client or server under test, and a handle to check the script
+   * Must be called from within a Tokio runtime
+   */
+  pub fn spawn(self) -> (DuplexStream, MockPeerHandle) {
+    let (under_test, mut peer_end) = duplex(MOCK_STREAM_CAPACITY);
+    let task = tokio::spawn(async move { self.run(&mut peer_end).await });
+    (under_test, MockPeerHandle { task })
+  }
+}
+
+/**
+ * A mock peer running on a background task
+ */
+#[derive(Debug)]
+pub struct MockPeerHandle {
+  task: JoinHandle<Result<()>>,
+}
+
+impl MockPeerHandle {
+  /**
+   * Waits for the script to end, returning the first deviation if there was one
+   */
+  pub async fn finish(self) -> Result<()> {
+    match self.task.await {
+      Ok(result) => result,
+      Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+  }
+
+  /**
+   * Waits for the script to end and fails the test on any deviation
+   */
+  pub async fn verify(self) {
+    if let Err(e) = self.finish().await {
+      panic!("{e}");
+    }
+  }
+}
@@ -0,0 +1,175 @@
+/**
+ * Multiplexed handshakes for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Several logical handshakes can share one connection when every message is
+ * tagged with a stream ID:
+ *
+ * ```text
+ * HELLO 5 S=1      client opens stream 1
+ * HELLO 6 S=1      server replies on stream 1
+ * HELLO 40 S=2     client opens stream 2
+ * HELLO 41 S=2
+ * HELLO 7 S=1      stream 1 complete
+ * HELLO 42 S=2     stream 2 complete
+ * ```
+ *
+ * Messages are newline-terminated so that several can arrive in one read.
+ * Each stream ID keeps its own sequence state and can be used once.
+ */
+use std::collections::HashMap;
+use std::fmt;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use crate::MSG_SIZE;
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::limits::MessageCounter;
+use crate::protocol::{
+  format_hello_with_stream_id, is_reset, parse_hello_with_stream_id, write_async_message,
+};
+use crate::sequence::next_seq;
+use crate::time::timeout;
+
+/**
+ * Where one logical handshake stands
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeState {
+  // HELLO Y was sent; waiting for HELLO Y+1
+  AwaitingFinal { server_seq: i32 },
+  Complete,
+}
+
+/**
+ * Totals for a finished multiplexed connection
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MuxStats {
+  // Streams that received their final HELLO
+  pub completed: u64,
+  // Streams still waiting for their final HELLO when the client closed
+  pub incomplete: u64,
+}
+
+/**
+ * Whether at least one stream was opened and none is still waiting for its final HELLO
+ */
+fn all_complete(streams: &HashMap<u32, HandshakeState>) -> bool {
+  !streams.is_empty()
+    && streams
+      .values()
+      .all(|state| *state == HandshakeState::Complete)
+}
+
+/**
+ * Runs tagged handshakes until the peer disconnects
+ * Untagged messages, reused stream IDs, too many streams and more than
+ * `max_connection_messages` messages end the connection with an error
+ * A connection reset counts as a normal close only when every opened stream is complete
+ */
+pub async fn run_async_mux_session<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  config: &HandshakeConfig,
+) -> Result<MuxStats> {
+  let mut streams: HashMap<u32, HandshakeState> = HashMap::new();
+  let mut pending = String::new();
+  let mut buffer = [0u8; MSG_SIZE];
+  let mut messages = MessageCounter::default();
+
+  loop {
+    let bytes_read = match timeout(
+      config.clock.as_ref(),
+      config.read_timeout,
+      stream.read(&mut buffer),
+    )
+    .await?
+    {
+      Ok(bytes_read) => bytes_read,
+      // A reset once every stream has its final HELLO is as good as a clean close
+      Err(e) if is_reset(&e) && pending.is_empty() && all_complete(&streams) => break,
+      Err(e) => return Err(e.into()),
+    };
+    // The client closing the connection ends the session
+    if bytes_read == 0 {
+      break;
+    }
+    pending.push_str(&String::from_utf8_lossy(&buffer[..bytes_read]));
+
+    // Only complete lines are handled; a partial message waits for the next read
+    let Some(end) = pending.rfind('\n') else {
+      if pending.len() > MSG_SIZE {
+        return Err(HandshakeError::InvalidMessageFormat {
+          message: pending,
+          offset: MSG_SIZE,
+          expected: "newline",
+        });
+      }
+      continue;
+    };
+    let lines: String = pending.drain(..=end).collect();
+
+    for message in lines.lines().map(str::trim).filter(|line| !line.is_empty()) {
+      config.record_message_size(message.len());
+      messages.count(config)?;
+      let (seq, stream_id) = parse_hello_with_stream_id(message)?;
+      let stream_id = stream_id.ok_or_else(|| {
+        HandshakeError::ProtocolViolation(format!("missing stream ID in '{message}'"))
+      })?;
+      outln!(
+        config,
+        "Received from {peer_addr} on stream {stream_id}: HELLO {seq}"
+      );
+
+      match streams.get(&stream_id).copied() {
+        // Step 1 for a new stream: reply HELLO Y where Y follows X under `config.sequence_policy`
+        None => {
+          if streams.len() >= config.max_mux_streams {
+            return Err(HandshakeError::SessionLimitExceeded {
+              limit: "streams",
+              value: streams.len() as u64 + 1,
+              max: config.max_mux_streams as u64,
+            });
+          }
+          let server_seq = next_seq(config.sequence_policy.as_ref(), seq)?;
+          let response = format_hello_with_stream_id(server_seq, stream_id);
+          write_async_message(stream, &format!("{response}\n"), config).await?;
+          streams.insert(stream_id, HandshakeState::AwaitingFinal { server_seq });
+        }
+        // Step 3: validate that Z follows Y
+        Some(HandshakeState::AwaitingFinal { server_seq }) => {
+          let expected_final = next_seq(config.sequence_policy.as_ref(), server_seq)?;
+          if !config.sequence_policy.validate(expected_final, seq) {
+            errln!(
+              config,
+              "ERROR: Expected HELLO {expected_final}, received HELLO {seq} from {peer_addr} \
+               on stream {stream_id}"
+            );
+          }
+          outln!(
+            config,
+            "Handshake completed with {peer_addr} on stream {stream_id}"
+          );
+          streams.insert(stream_id, HandshakeState::Complete);
+        }
+        Some(HandshakeState::Complete) => {
+          return Err(HandshakeError::ProtocolViolation(format!(
+            "stream {stream_id} already completed"
+          )));
+        }
+      }
+    }
+  }
+
+  let completed = streams
+    .values()
+    .filter(|state| **state == HandshakeState::Complete)
+    .count() as u64;
+  Ok(MuxStats {
+    completed,
+    incomplete: streams.len() as u64 - completed,
+  })
+}
@@ -0,0 +1,137 @@
+/**
+ * OpenTelemetry export for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With the `otel` feature every served connection runs inside a `connection`
+ * span, and each handshake step inside it becomes a `handshake_step` child
+ * span timed from the end of the previous step. `install_otel` routes those
+ * spans to an OTLP collector over HTTP. The connection span records its trace
+ * ID, so a log line or event carrying it can be matched to the trace.
+ */
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use opentelemetry::trace::{TraceContextExt, TraceId, TracerProvider as _};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::Span;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::error::{HandshakeError, Result};
+use crate::outcome::HANDSHAKE_STEPS;
+
+// Where a local collector accepts OTLP traces over HTTP
+pub const DEFAULT_OTEL_ENDPOINT: &str = "http://localhost:4318/v1/traces";
+
+/**
+ * Where and as what service spans are exported
+ */
+#[derive(Debug, Clone)]
+pub struct OtelConfig {
+  // OTLP/HTTP traces endpoint of the collector
+  pub endpoint: String,
+  // `service.name` the spans are reported under
+  pub service_name: String,
+}
+
+impl Default for OtelConfig {
+  fn default() -> Self {
+    Self {
+      endpoint: DEFAULT_OTEL_ENDPOINT.to_string(),
+      service_name: env!("CARGO_PKG_NAME").to_string(),
+    }
+  }
+}
+
+/**
+ * Keeps the exporter running; dropping it flushes the spans still buffered
+ */
+pub struct OtelGuard {
+  provider: SdkTracerProvider,
+}
+
+impl Drop for OtelGuard {
+  fn drop(&mut self) {
+    // Nothing is left to report an export error to on the way out
+    let _ = self.provider.shutdown();
+  }
+}
+
+/**
+ * Exports connection and step spans to the collector at `config.endpoint`
+ * Spans are sent in batches from a background thread; an unreachable collector only loses them
+ */
+pub fn install_otel(config: &OtelConfig) -> Result<OtelGuard> {
+  let exporter = SpanExporter::builder()
+    .with_http()
+    .with_endpoint(&config.endpoint)
+    .build()
+    .map_err(|e| {
+      HandshakeError::InvalidArguments(format!("invalid OTLP endpoint '{}': {e}", config.endpoint))
+    })?;
+  let provider = SdkTracerProvider::builder()
+    .with_batch_exporter(exporter)
+    .with_resource(
+      Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .build(),
+    )
+    .build();
+  install_otel_provider(provider)
+}
+
+/**
+ * Routes connection and step spans to an already built tracer provider
+ * Fails when the process already has a global tracing subscriber
+ */
+pub fn install_otel_provider(provider: SdkTracerProvider) -> Result<OtelGuard> {
+  let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+  let subscriber =
+    tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+  tracing::subscriber::set_global_default(subscriber).map_err(|_| {
+    HandshakeError::InvalidArguments("a tracing subscriber is already installed".to_string())
+  })?;
+  Ok(OtelGuard { provider })
+}
+
+/**
+ * The span a served connection runs in, with its trace ID recorded as `trace_id`
+ */
+pub(crate) fn connection_span(peer_addr: SocketAddr) -> Span {
+  let span = tracing::info_span!("connection", peer = %peer_addr, trace_id = Empty);
+  let trace_id = span.context().span().span_context().trace_id();
+  if trace_id != TraceId::INVALID {
+    span.record("trace_id", trace_id.to_string());
+  }
+  span
+}
+
+/**
+ * The span for `step` (1 for HELLO X), a child of the span the handshake runs in
+ */
+pub(crate) fn step_span(step: u8) -> Span {
+  let message = ["HELLO X", "HELLO Y", "HELLO Z"][usize::from(step) - 1];
+  tracing::info_span!(
+    "handshake_step",
+    step = i64::from(step),
+    message,
+    duration_us = Empty
+  )
+}
+
+/**
+ * Closes the span of a finished step, recording how long it took on the handshake's clock
+ * Opens the next step's span unless this was the last step
+ */
+pub(crate) fn finish_step_span(span: &mut Span, step: u8, elapsed: Duration) {
+  span.record("duration_us", elapsed.as_micros() as u64);
+  *span = if usize::from(step) < HANDSHAKE_STEPS {
+    step_span(step + 1)
+  } else {
+    Span::none()
+  };
+}
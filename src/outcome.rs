@@ -0,0 +1,127 @@
+/**
+ * Handshake results for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::time::{Duration, Instant};
+
+use crate::capabilities::Capabilities;
+use crate::config::HandshakeConfig;
+use crate::time::Clock;
+
+// HELLO X, HELLO Y and HELLO Z
+pub const HANDSHAKE_STEPS: usize = 3;
+
+/**
+ * What a completed handshake observed
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandshakeOutcome {
+  // Sequence number that opened the handshake
+  pub initial_seq: i32,
+  // Sequence number carried by the final HELLO
+  pub final_seq: i32,
+  // Time from sending our HELLO to receiving the peer's next message
+  pub rtt: Duration,
+  // Time for the whole handshake
+  pub duration: Duration,
+  // Time spent sending or awaiting HELLO X, Y and Z, each measured from the end of the
+  // previous step, so together they add up to roughly `duration`
+  pub step_durations: [Duration; HANDSHAKE_STEPS],
+  // Features both sides support, when `capabilities` was negotiated
+  pub capabilities: Option<Capabilities>,
+  // A line this handshake logged to stdout could not be written; the handshake went on regardless
+  pub logging_degraded: bool,
+  // `config.label` of the side that ran the handshake
+  pub label: Option<String>,
+  // Everything the two sides agreed on, for adapting the session that follows
+  pub negotiated: Negotiated,
+  // Time spent getting connected, when the handshake function made the connection itself
+  pub connect_phases: Option<ConnectPhases>,
+}
+
+/**
+ * Time spent before the handshake, split by phase
+ *
+ * Together with `HandshakeOutcome::duration` this attributes a slow
+ * connection to name resolution, TCP setup or the handshake itself.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectPhases {
+  // Resolving the host, or the SOCKS5 proxy's host when one is used
+  pub dns: Duration,
+  // TCP connect attempts, plus the SOCKS5 CONNECT when a proxy is used
+  pub connect: Duration,
+}
+
+/**
+ * The protocol options a handshake ended up using
+ *
+ * `features` is the common capability set when capabilities were exchanged
+ * and empty otherwise, the same set as `HandshakeOutcome::capabilities`. The
+ * flags say which optional exchanges actually happened on this connection.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Negotiated {
+  pub features: Capabilities,
+  // HELLO Y carried a nonce and HELLO Z echoed it
+  pub uses_nonce: bool,
+  // The READY/START greeting preceded HELLO X
+  pub uses_preamble: bool,
+}
+
+impl Negotiated {
+  pub(crate) fn new(
+    capabilities: Option<Capabilities>,
+    nonce: Option<u64>,
+    config: &HandshakeConfig,
+  ) -> Self {
+    Self {
+      features: capabilities.unwrap_or_default(),
+      uses_nonce: nonce.is_some(),
+      uses_preamble: config.preamble.is_some(),
+    }
+  }
+}
+
+/**
+ * Splits a running handshake into per-step durations
+ */
+pub(crate) struct StepTimer<'a> {
+  clock: &'a dyn Clock,
+  // End of the latest finished step, or the start of the handshake
+  mark: Instant,
+  steps: [Duration; HANDSHAKE_STEPS],
+  // Span of the step in progress
+  #[cfg(feature = "otel")]
+  span: tracing::Span,
+}
+
+impl<'a> StepTimer<'a> {
+  pub(crate) fn new(clock: &'a dyn Clock, started: Instant) -> Self {
+    Self {
+      clock,
+      mark: started,
+      steps: [Duration::ZERO; HANDSHAKE_STEPS],
+      #[cfg(feature = "otel")]
+      span: crate::otel::step_span(1),
+    }
+  }
+
+  /**
+   * Ends `step` (1 for HELLO X) now and returns how long it took
+   */
+  pub(crate) fn finish(&mut self, step: u8) -> Duration {
+    let now = self.clock.now();
+    let elapsed = now.duration_since(self.mark);
+    self.mark = now;
+    self.steps[usize::from(step) - 1] = elapsed;
+    #[cfg(feature = "otel")]
+    crate::otel::finish_step_span(&mut self.span, step, elapsed);
+    elapsed
+  }
+
+  pub(crate) fn steps(&self) -> [Duration; HANDSHAKE_STEPS] {
+    self.steps
+  }
+}
@@ -0,0 +1,103 @@
+/**
+ * Console output for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Library code prints through these macros instead of `println!` and
+ * `eprintln!`, so an embedding application can set `silent` in its config and
+ * rely on the event callback alone. The binaries keep the default, which
+ * prints as before. Helpers only the binaries call — listener creation, signal
+ * handling, `exit_with_error` and `ReportOnExit::stdout` — always print.
+ *
+ * `println!` panics when stdout cannot be written, say because it is a pipe
+ * whose reader went away. With `tolerate_log_failures` set such a line is
+ * dropped instead, so losing a log line does not cost a handshake. Handshakes
+ * log through `logln!`, which also remembers the loss so the outcome can
+ * report it as `logging_degraded`. The binaries turn it on; the default keeps
+ * `println!`, which test harnesses capture.
+ */
+use std::fmt;
+use std::io::Write;
+
+use crate::config::HandshakeConfig;
+
+/**
+ * Writes one line to stdout and flushes it, returning false if either failed
+ */
+pub(crate) fn print_line(args: fmt::Arguments<'_>) -> bool {
+  let mut stdout = std::io::stdout().lock();
+  writeln!(stdout, "{args}")
+    .and_then(|()| stdout.flush())
+    .is_ok()
+}
+
+/**
+ * Stdout logging for one handshake, tracking whether any line was lost
+ */
+#[derive(Debug, Default)]
+pub(crate) struct HandshakeLog {
+  degraded: bool,
+}
+
+impl HandshakeLog {
+  pub(crate) fn line(&mut self, config: &HandshakeConfig, args: fmt::Arguments<'_>) {
+    if config.silent {
+      return;
+    }
+    if let Some(label) = &config.label {
+      return self.write(config, format_args!("[{label}] {args}"));
+    }
+    self.write(config, args);
+  }
+
+  fn write(&mut self, config: &HandshakeConfig, args: fmt::Arguments<'_>) {
+    if !config.tolerate_log_failures {
+      println!("{args}");
+    } else if !print_line(args) {
+      self.degraded = true;
+    }
+  }
+
+  /**
+   * True once a line could not be written
+   */
+  pub(crate) fn degraded(&self) -> bool {
+    self.degraded
+  }
+}
+
+/**
+ * `println!` unless the given config is silent
+ * A failed write is ignored when the config tolerates log failures
+ */
+macro_rules! outln {
+  ($config:expr, $($arg:tt)*) => {
+    if !$config.silent {
+      if $config.tolerate_log_failures {
+        let _ = $crate::output::print_line(format_args!($($arg)*));
+      } else {
+        println!($($arg)*);
+      }
+    }
+  };
+}
+
+/**
+ * `outln!` for a handshake, recording a failed write in the given `HandshakeLog`
+ */
+macro_rules! logln {
+  ($log:expr, $config:expr, $($arg:tt)*) => {
+    $log.line($config, format_args!($($arg)*))
+  };
+}
+
+/**
+ * `eprintln!` unless the given config is silent
+ */
+macro_rules! errln {
+  ($config:expr, $($arg:tt)*) => {
+    if !$config.silent {
+      eprintln!($($arg)*);
+    }
+  };
+}
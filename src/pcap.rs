@@ -0,0 +1,238 @@
+/**
+ * Pcap trace writer for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The handshake runs above the socket, so the real TCP segments are never
+ * seen. This module synthesizes a minimal IPv4 or IPv6 + TCP packet around
+ * each application message, using the known endpoints and per-direction
+ * TCP sequence numbers, and writes them as a classic pcap file that
+ * Wireshark and tcpdump can open. Records use the raw IP link type, so no
+ * Ethernet header is invented.
+ */
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{HandshakeError, Result};
+use crate::events::HandshakeEvent;
+use crate::transcript::Direction;
+
+// Classic pcap magic number, microsecond timestamps
+pub const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+// LINKTYPE_RAW: each record starts with an IPv4 or IPv6 header
+pub const PCAP_LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65_535;
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_HEADER_LEN: usize = 20;
+const IP_PROTO_TCP: u8 = 6;
+const TTL: u8 = 64;
+// PSH | ACK: every synthesized segment carries data for an established connection
+const TCP_FLAGS_PSH_ACK: u8 = 0x18;
+// Sequence numbers start here on both sides, as if just after the TCP SYN exchange
+const INITIAL_TCP_SEQ: u32 = 1;
+
+/**
+ * Writes handshake messages as synthesized TCP segments in a pcap file
+ *
+ * `local` is the side that recorded the messages: outbound messages go from
+ * `local` to `remote` and inbound ones the other way.
+ */
+pub struct PcapWriter<W: Write> {
+  out: W,
+  local: SocketAddr,
+  remote: SocketAddr,
+  // Next TCP sequence number each side sends
+  local_seq: u32,
+  remote_seq: u32,
+  // The same moment on the monotonic and the wall clock, for timestamping events
+  anchor: (Instant, SystemTime),
+}
+
+impl<W: Write> PcapWriter<W> {
+  /**
+   * Writes the pcap file header
+   * Both endpoints must be IPv4 or both IPv6
+   */
+  pub fn new(mut out: W, local: SocketAddr, remote: SocketAddr) -> Result<Self> {
+    if local.is_ipv4() != remote.is_ipv4() {
+      return Err(HandshakeError::InvalidArguments(format!(
+        "pcap endpoints {local} and {remote} must use the same IP version"
+      )));
+    }
+
+    let mut header = Vec::with_capacity(24);
+    header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&4u16.to_le_bytes());
+    // Timezone offset and timestamp accuracy, both unused
+    header.extend_from_slice(&0i32.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&SNAPLEN.to_le_bytes());
+    header.extend_from_slice(&PCAP_LINKTYPE_RAW.to_le_bytes());
+    out.write_all(&header)?;
+
+    Ok(Self {
+      out,
+      local,
+      remote,
+      local_seq: INITIAL_TCP_SEQ,
+      remote_seq: INITIAL_TCP_SEQ,
+      anchor: (Instant::now(), SystemTime::now()),
+    })
+  }
+
+  /**
+   * Writes one message as a TCP segment captured at `timestamp`
+   */
+  pub fn write_message(
+    &mut self,
+    direction: Direction,
+    message: &[u8],
+    timestamp: SystemTime,
+  ) -> Result<()> {
+    let (src, dst, seq, ack) = match direction {
+      Direction::Outbound => (self.local, self.remote, self.local_seq, self.remote_seq),
+      Direction::Inbound => (self.remote, self.local, self.remote_seq, self.local_seq),
+    };
+    let packet = ip_packet(src, dst, &tcp_segment(src, dst, seq, ack, message))?;
+
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let len = packet.len() as u32;
+    let mut record = Vec::with_capacity(16 + packet.len());
+    record.extend_from_slice(&(since_epoch.as_secs() as u32).to_le_bytes());
+    record.extend_from_slice(&since_epoch.subsec_micros().to_le_bytes());
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&packet);
+    self.out.write_all(&record)?;
+
+    let sent = message.len() as u32;
+    match direction {
+      Direction::Outbound => self.local_seq = self.local_seq.wrapping_add(sent),
+      Direction::Inbound => self.remote_seq = self.remote_seq.wrapping_add(sent),
+    }
+    Ok(())
+  }
+
+  /**
+   * Writes a message reported to `config.on_event`, timestamped on the wall clock
+   */
+  pub fn write_event(&mut self, event: &HandshakeEvent) -> Result<()> {
+    let (instant, wall) = self.anchor;
+    let timestamp = match event.timestamp.checked_duration_since(instant) {
+      Some(after) => wall + after,
+      None => wall - instant.duration_since(event.timestamp),
+    };
+    self.write_message(event.direction, event.message.as_bytes(), timestamp)
+  }
+
+  /**
+   * Flushes and returns the underlying writer
+   */
+  pub fn into_inner(mut self) -> Result<W> {
+    self.out.flush()?;
+    Ok(self.out)
+  }
+}
+
+fn tcp_segment(src: SocketAddr, dst: SocketAddr, seq: u32, ack: u32, payload: &[u8]) -> Vec<u8> {
+  let mut segment = Vec::with_capacity(TCP_HEADER_LEN + payload.len());
+  segment.extend_from_slice(&src.port().to_be_bytes());
+  segment.extend_from_slice(&dst.port().to_be_bytes());
+  segment.extend_from_slice(&seq.to_be_bytes());
+  segment.extend_from_slice(&ack.to_be_bytes());
+  // Data offset in 32-bit words, no options
+  segment.push(((TCP_HEADER_LEN / 4) as u8) << 4);
+  segment.push(TCP_FLAGS_PSH_ACK);
+  segment.extend_from_slice(&u16::MAX.to_be_bytes());
+  // Checksum, filled in below, and urgent pointer
+  segment.extend_from_slice(&[0, 0, 0, 0]);
+  segment.extend_from_slice(payload);
+
+  let checksum = checksum(&[&pseudo_header(src.ip(), dst.ip(), segment.len()), &segment]);
+  segment[16..18].copy_from_slice(&checksum.to_be_bytes());
+  segment
+}
+
+/**
+ * The source, destination, protocol and length that the TCP checksum also covers
+ */
+fn pseudo_header(src: IpAddr, dst: IpAddr, tcp_len: usize) -> Vec<u8> {
+  let mut header = Vec::new();
+  match (src, dst) {
+    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+      header.extend_from_slice(&src.octets());
+      header.extend_from_slice(&dst.octets());
+      header.extend_from_slice(&[0, IP_PROTO_TCP]);
+      header.extend_from_slice(&(tcp_len as u16).to_be_bytes());
+    }
+    (src, dst) => {
+      header.extend_from_slice(&ipv6_octets(src));
+      header.extend_from_slice(&ipv6_octets(dst));
+      header.extend_from_slice(&(tcp_len as u32).to_be_bytes());
+      header.extend_from_slice(&[0, 0, 0, IP_PROTO_TCP]);
+    }
+  }
+  header
+}
+
+fn ip_packet(src: SocketAddr, dst: SocketAddr, segment: &[u8]) -> Result<Vec<u8>> {
+  let too_long = || {
+    HandshakeError::InvalidArguments(format!(
+      "message of {} bytes does not fit in one IP packet",
+      segment.len() - TCP_HEADER_LEN
+    ))
+  };
+
+  let mut packet = Vec::new();
+  match (src.ip(), dst.ip()) {
+    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+      let total_len = u16::try_from(IPV4_HEADER_LEN + segment.len()).map_err(|_| too_long())?;
+      packet.extend_from_slice(&[0x45, 0]);
+      packet.extend_from_slice(&total_len.to_be_bytes());
+      // Identification, then Don't Fragment with no fragment offset
+      packet.extend_from_slice(&[0, 0, 0x40, 0]);
+      packet.extend_from_slice(&[TTL, IP_PROTO_TCP, 0, 0]);
+      packet.extend_from_slice(&src.octets());
+      packet.extend_from_slice(&dst.octets());
+      let checksum = checksum(&[&packet]);
+      packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    }
+    (src, dst) => {
+      let payload_len = u16::try_from(segment.len()).map_err(|_| too_long())?;
+      packet.extend_from_slice(&[0x60, 0, 0, 0]);
+      packet.extend_from_slice(&payload_len.to_be_bytes());
+      packet.extend_from_slice(&[IP_PROTO_TCP, TTL]);
+      packet.extend_from_slice(&ipv6_octets(src));
+      packet.extend_from_slice(&ipv6_octets(dst));
+      debug_assert_eq!(packet.len(), IPV6_HEADER_LEN);
+    }
+  }
+  packet.extend_from_slice(segment);
+  Ok(packet)
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+  match ip {
+    IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+    IpAddr::V6(ip) => ip.octets(),
+  }
+}
+
+/**
+ * Internet checksum (RFC 1071) over the concatenated parts
+ */
+fn checksum(parts: &[&[u8]]) -> u16 {
+  let bytes: Vec<u8> = parts.concat();
+  let mut sum: u32 = bytes
+    .chunks(2)
+    .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+    .sum();
+  while sum > 0xffff {
+    sum = (sum & 0xffff) + (sum >> 16);
+  }
+  !(sum as u16)
+}
@@ -0,0 +1,66 @@
+/**
+ * Client address resolution for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Behind a load balancer or a NAT the socket peer is not the client. Every
+ * server asks `config.peer_resolver` which address a connection stands for,
+ * once when it is accepted, before any byte is read, and again once a PROXY
+ * header has been stripped. The first answer is what per-source limits are
+ * counted against; the second is what the handshake logs, records and checks
+ * a claimed `IP=` against. The default trusts a PROXY header when there is
+ * one and the socket peer otherwise; a custom resolver can map NAT addresses,
+ * trust headers only from known balancers, or pin an address in tests.
+ */
+use std::fmt;
+use std::net::SocketAddr;
+
+use crate::proxy::ProxyHeader;
+
+/**
+ * What is known about a connection's addresses when it is resolved
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerInfo {
+  // The address the socket is connected to
+  pub socket_peer: SocketAddr,
+  // This end of the socket, if the OS could report it
+  pub local: Option<SocketAddr>,
+  // The PROXY header read from the connection; `None` at accept time or without `proxy_protocol`
+  pub proxy: Option<ProxyHeader>,
+}
+
+impl PeerInfo {
+  /**
+   * A connection that was just accepted, before any header has been read
+   */
+  pub fn accepted(socket_peer: SocketAddr, local: Option<SocketAddr>) -> Self {
+    Self {
+      socket_peer,
+      local,
+      proxy: None,
+    }
+  }
+}
+
+/**
+ * Picks the address a connection is logged, counted and checked as
+ */
+pub trait PeerResolver: fmt::Debug + Send + Sync {
+  fn resolve(&self, peer: &PeerInfo) -> SocketAddr;
+}
+
+/**
+ * Default resolver: the PROXY header's source when it names one, otherwise the socket peer
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultPeerResolver;
+
+impl PeerResolver for DefaultPeerResolver {
+  fn resolve(&self, peer: &PeerInfo) -> SocketAddr {
+    peer
+      .proxy
+      .and_then(|header| header.source())
+      .unwrap_or(peer.socket_peer)
+  }
+}
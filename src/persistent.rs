@@ -0,0 +1,165 @@
+/**
+ * Persistent connections for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With `persistent` set, a server runs one handshake after another on each
+ * connection until the client closes it. Every handshake starts from a fresh
+ * HELLO X with its own sequence state, but bytes already read past a HELLO Z
+ * carry over to the next handshake, and so does the connection's count
+ * against `max_connection_messages`. A clean close between handshakes ends
+ * the connection normally, as does a connection that stays idle for a whole
+ * `read_timeout`; a close in the middle of a handshake is still a failure.
+ *
+ * Without a delimiter each read is one message, so a client that sends the
+ * next HELLO X right behind HELLO Z can have both land in one read. Clients
+ * that pipeline like that should frame their messages (see `delimiter`).
+ */
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream};
+
+use tokio::net::TcpStream as AsyncTcpStream;
+use tokio_util::sync::CancellationToken;
+
+use crate::buffer_pool::handshake_buffer;
+use crate::config::HandshakeConfig;
+use crate::cork::CorkedReplies;
+use crate::error::Result;
+use crate::framing::GrowableBuffer;
+use crate::limits::MessageCounter;
+use crate::metrics::ServerMetrics;
+use crate::outcome::HandshakeOutcome;
+use crate::protocol::{
+  MAX_INTERRUPTED_RETRIES, async_server_handshake, is_reset, server_handshake,
+};
+use crate::time::timeout;
+
+/**
+ * Runs handshakes on `stream` until the client closes it, calling `completed` after each one
+ * Returns how many handshakes completed, which is also recorded in `metrics`
+ */
+pub(crate) fn serve_persistent(
+  stream: &mut TcpStream,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+  mut completed: impl FnMut(&HandshakeOutcome),
+) -> Result<u64> {
+  let mut pending = handshake_buffer(&[], config);
+  let mut messages = MessageCounter::default();
+  let mut handshakes = 0;
+
+  let result = loop {
+    match next_handshake_started(stream, &pending, handshakes) {
+      Ok(true) => {}
+      Ok(false) => break Ok(handshakes),
+      Err(e) => break Err(e),
+    }
+    match server_handshake(stream, &mut pending, &mut messages, config) {
+      Ok(outcome) => {
+        handshakes += 1;
+        completed(&outcome);
+      }
+      Err(e) => break Err(e),
+    }
+  };
+  metrics.handshakes_per_connection.record(handshakes);
+  result
+}
+
+/**
+ * Async version of `serve_persistent`
+ * Cancelling `cancel` aborts a handshake in progress and ends an idle connection quietly
+ */
+pub(crate) async fn serve_persistent_async(
+  stream: &mut AsyncTcpStream,
+  peer_addr: SocketAddr,
+  cancel: &CancellationToken,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+  mut completed: impl FnMut(&HandshakeOutcome),
+) -> Result<u64> {
+  let mut pending = handshake_buffer(&[], config);
+  let mut messages = MessageCounter::default();
+  let mut handshakes = 0;
+
+  let result = loop {
+    match next_handshake_started_async(stream, &pending, handshakes, cancel, config).await {
+      Ok(true) => {}
+      Ok(false) => break Ok(handshakes),
+      Err(e) => break Err(e),
+    }
+    // Corked, each reply leaves as one segment
+    let mut replies = CorkedReplies::new(stream, config.cork_replies);
+    let handshake = async_server_handshake(
+      &mut replies,
+      &mut pending,
+      &mut messages,
+      peer_addr,
+      Some(cancel),
+      config,
+    )
+    .await;
+    match handshake {
+      Ok(outcome) => {
+        handshakes += 1;
+        completed(&outcome);
+      }
+      Err(e) => break Err(e),
+    }
+  };
+  metrics.handshakes_per_connection.record(handshakes);
+  result
+}
+
+/**
+ * Whether the client started another handshake rather than closing or going idle
+ * The first handshake always runs, so a silent client fails it as usual
+ */
+fn next_handshake_started(
+  stream: &TcpStream,
+  pending: &GrowableBuffer,
+  handshakes: u64,
+) -> Result<bool> {
+  if handshakes == 0 || !pending.is_empty() {
+    return Ok(true);
+  }
+  // The last handshake left `read_timeout` set on the stream
+  let mut retries = 0;
+  loop {
+    match stream.peek(&mut [0u8; 1]) {
+      Ok(bytes) => return Ok(bytes > 0),
+      Err(e) if e.kind() == ErrorKind::Interrupted && retries < MAX_INTERRUPTED_RETRIES => {
+        retries += 1;
+      }
+      Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+        return Ok(false);
+      }
+      Err(e) if is_reset(&e) => return Ok(false),
+      Err(e) => return Err(e.into()),
+    }
+  }
+}
+
+async fn next_handshake_started_async(
+  stream: &AsyncTcpStream,
+  pending: &GrowableBuffer,
+  handshakes: u64,
+  cancel: &CancellationToken,
+  config: &HandshakeConfig,
+) -> Result<bool> {
+  if handshakes == 0 || !pending.is_empty() {
+    return Ok(true);
+  }
+  let mut probe = [0u8; 1];
+  let peeked = tokio::select! {
+    _ = cancel.cancelled() => return Ok(false),
+    peeked = timeout(config.clock.as_ref(), config.read_timeout, stream.peek(&mut probe)) => peeked,
+  };
+  match peeked {
+    Ok(Ok(bytes)) => Ok(bytes > 0),
+    Ok(Err(e)) if is_reset(&e) => Ok(false),
+    Ok(Err(e)) => Err(e.into()),
+    // Idle for a whole read timeout
+    Err(_) => Ok(false),
+  }
+}
@@ -0,0 +1,184 @@
+/**
+ * Bounded worker pool for the thread pool server
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Accepted connections travel through a bounded channel to a fixed set of
+ * worker threads. When the channel is full the job is handed back to the
+ * caller instead of queueing without limit, which is where backpressure starts.
+ */
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Sender, TrySendError, bounded};
+
+use crate::metrics::ServerMetrics;
+
+// Default number of accepted connections allowed to wait for a worker
+pub const DEFAULT_QUEUE_CAPACITY: usize = 128;
+
+pub struct BoundedWorkerPool<T: Send + 'static> {
+  sender: Option<Sender<T>>,
+  workers: Vec<JoinHandle<()>>,
+  metrics: Arc<ServerMetrics>,
+  outstanding: Arc<Outstanding>,
+}
+
+/**
+ * Jobs queued or running, with a condvar signalled when the count reaches zero
+ */
+#[derive(Default)]
+struct Outstanding {
+  count: AtomicUsize,
+  lock: Mutex<()>,
+  idle: Condvar,
+}
+
+impl Outstanding {
+  fn finish_one(&self) {
+    if self.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+      // Take the lock so a waiter cannot miss the wakeup between its check and wait
+      let _guard = self.lock.lock().unwrap();
+      self.idle.notify_all();
+    }
+  }
+}
+
+impl<T: Send + 'static> BoundedWorkerPool<T> {
+  /**
+   * Spawns `num_workers` threads that run `handler` for each submitted job
+   */
+  pub fn new<F>(
+    num_workers: usize,
+    capacity: usize,
+    metrics: Arc<ServerMetrics>,
+    handler: F,
+  ) -> Self
+  where
+    F: Fn(T) + Send + Sync + 'static,
+  {
+    let (sender, receiver) = bounded::<T>(capacity);
+    let handler = Arc::new(handler);
+    let outstanding = Arc::new(Outstanding::default());
+
+    let workers = (0..num_workers)
+      .map(|_| {
+        let receiver = receiver.clone();
+        let handler = Arc::clone(&handler);
+        let metrics = Arc::clone(&metrics);
+        let outstanding = Arc::clone(&outstanding);
+        thread::spawn(move || {
+          // Exits once the pool is dropped and the queue has drained
+          for job in receiver.iter() {
+            metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            handler(job);
+            outstanding.finish_one();
+          }
+        })
+      })
+      .collect();
+
+    Self {
+      sender: Some(sender),
+      workers,
+      metrics,
+      outstanding,
+    }
+  }
+
+  /**
+   * Queues a job without blocking
+   * Returns the job back when the queue is full so the caller can reject it
+   */
+  pub fn try_submit(&self, job: T) -> std::result::Result<(), T> {
+    let sender = self
+      .sender
+      .as_ref()
+      .expect("pool sender is alive until drop");
+
+    // Count before sending so a fast worker never decrements below zero
+    self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+    self.outstanding.count.fetch_add(1, Ordering::AcqRel);
+    match sender.try_send(job) {
+      Ok(()) => Ok(()),
+      Err(TrySendError::Full(job)) | Err(TrySendError::Disconnected(job)) => {
+        self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        self.outstanding.finish_one();
+        self
+          .metrics
+          .rejected_queue_full
+          .fetch_add(1, Ordering::Relaxed);
+        Err(job)
+      }
+    }
+  }
+
+  /**
+   * Number of jobs waiting for a worker
+   */
+  pub fn queue_depth(&self) -> usize {
+    self.metrics.queue_depth.load(Ordering::Relaxed)
+  }
+
+  /**
+   * Number of jobs queued or still running
+   */
+  pub fn outstanding(&self) -> usize {
+    self.outstanding.count.load(Ordering::Acquire)
+  }
+
+  /**
+   * Blocks until every submitted job has finished or `timeout` elapses
+   * Returns whether the pool went idle in time
+   */
+  pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut guard = self.outstanding.lock.lock().unwrap();
+
+    while self.outstanding() > 0 {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return false;
+      }
+      guard = self
+        .outstanding
+        .idle
+        .wait_timeout(guard, remaining)
+        .unwrap()
+        .0;
+    }
+    true
+  }
+
+  /**
+   * Stops accepting jobs and waits up to `timeout` for the queued and running ones
+   *
+   * Returns whether every job finished. Workers still busy after the timeout
+   * are detached rather than joined so the caller can proceed.
+   */
+  pub fn shutdown(mut self, timeout: Duration) -> bool {
+    self.sender.take();
+    let drained = self.wait_idle_timeout(timeout);
+    if drained {
+      for worker in self.workers.drain(..) {
+        let _ = worker.join();
+      }
+    } else {
+      // Dropping the handles detaches the threads; Drop then has nothing to join
+      self.workers.clear();
+    }
+    drained
+  }
+}
+
+impl<T: Send + 'static> Drop for BoundedWorkerPool<T> {
+  fn drop(&mut self) {
+    // Closing the channel lets workers finish the queue and exit
+    self.sender.take();
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
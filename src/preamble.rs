@@ -0,0 +1,136 @@
+/**
+ * Greeting preamble for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Some deployments exchange banners before the handshake, like SMTP and SSH
+ * greetings. With `preamble` set in the config, the server opens with its
+ * greeting, the client answers with its reply, and only then does the client
+ * send HELLO X:
+ *
+ * ```text
+ * server -> client  READY
+ * client -> server  START
+ * client -> server  HELLO X   (the handshake proceeds as usual)
+ * ```
+ *
+ * Both ends must be configured with the same preamble; a token that does not
+ * match fails the handshake with a protocol violation. The preamble is not
+ * namespaced and sits outside the three handshake steps, so it is not
+ * reported to `on_event`.
+ */
+use std::net::TcpStream;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::framing::GrowableBuffer;
+use crate::protocol::{
+  read_async_message, read_sync_message, write_async_framed, write_sync_message,
+};
+
+// Default tokens, sent by the server and the client respectively
+pub const DEFAULT_PREAMBLE_GREETING: &str = "READY";
+pub const DEFAULT_PREAMBLE_REPLY: &str = "START";
+
+/**
+ * The two tokens exchanged before the handshake
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preamble {
+  // Sent by the server as soon as the connection is up
+  pub greeting: String,
+  // Sent by the client once it has seen the greeting
+  pub reply: String,
+}
+
+impl Default for Preamble {
+  fn default() -> Self {
+    Self {
+      greeting: DEFAULT_PREAMBLE_GREETING.to_string(),
+      reply: DEFAULT_PREAMBLE_REPLY.to_string(),
+    }
+  }
+}
+
+/**
+ * Fails unless the peer sent the expected preamble token
+ * Without a delimiter, the client's reply and HELLO X can arrive in one read,
+ * so anything after the token goes back into `pending` for the handshake
+ */
+fn expect_token(
+  expected: &str,
+  received: &str,
+  pending: &mut GrowableBuffer,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  let received = received.trim();
+  let rest = received
+    .strip_prefix(expected)
+    .map(str::trim_start)
+    .filter(|rest| rest.is_empty() || config.delimiter.as_bytes().is_empty());
+  let Some(rest) = rest else {
+    return Err(HandshakeError::ProtocolViolation(format!(
+      "expected preamble '{expected}', received '{received}'"
+    )));
+  };
+  pending.extend_from_slice(rest.as_bytes());
+  Ok(())
+}
+
+/**
+ * Server side: sends the greeting and waits for the client's reply
+ */
+pub(crate) fn server_preamble(
+  stream: &mut TcpStream,
+  pending: &mut GrowableBuffer,
+  preamble: &Preamble,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  write_sync_message(stream, &preamble.greeting, config)?;
+  let received = read_sync_message(stream, pending, config)?;
+  expect_token(&preamble.reply, &received, pending, config)
+}
+
+/**
+ * Client side: waits for the server's greeting and answers it
+ */
+pub(crate) fn client_preamble(
+  stream: &mut TcpStream,
+  pending: &mut GrowableBuffer,
+  preamble: &Preamble,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  let received = read_sync_message(stream, pending, config)?;
+  expect_token(&preamble.greeting, &received, pending, config)?;
+  write_sync_message(stream, &preamble.reply, config)
+}
+
+/**
+ * Async version: Server side of the preamble
+ */
+pub(crate) async fn server_preamble_async<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  pending: &mut GrowableBuffer,
+  preamble: &Preamble,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  write_async_framed(stream, &preamble.greeting, config).await?;
+  let received = read_async_message(stream, pending, config).await?;
+  expect_token(&preamble.reply, &received, pending, config)
+}
+
+/**
+ * Async version: Client side of the preamble
+ */
+pub(crate) async fn client_preamble_async<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  pending: &mut GrowableBuffer,
+  preamble: &Preamble,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  let received = read_async_message(stream, pending, config).await?;
+  expect_token(&preamble.greeting, &received, pending, config)?;
+  write_async_framed(stream, &preamble.reply, config).await
+}
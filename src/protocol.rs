@@ -3,38 +3,148 @@
  *
  * Author: Sae-Hwan Park
  */
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
+use std::borrow::Cow;
+use std::fmt;
+use std::future::{self, Future};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::pin::Pin;
+use std::task::Poll;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use rand::{Rng, RngCore};
 // Async imports
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream as AsyncTcpStream;
-use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 
 use crate::MSG_SIZE;
+use crate::buffer_pool::handshake_buffer;
+use crate::capabilities::{Capabilities, require_capabilities};
+use crate::config::HandshakeConfig;
+use crate::cork::corked;
 use crate::error::{HandshakeError, Result};
+use crate::framing::{
+  Delimiter, GrowableBuffer, read_until_delimiter_async_within, read_until_delimiter_before,
+};
+use crate::invariants::{check_read_len, check_sent};
+use crate::limits::MessageCounter;
+use crate::machine::{HandshakeMachine, Role};
+use crate::message::{HELLO_VERB, tokens, validate_hello_message};
+use crate::outcome::{HandshakeOutcome, StepTimer};
+use crate::output::HandshakeLog;
+use crate::peer::PeerInfo;
+use crate::preamble::{
+  client_preamble, client_preamble_async, server_preamble, server_preamble_async,
+};
+use crate::reply::ReplyStrategy;
+use crate::sequence::{IncrementPolicy, SequencePolicy, next_seq};
+use crate::time::timeout;
+use crate::transcript::Direction;
+use crate::utils::{apply_linger, apply_nodelay, connect_async_timed};
 
 // Timeout constants for async operations
 pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
 pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
 pub const CLIENT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
 
+// Times a sync read or write interrupted by a signal (EINTR) is retried
+pub const MAX_INTERRUPTED_RETRIES: u32 = 16;
+
+// Pause between sync reads of a non-blocking socket that has no data yet
+pub const WOULD_BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// Sent instead of HELLO Y by a server that refuses the connection under load
+pub const BUSY_MESSAGE: &str = "BUSY";
+// Sent instead of HELLO Y by a server already running its maximum concurrent handshakes
+pub const OVERLOADED_MESSAGE: &str = "OVERLOADED";
+
+// Largest random initial sequence, leaving room for Y = X + 1 and Z = X + 2
+pub const MAX_RANDOM_INITIAL_SEQ: i32 = i32::MAX - 2;
+
 /**
- * Parses a HELLO message and extracts the sequence number
+ * Parses a plain `HELLO <number>` message and extracts the sequence number
+ * Optional fields are rejected; use `validate_hello_message` to accept them
  */
 pub fn parse_hello_message(message: &str) -> Result<i32> {
-  let parts: Vec<&str> = message.split_whitespace().collect();
+  let validated = validate_hello_message(message)?;
 
-  if parts.len() != 2 || parts[0] != "HELLO" {
+  if validated.has_optional_fields() {
+    let offset = tokens(message).nth(2).map_or(0, |(offset, _)| offset);
     return Err(HandshakeError::InvalidMessageFormat {
       message: message.to_string(),
+      offset,
+      expected: "end of message",
     });
   }
 
-  parts[1]
-    .parse::<i32>()
-    .map_err(|_| HandshakeError::InvalidSequenceNumber(parts[1].to_string()))
+  Ok(validated.seq)
+}
+
+/**
+ * Parses a plain `HELLO <number>` message straight from its bytes
+ *
+ * Gives the same result as `parse_hello_message` on the same text, but a
+ * well-formed message is parsed in place as ASCII: no UTF-8 check and no
+ * allocation. Anything else, including every malformed message, goes through
+ * `parse_hello_message` so errors are reported exactly as on the string path.
+ */
+pub fn parse_hello_message_bytes(message: &[u8]) -> Result<i32> {
+  match scan_plain_hello(message) {
+    Some(seq) => Ok(seq),
+    None => parse_hello_message(&String::from_utf8_lossy(message)),
+  }
+}
+
+/**
+ * The sequence number of an all-ASCII `HELLO <number>`, or `None` for anything else
+ */
+fn scan_plain_hello(message: &[u8]) -> Option<i32> {
+  let mut parts = message
+    .split(u8::is_ascii_whitespace)
+    .filter(|token| !token.is_empty());
+  if parts.next()? != HELLO_VERB.as_bytes() {
+    return None;
+  }
+  let seq = scan_i32(parts.next()?)?;
+  parts.next().is_none().then_some(seq)
+}
+
+/**
+ * ASCII decimal with an optional sign, as accepted by `i32::from_str`; `None` on overflow
+ */
+fn scan_i32(token: &[u8]) -> Option<i32> {
+  let (negative, digits) = match token {
+    [b'-', digits @ ..] => (true, digits),
+    [b'+', digits @ ..] => (false, digits),
+    digits => (false, digits),
+  };
+  if digits.is_empty() {
+    return None;
+  }
+  digits.iter().try_fold(0i32, |value, &byte| {
+    let digit = i32::from(byte)
+      .checked_sub(i32::from(b'0'))
+      .filter(|d| (0..10).contains(d))?;
+    let value = value.checked_mul(10)?;
+    if negative {
+      value.checked_sub(digit)
+    } else {
+      value.checked_add(digit)
+    }
+  })
+}
+
+/**
+ * Parses a plain HELLO, in place from its bytes when `config.parse_in_place` is set
+ */
+fn parse_plain_hello(message: &str, config: &HandshakeConfig) -> Result<i32> {
+  if config.parse_in_place {
+    parse_hello_message_bytes(message.as_bytes())
+  } else {
+    parse_hello_message(message)
+  }
 }
 
 /**
@@ -44,159 +154,1176 @@ pub fn format_hello_message(seq_num: i32) -> String {
   format!("HELLO {seq_num}")
 }
 
+/**
+ * Parses a HELLO message that may carry a stream ID, e.g. `HELLO 5 S=3`
+ * Returns the sequence number and the stream ID if one was given
+ */
+pub fn parse_hello_with_stream_id(message: &str) -> Result<(i32, Option<u32>)> {
+  let validated = validate_hello_message(message)?;
+  Ok((validated.seq, validated.stream_id))
+}
+
+/**
+ * Formats a HELLO message tagged with a stream ID
+ */
+pub fn format_hello_with_stream_id(seq_num: i32, stream_id: u32) -> String {
+  format!("HELLO {seq_num} S={stream_id}")
+}
+
+/**
+ * Parses a HELLO message that may carry a nonce, e.g. `HELLO 6 N=42`
+ * Returns the sequence number and the nonce if one was given; other fields are rejected
+ */
+pub fn parse_hello_with_nonce(message: &str) -> Result<(i32, Option<u64>)> {
+  let validated = validate_hello_message(message)?;
+
+  if validated.stream_id.is_some() {
+    let offset = tokens(message).nth(2).map_or(0, |(offset, _)| offset);
+    return Err(HandshakeError::InvalidMessageFormat {
+      message: message.to_string(),
+      offset,
+      expected: "no stream ID",
+    });
+  }
+
+  Ok((validated.seq, validated.nonce))
+}
+
+/**
+ * Formats a HELLO message carrying a nonce
+ */
+pub fn format_hello_with_nonce(seq_num: i32, nonce: u64) -> String {
+  format!("HELLO {seq_num} N={nonce}")
+}
+
+/**
+ * Checks that the client echoed the nonce the server issued
+ */
+pub fn validate_nonce(expected: u64, received: Option<u64>) -> Result<()> {
+  if received == Some(expected) {
+    return Ok(());
+  }
+  Err(HandshakeError::NonceMismatch { expected, received })
+}
+
+/**
+ * Parses a HELLO message that may claim a source address, e.g. `HELLO 5 IP=1.2.3.4`
+ * Returns the sequence number and the claimed address if one was given; other fields are rejected
+ */
+pub fn parse_hello_with_claimed_ip(message: &str) -> Result<(i32, Option<IpAddr>)> {
+  let validated = validate_hello_message(message)?;
+
+  if validated.stream_id.is_some() || validated.nonce.is_some() {
+    let offset = tokens(message)
+      .skip(2)
+      .find(|(_, field)| !field.starts_with("IP="))
+      .map_or(0, |(offset, _)| offset);
+    return Err(HandshakeError::InvalidMessageFormat {
+      message: message.to_string(),
+      offset,
+      expected: "no field other than 'IP'",
+    });
+  }
+
+  Ok((validated.seq, validated.claimed_ip))
+}
+
+/**
+ * Formats a HELLO message claiming a source address
+ */
+pub fn format_hello_with_claimed_ip(seq_num: i32, claimed_ip: IpAddr) -> String {
+  format!("HELLO {seq_num} IP={claimed_ip}")
+}
+
+/**
+ * Checks the address a client claimed against the one it connected from
+ * IPv4-mapped IPv6 addresses match their IPv4 form
+ */
+pub fn verify_claimed_ip(claimed: IpAddr, actual: IpAddr) -> Result<()> {
+  if claimed.to_canonical() == actual.to_canonical() {
+    return Ok(());
+  }
+  Err(HandshakeError::AddressMismatch { claimed, actual })
+}
+
+/**
+ * Parses a HELLO message that may list capabilities, e.g. `HELLO 5 CAP=3`
+ * Returns the sequence number and the capabilities if given; other fields are rejected
+ */
+pub fn parse_hello_with_capabilities(message: &str) -> Result<(i32, Option<Capabilities>)> {
+  let validated = validate_hello_message(message)?;
+
+  if validated.stream_id.is_some() || validated.nonce.is_some() || validated.claimed_ip.is_some() {
+    let offset = tokens(message)
+      .skip(2)
+      .find(|(_, field)| !field.starts_with("CAP="))
+      .map_or(0, |(offset, _)| offset);
+    return Err(HandshakeError::InvalidMessageFormat {
+      message: message.to_string(),
+      offset,
+      expected: "no field other than 'CAP'",
+    });
+  }
+
+  Ok((validated.seq, validated.capabilities))
+}
+
+/**
+ * Formats a HELLO message listing capabilities
+ */
+pub fn format_hello_with_capabilities(seq_num: i32, capabilities: Capabilities) -> String {
+  format!("HELLO {seq_num} CAP={capabilities}")
+}
+
+/**
+ * Splits the `CAP=` field off a message when `config.capabilities` is set
+ * The rest of the message is left for the usual parsing; without negotiation it is untouched
+ */
+fn take_capabilities<'a>(
+  message: &'a str,
+  config: &HandshakeConfig,
+) -> Result<(Cow<'a, str>, Option<Capabilities>)> {
+  if config.capabilities.is_none() {
+    return Ok((Cow::Borrowed(message), None));
+  }
+  let capabilities = validate_hello_message(message)?.capabilities;
+  let rest: Vec<&str> = tokens(message)
+    .map(|(_, token)| token)
+    .filter(|token| !token.starts_with("CAP="))
+    .collect();
+  Ok((Cow::Owned(rest.join(" ")), capabilities))
+}
+
+/**
+ * Appends a `CAP=` field to an outgoing message when there is a set to send
+ */
+fn with_capabilities(message: String, capabilities: Option<Capabilities>) -> String {
+  match capabilities {
+    Some(capabilities) => format!("{message} CAP={capabilities}"),
+    None => message,
+  }
+}
+
+/**
+ * Server side: intersects the client's capabilities with `config.capabilities`
+ * Returns `None` when this server does not negotiate; a client that listed none shares nothing
+ */
+pub(crate) fn server_capabilities(
+  offered: Option<Capabilities>,
+  config: &HandshakeConfig,
+) -> Result<Option<Capabilities>> {
+  let Some(ours) = config.capabilities else {
+    return Ok(None);
+  };
+  let common = offered.unwrap_or_default() & ours;
+  require_capabilities(config.required_capabilities, common)?;
+  Ok(Some(common))
+}
+
+/**
+ * Client side: checks the common set the server answered with
+ * Returns `None` when this client does not negotiate; a server that answered none shares nothing
+ */
+fn client_capabilities(
+  answered: Option<Capabilities>,
+  config: &HandshakeConfig,
+) -> Result<Option<Capabilities>> {
+  let Some(ours) = config.capabilities else {
+    return Ok(None);
+  };
+  let common = answered.unwrap_or_default();
+  if !ours.contains(common) {
+    return Err(HandshakeError::ProtocolViolation(format!(
+      "server answered CAP={common}, beyond the offered CAP={ours}"
+    )));
+  }
+  require_capabilities(config.required_capabilities, common)?;
+  Ok(Some(common))
+}
+
+/**
+ * Prefixes an outgoing message with `config.namespace`, e.g. `MYAPP:HELLO 5`
+ */
+fn namespaced(message: String, config: &HandshakeConfig) -> String {
+  match &config.namespace {
+    Some(namespace) => format!("{namespace}:{message}"),
+    None => message,
+  }
+}
+
+/**
+ * Strips `config.namespace` from an incoming message
+ * A message from outside the namespace is a protocol violation
+ */
+pub(crate) fn strip_namespace<'a>(message: &'a str, config: &HandshakeConfig) -> Result<&'a str> {
+  let Some(namespace) = &config.namespace else {
+    return Ok(message);
+  };
+  message
+    .strip_prefix(namespace.as_str())
+    .and_then(|rest| rest.strip_prefix(':'))
+    .ok_or_else(|| {
+      HandshakeError::ProtocolViolation(format!(
+        "message '{message}' is outside namespace '{namespace}'"
+      ))
+    })
+}
+
+/**
+ * The server's HELLO Y, or `config.response_template` filled in when one is set
+ * A fresh nonce is issued when `config.require_nonce` is set or the template uses `{nonce}`,
+ * and `capabilities` is appended as a `CAP=` field when given
+ */
+pub(crate) fn server_reply(
+  server_seq: i32,
+  capabilities: Option<Capabilities>,
+  config: &HandshakeConfig,
+) -> (String, Option<u64>) {
+  let template = config.response_template.as_ref();
+  let issue_nonce = config.require_nonce || template.is_some_and(|t| t.uses_nonce());
+  let nonce = issue_nonce.then(rand::random::<u64>);
+  let reply = match (template, nonce) {
+    (Some(template), _) => template.render(server_seq, nonce),
+    (None, Some(nonce)) => format_hello_with_nonce(server_seq, nonce),
+    (None, None) => format_hello_message(server_seq),
+  };
+  let reply = namespaced(with_capabilities(reply, capabilities), config);
+  check_sent(config, &reply, server_seq);
+  (reply, nonce)
+}
+
+/**
+ * Parses the client's HELLO Z, checking that it echoes the nonce if one was issued
+ */
+pub(crate) fn parse_final_message(
+  message: &str,
+  nonce: Option<u64>,
+  config: &HandshakeConfig,
+) -> Result<i32> {
+  let message = strip_namespace(message, config)?;
+  let Some(expected) = nonce else {
+    return parse_plain_hello(message, config);
+  };
+  let (seq, received) = parse_hello_with_nonce(message)?;
+  validate_nonce(expected, received)?;
+  Ok(seq)
+}
+
+/**
+ * The client's HELLO Z, echoing the server's nonce if it sent one
+ */
+pub(crate) fn client_final_message(
+  final_seq: i32,
+  nonce: Option<u64>,
+  config: &HandshakeConfig,
+) -> String {
+  let message = match nonce {
+    Some(nonce) => format_hello_with_nonce(final_seq, nonce),
+    None => format_hello_message(final_seq),
+  };
+  let message = namespaced(message, config);
+  check_sent(config, &message, final_seq);
+  message
+}
+
+/**
+ * Picks a random initial sequence in `0..=MAX_RANDOM_INITIAL_SEQ` from `rng`
+ * Clients pass `rand::rng()`; tests can pass a seeded RNG for a reproducible chain
+ */
+pub fn random_initial_seq<R: RngCore + ?Sized>(rng: &mut R) -> i32 {
+  rng.random_range(0..=MAX_RANDOM_INITIAL_SEQ)
+}
+
+/**
+ * Client side: checks HELLO Y against our X
+ * Returns Y, the nonce to echo if the server sent one, and the negotiated capabilities
+ */
+pub(crate) fn parse_server_reply(
+  message: &str,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<(i32, Option<u64>, Option<Capabilities>)> {
+  check_server_busy(message)?;
+  let (reply, answered) = take_capabilities(strip_namespace(message, config)?, config)?;
+  let (received_seq, nonce) = parse_hello_with_nonce(&reply)?;
+  check_server_reply(config.sequence_policy.as_ref(), initial_seq, received_seq)?;
+  let capabilities = client_capabilities(answered, config)?;
+  Ok((received_seq, nonce, capabilities))
+}
+
+/**
+ * Fails with `ServerBusy` or `ServerOverloaded` when the server refused instead of sending HELLO Y
+ */
+fn check_server_busy(message: &str) -> Result<()> {
+  match message.trim() {
+    BUSY_MESSAGE => Err(HandshakeError::ServerBusy),
+    OVERLOADED_MESSAGE => Err(HandshakeError::ServerOverloaded),
+    _ => Ok(()),
+  }
+}
+
+/**
+ * Checks the server's reply against our initial sequence (expects Y = X + 1)
+ * An exact echo of X gets its own error since it hints at a loopback bug
+ */
+pub fn validate_server_reply(initial_seq: i32, received_seq: i32) -> Result<()> {
+  check_server_reply(&IncrementPolicy, initial_seq, received_seq)
+}
+
+/**
+ * `validate_server_reply` under `policy`, which says what Y should be
+ */
+fn check_server_reply(
+  policy: &dyn SequencePolicy,
+  initial_seq: i32,
+  received_seq: i32,
+) -> Result<()> {
+  let expected_seq = next_seq(policy, initial_seq)?;
+
+  if policy.validate(expected_seq, received_seq) {
+    return Ok(());
+  }
+
+  if received_seq == initial_seq {
+    return Err(HandshakeError::EchoDetected { seq: received_seq });
+  }
+
+  Err(HandshakeError::SequenceMismatch {
+    expected: expected_seq,
+    received: received_seq,
+  })
+}
+
 /**
  * Reads a message from TCP stream with timeout
+ * Reads interrupted by a signal are retried up to `MAX_INTERRUPTED_RETRIES` times,
+ * and a non-blocking stream is polled for up to `READ_TIMEOUT`
+ */
+pub fn read_message_from_stream<S: Read>(stream: &mut S) -> Result<String> {
+  let mut buffer = [0u8; MSG_SIZE];
+  let bytes_read = read_into_buffer(stream, &mut buffer, Instant::now() + READ_TIMEOUT)?;
+  Ok(decode_sync_message(&buffer[..bytes_read]))
+}
+
+/**
+ * Sync read of one message framed by `config.delimiter`
+ *
+ * `pending` holds bytes already consumed from the stream, such as a prefix
+ * read to classify the protocol, and keeps any bytes read past a delimiter.
+ * Fixed buffer reads also apply `config.detect_read_overflow`, and a prefix
+ * that already holds a whole message is returned without reading.
+ *
+ * The read gives up with `Timeout` once the stream's read timeout, or
+ * `config.read_timeout` if it has none, has passed. This also bounds a
+ * non-blocking stream, which is polled until data arrives.
  */
-pub fn read_message_from_stream(stream: &mut TcpStream) -> Result<String> {
+pub(crate) fn read_sync_message(
+  stream: &mut TcpStream,
+  pending: &mut GrowableBuffer,
+  config: &HandshakeConfig,
+) -> Result<String> {
+  let wait = stream.read_timeout()?.unwrap_or(config.read_timeout);
+  let deadline = Instant::now() + wait;
+  let delimiter = config.delimiter.as_bytes();
+  if !delimiter.is_empty() {
+    let message =
+      read_until_delimiter_before(stream, delimiter, pending, config.max_line_len, deadline)?;
+    config.record_message_size(message.len());
+    return Ok(decode_sync_message(&message));
+  }
+
+  let prefix = pending.take_all();
+  if prefix_is_complete(&prefix, config)? {
+    config.record_message_size(prefix.len());
+    return Ok(decode_sync_message(&prefix));
+  }
+
   let mut buffer = [0u8; MSG_SIZE];
+  buffer[..prefix.len()].copy_from_slice(&prefix);
+  let bytes_read = prefix.len() + read_into_buffer(stream, &mut buffer[prefix.len()..], deadline)?;
+  config.record_message_size(bytes_read);
+  check_read_len(config, bytes_read, MSG_SIZE);
+  if config.detect_read_overflow && bytes_read == MSG_SIZE && has_buffered_data(stream)? {
+    return Err(read_overflow());
+  }
+  Ok(decode_sync_message(&buffer[..bytes_read]))
+}
+
+/**
+ * Whether bytes read before the handshake started already form the first message
+ *
+ * Messages are not delimited, so a prefix that parses as HELLO, optional
+ * fields such as `CAP=` included, is taken as complete, just as if it had
+ * arrived in one read. Longer prefixes than `MSG_SIZE` are rejected.
+ */
+fn prefix_is_complete(prefix: &[u8], config: &HandshakeConfig) -> Result<bool> {
+  if prefix.len() > MSG_SIZE {
+    return Err(read_overflow());
+  }
+  let message = decode_sync_message(prefix);
+  Ok(
+    prefix.len() == MSG_SIZE
+      || strip_namespace(&message, config).is_ok_and(|hello| {
+        parse_plain_hello(hello, config).is_ok() || validate_hello_message(hello).is_ok()
+      }),
+  )
+}
 
-  let bytes_read = stream.read(&mut buffer)?;
+/**
+ * Fills `buffer` with one read, retrying EINTR and treating EOF as a disconnect
+ *
+ * `WouldBlock` comes from a non-blocking stream with no data yet, or from a
+ * read timeout expiring. Either way the read is retried every
+ * `WOULD_BLOCK_POLL_INTERVAL` until `deadline`, then fails with `Timeout`.
+ */
+pub(crate) fn read_into_buffer<S: Read>(
+  stream: &mut S,
+  buffer: &mut [u8],
+  deadline: Instant,
+) -> Result<usize> {
+  let mut retries = 0;
+  let bytes_read = loop {
+    match stream.read(buffer) {
+      Ok(bytes_read) => break bytes_read,
+      Err(e) if e.kind() == ErrorKind::Interrupted && retries < MAX_INTERRUPTED_RETRIES => {
+        retries += 1;
+      }
+      Err(e) if e.kind() == ErrorKind::WouldBlock => {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+          return Err(HandshakeError::Timeout);
+        }
+        thread::sleep(remaining.min(WOULD_BLOCK_POLL_INTERVAL));
+      }
+      Err(e) => return Err(e.into()),
+    }
+  };
   if bytes_read == 0 {
     return Err(HandshakeError::ClientDisconnected);
   }
+  Ok(bytes_read)
+}
+
+pub(crate) fn decode_sync_message(bytes: &[u8]) -> String {
+  let message = String::from_utf8_lossy(bytes);
+  message.trim_end_matches('\0').to_string()
+}
 
-  let message = String::from_utf8_lossy(&buffer[..bytes_read]);
-  let message = message.trim_end_matches('\0');
+/**
+ * Peeks without blocking to see whether the peer already sent more than was read
+ */
+fn has_buffered_data(stream: &TcpStream) -> Result<bool> {
+  let mut probe = [0u8; 1];
+  stream.set_nonblocking(true)?;
+  let peeked = stream.peek(&mut probe);
+  stream.set_nonblocking(false)?;
+  match peeked {
+    Ok(bytes) => Ok(bytes > 0),
+    Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+    Err(e) => Err(e.into()),
+  }
+}
 
-  Ok(message.to_string())
+pub(crate) fn read_overflow() -> HandshakeError {
+  HandshakeError::ProtocolViolation(format!("message exceeds the {MSG_SIZE}-byte buffer"))
 }
 
 /**
  * Writes a message to TCP stream
+ * Writes interrupted by a signal are retried up to `MAX_INTERRUPTED_RETRIES` times
+ * An empty message is rejected with `InvalidMessageFormat` before anything is written
+ */
+pub fn write_message_to_stream<S: Write>(stream: &mut S, message: &str) -> Result<()> {
+  reject_empty_message(message)?;
+  write_bytes_to_stream(stream, message.as_bytes())
+}
+
+/**
+ * Sync write of one message framed by `config.delimiter`
+ * One write call with `config.coalesce_writes`, otherwise one for the message and one for the delimiter
  */
-pub fn write_message_to_stream(stream: &mut TcpStream, message: &str) -> Result<()> {
-  stream.write_all(message.as_bytes())?;
+pub(crate) fn write_sync_message<S: Write>(
+  stream: &mut S,
+  message: &str,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  reject_empty_message(message)?;
+  let message = &pad_message(message, config);
+  if config.coalesce_writes {
+    let framed = config.delimiter.frame(message);
+    write_bytes_to_stream(stream, &framed)?;
+  } else {
+    write_bytes_to_stream(stream, message.as_bytes())?;
+    write_bytes_to_stream(stream, config.delimiter.as_bytes())?;
+  }
+  config.record_bytes_sent(message.len() + config.delimiter.as_bytes().len());
   Ok(())
 }
 
+/**
+ * Refuses to send an empty message
+ * Writing nothing would make the peer's read return 0 bytes, which looks like a disconnect
+ */
+fn reject_empty_message(message: &str) -> Result<()> {
+  if !message.is_empty() {
+    return Ok(());
+  }
+  Err(HandshakeError::InvalidMessageFormat {
+    message: String::new(),
+    offset: 0,
+    expected: "non-empty message",
+  })
+}
+
+fn write_bytes_to_stream<S: Write>(stream: &mut S, bytes: &[u8]) -> Result<()> {
+  let mut remaining = bytes;
+  let mut retries = 0;
+
+  while !remaining.is_empty() {
+    match stream.write(remaining) {
+      Ok(0) => return Err(std::io::Error::from(ErrorKind::WriteZero).into()),
+      Ok(written) => remaining = &remaining[written..],
+      Err(e) if e.kind() == ErrorKind::Interrupted && retries < MAX_INTERRUPTED_RETRIES => {
+        retries += 1;
+      }
+      Err(e) => return Err(e.into()),
+    }
+  }
+  Ok(())
+}
+
+/**
+ * Writes a message to TCP stream as the handshakes do with `config`
+ * Null-padded to `MSG_SIZE` with `config.pad_to_buffer` and framed by `config.delimiter`
+ */
+pub fn write_message_to_stream_with_config<S: Write>(
+  stream: &mut S,
+  message: &str,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  write_sync_message(stream, message, config)
+}
+
+/**
+ * The message as it goes on the wire: null-padded to `MSG_SIZE` with `config.pad_to_buffer`
+ * Delimited messages and messages already `MSG_SIZE` bytes or longer are left alone
+ */
+pub(crate) fn pad_message<'m>(message: &'m str, config: &HandshakeConfig) -> Cow<'m, str> {
+  if !config.pad_to_buffer || config.delimiter != Delimiter::None || message.len() >= MSG_SIZE {
+    return Cow::Borrowed(message);
+  }
+  let mut padded = String::with_capacity(MSG_SIZE);
+  padded.push_str(message);
+  padded.extend(std::iter::repeat_n('\0', MSG_SIZE - message.len()));
+  Cow::Owned(padded)
+}
+
 /**
  * Async version: Reads a message from TCP stream with timeout
  */
-pub async fn read_message_from_async_stream(stream: &mut AsyncTcpStream) -> Result<String> {
+pub async fn read_message_from_async_stream<S: AsyncRead + Unpin>(
+  stream: &mut S,
+) -> Result<String> {
+  read_async_message(
+    stream,
+    &mut GrowableBuffer::default(),
+    &HandshakeConfig::default(),
+  )
+  .await
+}
+
+/**
+ * Async read of one message framed by `config.delimiter`
+ * Bounded by the configured read timeout and clock; `pending` is as for `read_sync_message`
+ */
+pub(crate) async fn read_async_message<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  pending: &mut GrowableBuffer,
+  config: &HandshakeConfig,
+) -> Result<String> {
+  read_async_message_waiting(stream, pending, config, None).await
+}
+
+/**
+ * Async read of one message, allowing only `first_byte` for the first read when nothing is pending
+ * A peer that sends nothing in that time fails with `FirstByteTimeout`
+ */
+async fn read_async_message_waiting<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  pending: &mut GrowableBuffer,
+  config: &HandshakeConfig,
+  first_byte: Option<Duration>,
+) -> Result<String> {
+  let first_byte = first_byte.filter(|_| pending.is_empty());
+  let delimiter = config.delimiter.as_bytes();
+  if !delimiter.is_empty() {
+    if let Some(wait) = first_byte {
+      let mut buffer = [0u8; MSG_SIZE];
+      let bytes_read = first_byte_read(stream, &mut buffer, wait, config).await?;
+      pending.extend_from_slice(&buffer[..bytes_read]);
+    }
+    let message = timeout(
+      config.clock.as_ref(),
+      config.read_timeout,
+      read_until_delimiter_async_within(stream, delimiter, pending, config.max_line_len),
+    )
+    .await??;
+    config.record_message_size(message.len());
+    return Ok(decode_async_message(&message));
+  }
+
+  let prefix = pending.take_all();
+  if prefix_is_complete(&prefix, config)? {
+    config.record_message_size(prefix.len());
+    return Ok(decode_async_message(&prefix));
+  }
+
   let mut buffer = [0u8; MSG_SIZE];
+  buffer[..prefix.len()].copy_from_slice(&prefix);
+  let bytes_read = match first_byte {
+    Some(wait) => first_byte_read(stream, &mut buffer, wait, config).await?,
+    None => {
+      let bytes_read = timeout(
+        config.clock.as_ref(),
+        config.read_timeout,
+        stream.read(&mut buffer[prefix.len()..]),
+      )
+      .await?
+      .map_err(HandshakeError::Io)?;
+      if bytes_read == 0 {
+        return Err(HandshakeError::ClientDisconnected);
+      }
+      prefix.len() + bytes_read
+    }
+  };
+  config.record_message_size(bytes_read);
+  check_read_len(config, bytes_read, MSG_SIZE);
+  if config.detect_read_overflow && bytes_read == MSG_SIZE && has_ready_data(stream).await? {
+    return Err(read_overflow());
+  }
 
-  let bytes_read = timeout(READ_TIMEOUT, stream.read(&mut buffer))
-    .await
-    .map_err(|_| HandshakeError::Timeout)?
-    .map_err(HandshakeError::Io)?;
+  Ok(decode_async_message(&buffer[..bytes_read]))
+}
 
+/**
+ * One read that must start within `wait`, failing with `FirstByteTimeout` otherwise
+ */
+async fn first_byte_read<S: AsyncRead + Unpin>(
+  stream: &mut S,
+  buffer: &mut [u8],
+  wait: Duration,
+  config: &HandshakeConfig,
+) -> Result<usize> {
+  let bytes_read = match timeout(config.clock.as_ref(), wait, stream.read(buffer)).await {
+    Ok(read) => read?,
+    Err(HandshakeError::Timeout) => return Err(HandshakeError::FirstByteTimeout),
+    Err(e) => return Err(e),
+  };
   if bytes_read == 0 {
     return Err(HandshakeError::ClientDisconnected);
   }
+  Ok(bytes_read)
+}
 
-  let message = String::from_utf8_lossy(&buffer[..bytes_read]);
-  let message = message.trim_end_matches('\0').trim();
+/**
+ * Sync version: Waits up to `wait` for the peer's first byte without consuming it
+ * Restores `config.read_timeout` on the stream afterwards
+ */
+fn wait_for_first_byte(stream: &TcpStream, wait: Duration, config: &HandshakeConfig) -> Result<()> {
+  stream.set_read_timeout(Some(wait))?;
+  let deadline = Instant::now() + wait;
+  let mut retries = 0;
+  let result = loop {
+    match stream.peek(&mut [0u8; 1]) {
+      Ok(0) => break Err(HandshakeError::ClientDisconnected),
+      Ok(_) => break Ok(()),
+      Err(e) if e.kind() == ErrorKind::Interrupted && retries < MAX_INTERRUPTED_RETRIES => {
+        retries += 1;
+      }
+      Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+          break Err(HandshakeError::FirstByteTimeout);
+        }
+        thread::sleep(remaining.min(WOULD_BLOCK_POLL_INTERVAL));
+      }
+      Err(e) => break Err(e.into()),
+    }
+  };
+  stream.set_read_timeout(Some(config.read_timeout))?;
+  result
+}
+
+fn decode_async_message(bytes: &[u8]) -> String {
+  let message = String::from_utf8_lossy(bytes);
+  message.trim_end_matches('\0').trim().to_string()
+}
 
-  Ok(message.to_string())
+/**
+ * Polls the stream once to see whether more data is ready without waiting for it
+ * A byte that is ready gets consumed, which is fine since the caller fails the read anyway
+ */
+async fn has_ready_data<S: AsyncRead + Unpin>(stream: &mut S) -> Result<bool> {
+  let mut probe = [0u8; 1];
+  let mut probe = ReadBuf::new(&mut probe);
+  let ready = future::poll_fn(
+    |cx| match Pin::new(&mut *stream).poll_read(cx, &mut probe) {
+      Poll::Ready(result) => Poll::Ready(result.map(|()| !probe.filled().is_empty())),
+      Poll::Pending => Poll::Ready(Ok(false)),
+    },
+  )
+  .await?;
+  Ok(ready)
 }
 
 /**
  * Async version: Writes a message to TCP stream
+ * An empty message is rejected with `InvalidMessageFormat` before anything is written
+ */
+pub async fn write_message_to_async_stream<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  message: &str,
+) -> Result<()> {
+  write_async_message(stream, message, &HandshakeConfig::default()).await
+}
+
+/**
+ * Whether a write error is worth retrying
+ * Broken or reset connections are permanent and fail immediately
+ */
+pub fn is_transient(error: &std::io::Error) -> bool {
+  matches!(
+    error.kind(),
+    ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut
+  )
+}
+
+/**
+ * Whether a read error is the peer resetting the connection
+ * Callers that already received every required message treat it like a clean close
+ */
+pub(crate) fn is_reset(error: &std::io::Error) -> bool {
+  error.kind() == ErrorKind::ConnectionReset
+}
+
+/**
+ * Async write that retries transient errors up to `config.write_retries` times
+ * Waits `config.write_retry_delay` on `config.clock` between attempts
+ */
+pub(crate) async fn write_async_message<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  message: &str,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  reject_empty_message(message)?;
+  write_async_bytes(stream, message.as_bytes(), config).await?;
+  config.record_bytes_sent(message.len());
+  Ok(())
+}
+
+/**
+ * Async write of one message framed by `config.delimiter`
+ * Written in one call unless `config.coalesce_writes` is off, as for `write_sync_message`
  */
-pub async fn write_message_to_async_stream(
-  stream: &mut AsyncTcpStream,
+pub(crate) async fn write_async_framed<S: AsyncWrite + Unpin>(
+  stream: &mut S,
   message: &str,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  reject_empty_message(message)?;
+  let message = &pad_message(message, config);
+  if config.coalesce_writes {
+    let framed = config.delimiter.frame(message);
+    write_async_bytes(stream, &framed, config).await?;
+  } else {
+    write_async_bytes(stream, message.as_bytes(), config).await?;
+    write_async_bytes(stream, config.delimiter.as_bytes(), config).await?;
+  }
+  config.record_bytes_sent(message.len() + config.delimiter.as_bytes().len());
+  Ok(())
+}
+
+pub(crate) async fn write_async_bytes<S: AsyncWrite + Unpin>(
+  stream: &mut S,
+  bytes: &[u8],
+  config: &HandshakeConfig,
 ) -> Result<()> {
-  stream.write_all(message.as_bytes()).await?;
+  let mut remaining = bytes;
+  let mut retries = 0;
+
+  while !remaining.is_empty() {
+    match stream.write(remaining).await {
+      Ok(0) => return Err(std::io::Error::from(ErrorKind::WriteZero).into()),
+      Ok(written) => remaining = &remaining[written..],
+      Err(e) if is_transient(&e) && retries < config.write_retries => {
+        retries += 1;
+        config.clock.sleep(config.write_retry_delay).await;
+      }
+      Err(e) => return Err(e.into()),
+    }
+  }
   Ok(())
 }
 
+/**
+ * The client's first message: HELLO X, or the raw override when testing the server
+ * An empty override sends nothing, for a server that was handed HELLO X as a prefix
+ */
+pub(crate) fn first_client_message(initial_seq: i32, config: &HandshakeConfig) -> String {
+  let message = match (&config.raw_first_message, config.claimed_ip) {
+    (Some(raw), _) => return raw.clone(),
+    (None, Some(ip)) => format_hello_with_claimed_ip(initial_seq, ip),
+    (None, None) => format_hello_message(initial_seq),
+  };
+  let message = namespaced(with_capabilities(message, config.capabilities), config);
+  check_sent(config, &message, initial_seq);
+  message
+}
+
+/**
+ * Parses the client's HELLO X, checking any `IP=` claim when `config.verify_client_ip` is set
+ * `peer_ip` is only asked for when there is a claim to check against
+ * Also returns the capabilities the client listed, when `config.capabilities` is set
+ */
+pub(crate) fn parse_client_hello(
+  message: &str,
+  peer_ip: impl FnOnce() -> Option<IpAddr>,
+  config: &HandshakeConfig,
+) -> Result<(i32, Option<Capabilities>)> {
+  let (message, offered) = take_capabilities(strip_namespace(message, config)?, config)?;
+  if !config.verify_client_ip {
+    return Ok((parse_plain_hello(&message, config)?, offered));
+  }
+
+  let (client_seq, claimed) = parse_hello_with_claimed_ip(&message)?;
+  let Some(claimed) = claimed else {
+    return Ok((client_seq, offered));
+  };
+  let Some(actual) = peer_ip() else {
+    errln!(
+      config,
+      "WARNING: Cannot verify claimed IP={claimed}, peer address unknown"
+    );
+    return Ok((client_seq, offered));
+  };
+  if let Err(mismatch) = verify_claimed_ip(claimed, actual) {
+    errln!(config, "WARNING: {mismatch}");
+    if config.strict_client_ip {
+      return Err(mismatch);
+    }
+  }
+  Ok((client_seq, offered))
+}
+
 /**
  * Async version: Performs client-side 3-way handshake
  */
 pub async fn perform_async_client_handshake(
-  mut stream: AsyncTcpStream,
+  stream: AsyncTcpStream,
   initial_seq: i32,
-) -> Result<()> {
+) -> Result<HandshakeOutcome> {
+  perform_async_client_handshake_on(stream, initial_seq, &HandshakeConfig::default()).await
+}
+
+/**
+ * Async version: Performs client-side 3-way handshake over a transport the caller brings
+ *
+ * `S` can be any ordered, reliable byte stream, such as a QUIC stream, an
+ * in-memory pipe or a compression wrapper around a socket. It must be
+ * `AsyncRead + AsyncWrite` to exchange the messages and `Unpin` so it can be
+ * polled through a plain `&mut` borrow across await points.
+ *
+ * The caller owns connection setup and teardown: the handshake neither
+ * connects nor shuts the stream down. An owned stream is dropped on return;
+ * pass `&mut stream` instead to keep using it afterwards.
+ */
+pub async fn perform_async_client_handshake_on<S: AsyncRead + AsyncWrite + Unpin>(
+  mut stream: S,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  perform_async_client_handshake_with_config(&mut stream, initial_seq, config).await
+}
+
+/**
+ * Async version: Connects to `host` and performs client-side 3-way handshake
+ *
+ * Connects as `connect_async` does, then runs the handshake over the new
+ * stream and returns both. The outcome's `connect_phases` holds the DNS and
+ * connect times next to the handshake's own `duration`, and all three are
+ * logged, so a slow connection can be pinned on the phase that was slow.
+ */
+pub async fn perform_async_client_handshake_to(
+  host: &str,
+  port: u16,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<(AsyncTcpStream, HandshakeOutcome)> {
+  let (mut stream, phases) = connect_async_timed(host, port, config).await?;
+  let mut outcome =
+    perform_async_client_handshake_with_config(&mut stream, initial_seq, config).await?;
+  outln!(
+    config,
+    "Phases: DNS {:?}, connect {:?}, handshake {:?}",
+    phases.dns,
+    phases.connect,
+    outcome.duration
+  );
+  outcome.connect_phases = Some(phases);
+  Ok((stream, outcome))
+}
+
+/**
+ * Async version: Performs client-side 3-way handshake using the given config
+ * Works over any async byte stream (TCP, Unix socket, ...)
+ * All timeouts are measured by `config.clock`; the stream stays open for the caller
+ */
+pub async fn perform_async_client_handshake_with_config<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut log = HandshakeLog::default();
+  let mut pending = GrowableBuffer::default();
+  let mut machine = HandshakeMachine::new(Role::Client { initial_seq }, Cow::Borrowed(config));
+
   // Wrap entire handshake in timeout
-  let result = timeout(CLIENT_CONNECTION_TIMEOUT, async {
+  timeout(clock, config.client_connection_timeout, async {
+    // Answer the server's greeting first when a preamble is configured
+    if let Some(preamble) = &config.preamble {
+      client_preamble_async(stream, &mut pending, preamble, config).await?;
+    }
+
     // Step 1: Send HELLO X where X is initial sequence
-    let first_message = format_hello_message(initial_seq);
-    write_message_to_async_stream(&mut stream, &first_message).await?;
-    println!("Sent: {first_message}");
+    if let Some(first_message) = machine.poll_message() {
+      write_async_framed(stream, &first_message, config).await?;
+      config.emit_event(1, Direction::Outbound, &first_message, steps.finish(1));
+      logln!(log, config, "Sent: {first_message}");
+    }
 
-    // Step 2: Receive HELLO Y and validate Y = X + 1
-    let received_msg = read_message_from_async_stream(&mut stream).await?;
+    // Step 2: Receive HELLO Y and validate that Y follows X
+    let received_msg = read_async_message(stream, &mut pending, config).await?;
+    config.emit_event(2, Direction::Inbound, &received_msg, steps.finish(2));
+    let rtt = clock.now().duration_since(started);
 
     // Print received message to stdout
-    println!("Received: {received_msg}");
-    std::io::Write::flush(&mut std::io::stdout())?;
-
+    logln!(log, config, "Received: {received_msg}");
     // Parse and validate
-    let received_seq = parse_hello_message(&received_msg)?;
-    let expected_seq = initial_seq + 1;
-
-    if received_seq != expected_seq {
-      return Err(HandshakeError::SequenceMismatch {
-        expected: expected_seq,
-        received: received_seq,
-      });
-    }
+    machine.handle_message(&received_msg)?;
 
-    // Step 3: Send HELLO Z where Z = Y + 1
-    let final_seq = received_seq + 1;
-    let final_message = format_hello_message(final_seq);
-    write_message_to_async_stream(&mut stream, &final_message).await?;
-    println!("Sent: {final_message}");
+    // Step 3: Send HELLO Z where Z follows Y
+    let final_message = machine
+      .poll_message()
+      .expect("HELLO Z follows a valid HELLO Y");
+    write_async_framed(stream, &final_message, config).await?;
+    config.emit_event(3, Direction::Outbound, &final_message, steps.finish(3));
+    logln!(log, config, "Sent: {final_message}");
 
-    println!("Handshake completed successfully!");
-    Ok::<(), HandshakeError>(())
+    logln!(log, config, "Handshake completed successfully!");
+    Ok(timed_outcome(
+      &machine,
+      rtt,
+      clock.now().duration_since(started),
+      &steps,
+      &log,
+    ))
   })
-  .await
-  .map_err(|_| HandshakeError::Timeout)?;
+  .await?
+}
 
-  result
+/**
+ * Races a single handshake step against an optional cancellation token
+ * Returns `HandshakeError::Aborted` as soon as the token is cancelled
+ */
+pub(crate) async fn cancellable<T>(
+  cancel: Option<&CancellationToken>,
+  step: impl Future<Output = Result<T>>,
+) -> Result<T> {
+  match cancel {
+    Some(token) => tokio::select! {
+      _ = token.cancelled() => Err(HandshakeError::Aborted),
+      result = step => result,
+    },
+    None => step.await,
+  }
 }
 
 /**
  * Async version: Performs server-side 3-way handshake
+ *
+ * When a cancellation token is supplied, every await point is raced against it
+ * and the connection is shut down cleanly before returning `Aborted`
  */
 pub async fn perform_async_server_handshake(
   mut stream: AsyncTcpStream,
   peer_addr: std::net::SocketAddr,
-) -> Result<()> {
-  println!("Handling connection from {peer_addr}");
+  cancel: Option<&CancellationToken>,
+) -> Result<HandshakeOutcome> {
+  perform_async_server_handshake_with_config(
+    &mut stream,
+    peer_addr,
+    cancel,
+    &HandshakeConfig::default(),
+  )
+  .await
+}
+
+/**
+ * Async version: Performs server-side 3-way handshake using the given config
+ * Works over any async byte stream; `peer_addr` is only used for logging
+ * All timeouts are measured by `config.clock`
+ */
+pub async fn perform_async_server_handshake_with_config<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  cancel: Option<&CancellationToken>,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut pending = handshake_buffer(&[], config);
+  async_server_handshake(
+    stream,
+    &mut pending,
+    &mut MessageCounter::default(),
+    peer_addr,
+    cancel,
+    config,
+  )
+  .await
+}
+
+/**
+ * Async version: Performs server-side 3-way handshake after some bytes were already read
+ * `already_read` is taken as the start of HELLO X, e.g. bytes consumed to classify the protocol
+ */
+pub async fn perform_async_server_handshake_with_prefix<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  already_read: &[u8],
+  peer_addr: impl fmt::Display,
+  cancel: Option<&CancellationToken>,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut pending = handshake_buffer(already_read, config);
+  async_server_handshake(
+    stream,
+    &mut pending,
+    &mut MessageCounter::default(),
+    peer_addr,
+    cancel,
+    config,
+  )
+  .await
+}
+
+/**
+ * Async server side over the connection's unread bytes and message count
+ * A persistent connection passes the same ones to every handshake it runs
+ */
+pub(crate) async fn async_server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  pending: &mut GrowableBuffer,
+  messages: &mut MessageCounter,
+  peer_addr: impl fmt::Display,
+  cancel: Option<&CancellationToken>,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut log = HandshakeLog::default();
+  logln!(log, config, "Handling connection from {peer_addr}");
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut machine = HandshakeMachine::new(Role::Server, Cow::Borrowed(config));
 
   // Wrap the entire handshake in a timeout to prevent hanging connections
-  let result = timeout(CONNECTION_TIMEOUT, async {
-    // Step 1: Receive HELLO X
-    let received_msg = read_message_from_async_stream(&mut stream).await?;
+  let result = timeout(clock, config.connection_timeout, async {
+    // Greet the client first when a preamble is configured
+    if let Some(preamble) = &config.preamble {
+      cancellable(
+        cancel,
+        server_preamble_async(stream, pending, preamble, config),
+      )
+      .await?;
+      messages.count(config)?;
+    }
+
+    // Step 1: Receive HELLO X, shedding a peer that stays silent
+    let received_msg = cancellable(
+      cancel,
+      read_async_message_waiting(stream, pending, config, config.first_byte_timeout),
+    )
+    .await?;
+    messages.count(config)?;
+    config.emit_event(1, Direction::Inbound, &received_msg, steps.finish(1));
 
     // Print received message
-    println!("Received from {peer_addr}: {received_msg}");
-    std::io::Write::flush(&mut std::io::stdout())?;
+    logln!(log, config, "Received from {peer_addr}: {received_msg}");
 
-    // Parse the client's sequence number
-    let client_seq = parse_hello_message(&received_msg)?;
+    // Parse the client's sequence number, checking any claimed address against the peer's
+    let peer_ip = || {
+      let peer_addr = peer_addr.to_string();
+      match peer_addr.parse::<SocketAddr>() {
+        Ok(addr) => Some(addr.ip()),
+        Err(_) => peer_addr.parse::<IpAddr>().ok(),
+      }
+    };
+    machine.handle_message_from(&received_msg, peer_ip)?;
 
-    // Step 2: Send HELLO Y where Y = X + 1
-    let server_seq = client_seq + 1;
-    let response = format_hello_message(server_seq);
-    write_message_to_async_stream(&mut stream, &response).await?;
-    println!("Sent to {peer_addr}: {response}");
+    // Step 2: Send HELLO Y where Y follows X
+    let response = machine
+      .poll_message()
+      .expect("HELLO Y follows a valid HELLO X");
+    match &config.reply_strategy {
+      ReplyStrategy::Immediate => {
+        cancellable(cancel, write_async_framed(stream, &response, config)).await?;
+      }
+      // Framed into one buffer while it waits, so the released reply is a single write
+      ReplyStrategy::Batched(queue) => {
+        let framed = config.delimiter.frame(&pad_message(&response, config));
+        cancellable(cancel, async {
+          queue.wait_turn(&config.clock).await;
+          write_async_bytes(stream, &framed, config).await
+        })
+        .await?;
+        config.record_bytes_sent(framed.len());
+      }
+    }
+    config.emit_event(2, Direction::Outbound, &response, steps.finish(2));
+    logln!(log, config, "Sent to {peer_addr}: {response}");
+    let replied = clock.now();
 
-    // Step 3: Receive HELLO Z and validate Z = Y + 1
-    let final_msg = read_message_from_async_stream(&mut stream).await?;
+    // Step 3: Receive HELLO Z and validate that Z follows Y
+    let final_msg = cancellable(cancel, read_async_message(stream, pending, config)).await?;
+    messages.count(config)?;
+    config.emit_event(3, Direction::Inbound, &final_msg, steps.finish(3));
+    let rtt = clock.now().duration_since(replied);
 
     // Print received message
-    println!("Received from {peer_addr}: {final_msg}");
-    std::io::Write::flush(&mut std::io::stdout())?;
+    logln!(log, config, "Received from {peer_addr}: {final_msg}");
 
     // Parse and validate final sequence number
-    let final_seq = parse_hello_message(&final_msg)?;
-    let expected_final = server_seq + 1;
+    machine.handle_message(&final_msg)?;
 
-    if final_seq != expected_final {
-      eprintln!(
-        "ERROR: Expected HELLO {expected_final}, received HELLO {final_seq} from {peer_addr}"
-      );
-    }
-
-    println!("Handshake completed successfully with {peer_addr}");
-    Ok::<(), HandshakeError>(())
+    logln!(
+      log,
+      config,
+      "Handshake completed successfully with {peer_addr}"
+    );
+    Ok(timed_outcome(
+      &machine,
+      rtt,
+      clock.now().duration_since(started),
+      &steps,
+      &log,
+    ))
   })
-  .await
-  .map_err(|_| HandshakeError::Timeout)?;
+  .await?;
+
+  if let Err(HandshakeError::Aborted) = result {
+    // Close our side gracefully rather than leaving the peer to time out
+    let _ = stream.shutdown().await;
+  }
 
   result
 }
@@ -204,76 +1331,227 @@ pub async fn perform_async_server_handshake(
 /**
  * Performs client-side 3-way handshake
  */
-pub fn perform_client_handshake(mut stream: TcpStream, initial_seq: i32) -> Result<()> {
+pub fn perform_client_handshake(
+  mut stream: TcpStream,
+  initial_seq: i32,
+) -> Result<HandshakeOutcome> {
+  perform_client_handshake_with_config(&mut stream, initial_seq, &HandshakeConfig::default())
+}
+
+/**
+ * Performs client-side 3-way handshake using the given config
+ * The stream stays open for the caller
+ */
+pub fn perform_client_handshake_with_config(
+  stream: &mut TcpStream,
+  initial_seq: i32,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
   // Set read timeout for client
-  stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+  stream.set_read_timeout(Some(config.read_timeout))?;
+  apply_linger(stream, config)?;
+  apply_nodelay(stream, config)?;
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut log = HandshakeLog::default();
+  let mut pending = GrowableBuffer::default();
+  let mut machine = HandshakeMachine::new(Role::Client { initial_seq }, Cow::Borrowed(config));
+
+  // Answer the server's greeting first when a preamble is configured
+  if let Some(preamble) = &config.preamble {
+    client_preamble(stream, &mut pending, preamble, config)?;
+  }
 
   // Step 1: Send HELLO X where X is initial sequence
-  let first_message = format_hello_message(initial_seq);
-  write_message_to_stream(&mut stream, &first_message)?;
+  if let Some(first_message) = machine.poll_message() {
+    write_sync_message(stream, &first_message, config)?;
+    config.emit_event(1, Direction::Outbound, &first_message, steps.finish(1));
+  }
 
-  // Step 2: Receive HELLO Y and validate Y = X + 1
-  let received_msg = read_message_from_stream(&mut stream)?;
+  // Step 2: Receive HELLO Y and validate that Y follows X
+  let received_msg = read_sync_message(stream, &mut pending, config)?;
+  config.emit_event(2, Direction::Inbound, &received_msg, steps.finish(2));
+  let rtt = clock.now().duration_since(started);
 
   // Print received message to stdout
-  println!("{received_msg}");
-  std::io::Write::flush(&mut std::io::stdout())?;
-
+  logln!(log, config, "{received_msg}");
   // Parse and validate
-  let received_seq = parse_hello_message(&received_msg)?;
-  let expected_seq = initial_seq + 1;
+  machine.handle_message(&received_msg)?;
 
-  if received_seq != expected_seq {
-    return Err(HandshakeError::SequenceMismatch {
-      expected: expected_seq,
-      received: received_seq,
-    });
+  // Step 3: Send HELLO Z where Z follows Y
+  let final_message = machine
+    .poll_message()
+    .expect("HELLO Z follows a valid HELLO Y");
+  write_sync_message(stream, &final_message, config)?;
+  config.emit_event(3, Direction::Outbound, &final_message, steps.finish(3));
+  if let Some(wait) = config.confirm_final {
+    confirm_final(stream, wait, &mut pending, config)?;
   }
 
-  // Step 3: Send HELLO Z where Z = Y + 1
-  let final_seq = received_seq + 1;
-  let final_message = format_hello_message(final_seq);
-  write_message_to_stream(&mut stream, &final_message)?;
+  Ok(timed_outcome(
+    &machine,
+    rtt,
+    clock.now().duration_since(started),
+    &steps,
+    &log,
+  ))
+}
 
-  Ok(())
+/**
+ * Waits briefly after HELLO Z to learn whether the server accepted it
+ *
+ * A clean close, or silence until `wait` runs out, counts as acceptance.
+ * An `ERR <reason>` reply fails with `Rejected`, and any other message is a
+ * protocol violation. The read timeout is restored afterwards.
+ */
+fn confirm_final(
+  stream: &mut TcpStream,
+  wait: Duration,
+  pending: &mut GrowableBuffer,
+  config: &HandshakeConfig,
+) -> Result<()> {
+  stream.set_read_timeout(Some(wait))?;
+  let reply = read_sync_message(stream, pending, config);
+  stream.set_read_timeout(Some(config.read_timeout))?;
+
+  match reply {
+    Ok(message) => match message.strip_prefix("ERR") {
+      Some(reason) => Err(HandshakeError::Rejected(reason.trim().to_string())),
+      None => Err(HandshakeError::ProtocolViolation(format!(
+        "unexpected message after final HELLO: '{message}'"
+      ))),
+    },
+    Err(HandshakeError::ClientDisconnected | HandshakeError::Timeout) => Ok(()),
+    Err(HandshakeError::Io(e)) if e.kind() == ErrorKind::TimedOut => Ok(()),
+    Err(e) => Err(e),
+  }
 }
 
 /**
  * Performs server-side 3-way handshake
  */
-pub fn perform_server_handshake(mut stream: TcpStream) -> Result<()> {
+pub fn perform_server_handshake(mut stream: TcpStream) -> Result<HandshakeOutcome> {
+  perform_server_handshake_with_config(&mut stream, &HandshakeConfig::default())
+}
+
+/**
+ * Performs server-side 3-way handshake using the given config
+ */
+pub fn perform_server_handshake_with_config(
+  stream: &mut TcpStream,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut pending = handshake_buffer(&[], config);
+  server_handshake(stream, &mut pending, &mut MessageCounter::default(), config)
+}
+
+/**
+ * Performs server-side 3-way handshake after some bytes were already read
+ * `already_read` is taken as the start of HELLO X, e.g. bytes consumed to classify the protocol
+ */
+pub fn perform_server_handshake_with_prefix(
+  stream: &mut TcpStream,
+  already_read: &[u8],
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let mut pending = handshake_buffer(already_read, config);
+  server_handshake(stream, &mut pending, &mut MessageCounter::default(), config)
+}
+
+/**
+ * Server side over the connection's unread bytes and message count
+ * A persistent connection passes the same ones to every handshake it runs
+ */
+pub(crate) fn server_handshake(
+  stream: &mut TcpStream,
+  pending: &mut GrowableBuffer,
+  messages: &mut MessageCounter,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
   // Set read timeout for server
-  stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+  stream.set_read_timeout(Some(config.read_timeout))?;
+  apply_linger(stream, config)?;
+  apply_nodelay(stream, config)?;
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut log = HandshakeLog::default();
+  let mut machine = HandshakeMachine::new(Role::Server, Cow::Borrowed(config));
 
-  // Step 1: Receive HELLO X
-  let received_msg = read_message_from_stream(&mut stream)?;
+  // Greet the client first when a preamble is configured
+  if let Some(preamble) = &config.preamble {
+    server_preamble(stream, pending, preamble, config)?;
+    messages.count(config)?;
+  }
+
+  // Step 1: Receive HELLO X, shedding a peer that stays silent
+  if let Some(wait) = config.first_byte_timeout.filter(|_| pending.is_empty()) {
+    wait_for_first_byte(stream, wait, config)?;
+  }
+  let received_msg = read_sync_message(stream, pending, config)?;
+  messages.count(config)?;
+  config.emit_event(1, Direction::Inbound, &received_msg, steps.finish(1));
 
   // Print received message
-  println!("{received_msg}");
-  std::io::Write::flush(&mut std::io::stdout())?;
+  logln!(log, config, "{received_msg}");
 
-  // Parse the client's sequence number
-  let client_seq = parse_hello_message(&received_msg)?;
+  // Parse the client's sequence number, checking any claimed address against the resolved peer's
+  let peer_ip = || {
+    let peer = PeerInfo::accepted(stream.peer_addr().ok()?, stream.local_addr().ok());
+    Some(config.peer_resolver.resolve(&peer).ip())
+  };
+  machine.handle_message_from(&received_msg, peer_ip)?;
 
-  // Step 2: Send HELLO Y where Y = X + 1
-  let server_seq = client_seq + 1;
-  let response = format_hello_message(server_seq);
-  write_message_to_stream(&mut stream, &response)?;
+  // Step 2: Send HELLO Y where Y follows X
+  let response = machine
+    .poll_message()
+    .expect("HELLO Y follows a valid HELLO X");
+  corked(stream, config.cork_replies, |stream| {
+    write_sync_message(stream, &response, config)
+  })?;
+  config.emit_event(2, Direction::Outbound, &response, steps.finish(2));
+  let replied = clock.now();
 
-  // Step 3: Receive HELLO Z and validate Z = Y + 1
-  let final_msg = read_message_from_stream(&mut stream)?;
+  // Step 3: Receive HELLO Z and validate that Z follows Y
+  let final_msg = read_sync_message(stream, pending, config)?;
+  messages.count(config)?;
+  config.emit_event(3, Direction::Inbound, &final_msg, steps.finish(3));
+  let rtt = clock.now().duration_since(replied);
 
   // Print received message
-  println!("{final_msg}");
-  std::io::Write::flush(&mut std::io::stdout())?;
+  logln!(log, config, "{final_msg}");
 
   // Parse and validate final sequence number
-  let final_seq = parse_hello_message(&final_msg)?;
-  let expected_final = server_seq + 1;
+  machine.handle_message(&final_msg)?;
 
-  if final_seq != expected_final {
-    eprintln!("ERROR: Expected HELLO {expected_final}, received HELLO {final_seq}");
-  }
+  Ok(timed_outcome(
+    &machine,
+    rtt,
+    clock.now().duration_since(started),
+    &steps,
+    &log,
+  ))
+}
 
-  Ok(())
+/**
+ * The machine's outcome with the timings the transport measured
+ */
+fn timed_outcome(
+  machine: &HandshakeMachine,
+  rtt: Duration,
+  duration: Duration,
+  steps: &StepTimer,
+  log: &HandshakeLog,
+) -> HandshakeOutcome {
+  let outcome = machine
+    .outcome()
+    .expect("the handshake is complete once HELLO Z is through");
+  HandshakeOutcome {
+    rtt,
+    duration,
+    step_durations: steps.steps(),
+    logging_degraded: log.degraded(),
+    ..outcome
+  }
 }
@@ -0,0 +1,213 @@
+/**
+ * PROXY protocol v1 support for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Load balancers may prepend a single text line before the client's bytes:
+ *
+ * ```text
+ * PROXY TCP4 192.0.2.1 198.51.100.1 56324 8080\r\n
+ * PROXY TCP6 2001:db8::1 2001:db8::2 56324 8080\r\n
+ * PROXY UNKNOWN\r\n
+ * ```
+ *
+ * The header is read byte by byte so none of the HELLO that follows is consumed.
+ */
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Instant;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream as AsyncTcpStream;
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::peer::PeerInfo;
+use crate::protocol::read_into_buffer;
+use crate::time::timeout;
+
+// Longest possible v1 header including the trailing CRLF
+pub const PROXY_V1_MAX_LEN: usize = 107;
+
+const PROXY_V1_PREFIX: &[u8] = b"PROXY ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHeader {
+  Tcp4 {
+    source: SocketAddr,
+    destination: SocketAddr,
+  },
+  Tcp6 {
+    source: SocketAddr,
+    destination: SocketAddr,
+  },
+  // The balancer could not determine the original addresses
+  Unknown,
+}
+
+impl ProxyHeader {
+  /**
+   * Original client address, if the balancer reported one
+   */
+  pub fn source(&self) -> Option<SocketAddr> {
+    match self {
+      ProxyHeader::Tcp4 { source, .. } | ProxyHeader::Tcp6 { source, .. } => Some(*source),
+      ProxyHeader::Unknown => None,
+    }
+  }
+}
+
+/**
+ * Parses a complete PROXY v1 header line, with or without its CRLF
+ */
+pub fn parse_proxy_header(line: &str) -> Result<ProxyHeader> {
+  let violation = |reason: &str| HandshakeError::ProtocolViolation(format!("{reason}: {line:?}"));
+
+  let line = line.strip_suffix("\r\n").unwrap_or(line);
+  let parts: Vec<&str> = line.split(' ').collect();
+
+  if parts[0] != "PROXY" || parts.len() < 2 {
+    return Err(violation("expected PROXY header"));
+  }
+
+  let is_v6 = match parts[1] {
+    "UNKNOWN" => return Ok(ProxyHeader::Unknown),
+    "TCP4" => false,
+    "TCP6" => true,
+    _ => return Err(violation("unsupported PROXY protocol family")),
+  };
+
+  if parts.len() != 6 {
+    return Err(violation("expected 4 address fields in PROXY header"));
+  }
+
+  let parse_ip = |text: &str| -> Result<IpAddr> {
+    let ip: IpAddr = text
+      .parse()
+      .map_err(|_| violation("invalid address in PROXY header"))?;
+    if ip.is_ipv6() != is_v6 {
+      return Err(violation("address family does not match PROXY header"));
+    }
+    Ok(ip)
+  };
+  let parse_port = |text: &str| -> Result<u16> {
+    text
+      .parse()
+      .map_err(|_| violation("invalid port in PROXY header"))
+  };
+
+  let source = SocketAddr::new(parse_ip(parts[2])?, parse_port(parts[4])?);
+  let destination = SocketAddr::new(parse_ip(parts[3])?, parse_port(parts[5])?);
+
+  Ok(if is_v6 {
+    ProxyHeader::Tcp6 {
+      source,
+      destination,
+    }
+  } else {
+    ProxyHeader::Tcp4 {
+      source,
+      destination,
+    }
+  })
+}
+
+/**
+ * Appends one header byte, returning the full line once CRLF is seen
+ */
+fn push_header_byte(header: &mut Vec<u8>, byte: u8) -> Result<Option<String>> {
+  header.push(byte);
+
+  let checked = header.len().min(PROXY_V1_PREFIX.len());
+  if header[..checked] != PROXY_V1_PREFIX[..checked] {
+    return Err(HandshakeError::ProtocolViolation(format!(
+      "expected PROXY header, got {:?}",
+      String::from_utf8_lossy(header)
+    )));
+  }
+
+  if header.ends_with(b"\r\n") {
+    return Ok(Some(String::from_utf8_lossy(header).into_owned()));
+  }
+
+  if header.len() >= PROXY_V1_MAX_LEN {
+    return Err(HandshakeError::ProtocolViolation(format!(
+      "PROXY header exceeds {PROXY_V1_MAX_LEN} bytes"
+    )));
+  }
+
+  Ok(None)
+}
+
+/**
+ * Reads and parses a PROXY v1 header from a TCP stream
+ * Gives up with `Timeout` once `config.read_timeout` has passed for the whole header
+ */
+pub fn read_proxy_header_from_stream(
+  stream: &mut TcpStream,
+  config: &HandshakeConfig,
+) -> Result<ProxyHeader> {
+  stream.set_read_timeout(Some(config.read_timeout))?;
+  let deadline = Instant::now() + config.read_timeout;
+
+  let mut header = Vec::with_capacity(PROXY_V1_MAX_LEN);
+  let mut byte = [0u8; 1];
+  loop {
+    read_into_buffer(stream, &mut byte, deadline)?;
+    if let Some(line) = push_header_byte(&mut header, byte[0])? {
+      return parse_proxy_header(&line);
+    }
+  }
+}
+
+/**
+ * Async version: Reads and parses a PROXY v1 header from a TCP stream
+ */
+pub async fn read_proxy_header_from_async_stream(
+  stream: &mut AsyncTcpStream,
+  config: &HandshakeConfig,
+) -> Result<ProxyHeader> {
+  timeout(config.clock.as_ref(), config.read_timeout, async {
+    let mut header = Vec::with_capacity(PROXY_V1_MAX_LEN);
+    loop {
+      let byte = match stream.read_u8().await {
+        Ok(byte) => byte,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+          return Err(HandshakeError::ClientDisconnected);
+        }
+        Err(e) => return Err(HandshakeError::Io(e)),
+      };
+      if let Some(line) = push_header_byte(&mut header, byte)? {
+        return parse_proxy_header(&line);
+      }
+    }
+  })
+  .await?
+}
+
+/**
+ * Returns the address to report for a connection, as picked by `config.peer_resolver`
+ * Strips the PROXY header first when `config.proxy_protocol` is set and hands it to the
+ * resolver; the default falls back to the socket peer when the balancer sends `UNKNOWN`
+ */
+pub fn resolve_client_addr(stream: &mut TcpStream, config: &HandshakeConfig) -> Result<SocketAddr> {
+  let mut peer = PeerInfo::accepted(stream.peer_addr()?, stream.local_addr().ok());
+  if config.proxy_protocol {
+    peer.proxy = Some(read_proxy_header_from_stream(stream, config)?);
+  }
+  Ok(config.peer_resolver.resolve(&peer))
+}
+
+/**
+ * Async version: Returns the address to report for a connection
+ */
+pub async fn resolve_async_client_addr(
+  stream: &mut AsyncTcpStream,
+  peer_addr: SocketAddr,
+  config: &HandshakeConfig,
+) -> Result<SocketAddr> {
+  let mut peer = PeerInfo::accepted(peer_addr, stream.local_addr().ok());
+  if config.proxy_protocol {
+    peer.proxy = Some(read_proxy_header_from_async_stream(stream, config).await?);
+  }
+  Ok(config.peer_resolver.resolve(&peer))
+}
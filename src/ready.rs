@@ -0,0 +1,135 @@
+/**
+ * Readiness socket for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * A process supervisor can check whether a local server is ready by
+ * connecting to a Unix socket instead of speaking the protocol over TCP. Each
+ * connection gets one line, `OK` once the accept loop is serving and past its
+ * warmup, `NOTREADY` before that and from the moment shutdown starts
+ * draining, and is then closed. The answer comes from the server's metrics,
+ * so the socket works alongside any server model. Unix sockets are needed;
+ * elsewhere creating one fails.
+ */
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::metrics::ServerMetrics;
+use crate::reload::LiveConfig;
+
+pub const READY_REPLY: &str = "OK";
+pub const NOT_READY_REPLY: &str = "NOTREADY";
+
+/**
+ * The line a readiness check gets right now
+ */
+fn readiness_line(live: &LiveConfig, metrics: &ServerMetrics) -> String {
+  let reply = if metrics.is_ready(live.load().clock.now()) {
+    READY_REPLY
+  } else {
+    NOT_READY_REPLY
+  };
+  format!("{reply}\n")
+}
+
+/**
+ * Removes a socket file left behind by an earlier run, leaving anything else alone
+ */
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<()> {
+  use std::os::unix::fs::FileTypeExt;
+
+  match std::fs::symlink_metadata(path) {
+    Ok(meta) if meta.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+    Ok(_) => Ok(()),
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+    Err(e) => Err(e.into()),
+  }
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> crate::error::HandshakeError {
+  std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "readiness sockets need Unix domain sockets",
+  )
+  .into()
+}
+
+/**
+ * Async version: Answers readiness checks on a Unix socket at `path`
+ * The socket is bound before this returns; must be called within a Tokio runtime
+ */
+pub fn serve_ready_socket(
+  path: &Path,
+  live: LiveConfig,
+  metrics: Arc<ServerMetrics>,
+) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixListener;
+
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    outln!(live.load(), "Readiness socket at {}", path.display());
+    tokio::spawn(async move {
+      loop {
+        match listener.accept().await {
+          // The checker may hang up first; there is nobody to tell
+          Ok((mut stream, _)) => {
+            let line = readiness_line(&live, &metrics);
+            let _ = stream.write_all(line.as_bytes()).await;
+          }
+          Err(e) => errln!(live.load(), "Failed to accept readiness check: {e}"),
+        }
+      }
+    });
+    Ok(())
+  }
+
+  #[cfg(not(unix))]
+  {
+    let _ = (path, live, metrics);
+    Err(unsupported())
+  }
+}
+
+/**
+ * Answers readiness checks on a Unix socket at `path` from a background thread
+ * Blocking servers use this in place of `serve_ready_socket`
+ */
+pub fn serve_ready_socket_blocking(
+  path: &Path,
+  live: LiveConfig,
+  metrics: Arc<ServerMetrics>,
+) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    outln!(live.load(), "Readiness socket at {}", path.display());
+    std::thread::spawn(move || {
+      for stream in listener.incoming() {
+        match stream {
+          Ok(mut stream) => {
+            let line = readiness_line(&live, &metrics);
+            let _ = stream.write_all(line.as_bytes());
+          }
+          Err(e) => errln!(live.load(), "Failed to accept readiness check: {e}"),
+        }
+      }
+    });
+    Ok(())
+  }
+
+  #[cfg(not(unix))]
+  {
+    let _ = (path, live, metrics);
+    Err(unsupported())
+  }
+}
@@ -0,0 +1,188 @@
+/**
+ * Permissive "reflect" server handshake for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Meant for developing clients, not for production. The reflect handshake
+ * intentionally skips validation: it reads a message, replies with the next
+ * sequence number, reads one more message and logs everything along the way.
+ * Malformed messages are logged instead of failing the handshake, and a
+ * message with no recognizable sequence number is treated as sequence 0.
+ * Timeouts, disconnects and I/O errors still end the handshake with an error
+ * so a broken client cannot hang the server.
+ */
+use std::fmt;
+use std::net::TcpStream;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::HandshakeConfig;
+use crate::cork::corked;
+use crate::error::Result;
+use crate::framing::GrowableBuffer;
+use crate::outcome::{HandshakeOutcome, Negotiated, StepTimer};
+use crate::output::HandshakeLog;
+use crate::protocol::{
+  cancellable, format_hello_message, parse_hello_message, read_async_message, read_sync_message,
+  write_async_framed, write_sync_message,
+};
+use crate::time::timeout;
+use crate::transcript::Direction;
+use crate::utils::{apply_linger, apply_nodelay};
+
+/**
+ * Best-effort sequence number: the last token that parses as an integer, else 0
+ */
+fn lenient_seq(message: &str, peer_addr: impl fmt::Display, config: &HandshakeConfig) -> i32 {
+  let seq = message
+    .split_whitespace()
+    .rev()
+    .find_map(|token| token.parse::<i32>().ok());
+
+  match seq {
+    Some(seq) => {
+      if parse_hello_message(message).is_err() {
+        outln!(
+          config,
+          "Reflect: malformed message from {peer_addr}, using sequence {seq}"
+        );
+      }
+      seq
+    }
+    None => {
+      outln!(
+        config,
+        "Reflect: no sequence number from {peer_addr}, using 0"
+      );
+      0
+    }
+  }
+}
+
+/**
+ * Performs a reflect handshake: reads HELLO X, replies HELLO X+1, reads one more message
+ * Never fails on message content; timeouts come from `config.read_timeout`
+ */
+pub fn perform_server_handshake_reflect(
+  stream: &mut TcpStream,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  stream.set_read_timeout(Some(config.read_timeout))?;
+  apply_linger(stream, config)?;
+  apply_nodelay(stream, config)?;
+  let peer_addr = stream
+    .peer_addr()
+    .map(|addr| addr.to_string())
+    .unwrap_or_else(|_| "unknown".to_string());
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut log = HandshakeLog::default();
+  let mut pending = GrowableBuffer::default();
+
+  // Step 1: Receive whatever the client opens with
+  let received_msg = read_sync_message(stream, &mut pending, config)?;
+  config.emit_event(1, Direction::Inbound, &received_msg, steps.finish(1));
+  logln!(
+    log,
+    config,
+    "Reflect: received from {peer_addr}: {received_msg:?}"
+  );
+  let client_seq = lenient_seq(&received_msg, &peer_addr, config);
+
+  // Step 2: Reply with the sequence the client expects
+  let response = format_hello_message(client_seq.wrapping_add(1));
+  corked(stream, config.cork_replies, |stream| {
+    write_sync_message(stream, &response, config)
+  })?;
+  config.emit_event(2, Direction::Outbound, &response, steps.finish(2));
+  logln!(log, config, "Reflect: sent to {peer_addr}: {response}");
+  let replied = clock.now();
+
+  // Step 3: Receive and log the final message without validating it
+  let final_msg = read_sync_message(stream, &mut pending, config)?;
+  config.emit_event(3, Direction::Inbound, &final_msg, steps.finish(3));
+  let rtt = clock.now().duration_since(replied);
+  logln!(
+    log,
+    config,
+    "Reflect: received from {peer_addr}: {final_msg:?}"
+  );
+  let final_seq = lenient_seq(&final_msg, &peer_addr, config);
+
+  Ok(HandshakeOutcome {
+    initial_seq: client_seq,
+    final_seq,
+    rtt,
+    duration: clock.now().duration_since(started),
+    step_durations: steps.steps(),
+    capabilities: None,
+    logging_degraded: log.degraded(),
+    label: config.label.clone(),
+    negotiated: Negotiated::default(),
+    connect_phases: None,
+  })
+}
+
+/**
+ * Async version: Performs a reflect handshake over any async byte stream
+ * The whole handshake is bounded by `config.connection_timeout`
+ */
+pub async fn perform_async_server_handshake_reflect<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  cancel: Option<&CancellationToken>,
+  config: &HandshakeConfig,
+) -> Result<HandshakeOutcome> {
+  let clock = config.clock.as_ref();
+  let started = clock.now();
+  let mut steps = StepTimer::new(clock, started);
+  let mut log = HandshakeLog::default();
+  let mut pending = GrowableBuffer::default();
+
+  timeout(clock, config.connection_timeout, async {
+    // Step 1: Receive whatever the client opens with
+    let received_msg =
+      cancellable(cancel, read_async_message(stream, &mut pending, config)).await?;
+    config.emit_event(1, Direction::Inbound, &received_msg, steps.finish(1));
+    logln!(
+      log,
+      config,
+      "Reflect: received from {peer_addr}: {received_msg:?}"
+    );
+    let client_seq = lenient_seq(&received_msg, &peer_addr, config);
+
+    // Step 2: Reply with the sequence the client expects
+    let response = format_hello_message(client_seq.wrapping_add(1));
+    cancellable(cancel, write_async_framed(stream, &response, config)).await?;
+    config.emit_event(2, Direction::Outbound, &response, steps.finish(2));
+    logln!(log, config, "Reflect: sent to {peer_addr}: {response}");
+    let replied = clock.now();
+
+    // Step 3: Receive and log the final message without validating it
+    let final_msg = cancellable(cancel, read_async_message(stream, &mut pending, config)).await?;
+    config.emit_event(3, Direction::Inbound, &final_msg, steps.finish(3));
+    let rtt = clock.now().duration_since(replied);
+    logln!(
+      log,
+      config,
+      "Reflect: received from {peer_addr}: {final_msg:?}"
+    );
+    let final_seq = lenient_seq(&final_msg, &peer_addr, config);
+
+    Ok(HandshakeOutcome {
+      initial_seq: client_seq,
+      final_seq,
+      rtt,
+      duration: clock.now().duration_since(started),
+      step_durations: steps.steps(),
+      capabilities: None,
+      logging_degraded: log.degraded(),
+      label: config.label.clone(),
+      negotiated: Negotiated::default(),
+      connect_phases: None,
+    })
+  })
+  .await?
+}
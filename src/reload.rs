@@ -0,0 +1,277 @@
+/**
+ * Live config reloading for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Servers look up their settings in a `LiveConfig` as each connection is
+ * accepted, so swapping in a new config applies to the next handshake while
+ * in-flight ones keep the snapshot they started with. `--config <file>` reads
+ * settings from a TOML file on top of the command line flags, and on Unix the
+ * file is read again on SIGHUP without dropping any connection.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use serde::Deserialize;
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::limits::RateLimit;
+use crate::metrics::ServerMetrics;
+use crate::utils::parse_duration;
+
+/**
+ * Config shared with a running server; every new connection reads the current one
+ */
+pub type LiveConfig = Arc<ArcSwap<HandshakeConfig>>;
+
+pub fn live_config(config: HandshakeConfig) -> LiveConfig {
+  Arc::new(ArcSwap::from_pointee(config))
+}
+
+/**
+ * Settings from a `--config` TOML file, each overriding its command line flag when present
+ *
+ * Durations are written like `"500ms"` and the rate limit like `"100/1s"`.
+ * `max_per_source` and `warmup` are set up once when the server starts, so a
+ * reload leaves them alone; the port and queue capacity come only from the
+ * command line. Everything else applies to the next accepted connection.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigFile {
+  pub read_timeout: Option<Duration>,
+  pub first_byte_timeout: Option<Duration>,
+  pub connection_timeout: Option<Duration>,
+  pub drain_timeout: Option<Duration>,
+  pub slow_threshold: Option<Duration>,
+  pub rate_limit: Option<RateLimit>,
+  pub busy_response: Option<bool>,
+  // Only take effect on restart
+  pub max_per_source: Option<usize>,
+  pub warmup: Option<Duration>,
+}
+
+/**
+ * The file as written, before durations and the rate limit are parsed
+ */
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfigFile {
+  read_timeout: Option<String>,
+  first_byte_timeout: Option<String>,
+  connection_timeout: Option<String>,
+  drain_timeout: Option<String>,
+  slow_threshold: Option<String>,
+  rate_limit: Option<String>,
+  busy_response: Option<bool>,
+  max_per_source: Option<usize>,
+  warmup: Option<String>,
+}
+
+impl ConfigFile {
+  pub fn load(path: &Path) -> Result<Self> {
+    let text = fs::read_to_string(path).map_err(|e| {
+      HandshakeError::InvalidConfigFile(format!("could not read {}: {e}", path.display()))
+    })?;
+    Self::parse(&text)
+      .map_err(|e| HandshakeError::InvalidConfigFile(format!("{}: {}", path.display(), reason(e))))
+  }
+
+  /**
+   * Parses the TOML text of a config file, rejecting unknown keys
+   */
+  pub fn parse(text: &str) -> Result<Self> {
+    let raw: RawConfigFile = toml::from_str(text)
+      .map_err(|e| HandshakeError::InvalidConfigFile(e.message().to_string()))?;
+    let duration = |field: &str, value: Option<String>| {
+      value
+        .map(|value| parse_duration(&value))
+        .transpose()
+        .map_err(|e| HandshakeError::InvalidConfigFile(format!("{field}: {}", reason(e))))
+    };
+
+    let max_per_source = raw.max_per_source.filter(|&max| max > 0);
+    if max_per_source != raw.max_per_source {
+      return Err(HandshakeError::InvalidConfigFile(
+        "max_per_source: must be at least 1".to_string(),
+      ));
+    }
+    Ok(Self {
+      read_timeout: duration("read_timeout", raw.read_timeout)?,
+      first_byte_timeout: duration("first_byte_timeout", raw.first_byte_timeout)?,
+      connection_timeout: duration("connection_timeout", raw.connection_timeout)?,
+      drain_timeout: duration("drain_timeout", raw.drain_timeout)?,
+      slow_threshold: duration("slow_threshold", raw.slow_threshold)?,
+      rate_limit: raw
+        .rate_limit
+        .map(|limit| RateLimit::parse(&limit))
+        .transpose()
+        .map_err(|e| HandshakeError::InvalidConfigFile(format!("rate_limit: {}", reason(e))))?,
+      busy_response: raw.busy_response,
+      max_per_source,
+      warmup: duration("warmup", raw.warmup)?,
+    })
+  }
+
+  /**
+   * `base` with every setting present in the file applied on top
+   */
+  pub fn apply(&self, base: &HandshakeConfig) -> HandshakeConfig {
+    let mut config = base.clone();
+    if let Some(read_timeout) = self.read_timeout {
+      config.read_timeout = read_timeout;
+    }
+    if let Some(first_byte_timeout) = self.first_byte_timeout {
+      config.first_byte_timeout = Some(first_byte_timeout);
+    }
+    if let Some(connection_timeout) = self.connection_timeout {
+      config.connection_timeout = connection_timeout;
+    }
+    if let Some(drain_timeout) = self.drain_timeout {
+      config.drain_timeout = drain_timeout;
+    }
+    if let Some(slow_threshold) = self.slow_threshold {
+      config.slow_threshold = slow_threshold;
+    }
+    if let Some(rate_limit) = self.rate_limit {
+      config.accept_rate_limit = Some(rate_limit);
+    }
+    if let Some(busy_response) = self.busy_response {
+      config.busy_response = busy_response;
+    }
+    if let Some(max_per_source) = self.max_per_source {
+      config.max_connections_per_source = Some(max_per_source);
+    }
+    if let Some(warmup) = self.warmup {
+      config.warmup = Some(warmup);
+    }
+    config
+  }
+}
+
+/**
+ * The reason inside an argument error, without the command line wording
+ */
+fn reason(error: HandshakeError) -> String {
+  match error {
+    HandshakeError::InvalidArguments(reason) | HandshakeError::InvalidConfigFile(reason) => reason,
+    other => other.to_string(),
+  }
+}
+
+/**
+ * Builds the live config for a server, layering `path` over `base` if given
+ * On Unix the file is read again on every SIGHUP; a file that no longer parses keeps the current config
+ */
+pub fn load_live_config(base: HandshakeConfig, path: Option<PathBuf>) -> Result<LiveConfig> {
+  let Some(path) = path else {
+    return Ok(live_config(base));
+  };
+  let file = ConfigFile::load(&path)?;
+  let live = live_config(file.apply(&base));
+  reload_on_sighup(path, base, file, Arc::clone(&live))?;
+  Ok(live)
+}
+
+#[cfg(unix)]
+fn reload_on_sighup(
+  path: PathBuf,
+  base: HandshakeConfig,
+  mut current: ConfigFile,
+  live: LiveConfig,
+) -> Result<()> {
+  use signal_hook::consts::SIGHUP;
+  use signal_hook::iterator::Signals;
+
+  let mut signals = Signals::new([SIGHUP])?;
+  std::thread::spawn(move || {
+    for _ in signals.forever() {
+      match ConfigFile::load(&path) {
+        Ok(mut file) => {
+          if (file.max_per_source, file.warmup) != (current.max_per_source, current.warmup) {
+            errln!(
+              base,
+              "WARNING: max_per_source and warmup in {} only take effect on restart",
+              path.display()
+            );
+            file.max_per_source = current.max_per_source;
+            file.warmup = current.warmup;
+          }
+          live.store(Arc::new(file.apply(&base)));
+          outln!(
+            base,
+            "Received SIGHUP, reloaded config from {}",
+            path.display()
+          );
+          current = file;
+        }
+        Err(e) => errln!(
+          base,
+          "ERROR: Received SIGHUP, keeping the current config: {e}"
+        ),
+      }
+    }
+  });
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn reload_on_sighup(
+  path: PathBuf,
+  base: HandshakeConfig,
+  _current: ConfigFile,
+  _live: LiveConfig,
+) -> Result<()> {
+  errln!(
+    base,
+    "WARNING: SIGHUP is not available here, {} is only read at startup",
+    path.display()
+  );
+  Ok(())
+}
+
+/**
+ * The current live config, instrumented for a server's metrics
+ * Instrumenting clones the config, so it is only redone after a reload swapped it
+ */
+#[derive(Debug)]
+pub(crate) struct InstrumentedConfig {
+  live: LiveConfig,
+  // The live config `current` was made from
+  source: Arc<HandshakeConfig>,
+  current: Arc<HandshakeConfig>,
+}
+
+impl InstrumentedConfig {
+  pub(crate) fn new(live: LiveConfig, metrics: &ServerMetrics) -> Self {
+    let source = live.load_full();
+    let current = Arc::new(metrics.instrument(&source));
+    Self {
+      live,
+      source,
+      current,
+    }
+  }
+
+  /**
+   * The config as of the last `refresh`
+   */
+  pub(crate) fn get(&self) -> &Arc<HandshakeConfig> {
+    &self.current
+  }
+
+  /**
+   * Picks up a reloaded config, returning the one to use for the connection just accepted
+   */
+  pub(crate) fn refresh(&mut self, metrics: &ServerMetrics) -> &Arc<HandshakeConfig> {
+    let latest = self.live.load();
+    if !Arc::ptr_eq(&latest, &self.source) {
+      self.source = Arc::clone(&latest);
+      self.current = Arc::new(metrics.instrument(&latest));
+    }
+    &self.current
+  }
+}
@@ -0,0 +1,121 @@
+/**
+ * Reply scheduling for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * By default the async server writes HELLO Y the moment it is ready. With
+ * `ReplyStrategy::Batched` a ready reply joins a `ReplyQueue` shared by every
+ * connection instead. The first reply to join opens a window, and when the
+ * window closes every reply queued in it is released at once. Each reply goes
+ * to its own socket, framed into one buffer while it waits so that it leaves
+ * in a single write even with `coalesce_writes` off. What changes is that the
+ * replies go out in one burst per window rather than one at a time, at the
+ * cost of up to one window of extra latency per handshake. `cargo run --release --bin bench`
+ * measures both sides of that trade.
+ */
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::time::Clock;
+
+/**
+ * When the async server sends HELLO Y once it is ready
+ */
+#[derive(Debug, Clone, Default)]
+pub enum ReplyStrategy {
+  // Write each reply as soon as it is ready
+  #[default]
+  Immediate,
+  // Hold ready replies in a shared queue and flush them together once per window
+  Batched(Arc<ReplyQueue>),
+}
+
+impl ReplyStrategy {
+  /**
+   * Batches replies over `window`, in a queue of their own
+   */
+  pub fn batched(window: Duration) -> Self {
+    Self::Batched(Arc::new(ReplyQueue::new(window)))
+  }
+}
+
+/**
+ * Replies waiting for the current batching window to close
+ */
+#[derive(Debug)]
+pub struct ReplyQueue {
+  window: Duration,
+  waiting: Mutex<Vec<oneshot::Sender<()>>>,
+  batches: AtomicU64,
+  replies: AtomicU64,
+}
+
+impl ReplyQueue {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      waiting: Mutex::new(Vec::new()),
+      batches: AtomicU64::new(0),
+      replies: AtomicU64::new(0),
+    }
+  }
+
+  pub fn window(&self) -> Duration {
+    self.window
+  }
+
+  /**
+   * Batches released so far
+   */
+  pub fn batches(&self) -> u64 {
+    self.batches.load(Ordering::Relaxed)
+  }
+
+  /**
+   * Replies released so far, across all batches
+   */
+  pub fn replies(&self) -> u64 {
+    self.replies.load(Ordering::Relaxed)
+  }
+
+  /**
+   * Waits until the batch this reply joins is released
+   *
+   * The reply that opens a batch starts a task that releases it after
+   * `window` on `clock`, so a handshake abandoned while it waits never holds
+   * up the others.
+   */
+  pub(crate) async fn wait_turn(self: &Arc<Self>, clock: &Arc<dyn Clock>) {
+    let (release, released) = oneshot::channel();
+    let opens_batch = {
+      let mut waiting = self.waiting.lock().unwrap();
+      waiting.push(release);
+      waiting.len() == 1
+    };
+    if opens_batch {
+      let queue = Arc::clone(self);
+      let window_closed = clock.sleep(self.window);
+      tokio::spawn(async move {
+        window_closed.await;
+        queue.release();
+      });
+    }
+    let _ = released.await;
+  }
+
+  fn release(&self) {
+    let batch = mem::take(&mut *self.waiting.lock().unwrap());
+    self.batches.fetch_add(1, Ordering::Relaxed);
+    self
+      .replies
+      .fetch_add(batch.len() as u64, Ordering::Relaxed);
+    for release in batch {
+      // A reply whose handshake has ended meanwhile has nobody to release
+      let _ = release.send(());
+    }
+  }
+}
@@ -0,0 +1,174 @@
+/**
+ * Shutdown report for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * When a server stops, its metrics are condensed into one `RunReport` and
+ * printed as a single JSON line, readable by people and log pipelines alike.
+ * `ReportOnExit` makes sure that happens exactly once, whether the server
+ * stopped cleanly or because of an error.
+ */
+use std::fmt;
+use std::panic;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::metrics::{MESSAGE_SIZE_BUCKETS, ServerMetrics};
+
+/**
+ * Connection accounting for a whole server run
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunReport {
+  pub uptime: Duration,
+  pub accepted: u64,
+  // Refused by warmup, a limit or a full queue before any handshake ran
+  pub rejected: u64,
+  pub succeeded: u64,
+  // Includes the timed out ones
+  pub failed: u64,
+  pub timed_out: u64,
+  pub peak_concurrency: usize,
+  pub bytes_received: u64,
+  pub bytes_sent: u64,
+  // Received message counts per `MESSAGE_SIZE_BOUNDS` bucket
+  pub message_sizes: [u64; MESSAGE_SIZE_BUCKETS],
+  // Cancelled by the drain timeout or still being handled when the report was made
+  pub force_closed: u64,
+}
+
+impl RunReport {
+  pub fn from_metrics(metrics: &ServerMetrics) -> Self {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let still_open = metrics.active_connections.load(Ordering::Relaxed)
+      + metrics.queue_depth.load(Ordering::Relaxed);
+    Self {
+      uptime: metrics.started.elapsed(),
+      accepted: load(&metrics.accepted),
+      rejected: load(&metrics.rejected_queue_full)
+        + load(&metrics.per_source_rejected)
+        + load(&metrics.rejected_warmup)
+        + load(&metrics.rate_limited)
+        + load(&metrics.overloaded),
+      succeeded: load(&metrics.succeeded),
+      failed: load(&metrics.failed),
+      timed_out: load(&metrics.timed_out),
+      peak_concurrency: metrics.peak_connections.load(Ordering::Relaxed),
+      bytes_received: load(&metrics.bytes.received),
+      bytes_sent: load(&metrics.bytes.sent),
+      message_sizes: metrics.message_sizes.counts(),
+      force_closed: load(&metrics.force_closed) + still_open as u64,
+    }
+  }
+
+  /**
+   * Whether every connection was seen through to the end
+   */
+  pub fn is_clean(&self) -> bool {
+    self.force_closed == 0
+  }
+}
+
+impl fmt::Display for RunReport {
+  /**
+   * One JSON object on a single line
+   */
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{{\"event\":\"server_stopped\",\"uptime_ms\":{},\"accepted\":{},\"rejected\":{},\
+       \"succeeded\":{},\"failed\":{},\"timed_out\":{},\"peak_concurrency\":{},\
+       \"bytes_received\":{},\"bytes_sent\":{},\"message_sizes\":{:?},\"force_closed\":{},\
+       \"clean\":{}}}",
+      self.uptime.as_millis(),
+      self.accepted,
+      self.rejected,
+      self.succeeded,
+      self.failed,
+      self.timed_out,
+      self.peak_concurrency,
+      self.bytes_received,
+      self.bytes_sent,
+      self.message_sizes,
+      self.force_closed,
+      self.is_clean(),
+    )
+  }
+}
+
+/**
+ * Emits the `RunReport` of a server exactly once, at the latest when dropped
+ *
+ * Dropping covers a normal return, an early return on error and an unwinding
+ * panic. Builds that abort on panic never drop anything, so the report is
+ * emitted from the panic hook instead.
+ */
+pub struct ReportOnExit {
+  state: Arc<ReportState>,
+}
+
+struct ReportState {
+  metrics: Arc<ServerMetrics>,
+  emit: Box<dyn Fn(&RunReport) + Send + Sync>,
+  emitted: AtomicBool,
+}
+
+impl ReportState {
+  fn emit_once(&self) {
+    if !self.emitted.swap(true, Ordering::AcqRel) {
+      (self.emit)(&RunReport::from_metrics(&self.metrics));
+    }
+  }
+}
+
+impl ReportOnExit {
+  pub fn new(
+    metrics: Arc<ServerMetrics>,
+    emit: impl Fn(&RunReport) + Send + Sync + 'static,
+  ) -> Self {
+    let state = Arc::new(ReportState {
+      metrics,
+      emit: Box::new(emit),
+      emitted: AtomicBool::new(false),
+    });
+
+    if cfg!(panic = "abort") {
+      let previous = panic::take_hook();
+      let on_panic = Arc::clone(&state);
+      panic::set_hook(Box::new(move |info| {
+        previous(info);
+        on_panic.emit_once();
+      }));
+    }
+    Self { state }
+  }
+
+  /**
+   * Prints the report to stdout
+   */
+  pub fn stdout(metrics: Arc<ServerMetrics>) -> Self {
+    Self::new(metrics, |report| println!("{report}"))
+  }
+
+  /**
+   * Emits the report now unless it already was
+   */
+  pub fn emit(&self) {
+    self.state.emit_once();
+  }
+}
+
+impl fmt::Debug for ReportOnExit {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ReportOnExit")
+      .field("emitted", &self.state.emitted.load(Ordering::Relaxed))
+      .finish_non_exhaustive()
+  }
+}
+
+impl Drop for ReportOnExit {
+  fn drop(&mut self) {
+    self.state.emit_once();
+  }
+}
@@ -0,0 +1,86 @@
+/**
+ * CSV result logging for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * One row per handshake attempt, appended so several experiment runs can share
+ * a file. Columns:
+ *
+ * ```text
+ * run,initial_seq,final_seq,rtt_us,duration_us,status,error
+ * ```
+ */
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::{HandshakeError, Result};
+use crate::outcome::HandshakeOutcome;
+
+// Results file used when the client is not given `--results`
+pub const DEFAULT_RESULTS_PATH: &str = "results.csv";
+
+pub const RESULTS_CSV_HEADER: &str = "run,initial_seq,final_seq,rtt_us,duration_us,status,error";
+
+/**
+ * Appends handshake outcomes to a CSV file
+ */
+#[derive(Debug)]
+pub struct ResultsCsv {
+  writer: BufWriter<File>,
+}
+
+impl ResultsCsv {
+  /**
+   * Opens `path` for appending, writing the header if the file is new or empty
+   */
+  pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let is_empty = file.metadata()?.len() == 0;
+    let mut writer = BufWriter::new(file);
+    if is_empty {
+      writeln!(writer, "{RESULTS_CSV_HEADER}")?;
+    }
+    Ok(Self { writer })
+  }
+
+  /**
+   * Writes one row for a handshake attempt and flushes it
+   * `run` identifies the attempt, e.g. its line number in a sequence file
+   */
+  pub fn record(
+    &mut self,
+    run: usize,
+    initial_seq: i32,
+    result: &std::result::Result<HandshakeOutcome, HandshakeError>,
+  ) -> Result<()> {
+    match result {
+      Ok(outcome) => writeln!(
+        self.writer,
+        "{run},{},{},{},{},ok,",
+        outcome.initial_seq,
+        outcome.final_seq,
+        outcome.rtt.as_micros(),
+        outcome.duration.as_micros()
+      )?,
+      Err(e) => writeln!(
+        self.writer,
+        "{run},{initial_seq},,,,error,{}",
+        escape_field(&e.to_string())
+      )?,
+    }
+    self.writer.flush()?;
+    Ok(())
+  }
+}
+
+/**
+ * Quotes a field when it contains a separator, quote or line break
+ */
+fn escape_field(field: &str) -> String {
+  if field.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    field.to_string()
+  }
+}
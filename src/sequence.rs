@@ -0,0 +1,54 @@
+/**
+ * Sequence number rules for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The protocol answers each sequence with the next one: Y follows X and Z
+ * follows Y. `config.sequence_policy` says what "follows" means. The default
+ * adds one; a custom policy can step by two, XOR with a key or accept a
+ * window of values. Both sides must use the same policy, or the client
+ * rejects HELLO Y with `SequenceMismatch`. A sequence with no answer inside
+ * `i32`, like `i32::MAX` under the default, fails with `SequenceOverflow`.
+ */
+use std::fmt;
+
+use crate::error::{HandshakeError, Result};
+
+/**
+ * How each sequence in a handshake follows the previous one
+ */
+pub trait SequencePolicy: fmt::Debug + Send + Sync {
+  /**
+   * The sequence that answers `prev`: Y for X, and Z for Y
+   * `None` when the answer does not fit in an `i32`
+   */
+  fn next(&self, prev: i32) -> Option<i32>;
+
+  /**
+   * Whether the peer's `received` answer is acceptable where `next` gave `expected`
+   */
+  fn validate(&self, expected: i32, received: i32) -> bool {
+    expected == received
+  }
+}
+
+/**
+ * Default policy: each sequence is the previous one plus one
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IncrementPolicy;
+
+impl SequencePolicy for IncrementPolicy {
+  fn next(&self, prev: i32) -> Option<i32> {
+    prev.checked_add(1)
+  }
+}
+
+/**
+ * `policy.next(prev)`, failing with `SequenceOverflow` when `prev` has no answer
+ */
+pub(crate) fn next_seq(policy: &dyn SequencePolicy, prev: i32) -> Result<i32> {
+  policy
+    .next(prev)
+    .ok_or(HandshakeError::SequenceOverflow { seq: prev })
+}
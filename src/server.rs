@@ -0,0 +1,727 @@
+/**
+ * Reusable server loops for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::fmt;
+use std::future::Future;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpListener as AsyncTcpListener, TcpStream as AsyncTcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::config::{HandshakeConfig, ServerOptions};
+use crate::cork::CorkedReplies;
+use crate::error::Result;
+use crate::limits::SourceLimiter;
+use crate::metrics::ServerMetrics;
+use crate::mux::run_async_mux_session;
+use crate::outcome::HandshakeOutcome;
+use crate::output::print_line;
+use crate::peer::PeerInfo;
+use crate::persistent::serve_persistent_async;
+use crate::protocol::{
+  BUSY_MESSAGE, OVERLOADED_MESSAGE, cancellable, perform_async_server_handshake_with_config,
+};
+use crate::proxy::resolve_async_client_addr;
+use crate::reflect::perform_async_server_handshake_reflect;
+use crate::reload::{InstrumentedConfig, LiveConfig};
+#[cfg(feature = "compression")]
+use crate::session::run_async_compressed_echo_session;
+#[cfg(not(feature = "compression"))]
+use crate::session::run_async_echo_session;
+use crate::time::timeout;
+use crate::utils::{apply_linger, apply_nodelay};
+
+/**
+ * Totals reported when a server loop exits
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServeSummary {
+  pub accepted: u64,
+  pub succeeded: u64,
+  pub failed: u64,
+  // Handshakes still running when the drain timeout elapsed
+  pub force_closed: u64,
+}
+
+impl ServeSummary {
+  fn record(&mut self, result: &std::result::Result<Result<()>, tokio::task::JoinError>) {
+    match result {
+      Ok(Ok(())) => self.succeeded += 1,
+      _ => self.failed += 1,
+    }
+  }
+}
+
+impl fmt::Display for ServeSummary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "accepted={} succeeded={} failed={} force_closed={}",
+      self.accepted, self.succeeded, self.failed, self.force_closed
+    )
+  }
+}
+
+/**
+ * Logs and counts a completed handshake that took longer than `config.slow_threshold`
+ * Returns whether the handshake was slow
+ */
+pub fn record_slow_handshake(
+  outcome: &HandshakeOutcome,
+  peer_addr: impl fmt::Display,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) -> bool {
+  if outcome.duration <= config.slow_threshold {
+    return false;
+  }
+  metrics.slow_handshakes.fetch_add(1, Ordering::Relaxed);
+  errln!(
+    config,
+    "WARNING: Slow handshake with {peer_addr}: took {:?} (threshold {:?})",
+    outcome.duration,
+    config.slow_threshold
+  );
+  true
+}
+
+/**
+ * Logs how long each step of a completed handshake took and adds them to the step histograms
+ */
+pub fn record_step_durations(
+  outcome: &HandshakeOutcome,
+  peer_addr: impl fmt::Display,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) {
+  metrics.record_step_durations(outcome);
+  let [hello_x, hello_y, hello_z] = outcome.step_durations;
+  outln!(
+    config,
+    "Handshake steps with {peer_addr}: HELLO X {hello_x:?}, HELLO Y {hello_y:?}, HELLO Z {hello_z:?}"
+  );
+}
+
+/**
+ * Records a completed handshake's timings and warns if some of its log output was lost
+ */
+pub(crate) fn record_completed_handshake(
+  outcome: &HandshakeOutcome,
+  peer_addr: impl fmt::Display,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) {
+  record_slow_handshake(outcome, &peer_addr, config, metrics);
+  record_step_durations(outcome, &peer_addr, config, metrics);
+  if outcome.logging_degraded {
+    errln!(
+      config,
+      "WARNING: Some log output for {peer_addr} could not be written to stdout"
+    );
+  }
+}
+
+/**
+ * Logs an accepted connection, as its full 5-tuple when `config.log_five_tuple` is set
+ * A local address the OS could not report is logged as `unknown`
+ */
+pub(crate) fn log_accepted(
+  local_addr: Option<SocketAddr>,
+  peer_addr: SocketAddr,
+  config: &HandshakeConfig,
+) {
+  if !config.log_five_tuple {
+    outln!(config, "Accepted connection from {peer_addr}");
+    return;
+  }
+  let local = local_addr.map_or_else(|| "unknown".to_string(), |addr| addr.to_string());
+  outln!(
+    config,
+    "Accepted connection proto=tcp local={local} peer={peer_addr}"
+  );
+}
+
+/**
+ * Refuses connections until the configured warmup period has passed
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct WarmupGate {
+  until: Option<Instant>,
+}
+
+impl WarmupGate {
+  /**
+   * Starts the warmup period now and records in `metrics` when it ends
+   * Only the first loop to start on a set of metrics records it
+   */
+  pub(crate) fn start(config: &HandshakeConfig, metrics: &ServerMetrics) -> Self {
+    let now = config.clock.now();
+    let until = config.warmup.map(|warmup| now + warmup);
+    let _ = metrics.ready_at.set(until.unwrap_or(now));
+    Self { until }
+  }
+
+  /**
+   * Returns true, after logging and counting the refusal, while still warming up
+   */
+  pub(crate) fn refuse(
+    &mut self,
+    peer_addr: SocketAddr,
+    config: &HandshakeConfig,
+    metrics: &ServerMetrics,
+  ) -> bool {
+    let Some(until) = self.until else {
+      return false;
+    };
+    let now = config.clock.now();
+    if now >= until {
+      // Warmed up for good; skip the clock from now on
+      self.until = None;
+      return false;
+    }
+
+    metrics.rejected_warmup.fetch_add(1, Ordering::Relaxed);
+    errln!(
+      config,
+      "Warming up for another {:?}, refused connection from {peer_addr}",
+      until - now
+    );
+    true
+  }
+}
+
+/**
+ * Refuses connections beyond `config.accept_rate_limit` in each fixed window
+ * A window starts with the first connection accepted after the previous one ended
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RateGate {
+  window_start: Instant,
+  // Connections let through in the current window
+  admitted: u32,
+}
+
+impl RateGate {
+  pub(crate) fn start(config: &HandshakeConfig) -> Self {
+    Self {
+      window_start: config.clock.now(),
+      admitted: 0,
+    }
+  }
+
+  /**
+   * Returns true, after logging and counting the refusal, once the window is full
+   */
+  pub(crate) fn refuse(
+    &mut self,
+    peer_addr: SocketAddr,
+    config: &HandshakeConfig,
+    metrics: &ServerMetrics,
+  ) -> bool {
+    // Read per connection so a reloaded limit applies at once
+    let Some(limit) = config.accept_rate_limit else {
+      return false;
+    };
+    let now = config.clock.now();
+    if now.duration_since(self.window_start) >= limit.window {
+      self.window_start = now;
+      self.admitted = 0;
+    }
+    if self.admitted < limit.max_connections {
+      self.admitted += 1;
+      return false;
+    }
+
+    metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+    errln!(
+      config,
+      "ERROR: Rate limit of {limit} reached, refused connection from {peer_addr}"
+    );
+    true
+  }
+}
+
+/**
+ * Tells a refused client the server is busy when `config.busy_response` is set
+ */
+pub(crate) fn send_busy_async(stream: AsyncTcpStream, config: &HandshakeConfig) {
+  if config.busy_response {
+    send_refusal_async(stream, BUSY_MESSAGE, config);
+  }
+}
+
+/**
+ * Sends `message` to a refused client before the connection is closed
+ * Best effort: the short write goes straight to the non-blocking socket, so
+ * the accept loop never waits on it
+ */
+pub(crate) fn send_refusal_async(stream: AsyncTcpStream, message: &str, config: &HandshakeConfig) {
+  if let Ok(mut stream) = stream.into_std() {
+    let _ = stream.write_all(&config.delimiter.frame(message));
+  }
+}
+
+// An accept that returns this quickly found a connection already queued
+const ACCEPT_READY_WINDOW: Duration = Duration::from_millis(1);
+
+/**
+ * Estimates how long each connection sat in the kernel accept queue
+ *
+ * The kernel does not report queue time, so the loop timestamps itself: when
+ * accept returns at once, the connection was already waiting, for at most as
+ * long as the loop was busy since the previous accept. When accept had to
+ * wait, the loop was idle and the connection was picked up immediately. A
+ * high estimate means the loop itself is the bottleneck, not the handlers.
+ */
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AcceptTimer {
+  last_accept: Option<Instant>,
+  // Previous accept, if the loop has been busy ever since
+  busy_since: Option<Instant>,
+  ready_at: Instant,
+}
+
+impl AcceptTimer {
+  pub(crate) fn start(config: &HandshakeConfig) -> Self {
+    Self {
+      last_accept: None,
+      busy_since: None,
+      ready_at: config.clock.now(),
+    }
+  }
+
+  /**
+   * Marks the loop as waiting in accept again
+   * Waking for anything but a connection forgets the previous accept, since idle time followed it
+   */
+  pub(crate) fn ready(&mut self, config: &HandshakeConfig) {
+    self.busy_since = self.last_accept.take();
+    self.ready_at = config.clock.now();
+  }
+
+  /**
+   * Updates the latency gauge for a connection just accepted, warning on a spike
+   */
+  pub(crate) fn accepted(
+    &mut self,
+    peer_addr: SocketAddr,
+    config: &HandshakeConfig,
+    metrics: &ServerMetrics,
+  ) {
+    let now = config.clock.now();
+    let waited = now.duration_since(self.ready_at);
+    let latency = match self.busy_since {
+      Some(since) if waited <= ACCEPT_READY_WINDOW => now.duration_since(since),
+      _ => Duration::ZERO,
+    };
+    self.last_accept = Some(now);
+
+    metrics
+      .accept_latency_us
+      .store(latency.as_micros() as u64, Ordering::Relaxed);
+    if latency > config.accept_latency_threshold {
+      metrics
+        .accept_latency_spikes
+        .fetch_add(1, Ordering::Relaxed);
+      errln!(
+        config,
+        "WARNING: Accept loop falling behind: {peer_addr} waited up to {latency:?} to be accepted \
+         (threshold {:?})",
+        config.accept_latency_threshold
+      );
+    }
+  }
+}
+
+/**
+ * Handles one accepted connection, stripping a PROXY header first if configured
+ * Logs the outcome against the client address picked by `config.peer_resolver`
+ */
+async fn handle_connection(
+  mut stream: AsyncTcpStream,
+  peer_addr: SocketAddr,
+  cancel: &CancellationToken,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) -> Result<()> {
+  let _active = metrics.track_connection();
+  let mut client_addr = peer_addr;
+
+  let result = async {
+    apply_linger(&stream, config)?;
+    apply_nodelay(&stream, config)?;
+    client_addr = resolve_async_client_addr(&mut stream, peer_addr, config).await?;
+    if client_addr != peer_addr {
+      outln!(
+        config,
+        "Connection from {peer_addr} resolved to client {client_addr}"
+      );
+    }
+    // A multiplexed connection carries its own tagged handshakes
+    if config.multiplex {
+      let session = run_async_mux_session(&mut stream, client_addr, config);
+      let stats = cancellable(Some(cancel), session).await?;
+      outln!(
+        config,
+        "Multiplexed connection with {client_addr} closed: {} completed, {} incomplete",
+        stats.completed,
+        stats.incomplete
+      );
+      return Ok(());
+    }
+
+    // A persistent connection runs handshakes until the client closes it
+    if config.persistent {
+      let completed = |outcome: &HandshakeOutcome| {
+        record_completed_handshake(outcome, client_addr, config, metrics)
+      };
+      let handshakes =
+        serve_persistent_async(&mut stream, client_addr, cancel, config, metrics, completed)
+          .await?;
+      outln!(
+        config,
+        "Persistent connection with {client_addr} closed after {handshakes} handshakes"
+      );
+      return Ok(());
+    }
+
+    // Corked, each reply leaves as one segment; the echo session below writes as usual
+    let mut replies = CorkedReplies::new(&mut stream, config.cork_replies);
+    let outcome = if config.reflect {
+      perform_async_server_handshake_reflect(&mut replies, client_addr, Some(cancel), config)
+        .await?
+    } else {
+      perform_async_server_handshake_with_config(&mut replies, client_addr, Some(cancel), config)
+        .await?
+    };
+    drop(replies);
+    record_completed_handshake(&outcome, client_addr, config, metrics);
+
+    if config.echo_session {
+      #[cfg(feature = "compression")]
+      let session = {
+        let compression = Compression::negotiate(outcome.capabilities);
+        run_async_compressed_echo_session(&mut stream, client_addr, compression, config)
+      };
+      #[cfg(not(feature = "compression"))]
+      let session = run_async_echo_session(&mut stream, client_addr, config);
+      let stats = cancellable(Some(cancel), session).await?;
+      outln!(
+        config,
+        "Session with {client_addr} ended after {} messages ({} bytes)",
+        stats.messages,
+        stats.bytes
+      );
+    }
+    Ok(())
+  };
+  // Every step of the handshake below is a child of the connection's span
+  #[cfg(feature = "otel")]
+  let result = tracing::Instrument::instrument(result, crate::otel::connection_span(peer_addr));
+  let result = result.await;
+
+  metrics.record_result(&result);
+  match &result {
+    Ok(_) => outln!(config, "Successfully handled connection from {client_addr}"),
+    Err(e) => errln!(config, "ERROR handling {client_addr}: {e}"),
+  }
+  result
+}
+
+/**
+ * Spawns the task handling connection `conn_id`
+ * With the `console` feature tokio-console lists it as `handshake-{conn_id}`
+ */
+fn spawn_handshake(
+  tasks: &mut JoinSet<Result<()>>,
+  #[cfg_attr(
+    not(all(feature = "console", tokio_unstable)),
+    expect(unused_variables)
+  )]
+  conn_id: u64,
+  handling: impl Future<Output = Result<()>> + Send + 'static,
+) {
+  #[cfg(all(feature = "console", tokio_unstable))]
+  tasks
+    .build_task()
+    .name(&format!("handshake-{conn_id}"))
+    .spawn(handling)
+    .expect("spawning on the current runtime cannot fail");
+  #[cfg(not(all(feature = "console", tokio_unstable)))]
+  tasks.spawn(handling);
+}
+
+/**
+ * Resolves when the process receives Ctrl-C or, on Unix, SIGTERM
+ * Both signals start the same graceful drain. On Unix the handlers are
+ * installed when this is called, not when the future is first polled, so a
+ * signal that arrives while the listener is still being set up is not lost.
+ * Which signal arrived is printed as `config` says.
+ */
+pub fn shutdown_signal(config: LiveConfig) -> impl Future<Output = ()> {
+  #[cfg(unix)]
+  {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let interrupt = signal(SignalKind::interrupt());
+    let terminate = signal(SignalKind::terminate());
+    async move {
+      match (interrupt, terminate) {
+        (Ok(mut interrupt), Ok(mut terminate)) => tokio::select! {
+          _ = interrupt.recv() => outln!(config.load(), "Received Ctrl-C"),
+          _ = terminate.recv() => outln!(config.load(), "Received SIGTERM"),
+        },
+        (interrupt, terminate) => {
+          if let Err(e) = terminate {
+            errln!(
+              config.load(),
+              "ERROR: Could not install SIGTERM handler: {e}"
+            );
+          }
+          match interrupt {
+            Ok(mut interrupt) => {
+              interrupt.recv().await;
+            }
+            Err(_) => {
+              let _ = tokio::signal::ctrl_c().await;
+            }
+          }
+          outln!(config.load(), "Received Ctrl-C");
+        }
+      }
+    }
+  }
+
+  #[cfg(not(unix))]
+  async move {
+    let _ = tokio::signal::ctrl_c().await;
+    outln!(config.load(), "Received Ctrl-C");
+  }
+}
+
+/**
+ * Resolves once `run_for` has elapsed, or never when it is `None`
+ * Race it against `shutdown_signal` to cap how long a server runs
+ */
+pub async fn run_timer(run_for: Option<Duration>, config: LiveConfig) {
+  match run_for {
+    Some(run_for) => {
+      tokio::time::sleep(run_for).await;
+      outln!(config.load(), "Run time of {run_for:?} elapsed");
+    }
+    None => std::future::pending().await,
+  }
+}
+
+/**
+ * Calls `on_signal` from a background thread on Ctrl-C or, on Unix, SIGTERM
+ * Blocking servers use this to start the same drain as the async server
+ */
+pub fn on_shutdown_signal(on_signal: impl FnOnce() + Send + 'static) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    std::thread::spawn(move || {
+      if let Some(signal) = signals.forever().next() {
+        let name = if signal == SIGTERM {
+          "SIGTERM"
+        } else {
+          "Ctrl-C"
+        };
+        // A closed stdout must not keep the server from stopping
+        let _ = print_line(format_args!("Received {name}"));
+        on_signal();
+      }
+    });
+  }
+
+  #[cfg(not(unix))]
+  {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+      .enable_all()
+      .build()?;
+    std::thread::spawn(move || {
+      if runtime.block_on(tokio::signal::ctrl_c()).is_ok() {
+        let _ = print_line(format_args!("Received Ctrl-C"));
+        on_signal();
+      }
+    });
+  }
+
+  Ok(())
+}
+
+/**
+ * Runs the async accept loop until `shutdown` resolves, then drains
+ *
+ * Each accepted connection uses the config current in `config` at that
+ * moment. In-flight handshakes get up to `config.drain_timeout` to finish
+ * while new connections are accepted only to be closed. Any handshakes still
+ * running after that are aborted and counted as `force_closed`. Of
+ * `options`, only `max_concurrent_handshakes` applies to this model.
+ *
+ * When the runtime that drives the listener shuts down, a pending accept
+ * fails with a shutdown error instead of a connection; that is treated like
+ * `shutdown` resolving, not logged as an accept error, and the drain stops
+ * accepting. If the runtime running this future shuts down instead, the
+ * future is dropped at its next await: the listener closes and every
+ * in-flight handshake task is aborted with it, without anything logged.
+ */
+pub async fn serve_async(
+  listener: AsyncTcpListener,
+  config: LiveConfig,
+  metrics: Arc<ServerMetrics>,
+  options: ServerOptions,
+  shutdown: impl Future<Output = ()>,
+) -> ServeSummary {
+  let mut live = InstrumentedConfig::new(config, &metrics);
+  let mut summary = ServeSummary::default();
+  let mut tasks = JoinSet::new();
+  let cancel = CancellationToken::new();
+  let limiter = Arc::new(SourceLimiter::new(live.get().max_connections_per_source));
+  // Bounds the connections handled at once, taken without waiting in the accept loop
+  let handshake_slots = options
+    .max_concurrent_handshakes
+    .map(|max| Arc::new(Semaphore::new(max)));
+  let mut warmup = WarmupGate::start(live.get(), &metrics);
+  let mut rate = RateGate::start(live.get());
+  let mut accept_timer = AcceptTimer::start(live.get());
+  // Cleared once the listener's runtime is gone and accept can only fail
+  let mut listening = true;
+  tokio::pin!(shutdown);
+
+  // Main async event loop
+  // Accept connections and spawn async tasks to handle them
+  loop {
+    accept_timer.ready(live.get());
+    tokio::select! {
+      _ = &mut shutdown => break,
+      // Reap finished tasks so the set does not grow without bound
+      Some(result) = tasks.join_next(), if !tasks.is_empty() => summary.record(&result),
+      accepted = listener.accept() => match accepted {
+        Ok((stream, peer_addr)) => {
+          let config = Arc::clone(live.refresh(&metrics));
+          accept_timer.accepted(peer_addr, &config, &metrics);
+          summary.accepted += 1;
+          metrics.accepted.fetch_add(1, Ordering::Relaxed);
+          let local_addr = stream.local_addr().ok();
+          log_accepted(local_addr, peer_addr, &config);
+          // Limits count the client the resolver names before any byte is read; the PROXY
+          // header comes later, in the handler, so behind a balancer that is the balancer
+          let source = config.peer_resolver.resolve(&PeerInfo::accepted(peer_addr, local_addr));
+
+          if warmup.refuse(source, &config, &metrics) {
+            continue;
+          }
+          if rate.refuse(source, &config, &metrics) {
+            send_busy_async(stream, &config);
+            continue;
+          }
+
+          // Refuse the connection when its source already has the maximum open
+          let Some(guard) = limiter.try_acquire(source.ip()) else {
+            metrics.per_source_rejected.fetch_add(1, Ordering::Relaxed);
+            errln!(config, "ERROR: Too many connections from {}, closed {peer_addr}", source.ip());
+            send_busy_async(stream, &config);
+            continue;
+          };
+
+          // Refuse the connection with OVERLOADED when every handshake slot is taken
+          let permit = match &handshake_slots {
+            Some(slots) => match Arc::clone(slots).try_acquire_owned() {
+              Ok(permit) => Some(permit),
+              Err(_) => {
+                metrics.overloaded.fetch_add(1, Ordering::Relaxed);
+                errln!(config, "ERROR: Too many concurrent handshakes, refused {peer_addr}");
+                send_refusal_async(stream, OVERLOADED_MESSAGE, &config);
+                continue;
+              }
+            },
+            None => None,
+          };
+
+          // Each connection is a lightweight task that runs independently
+          let config = Arc::clone(&config);
+          let metrics = Arc::clone(&metrics);
+          let cancel = cancel.clone();
+          let handling = async move {
+            // The guard and permit are released however the handler ends
+            let _guard = guard;
+            let _permit = permit;
+            handle_connection(stream, peer_addr, &cancel, &config, &metrics).await
+          };
+          spawn_handshake(&mut tasks, summary.accepted, handling);
+        }
+        Err(e) if tokio::runtime::is_rt_shutdown_err(&e) => {
+          outln!(live.get(), "Runtime is shutting down, no longer accepting connections");
+          listening = false;
+          break;
+        }
+        Err(e) => {
+          errln!(live.get(), "ERROR accepting connection: {e}");
+          // Continue accepting other connections
+        }
+      },
+    }
+  }
+
+  // From here on new connections are refused while in-flight handshakes finish
+  metrics.draining.store(true, Ordering::Relaxed);
+  outln!(
+    live.get(),
+    "Shutting down, draining {} in-flight connection(s)",
+    tasks.len()
+  );
+
+  let config = live.refresh(&metrics);
+  let drained = timeout(config.clock.as_ref(), config.drain_timeout, async {
+    loop {
+      tokio::select! {
+        result = tasks.join_next() => match result {
+          Some(result) => summary.record(&result),
+          None => break,
+        },
+        accepted = listener.accept(), if listening => match accepted {
+          Ok((stream, peer_addr)) => {
+            drop(stream);
+            errln!(config, "Draining, refused connection from {peer_addr}");
+          }
+          Err(e) => listening = !tokio::runtime::is_rt_shutdown_err(&e),
+        },
+      }
+    }
+  })
+  .await;
+  drop(listener);
+
+  if drained.is_err() {
+    summary.force_closed = tasks.len() as u64;
+    metrics
+      .force_closed
+      .fetch_add(summary.force_closed, Ordering::Relaxed);
+    errln!(
+      config,
+      "Drain timeout elapsed, force closing {} connection(s)",
+      summary.force_closed
+    );
+    // Aborting drops each task's socket, so even a task that ignores `cancel` cannot hold up shutdown
+    cancel.cancel();
+    tasks.abort_all();
+    while tasks.join_next().await.is_some() {}
+  }
+
+  summary
+}
@@ -0,0 +1,172 @@
+/**
+ * Post-handshake echo session for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Once the handshake succeeds the server can keep the connection open and echo
+ * every message back until the client closes it. With `chunked_session` set the
+ * messages are chunked payloads (see `chunked`), so they can be far larger than
+ * a handshake message. When the handshake negotiated a codec (see
+ * `compression`), `run_async_compressed_echo_session` compresses them on top.
+ * Message and byte limits stop an abusive peer from holding the session
+ * forever.
+ */
+use std::fmt;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::chunked::{recv_chunked, send_chunked};
+#[cfg(feature = "compression")]
+use crate::compression::{Compression, recv_compressed, send_compressed};
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::framing::GrowableBuffer;
+use crate::protocol::{is_reset, read_async_message, write_async_framed};
+
+/**
+ * Totals for a finished echo session
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionStats {
+  pub messages: u64,
+  pub bytes: u64,
+}
+
+/**
+ * How chunked session payloads are put on the wire
+ */
+trait PayloadCodec {
+  async fn recv<S: AsyncRead + Unpin>(
+    &self,
+    stream: &mut S,
+    config: &HandshakeConfig,
+  ) -> Result<Vec<u8>>;
+
+  async fn send<S: AsyncWrite + Unpin>(
+    &self,
+    stream: &mut S,
+    data: &[u8],
+    config: &HandshakeConfig,
+  ) -> Result<()>;
+}
+
+// Payloads sent as they are
+struct Plaintext;
+
+impl PayloadCodec for Plaintext {
+  async fn recv<S: AsyncRead + Unpin>(
+    &self,
+    stream: &mut S,
+    config: &HandshakeConfig,
+  ) -> Result<Vec<u8>> {
+    recv_chunked(stream, config).await
+  }
+
+  async fn send<S: AsyncWrite + Unpin>(
+    &self,
+    stream: &mut S,
+    data: &[u8],
+    config: &HandshakeConfig,
+  ) -> Result<()> {
+    send_chunked(stream, data, config).await
+  }
+}
+
+#[cfg(feature = "compression")]
+impl PayloadCodec for Compression {
+  async fn recv<S: AsyncRead + Unpin>(
+    &self,
+    stream: &mut S,
+    config: &HandshakeConfig,
+  ) -> Result<Vec<u8>> {
+    recv_compressed(stream, *self, config).await
+  }
+
+  async fn send<S: AsyncWrite + Unpin>(
+    &self,
+    stream: &mut S,
+    data: &[u8],
+    config: &HandshakeConfig,
+  ) -> Result<()> {
+    send_compressed(stream, data, *self, config).await
+  }
+}
+
+/**
+ * Echoes messages until the peer disconnects or a session limit is exceeded
+ * A connection reset between messages counts as a disconnect
+ */
+pub async fn run_async_echo_session<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  config: &HandshakeConfig,
+) -> Result<SessionStats> {
+  echo_session(stream, peer_addr, &Plaintext, config).await
+}
+
+/**
+ * Like `run_async_echo_session`, but chunked payloads are encoded with `compression`
+ * Limits count decompressed bytes; `Compression::None` behaves exactly like the plain session
+ */
+#[cfg(feature = "compression")]
+pub async fn run_async_compressed_echo_session<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  compression: Compression,
+  config: &HandshakeConfig,
+) -> Result<SessionStats> {
+  echo_session(stream, peer_addr, &compression, config).await
+}
+
+async fn echo_session<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  peer_addr: impl fmt::Display,
+  codec: &impl PayloadCodec,
+  config: &HandshakeConfig,
+) -> Result<SessionStats> {
+  let mut stats = SessionStats::default();
+  let mut pending = GrowableBuffer::default();
+
+  loop {
+    let received = if config.chunked_session {
+      codec.recv(stream, config).await
+    } else {
+      read_async_message(stream, &mut pending, config)
+        .await
+        .map(String::into_bytes)
+    };
+    let message = match received {
+      Ok(message) => message,
+      // A clean close is the normal way for a session to end
+      Err(HandshakeError::ClientDisconnected) => return Ok(stats),
+      // The handshake already delivered every required message, so a reset ends the session too
+      Err(HandshakeError::Io(e)) if is_reset(&e) => return Ok(stats),
+      Err(e) => return Err(e),
+    };
+
+    stats.messages += 1;
+    stats.bytes += message.len() as u64;
+
+    let exceeded = if stats.messages > config.max_session_messages {
+      Some(("messages", stats.messages, config.max_session_messages))
+    } else if stats.bytes > config.max_session_bytes {
+      Some(("bytes", stats.bytes, config.max_session_bytes))
+    } else {
+      None
+    };
+
+    if let Some((limit, value, max)) = exceeded {
+      errln!(
+        config,
+        "Ending session with {peer_addr}: {limit} limit of {max} exceeded"
+      );
+      return Err(HandshakeError::SessionLimitExceeded { limit, value, max });
+    }
+
+    if config.chunked_session {
+      codec.send(stream, &message, config).await?;
+    } else {
+      write_async_framed(stream, &String::from_utf8_lossy(&message), config).await?;
+    }
+  }
+}
@@ -0,0 +1,294 @@
+/**
+ * SOCKS5 client for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Opens a tunnel through a SOCKS5 proxy (RFC 1928) so the handshake can run
+ * over it unchanged. Only the CONNECT command is used, with either no
+ * authentication or username/password authentication (RFC 1929). Host names
+ * are passed to the proxy unresolved, which is what Tor expects.
+ */
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::error::{HandshakeError, Result};
+
+const SOCKS_VERSION: u8 = 0x05;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USER_PASS: u8 = 0x02;
+const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+const USER_PASS_VERSION: u8 = 0x01;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/**
+ * Username and password for RFC 1929 authentication
+ */
+#[derive(Clone, PartialEq, Eq)]
+pub struct Socks5Credentials {
+  pub username: String,
+  pub password: String,
+}
+
+// Keep the password out of logs
+impl fmt::Debug for Socks5Credentials {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Socks5Credentials")
+      .field("username", &self.username)
+      .finish_non_exhaustive()
+  }
+}
+
+/**
+ * Where the SOCKS5 proxy listens and how to authenticate to it
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+  pub host: String,
+  pub port: u16,
+  pub credentials: Option<Socks5Credentials>,
+}
+
+impl Socks5Proxy {
+  /**
+   * Parses `[user:pass@]host:port`; IPv6 hosts go in brackets
+   */
+  pub fn parse(spec: &str) -> Result<Self> {
+    let invalid = || {
+      HandshakeError::InvalidArguments(format!(
+        "invalid SOCKS5 proxy '{spec}', expected [user:pass@]host:port"
+      ))
+    };
+
+    let (credentials, address) = match spec.rsplit_once('@') {
+      Some((userinfo, address)) => {
+        let (username, password) = userinfo.split_once(':').ok_or_else(invalid)?;
+        let credentials = Socks5Credentials {
+          username: username.to_string(),
+          password: password.to_string(),
+        };
+        (Some(credentials), address)
+      }
+      None => (None, spec),
+    };
+    let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port.parse().map_err(|_| invalid())?;
+    if host.is_empty() {
+      return Err(invalid());
+    }
+
+    Ok(Self {
+      host: host.to_string(),
+      port,
+      credentials,
+    })
+  }
+}
+
+impl fmt::Display for Socks5Proxy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.host.parse::<IpAddr>() {
+      Ok(IpAddr::V6(_)) => write!(f, "[{}]:{}", self.host, self.port),
+      _ => write!(f, "{}:{}", self.host, self.port),
+    }
+  }
+}
+
+/**
+ * Negotiates a CONNECT to `host:port` over a stream already connected to the proxy
+ * On success the stream carries the tunneled connection
+ */
+pub fn socks5_handshake<S: Read + Write>(
+  stream: &mut S,
+  proxy: &Socks5Proxy,
+  host: &str,
+  port: u16,
+) -> Result<()> {
+  stream.write_all(&greeting(proxy))?;
+  let mut choice = [0u8; 2];
+  stream.read_exact(&mut choice)?;
+
+  if let Some(credentials) = check_method(choice, proxy)? {
+    stream.write_all(&auth_request(credentials)?)?;
+    let mut status = [0u8; 2];
+    stream.read_exact(&mut status)?;
+    check_auth(status)?;
+  }
+
+  stream.write_all(&connect_request(host, port)?)?;
+  let mut header = [0u8; 4];
+  stream.read_exact(&mut header)?;
+  // The address the proxy bound for us is read and discarded
+  let bound_len = match check_connect(header)? {
+    Some(len) => len,
+    None => {
+      let mut len = [0u8; 1];
+      stream.read_exact(&mut len)?;
+      len[0] as usize + 2
+    }
+  };
+  stream.read_exact(&mut vec![0u8; bound_len])?;
+  Ok(())
+}
+
+/**
+ * Async version: Negotiates a CONNECT to `host:port` over a stream already connected to the proxy
+ */
+pub async fn socks5_handshake_async<S: AsyncRead + AsyncWrite + Unpin>(
+  stream: &mut S,
+  proxy: &Socks5Proxy,
+  host: &str,
+  port: u16,
+) -> Result<()> {
+  stream.write_all(&greeting(proxy)).await?;
+  let mut choice = [0u8; 2];
+  stream.read_exact(&mut choice).await?;
+
+  if let Some(credentials) = check_method(choice, proxy)? {
+    stream.write_all(&auth_request(credentials)?).await?;
+    let mut status = [0u8; 2];
+    stream.read_exact(&mut status).await?;
+    check_auth(status)?;
+  }
+
+  stream.write_all(&connect_request(host, port)?).await?;
+  let mut header = [0u8; 4];
+  stream.read_exact(&mut header).await?;
+  let bound_len = match check_connect(header)? {
+    Some(len) => len,
+    None => {
+      let mut len = [0u8; 1];
+      stream.read_exact(&mut len).await?;
+      len[0] as usize + 2
+    }
+  };
+  stream.read_exact(&mut vec![0u8; bound_len]).await?;
+  Ok(())
+}
+
+fn socks_error(reason: impl Into<String>) -> HandshakeError {
+  HandshakeError::Socks5(reason.into())
+}
+
+/**
+ * Offers no authentication, plus username/password when credentials are configured
+ */
+fn greeting(proxy: &Socks5Proxy) -> Vec<u8> {
+  match proxy.credentials {
+    Some(_) => vec![SOCKS_VERSION, 2, METHOD_NO_AUTH, METHOD_USER_PASS],
+    None => vec![SOCKS_VERSION, 1, METHOD_NO_AUTH],
+  }
+}
+
+/**
+ * Checks the method the proxy picked, returning the credentials to send if it wants them
+ */
+fn check_method(choice: [u8; 2], proxy: &Socks5Proxy) -> Result<Option<&Socks5Credentials>> {
+  if choice[0] != SOCKS_VERSION {
+    return Err(socks_error(format!(
+      "proxy replied with version {}",
+      choice[0]
+    )));
+  }
+  match (choice[1], &proxy.credentials) {
+    (METHOD_NO_AUTH, _) => Ok(None),
+    (METHOD_USER_PASS, Some(credentials)) => Ok(Some(credentials)),
+    (METHOD_USER_PASS, None) => Err(socks_error("proxy requires a username and password")),
+    (METHOD_NONE_ACCEPTABLE, _) => Err(socks_error("proxy accepted none of the offered methods")),
+    (method, _) => Err(socks_error(format!(
+      "proxy chose unsupported method {method:#04x}"
+    ))),
+  }
+}
+
+fn auth_request(credentials: &Socks5Credentials) -> Result<Vec<u8>> {
+  let username = credentials.username.as_bytes();
+  let password = credentials.password.as_bytes();
+  let (Ok(username_len), Ok(password_len)) =
+    (u8::try_from(username.len()), u8::try_from(password.len()))
+  else {
+    return Err(socks_error(
+      "username and password must be at most 255 bytes",
+    ));
+  };
+
+  let mut request = vec![USER_PASS_VERSION, username_len];
+  request.extend_from_slice(username);
+  request.push(password_len);
+  request.extend_from_slice(password);
+  Ok(request)
+}
+
+fn check_auth(status: [u8; 2]) -> Result<()> {
+  match status[1] {
+    0x00 => Ok(()),
+    _ => Err(socks_error("proxy rejected the username or password")),
+  }
+}
+
+/**
+ * CONNECT request; IP literals are sent as addresses, anything else as a domain name
+ */
+fn connect_request(host: &str, port: u16) -> Result<Vec<u8>> {
+  let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0x00];
+  match host.parse::<IpAddr>() {
+    Ok(IpAddr::V4(ip)) => {
+      request.push(ATYP_IPV4);
+      request.extend_from_slice(&ip.octets());
+    }
+    Ok(IpAddr::V6(ip)) => {
+      request.push(ATYP_IPV6);
+      request.extend_from_slice(&ip.octets());
+    }
+    Err(_) => {
+      let len = u8::try_from(host.len())
+        .map_err(|_| socks_error(format!("host name '{host}' is longer than 255 bytes")))?;
+      request.push(ATYP_DOMAIN);
+      request.push(len);
+      request.extend_from_slice(host.as_bytes());
+    }
+  }
+  request.extend_from_slice(&port.to_be_bytes());
+  Ok(request)
+}
+
+/**
+ * Checks the CONNECT reply header, returning how many address and port bytes follow it
+ * `None` means a domain address, whose length byte comes first
+ */
+fn check_connect(header: [u8; 4]) -> Result<Option<usize>> {
+  if header[0] != SOCKS_VERSION {
+    return Err(socks_error(format!(
+      "proxy replied with version {}",
+      header[0]
+    )));
+  }
+  let reason = match header[1] {
+    0x00 => None,
+    0x01 => Some("general SOCKS server failure"),
+    0x02 => Some("connection not allowed by ruleset"),
+    0x03 => Some("network unreachable"),
+    0x04 => Some("host unreachable"),
+    0x05 => Some("connection refused"),
+    0x06 => Some("TTL expired"),
+    0x07 => Some("command not supported"),
+    0x08 => Some("address type not supported"),
+    _ => Some("unknown failure"),
+  };
+  if let Some(reason) = reason {
+    return Err(socks_error(format!("CONNECT failed: {reason}")));
+  }
+
+  match header[3] {
+    ATYP_IPV4 => Ok(Some(4 + 2)),
+    ATYP_IPV6 => Ok(Some(16 + 2)),
+    ATYP_DOMAIN => Ok(None),
+    atyp => Err(socks_error(format!("unknown address type {atyp:#04x}"))),
+  }
+}
@@ -0,0 +1,275 @@
+/**
+ * Step-by-step handshakes for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * `Handshake` runs the same protocol as `perform_client_handshake_with_config`,
+ * but as a state machine the caller drives. The protocol itself is a
+ * `HandshakeMachine`; this adds reading, writing and step timing over a
+ * `Read + Write` stream. Each `poll_step` advances at most
+ * one step: it writes HELLO X, reads and checks HELLO Y, or writes HELLO Z.
+ * The stream may be blocking or non-blocking. When it would block,
+ * `poll_step` returns `Pending` with its progress kept, so the caller can
+ * wait for readiness in its own event loop (`wants_write` says which
+ * readiness) and poll again. Nothing here sleeps or keeps a deadline; a
+ * handshake that stalls is the caller's to time out.
+ *
+ * Only the client role is implemented so far, without a preamble or
+ * `confirm_final`; `Handshake::new` refuses those.
+ */
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::MSG_SIZE;
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::framing::{GrowableBuffer, take_message};
+use crate::machine::{HandshakeMachine, Role};
+use crate::outcome::{HANDSHAKE_STEPS, HandshakeOutcome};
+use crate::output::HandshakeLog;
+use crate::protocol::{decode_sync_message, pad_message};
+use crate::transcript::Direction;
+
+/**
+ * What one `poll_step` call got to
+ */
+#[derive(Debug)]
+pub enum StepStatus {
+  // A step finished or the stream would block; poll again
+  Pending,
+  // The last step finished
+  Complete(HandshakeOutcome),
+  // The handshake failed; polling again keeps failing
+  Error(HandshakeError),
+}
+
+/**
+ * A message on its way out
+ */
+struct Sending {
+  step: u8,
+  message: String,
+  framed: Vec<u8>,
+  // Bytes of `framed` already written
+  written: usize,
+}
+
+/**
+ * A client handshake driven one step at a time
+ */
+pub struct Handshake {
+  machine: HandshakeMachine<'static>,
+  sending: Option<Sending>,
+  finished: bool,
+  // Bytes read but not yet taken as a message
+  pending: GrowableBuffer,
+  log: HandshakeLog,
+  started: Instant,
+  // End of the latest finished step
+  mark: Instant,
+  steps: [Duration; HANDSHAKE_STEPS],
+  rtt: Duration,
+}
+
+impl fmt::Debug for Handshake {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Handshake")
+      .field("next_step", &self.next_step())
+      .finish()
+  }
+}
+
+impl Handshake {
+  /**
+   * Prepares a handshake; nothing is sent until the first `poll_step`
+   * Fails with `InvalidArguments` for the server role, a preamble or `confirm_final`
+   */
+  pub fn new(role: Role, config: HandshakeConfig) -> Result<Self> {
+    if role == Role::Server {
+      return Err(HandshakeError::InvalidArguments(
+        "step-by-step handshakes support only the client role so far".to_string(),
+      ));
+    }
+    if config.preamble.is_some() || config.confirm_final.is_some() {
+      return Err(HandshakeError::InvalidArguments(
+        "step-by-step handshakes support neither a preamble nor confirm_final".to_string(),
+      ));
+    }
+
+    let started = config.clock.now();
+    let mut handshake = Self {
+      machine: HandshakeMachine::new(role, Cow::Owned(config)),
+      sending: None,
+      finished: false,
+      pending: GrowableBuffer::default(),
+      log: HandshakeLog::default(),
+      started,
+      mark: started,
+      steps: [Duration::ZERO; HANDSHAKE_STEPS],
+      rtt: Duration::ZERO,
+    };
+    handshake.queue_message();
+    Ok(handshake)
+  }
+
+  /**
+   * The step the next `poll_step` works on (1 for HELLO X), or `None` once finished
+   */
+  pub fn next_step(&self) -> Option<u8> {
+    if self.finished {
+      return None;
+    }
+    match &self.sending {
+      Some(sending) => Some(sending.step),
+      None => self.machine.next_step(),
+    }
+  }
+
+  /**
+   * Whether the next step writes, so an event loop knows to wait for writability
+   */
+  pub fn wants_write(&self) -> bool {
+    !self.finished && self.sending.is_some()
+  }
+
+  /**
+   * Advances the handshake by at most one step
+   * Returns `Pending` as soon as `stream` would block; call again once it is ready
+   */
+  pub fn poll_step<S: Read + Write>(&mut self, stream: &mut S) -> StepStatus {
+    if self.finished {
+      return StepStatus::Error(HandshakeError::ProtocolViolation(
+        "the handshake has already finished".to_string(),
+      ));
+    }
+    match self.advance(stream) {
+      Ok(Some(outcome)) => {
+        self.finished = true;
+        StepStatus::Complete(outcome)
+      }
+      Ok(None) => StepStatus::Pending,
+      Err(e) => {
+        self.finished = true;
+        StepStatus::Error(e)
+      }
+    }
+  }
+
+  /**
+   * Takes the machine's next message, if it has one, to be written
+   */
+  fn queue_message(&mut self) {
+    let step = self.machine.next_step();
+    if let (Some(step), Some(message)) = (step, self.machine.poll_message()) {
+      let config = self.machine.config();
+      self.sending = Some(Sending {
+        step,
+        framed: config.delimiter.frame(&pad_message(&message, config)),
+        message,
+        written: 0,
+      });
+    }
+  }
+
+  /**
+   * One step's worth of I/O; `Some` once the handshake is complete
+   */
+  fn advance<S: Read + Write>(&mut self, stream: &mut S) -> Result<Option<HandshakeOutcome>> {
+    if let Some(sending) = &mut self.sending {
+      if !write_some(stream, &sending.framed, &mut sending.written)? {
+        return Ok(None);
+      }
+      let Some(Sending { step, message, .. }) = self.sending.take() else {
+        return Ok(None);
+      };
+      let config = self.machine.config();
+      config.record_bytes_sent(message.len() + config.delimiter.as_bytes().len());
+      let elapsed = self.finish(step);
+      let config = self.machine.config();
+      config.emit_event(step, Direction::Outbound, &message, elapsed);
+      return Ok(self.machine.outcome().map(|outcome| HandshakeOutcome {
+        rtt: self.rtt,
+        duration: self.mark.duration_since(self.started),
+        step_durations: self.steps,
+        logging_degraded: self.log.degraded(),
+        ..outcome
+      }));
+    }
+
+    let Some(step) = self.machine.next_step() else {
+      return Ok(None);
+    };
+    let Some(received_msg) = self.read_message(stream)? else {
+      return Ok(None);
+    };
+    let elapsed = self.finish(step);
+    self.rtt = self.mark.duration_since(self.started);
+    let config = self.machine.config();
+    config.emit_event(step, Direction::Inbound, &received_msg, elapsed);
+    logln!(self.log, config, "{received_msg}");
+
+    // Step 3 goes out on the next poll
+    self.machine.handle_message(&received_msg)?;
+    self.queue_message();
+    Ok(None)
+  }
+
+  /**
+   * Reads until a whole message is in, or `None` when the stream would block first
+   * Without a delimiter one read is one message, as in the blocking handshake
+   */
+  fn read_message<S: Read>(&mut self, stream: &mut S) -> Result<Option<String>> {
+    let config = self.machine.config();
+    let delimiter = config.delimiter.as_bytes();
+    let mut buffer = [0u8; MSG_SIZE];
+    loop {
+      if !delimiter.is_empty()
+        && let Some(message) = take_message(&mut self.pending, delimiter, config.max_line_len)?
+      {
+        config.record_message_size(message.len());
+        return Ok(Some(decode_sync_message(&message)));
+      }
+      let bytes_read = match stream.read(&mut buffer) {
+        Ok(0) => return Err(HandshakeError::ClientDisconnected),
+        Ok(bytes_read) => bytes_read,
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+        Err(e) => return Err(e.into()),
+      };
+      if delimiter.is_empty() {
+        config.record_message_size(bytes_read);
+        return Ok(Some(decode_sync_message(&buffer[..bytes_read])));
+      }
+      self.pending.extend_from_slice(&buffer[..bytes_read]);
+    }
+  }
+
+  /**
+   * Ends `step` now and returns how long it took
+   */
+  fn finish(&mut self, step: u8) -> Duration {
+    let now = self.machine.config().clock.now();
+    let elapsed = now.duration_since(self.mark);
+    self.mark = now;
+    self.steps[usize::from(step) - 1] = elapsed;
+    elapsed
+  }
+}
+
+/**
+ * Writes from `written` on until `outgoing` is done, or returns `false` when the stream would block
+ */
+fn write_some<S: Write>(stream: &mut S, outgoing: &[u8], written: &mut usize) -> Result<bool> {
+  while *written < outgoing.len() {
+    match stream.write(&outgoing[*written..]) {
+      Ok(0) => return Err(std::io::Error::from(ErrorKind::WriteZero).into()),
+      Ok(count) => *written += count,
+      Err(e) if e.kind() == ErrorKind::Interrupted => {}
+      Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+      Err(e) => return Err(e.into()),
+    }
+  }
+  Ok(true)
+}
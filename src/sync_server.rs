@@ -0,0 +1,450 @@
+/**
+ * Blocking server loops for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The sequential, threaded and thread pool servers share one connection
+ * handler and differ only in where it runs. Each loop blocks in `accept` and
+ * returns once `stop` is cancelled; `stop_blocking_server` cancels it and
+ * wakes the pending `accept` with a local connection.
+ */
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{HandshakeConfig, ServerOptions};
+use crate::error::Result;
+use crate::limits::{ConcurrencyLimit, ConcurrencyPermit, SourceGuard, SourceLimiter};
+use crate::metrics::ServerMetrics;
+use crate::outcome::HandshakeOutcome;
+use crate::peer::PeerInfo;
+use crate::persistent::serve_persistent;
+use crate::pool::BoundedWorkerPool;
+use crate::protocol::{BUSY_MESSAGE, OVERLOADED_MESSAGE, perform_server_handshake_with_config};
+use crate::proxy::resolve_client_addr;
+use crate::reflect::perform_server_handshake_reflect;
+use crate::reload::{InstrumentedConfig, LiveConfig};
+use crate::server::{
+  AcceptTimer, RateGate, WarmupGate, log_accepted, on_shutdown_signal, record_completed_handshake,
+};
+
+// How often the thread pool drain checks for refused connections and drain completion
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/**
+ * Handles one accepted connection on the current thread
+ * Strips a PROXY header first when enabled, logs the outcome and counts it in `metrics`
+ */
+pub fn handle_sync_connection(
+  stream: TcpStream,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) -> Result<()> {
+  let _active = metrics.track_connection();
+  let result = run_sync_connection(stream, config, metrics);
+  metrics.record_result(&result);
+  result
+}
+
+fn run_sync_connection(
+  mut stream: TcpStream,
+  config: &HandshakeConfig,
+  metrics: &ServerMetrics,
+) -> Result<()> {
+  // Every step of the handshake below is a child of the connection's span
+  #[cfg(feature = "otel")]
+  let _span = stream
+    .peer_addr()
+    .ok()
+    .map(|peer_addr| crate::otel::connection_span(peer_addr).entered());
+  let peer_addr = match resolve_client_addr(&mut stream, config) {
+    Ok(addr) => addr.to_string(),
+    Err(e) => {
+      let socket_peer = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+      errln!(config, "ERROR: Handshake failed with {socket_peer}: {e}");
+      return Err(e);
+    }
+  };
+
+  // A persistent connection runs handshakes until the client closes it
+  if config.persistent {
+    let completed =
+      |outcome: &HandshakeOutcome| record_completed_handshake(outcome, &peer_addr, config, metrics);
+    return match serve_persistent(&mut stream, config, metrics, completed) {
+      Ok(handshakes) => {
+        outln!(
+          config,
+          "Successfully handled {handshakes} handshakes on connection from {peer_addr}"
+        );
+        Ok(())
+      }
+      Err(e) => {
+        errln!(config, "ERROR: Handshake failed with {peer_addr}: {e}");
+        Err(e)
+      }
+    };
+  }
+
+  // Reflect mode skips validation for client development
+  let result = if config.reflect {
+    perform_server_handshake_reflect(&mut stream, config)
+  } else {
+    perform_server_handshake_with_config(&mut stream, config)
+  };
+  match result {
+    Ok(outcome) => {
+      record_completed_handshake(&outcome, &peer_addr, config, metrics);
+      outln!(config, "Successfully handled connection from {peer_addr}");
+      Ok(())
+    }
+    Err(e) => {
+      errln!(config, "ERROR: Handshake failed with {peer_addr}: {e}");
+      Err(e)
+    }
+  }
+}
+
+/**
+ * Cancels `stop` and wakes a blocking server listening on `addr`
+ */
+pub fn stop_blocking_server(stop: &CancellationToken, addr: SocketAddr) {
+  stop.cancel();
+  let wake_addr = match addr {
+    SocketAddr::V4(v4) if v4.ip().is_unspecified() => SocketAddr::from(([127, 0, 0, 1], v4.port())),
+    SocketAddr::V6(v6) if v6.ip().is_unspecified() => {
+      SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, v6.port()))
+    }
+    addr => addr,
+  };
+  let _ = TcpStream::connect(wake_addr);
+}
+
+/**
+ * Returns a token cancelled on Ctrl-C or SIGTERM, or once `run_for` has elapsed
+ * Either way `listener` is woken so the loop notices
+ */
+pub fn stop_on_shutdown_signal(
+  listener: &TcpListener,
+  run_for: Option<Duration>,
+  config: LiveConfig,
+) -> Result<CancellationToken> {
+  let stop = CancellationToken::new();
+  let addr = listener.local_addr()?;
+
+  let signal_stop = stop.clone();
+  on_shutdown_signal(move || stop_blocking_server(&signal_stop, addr))?;
+
+  if let Some(run_for) = run_for {
+    let timer_stop = stop.clone();
+    thread::spawn(move || {
+      thread::sleep(run_for);
+      if !timer_stop.is_cancelled() {
+        outln!(config.load(), "Run time of {run_for:?} elapsed");
+        stop_blocking_server(&timer_stop, addr);
+      }
+    });
+  }
+  Ok(stop)
+}
+
+/**
+ * What one pass of the accept loop produced
+ */
+enum Accepted {
+  // With the config current when it was accepted
+  Connection(TcpStream, SocketAddr, SourceGuard, Arc<HandshakeConfig>),
+  // Refused or failed; keep accepting
+  Skipped,
+  Stopped,
+}
+
+/**
+ * Per-loop accept state: the live config, the warmup and rate gates and the accept queue timer
+ */
+struct AcceptState {
+  config: InstrumentedConfig,
+  warmup: WarmupGate,
+  rate: RateGate,
+  timer: AcceptTimer,
+}
+
+impl AcceptState {
+  fn start(config: LiveConfig, metrics: &ServerMetrics) -> Self {
+    let config = InstrumentedConfig::new(config, metrics);
+    Self {
+      warmup: WarmupGate::start(config.get(), metrics),
+      rate: RateGate::start(config.get()),
+      timer: AcceptTimer::start(config.get()),
+      config,
+    }
+  }
+}
+
+/**
+ * Accepts the next connection, counting it and applying the rate and per-source limits
+ * The connection gets the config current once it has been accepted
+ */
+fn accept_next(
+  listener: &TcpListener,
+  metrics: &ServerMetrics,
+  limiter: &Arc<SourceLimiter>,
+  state: &mut AcceptState,
+  stop: &CancellationToken,
+) -> Accepted {
+  state.timer.ready(state.config.get());
+  let accepted = listener.accept();
+  if stop.is_cancelled() {
+    return Accepted::Stopped;
+  }
+
+  match accepted {
+    Ok((mut stream, addr)) => {
+      let config = Arc::clone(state.config.refresh(metrics));
+      let config = config.as_ref();
+      state.timer.accepted(addr, config, metrics);
+      metrics.accepted.fetch_add(1, Ordering::Relaxed);
+      let local_addr = stream.local_addr().ok();
+      log_accepted(local_addr, addr, config);
+      // Limits count the client the resolver names before any byte is read; the PROXY header
+      // comes later, in the handler, so behind a balancer that is the balancer's address
+      let source = config
+        .peer_resolver
+        .resolve(&PeerInfo::accepted(addr, local_addr));
+
+      if state.warmup.refuse(source, config, metrics) {
+        return Accepted::Skipped;
+      }
+      if state.rate.refuse(source, config, metrics) {
+        send_busy(&mut stream, config);
+        return Accepted::Skipped;
+      }
+
+      // Refuse the connection when its source already has the maximum open
+      let Some(guard) = limiter.try_acquire(source.ip()) else {
+        metrics.per_source_rejected.fetch_add(1, Ordering::Relaxed);
+        errln!(
+          config,
+          "ERROR: Too many connections from {}, closed {addr} ({})",
+          source.ip(),
+          metrics.snapshot()
+        );
+        send_busy(&mut stream, config);
+        return Accepted::Skipped;
+      };
+      Accepted::Connection(stream, addr, guard, Arc::clone(state.config.get()))
+    }
+    Err(e) => {
+      errln!(
+        state.config.get(),
+        "ERROR: Failed to accept connection: {e}"
+      );
+      // Continue accepting other connections
+      Accepted::Skipped
+    }
+  }
+}
+
+/**
+ * Tells a refused client the server is busy when `config.busy_response` is set
+ * Best effort: the connection is closed right after either way
+ */
+fn send_busy(stream: &mut TcpStream, config: &HandshakeConfig) {
+  if config.busy_response {
+    let _ = stream.write_all(&config.delimiter.frame(BUSY_MESSAGE));
+  }
+}
+
+/**
+ * Tells a client refused by `options.max_concurrent_handshakes` that the server is overloaded
+ */
+fn send_overloaded(stream: &mut TcpStream, config: &HandshakeConfig) {
+  let _ = stream.write_all(&config.delimiter.frame(OVERLOADED_MESSAGE));
+}
+
+/**
+ * Handles one client at a time until `stop` is cancelled
+ */
+pub fn serve_sequential(
+  listener: TcpListener,
+  config: &LiveConfig,
+  metrics: &ServerMetrics,
+  stop: &CancellationToken,
+) {
+  let mut state = AcceptState::start(Arc::clone(config), metrics);
+  let limiter = Arc::new(SourceLimiter::new(
+    state.config.get().max_connections_per_source,
+  ));
+
+  loop {
+    match accept_next(&listener, metrics, &limiter, &mut state, stop) {
+      // Continue to next client regardless of handshake result
+      Accepted::Connection(stream, _, _guard, config) => {
+        let _ = handle_sync_connection(stream, &config, metrics);
+      }
+      Accepted::Skipped => {}
+      Accepted::Stopped => break,
+    }
+  }
+  // Nothing is accepted from here on
+  metrics.draining.store(true, Ordering::Relaxed);
+}
+
+/**
+ * Spawns a thread per client until `stop` is cancelled
+ * Threads still running when the loop returns are left to finish on their own
+ */
+pub fn serve_threaded(
+  listener: TcpListener,
+  config: LiveConfig,
+  metrics: Arc<ServerMetrics>,
+  stop: &CancellationToken,
+) {
+  let mut state = AcceptState::start(config, &metrics);
+  let limiter = Arc::new(SourceLimiter::new(
+    state.config.get().max_connections_per_source,
+  ));
+
+  loop {
+    match accept_next(&listener, &metrics, &limiter, &mut state, stop) {
+      // Move the stream and source guard into the thread to transfer ownership
+      Accepted::Connection(stream, _, guard, config) => {
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+          let _ = handle_sync_connection(stream, &config, &metrics);
+          drop(guard);
+        });
+      }
+      Accepted::Skipped => {}
+      Accepted::Stopped => break,
+    }
+  }
+  // Nothing is accepted from here on
+  metrics.draining.store(true, Ordering::Relaxed);
+}
+
+// A connection queued for a thread pool worker with everything it holds until handled
+type PoolJob = (
+  TcpStream,
+  SourceGuard,
+  Option<ConcurrencyPermit>,
+  Arc<HandshakeConfig>,
+);
+
+/**
+ * Feeds a bounded worker pool until `stop` is cancelled, then drains it
+ *
+ * The pool runs `options.worker_count()` workers behind a queue of
+ * `options.queue_capacity` connections. With
+ * `options.max_concurrent_handshakes` set, a connection holds a permit
+ * from accept until its worker finishes, queued or running, and a connection
+ * that finds none left is refused with `OVERLOADED`. While draining, new
+ * connections are closed immediately, and queued or running handshakes get
+ * up to `config.drain_timeout` to finish. Returns whether every handshake
+ * finished in time. With one worker, connections are handled strictly in the
+ * order they were accepted, as the sequential server handles them.
+ */
+pub fn serve_threadpool(
+  listener: TcpListener,
+  config: LiveConfig,
+  metrics: Arc<ServerMetrics>,
+  options: ServerOptions,
+  stop: &CancellationToken,
+) -> bool {
+  let mut state = AcceptState::start(config, &metrics);
+  let limiter = Arc::new(SourceLimiter::new(
+    state.config.get().max_connections_per_source,
+  ));
+  let handshake_slots = options
+    .max_concurrent_handshakes
+    .map(|max| Arc::new(ConcurrencyLimit::new(max)));
+  let worker_metrics = Arc::clone(&metrics);
+  let pool = BoundedWorkerPool::new(
+    options.worker_count(),
+    options.queue_capacity,
+    Arc::clone(&metrics),
+    // The source guard and permit travel with the job and are released when the worker finishes
+    move |(stream, _guard, _permit, config): PoolJob| {
+      let _ = handle_sync_connection(stream, &config, &worker_metrics);
+    },
+  );
+
+  // Main server loop - hand connections to the workers through the queue
+  loop {
+    match accept_next(&listener, &metrics, &limiter, &mut state, stop) {
+      Accepted::Connection(mut stream, addr, guard, config) => {
+        // Refuse the connection with OVERLOADED when every handshake slot is taken
+        let permit = match &handshake_slots {
+          Some(slots) => match slots.try_acquire() {
+            Some(permit) => Some(permit),
+            None => {
+              metrics.overloaded.fetch_add(1, Ordering::Relaxed);
+              errln!(
+                config,
+                "ERROR: Too many concurrent handshakes, refused {addr}"
+              );
+              send_overloaded(&mut stream, &config);
+              continue;
+            }
+          },
+          None => None,
+        };
+
+        // Backpressure: close the connection when every queue slot is taken
+        if let Err((mut stream, _, _, config)) = pool.try_submit((stream, guard, permit, config)) {
+          send_busy(&mut stream, &config);
+          errln!(
+            config,
+            "ERROR: Queue full, closed connection from {addr} ({})",
+            metrics.snapshot()
+          );
+        }
+      }
+      Accepted::Skipped => {}
+      Accepted::Stopped => break,
+    }
+  }
+
+  // Give queued and running handshakes a bounded time to finish on another thread
+  metrics.draining.store(true, Ordering::Relaxed);
+  outln!(
+    state.config.get(),
+    "Shutting down, draining {} outstanding connection(s)",
+    pool.outstanding()
+  );
+  let drain_timeout = state.config.refresh(&metrics).drain_timeout;
+  let (done, drained) = mpsc::channel();
+  thread::spawn(move || {
+    let _ = done.send(pool.shutdown(drain_timeout));
+  });
+
+  // Meanwhile refuse new connections so clients fail fast instead of queueing
+  if let Err(e) = listener.set_nonblocking(true) {
+    errln!(
+      state.config.get(),
+      "ERROR: Could not stop blocking on accept: {e}"
+    );
+  }
+  loop {
+    while let Ok((stream, addr)) = listener.accept() {
+      drop(stream);
+      errln!(
+        state.config.get(),
+        "Draining, refused connection from {addr}"
+      );
+    }
+    match drained.recv_timeout(DRAIN_POLL_INTERVAL) {
+      Ok(drained) => return drained,
+      Err(RecvTimeoutError::Timeout) => continue,
+      Err(RecvTimeoutError::Disconnected) => return false,
+    }
+  }
+}
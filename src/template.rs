@@ -0,0 +1,113 @@
+/**
+ * Server response templates for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * A template replaces the server's `HELLO Y` reply for interop testing.
+ * Placeholders are substituted per reply:
+ *
+ * ```text
+ * {seq}    the server sequence Y (required)
+ * {nonce}  a fresh nonce the client must echo in its final HELLO
+ * {{ }}    literal braces
+ * ```
+ *
+ * Templates are checked when they are parsed, so a bad one is rejected
+ * before the server starts rather than on the first connection.
+ */
+use crate::error::{HandshakeError, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+  Literal(String),
+  Seq,
+  Nonce,
+}
+
+/**
+ * A validated reply template
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseTemplate {
+  parts: Vec<Part>,
+}
+
+impl ResponseTemplate {
+  /**
+   * Parses and validates a template
+   * Unknown placeholders, unbalanced braces and a missing `{seq}` are rejected
+   */
+  pub fn parse(template: &str) -> Result<Self> {
+    let invalid = |reason: &str| HandshakeError::InvalidResponseTemplate {
+      template: template.to_string(),
+      reason: reason.to_string(),
+    };
+
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+      match c {
+        '{' if chars.as_str().starts_with('{') => {
+          chars.next();
+          literal.push('{');
+        }
+        '}' if chars.as_str().starts_with('}') => {
+          chars.next();
+          literal.push('}');
+        }
+        '{' => {
+          let rest = chars.as_str();
+          let end = rest.find('}').ok_or_else(|| invalid("unclosed '{'"))?;
+          let part = match &rest[..end] {
+            "seq" => Part::Seq,
+            "nonce" => Part::Nonce,
+            name => return Err(invalid(&format!("unknown placeholder '{{{name}}}'"))),
+          };
+          if !literal.is_empty() {
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+          }
+          parts.push(part);
+          chars = rest[end + 1..].chars();
+        }
+        '}' => return Err(invalid("unmatched '}'")),
+        c => literal.push(c),
+      }
+    }
+    if !literal.is_empty() {
+      parts.push(Part::Literal(literal));
+    }
+
+    if !parts.contains(&Part::Seq) {
+      return Err(invalid("missing '{seq}'"));
+    }
+    Ok(Self { parts })
+  }
+
+  /**
+   * Whether replies carry a nonce that the client must echo
+   */
+  pub fn uses_nonce(&self) -> bool {
+    self.parts.contains(&Part::Nonce)
+  }
+
+  /**
+   * Fills in the placeholders
+   * `{nonce}` expands to nothing when no nonce is given
+   */
+  pub fn render(&self, seq: i32, nonce: Option<u64>) -> String {
+    let mut reply = String::new();
+    for part in &self.parts {
+      match part {
+        Part::Literal(text) => reply.push_str(text),
+        Part::Seq => reply.push_str(&seq.to_string()),
+        Part::Nonce => {
+          if let Some(nonce) = nonce {
+            reply.push_str(&nonce.to_string());
+          }
+        }
+      }
+    }
+    reply
+  }
+}
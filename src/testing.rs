@@ -0,0 +1,70 @@
+/**
+ * Test server for crates built on 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * `TestServer` runs the async server on an ephemeral loopback port with its
+ * own runtime on a background thread, so it works from plain `#[test]`s and
+ * `#[tokio::test]`s alike. The server stops when the handle is dropped.
+ * Enabled with the `testing` feature.
+ */
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::config::HandshakeConfig;
+use crate::error::Result;
+use crate::harness::{RunningServer, ServerModel, spawn_server};
+use crate::metrics::ServerMetrics;
+
+/**
+ * Handle to a running async handshake server, shut down on drop
+ */
+pub struct TestServer {
+  // Taken by `Drop`
+  server: Option<RunningServer>,
+}
+
+impl TestServer {
+  /**
+   * Starts a server with the default config
+   */
+  pub fn start() -> Result<Self> {
+    Self::start_with_config(HandshakeConfig::default())
+  }
+
+  /**
+   * Starts a server with the given config
+   */
+  pub fn start_with_config(config: HandshakeConfig) -> Result<Self> {
+    let server = spawn_server(ServerModel::Async, config)?;
+    Ok(Self {
+      server: Some(server),
+    })
+  }
+
+  /**
+   * Loopback address the server is listening on
+   */
+  pub fn addr(&self) -> SocketAddr {
+    self.running().addr
+  }
+
+  /**
+   * Live counters of the running server
+   */
+  pub fn metrics(&self) -> &Arc<ServerMetrics> {
+    &self.running().metrics
+  }
+
+  fn running(&self) -> &RunningServer {
+    self.server.as_ref().expect("server runs until dropped")
+  }
+}
+
+impl Drop for TestServer {
+  fn drop(&mut self) {
+    if let Some(server) = self.server.take() {
+      server.stop();
+    }
+  }
+}
@@ -0,0 +1,109 @@
+/**
+ * Pluggable time source for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+use crate::error::{HandshakeError, Result};
+
+/**
+ * Boxed sleep future returned by a clock
+ */
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/**
+ * Source of the current time and of sleeps used for handshake timeouts
+ */
+pub trait Clock: fmt::Debug + Send + Sync {
+  fn now(&self) -> Instant;
+
+  fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/**
+ * Real clock backed by the Tokio timer
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+  fn now(&self) -> Instant {
+    tokio::time::Instant::now().into_std()
+  }
+
+  fn sleep(&self, duration: Duration) -> Sleep {
+    Box::pin(tokio::time::sleep(duration))
+  }
+}
+
+/**
+ * Manually driven clock for deterministic tests
+ * Time only moves when `advance` is called, waking any sleep whose deadline has passed
+ */
+#[derive(Debug, Clone)]
+pub struct MockClock {
+  current: Arc<watch::Sender<Instant>>,
+}
+
+impl MockClock {
+  pub fn new() -> Self {
+    let (current, _) = watch::channel(Instant::now());
+    Self {
+      current: Arc::new(current),
+    }
+  }
+
+  /**
+   * Moves the clock forward, releasing sleeps that are now due
+   */
+  pub fn advance(&self, duration: Duration) {
+    self.current.send_modify(|now| *now += duration);
+  }
+}
+
+impl Default for MockClock {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Clock for MockClock {
+  fn now(&self) -> Instant {
+    *self.current.borrow()
+  }
+
+  fn sleep(&self, duration: Duration) -> Sleep {
+    let deadline = self.now() + duration;
+    let mut changes = self.current.subscribe();
+
+    Box::pin(async move {
+      while *changes.borrow_and_update() < deadline {
+        if changes.changed().await.is_err() {
+          // The clock is gone, so time can never reach the deadline
+          std::future::pending::<()>().await;
+        }
+      }
+    })
+  }
+}
+
+/**
+ * Runs a future to completion unless the clock's sleep finishes first
+ */
+pub async fn timeout<F: Future>(
+  clock: &dyn Clock,
+  duration: Duration,
+  future: F,
+) -> Result<F::Output> {
+  tokio::select! {
+    output = future => Ok(output),
+    _ = clock.sleep(duration) => Err(HandshakeError::Timeout),
+  }
+}
@@ -0,0 +1,119 @@
+/**
+ * Plaintext and TLS on one port for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * `DualAcceptor` serves plaintext and TLS clients on the same listener. It
+ * peeks at the first byte of each connection without consuming it: a TLS
+ * ClientHello always opens with a handshake record (`0x16`), which no text
+ * message starts with. TLS connections are then decrypted first, and both
+ * kinds run the same application handshake. Only available with the `tls`
+ * feature.
+ */
+use std::fmt;
+use std::sync::Arc;
+
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::server::TlsStream;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::HandshakeConfig;
+use crate::error::{HandshakeError, Result};
+use crate::outcome::HandshakeOutcome;
+use crate::protocol::{cancellable, perform_async_server_handshake_with_config};
+use crate::time::timeout;
+
+// Content type of a TLS handshake record, the first byte of every ClientHello
+pub const TLS_HANDSHAKE_RECORD: u8 = 0x16;
+
+/**
+ * Whether a connection's first byte opens a TLS handshake
+ */
+pub fn looks_like_tls(first_byte: u8) -> bool {
+  first_byte == TLS_HANDSHAKE_RECORD
+}
+
+/**
+ * A connection after its application handshake, still open for the caller
+ */
+pub enum MaybeTlsStream {
+  Plain(TcpStream),
+  Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl MaybeTlsStream {
+  /**
+   * Whether the client connected with TLS
+   */
+  pub fn is_tls(&self) -> bool {
+    matches!(self, MaybeTlsStream::Tls(_))
+  }
+}
+
+/**
+ * Accepts plaintext and TLS handshakes on the same port
+ */
+#[derive(Clone)]
+pub struct DualAcceptor {
+  tls: TlsAcceptor,
+}
+
+impl DualAcceptor {
+  /**
+   * Creates an acceptor that terminates TLS with `tls_config`
+   */
+  pub fn new(tls_config: Arc<ServerConfig>) -> Self {
+    Self {
+      tls: TlsAcceptor::from(tls_config),
+    }
+  }
+
+  /**
+   * Classifies a freshly accepted connection and runs the server handshake over it
+   *
+   * The wait for the first byte is bounded by `config.first_byte_timeout`, or
+   * `config.read_timeout` if unset. The TLS handshake and the application
+   * handshake both count against `config.connection_timeout`.
+   */
+  pub async fn accept(
+    &self,
+    stream: TcpStream,
+    peer_addr: impl fmt::Display,
+    cancel: Option<&CancellationToken>,
+    config: &HandshakeConfig,
+  ) -> Result<(MaybeTlsStream, HandshakeOutcome)> {
+    let clock = config.clock.as_ref();
+    let wait = config.first_byte_timeout.unwrap_or(config.read_timeout);
+    let mut first = [0u8; 1];
+    let peeked = cancellable(cancel, async {
+      timeout(clock, wait, stream.peek(&mut first))
+        .await
+        .map_err(|_| HandshakeError::FirstByteTimeout)?
+        .map_err(HandshakeError::from)
+    })
+    .await?;
+    if peeked == 0 {
+      return Err(HandshakeError::ClientDisconnected);
+    }
+
+    if !looks_like_tls(first[0]) {
+      let mut stream = stream;
+      let outcome =
+        perform_async_server_handshake_with_config(&mut stream, &peer_addr, cancel, config).await?;
+      return Ok((MaybeTlsStream::Plain(stream), outcome));
+    }
+
+    let mut stream = cancellable(cancel, async {
+      timeout(clock, config.connection_timeout, self.tls.accept(stream))
+        .await?
+        .map_err(HandshakeError::from)
+    })
+    .await?;
+    outln!(config, "TLS established with {peer_addr}");
+    let outcome =
+      perform_async_server_handshake_with_config(&mut stream, &peer_addr, cancel, config).await?;
+    Ok((MaybeTlsStream::Tls(Box::new(stream)), outcome))
+  }
+}
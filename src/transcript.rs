@@ -0,0 +1,118 @@
+/**
+ * Recorded session transcripts for 3-way Handshake Protocol
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Transcript format, one entry per line:
+ *
+ * ```text
+ * <direction> <delay_ms> <payload>
+ * ```
+ *
+ * - direction: `>` for a message we send, `<` for a message we expect to receive
+ * - delay_ms: milliseconds to wait before performing this entry
+ * - payload: rest of the line after the separating whitespace, sent verbatim
+ *   apart from the escapes `\n`, `\r`, `\t`, `\0`, `\\` and `\xNN`
+ *
+ * Blank lines and lines starting with `#` are ignored. Example:
+ *
+ * ```text
+ * > 0   HELLO 5
+ * < 0   HELLO 6
+ * > 250 HELLO 7
+ * ```
+ */
+use std::time::Duration;
+
+use crate::error::{HandshakeError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  Outbound,
+  Inbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptEntry {
+  pub direction: Direction,
+  pub delay: Duration,
+  pub payload: Vec<u8>,
+}
+
+/**
+ * Parses a whole transcript, reporting the first bad line
+ */
+pub fn parse_transcript(text: &str) -> Result<Vec<TranscriptEntry>> {
+  let mut entries = Vec::new();
+
+  for (index, line) in text.lines().enumerate() {
+    let line_number = index + 1;
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+
+    let invalid = |reason: &str| HandshakeError::InvalidTranscript {
+      line: line_number,
+      reason: reason.to_string(),
+    };
+
+    let (direction, rest) = match trimmed.split_at(1) {
+      (">", rest) => (Direction::Outbound, rest),
+      ("<", rest) => (Direction::Inbound, rest),
+      _ => return Err(invalid("expected '>' or '<' direction")),
+    };
+
+    let rest = rest.trim_start();
+    let delay_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let delay_ms: u64 = rest[..delay_end]
+      .parse()
+      .map_err(|_| invalid("expected delay in milliseconds"))?;
+
+    // Leading whitespace in a payload has to be written as `\x20`
+    let payload = rest[delay_end..].trim_start();
+    let payload = unescape_payload(payload).map_err(|reason| invalid(&reason))?;
+
+    entries.push(TranscriptEntry {
+      direction,
+      delay: Duration::from_millis(delay_ms),
+      payload,
+    });
+  }
+
+  Ok(entries)
+}
+
+/**
+ * Expands the escapes supported in transcript payloads
+ */
+fn unescape_payload(payload: &str) -> std::result::Result<Vec<u8>, String> {
+  let mut bytes = Vec::with_capacity(payload.len());
+  let mut chars = payload.chars();
+
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      let mut encoded = [0u8; 4];
+      bytes.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+      continue;
+    }
+
+    match chars.next() {
+      Some('n') => bytes.push(b'\n'),
+      Some('r') => bytes.push(b'\r'),
+      Some('t') => bytes.push(b'\t'),
+      Some('0') => bytes.push(0),
+      Some('\\') => bytes.push(b'\\'),
+      Some('x') => {
+        let hex: String = chars.by_ref().take(2).collect();
+        let byte =
+          u8::from_str_radix(&hex, 16).map_err(|_| format!("invalid hex escape '\\x{hex}'"))?;
+        bytes.push(byte);
+      }
+      Some(other) => return Err(format!("unknown escape '\\{other}'")),
+      None => return Err("dangling '\\' at end of line".to_string()),
+    }
+  }
+
+  Ok(bytes)
+}
@@ -4,77 +4,987 @@
  * Author: Sae-Hwan Park
  */
 use std::env;
-use std::net::TcpListener;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+#[cfg(unix)]
+use std::os::unix::io::{BorrowedFd, FromRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::net::SocketAddr as UnixSocketAddr;
+
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
 
 // Async imports
-use tokio::net::TcpListener as AsyncTcpListener;
+use tokio::net::{
+  TcpListener as AsyncTcpListener, TcpSocket as AsyncTcpSocket, TcpStream as AsyncTcpStream,
+  lookup_host,
+};
+#[cfg(target_os = "linux")]
+use tokio::net::{UnixListener as AsyncUnixListener, UnixStream as AsyncUnixStream};
 
+use crate::capabilities::Capabilities;
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+use crate::config::{HandshakeConfig, ServerOptions};
 use crate::error::{HandshakeError, Result};
+use crate::limits::RateLimit;
+#[cfg(feature = "otel")]
+use crate::otel::OtelConfig;
+use crate::outcome::ConnectPhases;
+use crate::preamble::Preamble;
+use crate::protocol::{CLIENT_CONNECTION_TIMEOUT, random_initial_seq};
+use crate::results::DEFAULT_RESULTS_PATH;
+use crate::socks5::{Socks5Proxy, socks5_handshake, socks5_handshake_async};
+use crate::template::ResponseTemplate;
+use crate::time::timeout;
+
+/**
+ * Client command line options
+ */
+#[derive(Debug, Clone)]
+pub struct ClientArgs {
+  pub server_ip: String,
+  pub port: u16,
+  // Unused when `seq_file` is set; `random` on the command line picks one
+  pub initial_seq: i32,
+  // Keep the connection open this long after a successful handshake
+  pub hold: Option<Duration>,
+  // File of initial sequences, one handshake per line
+  pub seq_file: Option<String>,
+  // CSV file that per-run outcomes are appended to
+  pub results_path: String,
+  // Testing only: sent verbatim in place of the first HELLO
+  pub raw_first: Option<String>,
+  // Local address to bind before connecting
+  pub bind: Option<SocketAddr>,
+  // Wait for the server to accept or reject HELLO Z (client-sync only)
+  pub confirm_final: bool,
+  // Tunnel the connection through this SOCKS5 proxy
+  pub socks5: Option<Socks5Proxy>,
+  // Prefix every message with `<namespace>:`; must match the server
+  pub namespace: Option<String>,
+  // Source address claimed with `IP=` in the first HELLO
+  pub claim_ip: Option<IpAddr>,
+  // Expect the server's READY greeting and answer START before HELLO
+  pub preamble: bool,
+  // Bind to the first free source port in this range
+  pub source_port_range: Option<RangeInclusive<u16>>,
+  // Offer these capabilities in HELLO X
+  pub capabilities: Option<Capabilities>,
+  // Fail unless the server shares these capabilities
+  pub required_capabilities: Capabilities,
+}
 
 /**
  * Parses client command line arguments
- * Returns (server_ip, port, initial_sequence)
+ * Returns the positional arguments plus any optional flags
+ */
+pub fn parse_client_args() -> Result<ClientArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <server_ip> <server_port> (<initial_sequence> | random | --seq-file <path>) \
+       [--results <csv>] [--hold-ms <ms>] [--raw-first <message>] [--bind <addr>] \
+       [--confirm-final] [--socks5 <[user:pass@]host:port>] [--namespace <name>] \
+       [--claim-ip <addr>] [--preamble] [--source-port-range <first>-<last>] \
+       [--capabilities <hex>] [--require-capabilities <hex>]",
+      args[0]
+    ))
+  };
+
+  let mut positional = Vec::new();
+  let mut hold = None;
+  let mut seq_file = None;
+  let mut results_path = DEFAULT_RESULTS_PATH.to_string();
+  let mut raw_first = None;
+  let mut bind = None;
+  let mut confirm_final = false;
+  let mut socks5 = None;
+  let mut namespace = None;
+  let mut claim_ip = None;
+  let mut preamble = false;
+  let mut source_port_range = None;
+  let mut capabilities = None;
+  let mut required_capabilities = Capabilities::NONE;
+
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--hold-ms" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let millis: u64 = value.parse().map_err(|_| {
+          HandshakeError::InvalidArguments(format!("invalid hold duration '{value}'"))
+        })?;
+        hold = Some(Duration::from_millis(millis));
+      }
+      "--seq-file" => seq_file = Some(rest.next().ok_or_else(usage)?.clone()),
+      "--results" => results_path = rest.next().ok_or_else(usage)?.clone(),
+      "--raw-first" => raw_first = Some(rest.next().ok_or_else(usage)?.clone()),
+      "--bind" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let addr = value.parse().map_err(|_| {
+          HandshakeError::InvalidArguments(format!("invalid bind address '{value}'"))
+        })?;
+        bind = Some(addr);
+      }
+      "--confirm-final" => confirm_final = true,
+      "--socks5" => socks5 = Some(Socks5Proxy::parse(rest.next().ok_or_else(usage)?)?),
+      "--namespace" => namespace = Some(parse_namespace(rest.next().ok_or_else(usage)?)?),
+      "--claim-ip" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let ip = value.parse().map_err(|_| {
+          HandshakeError::InvalidArguments(format!("invalid claimed address '{value}'"))
+        })?;
+        claim_ip = Some(ip);
+      }
+      "--preamble" => preamble = true,
+      "--source-port-range" => {
+        source_port_range = Some(parse_port_range(rest.next().ok_or_else(usage)?)?);
+      }
+      "--capabilities" => capabilities = Some(rest.next().ok_or_else(usage)?.parse()?),
+      "--require-capabilities" => required_capabilities = rest.next().ok_or_else(usage)?.parse()?,
+      flag if flag.starts_with("--") => return Err(usage()),
+      value => positional.push(value),
+    }
+  }
+
+  // A sequence file replaces the single initial sequence argument
+  let (server_ip, port, initial_seq) = match (&seq_file, &positional[..]) {
+    (None, [server_ip, port, initial_seq]) => (*server_ip, *port, *initial_seq),
+    (Some(_), [server_ip, port]) => (*server_ip, *port, "0"),
+    _ => return Err(usage()),
+  };
+
+  let port: u16 = port
+    .parse()
+    .map_err(|_| HandshakeError::InvalidPort(port.to_string()))?;
+
+  let initial_seq = match initial_seq {
+    "random" => random_initial_seq(&mut rand::rng()),
+    value => value
+      .parse()
+      .map_err(|_| HandshakeError::InvalidSequenceNumber(value.to_string()))?,
+  };
+
+  Ok(ClientArgs {
+    server_ip: server_ip.to_string(),
+    port,
+    initial_seq,
+    hold,
+    seq_file,
+    results_path,
+    raw_first,
+    bind,
+    confirm_final,
+    socks5,
+    namespace,
+    claim_ip,
+    preamble,
+    source_port_range,
+    // Requiring features implies offering them
+    capabilities: capabilities
+      .or((!required_capabilities.is_empty()).then_some(required_capabilities)),
+    required_capabilities,
+  })
+}
+
+/**
+ * Parses replay client command line arguments
+ * Returns (server_ip, port, transcript_path)
  */
-pub fn parse_client_args() -> Result<(String, u16, i32)> {
+pub fn parse_replay_args() -> Result<(String, u16, String)> {
   let args: Vec<String> = env::args().collect();
 
   if args.len() != 4 {
     return Err(HandshakeError::InvalidArguments(format!(
-      "Usage: {} <server_ip> <server_port> <initial_sequence>",
+      "Usage: {} <server_ip> <server_port> <transcript_file>",
       args[0]
     )));
   }
 
-  let server_ip = args[1].clone();
-
   let port: u16 = args[2]
     .parse()
     .map_err(|_| HandshakeError::InvalidPort(args[2].clone()))?;
 
-  let initial_seq: i32 = args[3]
-    .parse()
-    .map_err(|_| HandshakeError::InvalidSequenceNumber(args[3].clone()))?;
+  Ok((args[1].clone(), port, args[3].clone()))
+}
 
-  Ok((server_ip, port, initial_seq))
+// Server flags by what they affect, listed after the usage line
+const SERVER_OPTIONS: &str = "\
+Server:
+  --workers <n>  --queue-capacity <n>  --max-concurrent <n>
+  --run-for <duration>  --ready-socket <path>  --config <file>
+Limits:
+  --max-per-source <n>  --max-messages <n>  --rate-limit <n>/<duration>
+  --warmup-ms <ms>  --first-byte-timeout <duration>  --busy-response
+Handshake:
+  --proxy-protocol  --nonce  --namespace <name>  --response-template <template>
+  --verify-client-ip  --strict-client-ip  --preamble
+  --capabilities <hex>  --require-capabilities <hex>  --compress
+Sessions:
+  --echo-session  --chunked-session  --reflect  --multiplex  --persistent
+Output:
+  --profile <default|throughput>  --log-five-tuple  --otel-endpoint <url>";
+
+/**
+ * Server command line options
+ */
+#[derive(Debug, Clone)]
+pub struct ServerArgs {
+  // 0 when serving `listen_fd` instead
+  pub port: u16,
+  // Worker, queue and concurrency settings adjusted by flags
+  pub options: ServerOptions,
+  // Handshake settings adjusted by flags
+  pub config: HandshakeConfig,
+  // Shut down gracefully after this long
+  pub run_for: Option<Duration>,
+  // TOML file layered over the flags and read again on SIGHUP
+  pub config_file: Option<PathBuf>,
+  // Unix socket answering OK or NOTREADY to readiness checks
+  pub ready_socket: Option<PathBuf>,
+  // Already-bound listening socket to serve instead of binding `port`, e.g. from systemd (Unix only)
+  pub listen_fd: Option<i32>,
+  // OTLP collector the connection and step spans are exported to
+  #[cfg(feature = "otel")]
+  pub otel: Option<OtelConfig>,
 }
 
 /**
  * Parses server command line arguments
- * Returns port number
+ * Returns the port plus any optional flags
  */
-pub fn parse_server_args() -> Result<u16> {
+pub fn parse_server_args() -> Result<ServerArgs> {
   let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <server_port | --fd <n>> [options]\n{SERVER_OPTIONS}",
+      args[0]
+    ))
+  };
 
-  if args.len() != 2 {
+  let mut port = None;
+  let mut options = ServerOptions::default();
+  // A server keeps handshaking when its stdout goes away, warning on stderr instead
+  let mut config = HandshakeConfig {
+    tolerate_log_failures: true,
+    ..HandshakeConfig::default()
+  };
+  let mut run_for = None;
+  let mut config_file = None;
+  let mut ready_socket = None;
+  let mut listen_fd = None;
+  #[cfg(feature = "compression")]
+  let mut compress = false;
+  #[cfg(feature = "otel")]
+  let mut otel = None;
+
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--queue-capacity" => {
+        let value = rest.next().ok_or_else(usage)?;
+        options.queue_capacity = value
+          .parse()
+          .ok()
+          .filter(|&capacity| capacity > 0)
+          .ok_or_else(|| {
+            HandshakeError::InvalidArguments(format!("invalid queue capacity '{value}'"))
+          })?;
+      }
+      "--max-per-source" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let max = value.parse().ok().filter(|&max| max > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid per-source limit '{value}'"))
+        })?;
+        config.max_connections_per_source = Some(max);
+      }
+      "--max-concurrent" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let max = value.parse().ok().filter(|&max| max > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid concurrency limit '{value}'"))
+        })?;
+        options.max_concurrent_handshakes = Some(max);
+      }
+      "--workers" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let workers = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid worker count '{value}'"))
+        })?;
+        options.workers = Some(workers);
+      }
+      "--max-messages" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let max = value.parse().ok().filter(|&max| max > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid message limit '{value}'"))
+        })?;
+        config.max_connection_messages = Some(max);
+      }
+      "--warmup-ms" => {
+        let value = rest.next().ok_or_else(usage)?;
+        let millis: u64 = value.parse().map_err(|_| {
+          HandshakeError::InvalidArguments(format!("invalid warmup duration '{value}'"))
+        })?;
+        config.warmup = Some(Duration::from_millis(millis));
+      }
+      "--run-for" => run_for = Some(parse_duration(rest.next().ok_or_else(usage)?)?),
+      "--config" => config_file = Some(PathBuf::from(rest.next().ok_or_else(usage)?)),
+      "--ready-socket" => ready_socket = Some(PathBuf::from(rest.next().ok_or_else(usage)?)),
+      "--fd" => {
+        let value = rest.next().ok_or_else(usage)?;
+        if cfg!(not(unix)) {
+          return Err(HandshakeError::InvalidArguments(
+            "--fd is only supported on Unix".to_string(),
+          ));
+        }
+        let fd = value
+          .parse()
+          .ok()
+          .filter(|&fd: &i32| fd >= 0)
+          .ok_or_else(|| {
+            HandshakeError::InvalidArguments(format!("invalid file descriptor '{value}'"))
+          })?;
+        listen_fd = Some(fd);
+      }
+      "--response-template" => {
+        let template = ResponseTemplate::parse(rest.next().ok_or_else(usage)?)?;
+        config.response_template = Some(template);
+      }
+      "--namespace" => config.namespace = Some(parse_namespace(rest.next().ok_or_else(usage)?)?),
+      "--rate-limit" => {
+        config.accept_rate_limit = Some(RateLimit::parse(rest.next().ok_or_else(usage)?)?);
+      }
+      "--busy-response" => config.busy_response = true,
+      "--first-byte-timeout" => {
+        config.first_byte_timeout = Some(parse_duration(rest.next().ok_or_else(usage)?)?);
+      }
+      "--proxy-protocol" => config.proxy_protocol = true,
+      "--log-five-tuple" => config.log_five_tuple = true,
+      "--echo-session" => config.echo_session = true,
+      "--chunked-session" => {
+        config.echo_session = true;
+        config.chunked_session = true;
+      }
+      "--reflect" => config.reflect = true,
+      "--multiplex" => config.multiplex = true,
+      "--persistent" => config.persistent = true,
+      "--nonce" => config.require_nonce = true,
+      "--verify-client-ip" => config.verify_client_ip = true,
+      "--strict-client-ip" => {
+        config.verify_client_ip = true;
+        config.strict_client_ip = true;
+      }
+      "--preamble" => config.preamble = Some(Preamble::default()),
+      "--capabilities" => config.capabilities = Some(rest.next().ok_or_else(usage)?.parse()?),
+      "--require-capabilities" => {
+        config.required_capabilities = rest.next().ok_or_else(usage)?.parse()?;
+      }
+      #[cfg(feature = "compression")]
+      "--compress" => compress = true,
+      "--profile" => config.apply_profile(rest.next().ok_or_else(usage)?.parse()?),
+      #[cfg(feature = "otel")]
+      "--otel-endpoint" => {
+        otel = Some(OtelConfig {
+          endpoint: rest.next().ok_or_else(usage)?.clone(),
+          ..OtelConfig::default()
+        });
+      }
+      flag if flag.starts_with("--") => return Err(usage()),
+      value if port.is_none() => {
+        port = Some(
+          value
+            .parse()
+            .map_err(|_| HandshakeError::InvalidPort(value.to_string()))?,
+        );
+      }
+      _ => return Err(usage()),
+    }
+  }
+  // Requiring features implies offering them
+  if config.capabilities.is_none() && !config.required_capabilities.is_empty() {
+    config.capabilities = Some(config.required_capabilities);
+  }
+  // Offering compression adds the codecs to whatever else is offered
+  #[cfg(feature = "compression")]
+  if compress {
+    let offered = config.capabilities.unwrap_or_default();
+    config.capabilities = Some(offered | Compression::SUPPORTED);
+  }
+
+  // Persistent connections run strict handshakes and nothing else
+  if config.persistent && (config.reflect || config.multiplex || config.echo_session) {
+    return Err(HandshakeError::InvalidArguments(
+      "--persistent cannot be combined with --reflect, --multiplex or --echo-session".to_string(),
+    ));
+  }
+
+  // Either a port to bind or an inherited listener, never both
+  let port = match (port, listen_fd) {
+    (Some(port), None) => port,
+    (None, Some(_)) => 0,
+    _ => return Err(usage()),
+  };
+
+  Ok(ServerArgs {
+    port,
+    options,
+    config,
+    run_for,
+    config_file,
+    ready_socket,
+    listen_fd,
+    #[cfg(feature = "otel")]
+    otel,
+  })
+}
+
+/**
+ * Parses a duration such as `10s`, `500ms` or `2m`; a bare number means seconds
+ */
+pub fn parse_duration(value: &str) -> Result<Duration> {
+  let invalid = || HandshakeError::InvalidArguments(format!("invalid duration '{value}'"));
+  let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+    Some(split) => value.split_at(split),
+    None => (value, "s"),
+  };
+  let number: u64 = number.parse().map_err(|_| invalid())?;
+
+  match unit {
+    "ms" => Ok(Duration::from_millis(number)),
+    "s" => Ok(Duration::from_secs(number)),
+    "m" => Ok(Duration::from_secs(number * 60)),
+    _ => Err(invalid()),
+  }
+}
+
+/**
+ * Parses a source port range such as `40000-40100`; both ends are included
+ */
+fn parse_port_range(value: &str) -> Result<RangeInclusive<u16>> {
+  let invalid = || HandshakeError::InvalidArguments(format!("invalid source port range '{value}'"));
+  let (first, last) = value.split_once('-').ok_or_else(invalid)?;
+  let first: u16 = first.parse().map_err(|_| invalid())?;
+  let last: u16 = last.parse().map_err(|_| invalid())?;
+  if first == 0 || first > last {
+    return Err(invalid());
+  }
+  Ok(first..=last)
+}
+
+/**
+ * Checks a message namespace given on the command line
+ * It must be non-empty and free of whitespace and `:`, which separates it from the message
+ */
+fn parse_namespace(value: &str) -> Result<String> {
+  if value.is_empty() || value.contains(|c: char| c == ':' || c.is_whitespace()) {
     return Err(HandshakeError::InvalidArguments(format!(
-      "Usage: {} <server_port>",
-      args[0]
+      "invalid namespace '{value}'"
     )));
   }
+  Ok(value.to_string())
+}
+
+/**
+ * Exponential backoff between retries
+ *
+ * Delays start at `base` and grow by `factor` each time, never exceeding `max`.
+ * A `jitter` of 0.2 scales each delay by a random amount in [0.8, 1.2] (still
+ * capped at `max`) so retrying peers spread out. Only durations are returned;
+ * the caller sleeps on whichever runtime it uses.
+ */
+#[derive(Debug, Clone)]
+pub struct Backoff {
+  pub base: Duration,
+  pub max: Duration,
+  pub factor: f64,
+  // Fraction of each delay that is randomized, from 0.0 to 1.0
+  pub jitter: f64,
+  // Delay before jitter that the next call hands out
+  current: Duration,
+}
+
+impl Backoff {
+  pub fn new(base: Duration, max: Duration, factor: f64, jitter: f64) -> Self {
+    Self {
+      base,
+      max,
+      factor,
+      jitter,
+      current: base.min(max),
+    }
+  }
+
+  /**
+   * Returns the delay to wait before the next retry and advances the schedule
+   */
+  pub fn next_delay(&mut self) -> Duration {
+    let delay = self.current;
+    self.current = Duration::try_from_secs_f64(delay.as_secs_f64() * self.factor)
+      .unwrap_or(self.max)
+      .min(self.max);
+
+    let jitter = self.jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+      return delay;
+    }
+    let scale = rand::random_range(1.0 - jitter..=1.0 + jitter);
+    delay.mul_f64(scale).min(self.max)
+  }
+
+  /**
+   * Starts the schedule over from `base`, e.g. after a successful attempt
+   */
+  pub fn reset(&mut self) {
+    self.current = self.base.min(self.max);
+  }
+}
+
+/**
+ * Benchmark command line options
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct BenchArgs {
+  // Handshakes driven against each server model
+  pub connections: usize,
+  // Client threads running handshakes at the same time
+  pub concurrency: usize,
+  // Batching window for the async server's batched-reply runs, in milliseconds
+  pub reply_window_ms: usize,
+}
+
+/**
+ * Parses benchmark command line arguments
+ */
+pub fn parse_bench_args() -> Result<BenchArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} [--connections <n>] [--concurrency <n>] [--reply-window <ms>]",
+      args[0]
+    ))
+  };
+
+  let mut bench = BenchArgs {
+    connections: 1000,
+    concurrency: 16,
+    reply_window_ms: 1,
+  };
+
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    let target = match arg.as_str() {
+      "--connections" => &mut bench.connections,
+      "--concurrency" => &mut bench.concurrency,
+      "--reply-window" => &mut bench.reply_window_ms,
+      _ => return Err(usage()),
+    };
+    let value = rest.next().ok_or_else(usage)?;
+    *target = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+      HandshakeError::InvalidArguments(format!("invalid value '{value}' for {arg}"))
+    })?;
+  }
+
+  Ok(bench)
+}
+
+/**
+ * Server model probe command line options
+ */
+#[derive(Debug, Clone)]
+pub struct ProbeArgs {
+  pub server_ip: String,
+  pub port: u16,
+  // Handshakes run while one connection is held open without sending HELLO
+  pub connections: usize,
+  // How long those handshakes get before the held connection is released
+  pub window: Duration,
+}
+
+/**
+ * Parses server model probe command line arguments
+ */
+pub fn parse_probe_args() -> Result<ProbeArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <server_ip> <server_port> [--connections <n>] [--window <duration>]",
+      args[0]
+    ))
+  };
+
+  let mut positional = Vec::new();
+  let mut connections = 4;
+  let mut window = Duration::from_secs(1);
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--connections" => {
+        let value = rest.next().ok_or_else(usage)?;
+        connections = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid value '{value}' for {arg}"))
+        })?;
+      }
+      "--window" => window = parse_duration(rest.next().ok_or_else(usage)?)?,
+      flag if flag.starts_with("--") => return Err(usage()),
+      value => positional.push(value),
+    }
+  }
+
+  let [server_ip, port] = positional[..] else {
+    return Err(usage());
+  };
+  let port: u16 = port
+    .parse()
+    .map_err(|_| HandshakeError::InvalidPort(port.to_string()))?;
+
+  Ok(ProbeArgs {
+    server_ip: server_ip.to_string(),
+    port,
+    connections,
+    window,
+  })
+}
+
+/**
+ * Conformance client command line options
+ */
+#[derive(Debug, Clone)]
+pub struct ConformanceArgs {
+  pub server_ip: String,
+  pub port: u16,
+  // How long each case waits for the server to reply or close
+  pub timeout: Duration,
+}
+
+/**
+ * Parses conformance client command line arguments
+ */
+pub fn parse_conformance_args() -> Result<ConformanceArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <server_ip> <server_port> [--timeout <duration>]",
+      args[0]
+    ))
+  };
+
+  let mut positional = Vec::new();
+  let mut timeout = CLIENT_CONNECTION_TIMEOUT;
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--timeout" => timeout = parse_duration(rest.next().ok_or_else(usage)?)?,
+      flag if flag.starts_with("--") => return Err(usage()),
+      value => positional.push(value),
+    }
+  }
+
+  let [server_ip, port] = positional[..] else {
+    return Err(usage());
+  };
+  let port: u16 = port
+    .parse()
+    .map_err(|_| HandshakeError::InvalidPort(port.to_string()))?;
+
+  Ok(ConformanceArgs {
+    server_ip: server_ip.to_string(),
+    port,
+    timeout,
+  })
+}
+
+/**
+ * Fan-out client command line options
+ */
+#[derive(Debug, Clone)]
+pub struct FanoutArgs {
+  // Servers to handshake with, as host and port, in the order they were given
+  pub targets: Vec<(String, u16)>,
+  // How many handshakes run at once
+  pub concurrency: usize,
+  // Exit successfully even when some targets failed
+  pub allow_failures: bool,
+}
+
+// Handshakes the fan-out client runs at once unless `--concurrency` says otherwise
+pub const DEFAULT_FANOUT_CONCURRENCY: usize = 16;
+
+/**
+ * Parses fan-out client command line arguments
+ * Targets are `host:port`, with IPv6 hosts in brackets, given inline or one per line in
+ * `--targets-file`, where blank lines and `#` comments are skipped
+ */
+pub fn parse_fanout_args() -> Result<FanoutArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <host:port>... [--targets-file <path>] [--concurrency <n>] [--allow-failures]",
+      args[0]
+    ))
+  };
+
+  let mut specs = Vec::new();
+  let mut concurrency = DEFAULT_FANOUT_CONCURRENCY;
+  let mut allow_failures = false;
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--targets-file" => {
+        let path = rest.next().ok_or_else(usage)?;
+        let text = std::fs::read_to_string(path)?;
+        specs.extend(
+          text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string),
+        );
+      }
+      "--concurrency" => {
+        let value = rest.next().ok_or_else(usage)?;
+        concurrency = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+          HandshakeError::InvalidArguments(format!("invalid value '{value}' for {arg}"))
+        })?;
+      }
+      "--allow-failures" => allow_failures = true,
+      flag if flag.starts_with("--") => return Err(usage()),
+      value => specs.push(value.to_string()),
+    }
+  }
+
+  if specs.is_empty() {
+    return Err(usage());
+  }
+  let targets = specs
+    .iter()
+    .map(|spec| parse_target(spec))
+    .collect::<Result<_>>()?;
+
+  Ok(FanoutArgs {
+    targets,
+    concurrency,
+    allow_failures,
+  })
+}
+
+/**
+ * Persistent client command line options
+ */
+#[derive(Debug, Clone)]
+pub struct PersistentArgs {
+  pub server_ip: String,
+  pub port: u16,
+  // Handshakes run back to back on the one connection
+  pub handshakes: usize,
+  // Sequence every handshake starts from; a fresh random one for each when unset
+  pub initial_seq: Option<i32>,
+}
+
+/**
+ * Parses persistent client command line arguments
+ */
+pub fn parse_persistent_args() -> Result<PersistentArgs> {
+  let args: Vec<String> = env::args().collect();
+  let usage = || {
+    HandshakeError::InvalidArguments(format!(
+      "Usage: {} <server_ip> <server_port> <handshakes> [--initial-seq <n>]",
+      args[0]
+    ))
+  };
+
+  let mut positional = Vec::new();
+  let mut initial_seq = None;
+  let mut rest = args.iter().skip(1);
+  while let Some(arg) = rest.next() {
+    match arg.as_str() {
+      "--initial-seq" => {
+        let value = rest.next().ok_or_else(usage)?;
+        initial_seq = Some(
+          value
+            .parse()
+            .map_err(|_| HandshakeError::InvalidSequenceNumber(value.to_string()))?,
+        );
+      }
+      flag if flag.starts_with("--") => return Err(usage()),
+      value => positional.push(value),
+    }
+  }
 
-  let port: u16 = args[1]
+  let [server_ip, port, handshakes] = positional[..] else {
+    return Err(usage());
+  };
+  let port: u16 = port
     .parse()
-    .map_err(|_| HandshakeError::InvalidPort(args[1].clone()))?;
+    .map_err(|_| HandshakeError::InvalidPort(port.to_string()))?;
+  let handshakes = handshakes.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+    HandshakeError::InvalidArguments(format!("invalid handshake count '{handshakes}'"))
+  })?;
 
-  Ok(port)
+  Ok(PersistentArgs {
+    server_ip: server_ip.to_string(),
+    port,
+    handshakes,
+    initial_seq,
+  })
+}
+
+/**
+ * Splits a `host:port` target, unwrapping a bracketed IPv6 host
+ */
+fn parse_target(spec: &str) -> Result<(String, u16)> {
+  let (host, port) = spec
+    .rsplit_once(':')
+    .ok_or_else(|| HandshakeError::InvalidArguments(format!("target '{spec}' is not host:port")))?;
+  let host = host.trim_start_matches('[').trim_end_matches(']');
+  if host.is_empty() {
+    return Err(HandshakeError::InvalidArguments(format!(
+      "target '{spec}' has no host"
+    )));
+  }
+  let port = port
+    .parse()
+    .map_err(|_| HandshakeError::InvalidPort(port.to_string()))?;
+  Ok((host.to_string(), port))
+}
+
+/**
+ * Turns a failed listener bind into `PortInUse` when the port is taken
+ */
+fn bind_error(port: u16, error: std::io::Error) -> HandshakeError {
+  match error.kind() {
+    std::io::ErrorKind::AddrInUse => HandshakeError::PortInUse(port),
+    _ => HandshakeError::Io(error),
+  }
 }
 
 /**
  * Creates and binds a TCP listener
+ * A port another socket is listening on fails with `PortInUse`
  */
 pub fn create_listener(port: u16) -> Result<TcpListener> {
   let bind_addr = format!("0.0.0.0:{port}");
-  let listener = TcpListener::bind(&bind_addr).map_err(|e| HandshakeError::Io(e))?;
+  let listener = TcpListener::bind(&bind_addr).map_err(|e| bind_error(port, e))?;
 
-  println!("Listening on {bind_addr}");
+  // Port 0 picks a free port, so report the one actually bound
+  let bound = listener
+    .local_addr()
+    .map_or(bind_addr, |addr| addr.to_string());
+  println!("Listening on {bound}");
   Ok(listener)
 }
 
+/**
+ * Unix only: Takes over an already-bound listening TCP socket, e.g. one passed by systemd
+ *
+ * `fd` must be an open descriptor this process owns and nothing else uses;
+ * the listener closes it when dropped. Anything but a TCP socket that is
+ * listening is refused and left open.
+ */
+#[cfg(unix)]
+pub fn create_listener_from_fd(fd: RawFd) -> Result<TcpListener> {
+  check_listening_socket(fd)?;
+  // SAFETY: `fd` is an open TCP listening socket, and the caller hands its ownership over
+  let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+  let bound = listener.local_addr()?;
+  println!("Listening on {bound} (inherited fd {fd})");
+  Ok(listener)
+}
+
+/**
+ * Fails with `InvalidArguments` unless `fd` is a TCP socket that is listening
+ */
+#[cfg(unix)]
+fn check_listening_socket(fd: RawFd) -> Result<()> {
+  let not_listening = |reason: &dyn std::fmt::Display| {
+    HandshakeError::InvalidArguments(format!("fd {fd} is not a listening TCP socket: {reason}"))
+  };
+  // SAFETY: only used for the checks below, while the caller still owns `fd`
+  let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+  let socket = SockRef::from(&borrowed);
+
+  let kind = socket.r#type().map_err(|e| not_listening(&e))?;
+  if kind != Type::STREAM {
+    return Err(not_listening(&"not a stream socket"));
+  }
+  let local = socket.local_addr().map_err(|e| not_listening(&e))?;
+  if local.as_socket().is_none() {
+    return Err(not_listening(&"not an IP socket"));
+  }
+  #[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux"
+  ))]
+  if !socket.is_listener().map_err(|e| not_listening(&e))? {
+    return Err(not_listening(&"listen() was never called on it"));
+  }
+  Ok(())
+}
+
+/**
+ * Creates the listener a server binary was asked for: the inherited `listen_fd`, or a new one on `port`
+ */
+pub fn create_server_listener(port: u16, listen_fd: Option<i32>) -> Result<TcpListener> {
+  #[cfg(unix)]
+  if let Some(fd) = listen_fd {
+    return create_listener_from_fd(fd);
+  }
+  #[cfg(not(unix))]
+  let _ = listen_fd;
+  create_listener(port)
+}
+
+/**
+ * Applies `config.linger` to a TCP socket so it takes effect when the socket is closed
+ *
+ * `Some(Duration::ZERO)` makes close abortive: unsent data is discarded and
+ * the peer gets RST instead of FIN, which tears down fast and skips
+ * TIME_WAIT but can look like an error to the peer. A nonzero linger makes
+ * close block until unsent data is acknowledged or the timeout passes, which
+ * on an async socket blocks the runtime thread that drops it. `None` leaves
+ * the OS default: close returns at once and FIN is sent in the background.
+ */
+pub fn apply_linger<S>(socket: &S, config: &HandshakeConfig) -> Result<()>
+where
+  for<'s> SockRef<'s>: From<&'s S>,
+{
+  if config.linger.is_some() {
+    SockRef::from(socket).set_linger(config.linger)?;
+  }
+  Ok(())
+}
+
+/**
+ * Turns on TCP_NODELAY when `config.tcp_nodelay` is set
+ *
+ * Every handshake message is a few bytes written on its own, which Nagle's
+ * algorithm may hold back until the previous one is acknowledged.
+ */
+pub fn apply_nodelay<S>(socket: &S, config: &HandshakeConfig) -> Result<()>
+where
+  for<'s> SockRef<'s>: From<&'s S>,
+{
+  if config.tcp_nodelay {
+    SockRef::from(socket).set_tcp_nodelay(true)?;
+  }
+  Ok(())
+}
+
 /**
  * Formats a socket address for display
+ * An IPv6 host is bracketed, so the result also parses as an address
  */
 pub fn format_server_address(ip: &str, port: u16) -> String {
+  if ip.contains(':') && !ip.starts_with('[') {
+    return format!("[{ip}]:{port}");
+  }
   format!("{ip}:{port}")
 }
 
@@ -92,22 +1002,261 @@ pub fn calculate_optimal_thread_count() -> usize {
 
 /**
  * Handles program exit with error message
+ * The exit code tells the failure apart, see `HandshakeError::exit_code`
  */
 pub fn exit_with_error(error: &HandshakeError) -> ! {
   eprintln!("ERROR: {error}");
-  process::exit(1);
+  process::exit(error.exit_code());
 }
 
 /**
  * Async version: Creates and binds a TCP listener
+ * A port another socket is listening on fails with `PortInUse`
  */
 pub async fn create_async_listener(port: u16) -> Result<AsyncTcpListener> {
   let bind_addr = format!("0.0.0.0:{port}");
   let listener = AsyncTcpListener::bind(&bind_addr)
     .await
-    .map_err(|e| HandshakeError::Io(e))?;
+    .map_err(|e| bind_error(port, e))?;
 
-  println!("Event-driven server listening on {bind_addr}");
+  let bound = listener
+    .local_addr()
+    .map_or(bind_addr, |addr| addr.to_string());
+  println!("Event-driven server listening on {bound}");
   println!("Using Tokio async runtime for concurrent connection handling");
   Ok(listener)
 }
+
+/**
+ * Async version, Unix only: Takes over an already-bound listening TCP socket
+ * Must be called from within a Tokio runtime
+ */
+#[cfg(unix)]
+pub fn create_async_listener_from_fd(fd: RawFd) -> Result<AsyncTcpListener> {
+  let listener = create_listener_from_fd(fd)?;
+  listener.set_nonblocking(true)?;
+  Ok(AsyncTcpListener::from_std(listener)?)
+}
+
+/**
+ * Async version: Creates the listener a server binary was asked for
+ */
+pub async fn create_async_server_listener(
+  port: u16,
+  listen_fd: Option<i32>,
+) -> Result<AsyncTcpListener> {
+  #[cfg(unix)]
+  if let Some(fd) = listen_fd {
+    return create_async_listener_from_fd(fd);
+  }
+  #[cfg(not(unix))]
+  let _ = listen_fd;
+  create_async_listener(port).await
+}
+
+/**
+ * Linux only: Creates a Unix listener in the abstract namespace
+ * The name lives only in the kernel, so no socket file is left behind
+ * Must be called from within a Tokio runtime
+ */
+#[cfg(target_os = "linux")]
+pub fn create_abstract_unix_listener(name: &str) -> Result<AsyncUnixListener> {
+  let addr = UnixSocketAddr::from_abstract_name(name.as_bytes())?;
+  let listener = std::os::unix::net::UnixListener::bind_addr(&addr)?;
+  listener.set_nonblocking(true)?;
+
+  println!("Listening on abstract Unix socket @{name}");
+  Ok(AsyncUnixListener::from_std(listener)?)
+}
+
+/**
+ * Async version: Resolves `host` and connects to the first address that accepts
+ *
+ * The lookup is bounded by `config.dns_timeout` and fails with `DnsTimeout`;
+ * the connect attempts together are bounded by `config.connect_timeout` and
+ * fail with `Timeout`, so slow DNS never eats into the connect budget. With
+ * `config.socks5_proxy` set, the proxy is reached this way instead and asked
+ * to CONNECT to `host`, which it resolves itself, within another
+ * `connect_timeout`.
+ */
+pub async fn connect_async(
+  host: &str,
+  port: u16,
+  config: &HandshakeConfig,
+) -> Result<AsyncTcpStream> {
+  let (stream, _) = connect_async_timed(host, port, config).await?;
+  Ok(stream)
+}
+
+/**
+ * Async version: `connect_async`, also reporting how long resolving and connecting took
+ * Both phases are measured on `config.clock`
+ */
+pub async fn connect_async_timed(
+  host: &str,
+  port: u16,
+  config: &HandshakeConfig,
+) -> Result<(AsyncTcpStream, ConnectPhases)> {
+  let Some(proxy) = &config.socks5_proxy else {
+    return connect_direct_async(host, port, config).await;
+  };
+  let (mut stream, mut phases) = connect_direct_async(&proxy.host, proxy.port, config).await?;
+  let clock = config.clock.as_ref();
+  let negotiating = clock.now();
+  timeout(
+    clock,
+    config.connect_timeout,
+    socks5_handshake_async(&mut stream, proxy, host, port),
+  )
+  .await??;
+  phases.connect += clock.now().duration_since(negotiating);
+  Ok((stream, phases))
+}
+
+async fn connect_direct_async(
+  host: &str,
+  port: u16,
+  config: &HandshakeConfig,
+) -> Result<(AsyncTcpStream, ConnectPhases)> {
+  let clock = config.clock.as_ref();
+  let resolving = clock.now();
+  let addrs: Vec<SocketAddr> =
+    match timeout(clock, config.dns_timeout, lookup_host((host, port))).await {
+      Ok(addrs) => addrs?.collect(),
+      Err(_) => {
+        return Err(HandshakeError::DnsTimeout {
+          host: host.to_string(),
+        });
+      }
+    };
+  let connecting = clock.now();
+
+  let stream = timeout(clock, config.connect_timeout, async {
+    let mut last_error = None;
+    for addr in &addrs {
+      let connected = match client_socket(addr, config)? {
+        Some(socket) => {
+          socket.set_nonblocking(true)?;
+          AsyncTcpSocket::from_std_stream(socket.into())
+            .connect(*addr)
+            .await
+        }
+        None => AsyncTcpStream::connect(addr).await,
+      };
+      match connected {
+        Ok(stream) => return Ok(stream),
+        Err(e) => last_error = Some(e),
+      }
+    }
+    Err(no_connection(host, last_error))
+  })
+  .await??;
+  let phases = ConnectPhases {
+    dns: connecting.duration_since(resolving),
+    connect: clock.now().duration_since(connecting),
+  };
+  Ok((stream, phases))
+}
+
+/**
+ * Connects to `host`, tunneling through `config.socks5_proxy` when set
+ * Resolved addresses are tried in order, bound to `config.bind_addr` and a free port in
+ * `config.source_port_range` first when they are set
+ */
+pub fn connect_sync(host: &str, port: u16, config: &HandshakeConfig) -> Result<TcpStream> {
+  let Some(proxy) = &config.socks5_proxy else {
+    return connect_direct_sync(host, port, config);
+  };
+  let mut stream = connect_direct_sync(&proxy.host, proxy.port, config)?;
+  // Bound the negotiation so a silent proxy cannot hang the client
+  stream.set_read_timeout(Some(config.connect_timeout))?;
+  socks5_handshake(&mut stream, proxy, host, port)?;
+  stream.set_read_timeout(None)?;
+  Ok(stream)
+}
+
+fn connect_direct_sync(host: &str, port: u16, config: &HandshakeConfig) -> Result<TcpStream> {
+  let mut last_error = None;
+  for addr in (host, port).to_socket_addrs()? {
+    let connected = match client_socket(&addr, config)? {
+      Some(socket) => socket.connect(&addr.into()).map(|()| socket.into()),
+      None => TcpStream::connect(addr),
+    };
+    match connected {
+      Ok(stream) => return Ok(stream),
+      Err(e) => last_error = Some(e),
+    }
+  }
+  Err(no_connection(host, last_error))
+}
+
+/**
+ * The last connect error, or a not-found error when `host` resolved to nothing
+ */
+fn no_connection(host: &str, last_error: Option<std::io::Error>) -> HandshakeError {
+  let error = last_error.unwrap_or_else(|| {
+    std::io::Error::new(
+      std::io::ErrorKind::NotFound,
+      format!("'{host}' resolved to no addresses"),
+    )
+  });
+  HandshakeError::Io(error)
+}
+
+/**
+ * Creates a TCP socket for reaching `remote`, bound as `config` asks, or `None` to let the OS pick
+ * With `config.source_port_range` set, the ports are tried in order until one is free
+ */
+fn client_socket(remote: &SocketAddr, config: &HandshakeConfig) -> Result<Option<Socket>> {
+  let Some(range) = &config.source_port_range else {
+    return config
+      .bind_addr
+      .map(|local| bound_socket(local, remote))
+      .transpose();
+  };
+  let ip = match config.bind_addr {
+    Some(local) => local.ip(),
+    None if remote.is_ipv4() => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    None => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+  };
+  for port in range.clone() {
+    match bound_socket(SocketAddr::new(ip, port), remote) {
+      Err(HandshakeError::LocalAddressInUse(_)) => continue,
+      bound => return bound.map(Some),
+    }
+  }
+  Err(HandshakeError::SourcePortsExhausted {
+    first: *range.start(),
+    last: *range.end(),
+  })
+}
+
+/**
+ * Creates a TCP socket for reaching `remote` and binds it to `local`
+ * A local address that is already taken fails with `LocalAddressInUse`
+ */
+fn bound_socket(local: SocketAddr, remote: &SocketAddr) -> Result<Socket> {
+  let socket = Socket::new(
+    Domain::for_address(*remote),
+    Type::STREAM,
+    Some(Protocol::TCP),
+  )?;
+  socket.bind(&local.into()).map_err(|e| match e.kind() {
+    std::io::ErrorKind::AddrInUse => HandshakeError::LocalAddressInUse(local),
+    _ => HandshakeError::Io(e),
+  })?;
+  Ok(socket)
+}
+
+/**
+ * Linux only: Connects to a Unix listener in the abstract namespace
+ */
+#[cfg(target_os = "linux")]
+pub async fn connect_abstract_unix(name: &str) -> Result<AsyncUnixStream> {
+  let addr = UnixSocketAddr::from_abstract_name(name.as_bytes())?;
+  // Connecting to a local socket completes immediately, so blocking here is fine
+  let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+  stream.set_nonblocking(true)?;
+
+  Ok(AsyncUnixStream::from_std(stream)?)
+}
@@ -0,0 +1,44 @@
+#![cfg(target_os = "linux")]
+/**
+ * Abstract-namespace Unix socket tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::path::Path;
+
+use tcp_handshake::{
+  HandshakeConfig, connect_abstract_unix, create_abstract_unix_listener,
+  perform_async_client_handshake_with_config, perform_async_server_handshake_with_config,
+};
+
+#[tokio::test]
+async fn handshake_runs_over_an_abstract_socket_without_a_file() {
+  let name = format!("tcp-handshake-test-{}", std::process::id());
+  let config = HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+
+  let listener = create_abstract_unix_listener(&name).unwrap();
+  let mut client = connect_abstract_unix(&name).await.unwrap();
+  let (mut server, _) = listener.accept().await.unwrap();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client, 20, &config),
+    perform_async_server_handshake_with_config(&mut server, "abstract peer", None, &config),
+  );
+  assert_eq!(client.unwrap().final_seq, 22);
+  assert_eq!(server.unwrap().final_seq, 22);
+
+  // The kernel lists the socket under its abstract name, and nothing exists on disk
+  let sockets = std::fs::read_to_string("/proc/net/unix").unwrap();
+  assert!(sockets.contains(&format!("@{name}")), "{sockets}");
+  assert!(!Path::new(&name).exists());
+  assert!(!Path::new("/tmp").join(&name).exists());
+}
+
+#[tokio::test]
+async fn connecting_to_an_unbound_name_fails() {
+  let name = format!("tcp-handshake-unbound-{}", std::process::id());
+  assert!(connect_abstract_unix(&name).await.is_err());
+}
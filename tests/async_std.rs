@@ -0,0 +1,89 @@
+/**
+ * async-std handshake tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Everything here runs on async-std's executor, with no tokio runtime, so a
+ * tokio-only primitive slipping into the shared handshake code fails these.
+ */
+use std::time::{Duration, Instant};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
+
+use tcp_handshake::async_std_rt::{
+  default_config, perform_async_client_handshake, perform_async_server_handshake,
+};
+use tcp_handshake::{HandshakeConfig, HandshakeError, perform_async_server_handshake_with_config};
+
+#[test]
+fn client_and_server_handshake_on_async_std() {
+  task::block_on(async {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+      let (stream, peer_addr) = listener.accept().await.unwrap();
+      perform_async_server_handshake(stream, peer_addr, None).await
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let client = perform_async_client_handshake(stream, 100).await.unwrap();
+    let server = server.await.unwrap();
+
+    assert_eq!(client.initial_seq, 100);
+    assert_eq!(client.final_seq, 102);
+    assert_eq!(server.initial_seq, 100);
+    assert_eq!(server.final_seq, 102);
+  });
+}
+
+#[test]
+fn async_std_timers_enforce_the_read_timeout() {
+  task::block_on(async {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    // The client connects and never sends HELLO X
+    let _client = TcpStream::connect(listener.local_addr().unwrap())
+      .await
+      .unwrap();
+    let (stream, peer_addr) = listener.accept().await.unwrap();
+
+    let config = HandshakeConfig {
+      read_timeout: Duration::from_millis(100),
+      silent: true,
+      ..default_config()
+    };
+    let started = Instant::now();
+    let result =
+      perform_async_server_handshake_with_config(&mut stream.compat(), peer_addr, None, &config)
+        .await;
+    assert!(matches!(result, Err(HandshakeError::Timeout)), "{result:?}");
+    assert!(
+      started.elapsed() < Duration::from_secs(2),
+      "{:?}",
+      started.elapsed()
+    );
+  });
+}
+
+#[test]
+fn cancelling_aborts_an_async_std_server_handshake() {
+  task::block_on(async {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let _client = TcpStream::connect(listener.local_addr().unwrap())
+      .await
+      .unwrap();
+    let (stream, peer_addr) = listener.accept().await.unwrap();
+
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    task::spawn(async move {
+      task::sleep(Duration::from_millis(50)).await;
+      canceller.cancel();
+    });
+
+    let result = perform_async_server_handshake(stream, peer_addr, Some(&cancel)).await;
+    assert!(matches!(result, Err(HandshakeError::Aborted)), "{result:?}");
+  });
+}
@@ -0,0 +1,49 @@
+/**
+ * Exponential backoff schedule tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::time::Duration;
+
+use tcp_handshake::Backoff;
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn delays_grow_by_factor() {
+  let mut backoff = Backoff::new(10 * MS, Duration::from_secs(10), 2.0, 0.0);
+  let delays: Vec<_> = (0..5).map(|_| backoff.next_delay()).collect();
+  assert_eq!(delays, [10 * MS, 20 * MS, 40 * MS, 80 * MS, 160 * MS]);
+}
+
+#[test]
+fn delays_are_capped_and_reset() {
+  let mut backoff = Backoff::new(100 * MS, 300 * MS, 2.0, 0.0);
+  let delays: Vec<_> = (0..5).map(|_| backoff.next_delay()).collect();
+  assert_eq!(delays, [100 * MS, 200 * MS, 300 * MS, 300 * MS, 300 * MS]);
+
+  // A huge factor saturates at the cap instead of overflowing
+  let mut backoff = Backoff::new(100 * MS, 300 * MS, f64::MAX, 0.0);
+  backoff.next_delay();
+  assert_eq!(backoff.next_delay(), 300 * MS);
+  assert_eq!(backoff.next_delay(), 300 * MS);
+
+  backoff.reset();
+  assert_eq!(backoff.next_delay(), 100 * MS);
+}
+
+#[test]
+fn jitter_stays_within_bounds() {
+  let mut backoff = Backoff::new(100 * MS, Duration::from_secs(1), 1.0, 0.5);
+  let delays: Vec<_> = (0..200).map(|_| backoff.next_delay()).collect();
+  assert!(
+    delays
+      .iter()
+      .all(|delay| (50 * MS..=150 * MS).contains(delay))
+  );
+  assert!(delays.iter().any(|delay| *delay != delays[0]));
+
+  // Jitter never pushes a delay past the cap
+  let mut backoff = Backoff::new(100 * MS, 100 * MS, 2.0, 0.5);
+  assert!((0..200).all(|_| backoff.next_delay() <= 100 * MS));
+}
@@ -0,0 +1,68 @@
+/**
+ * Client local address binding tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{SocketAddr, TcpListener};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ServerModel, connect_async, connect_sync,
+  perform_async_client_handshake_with_config, perform_client_handshake_with_config, spawn_server,
+};
+
+fn bound_config(bind_addr: SocketAddr) -> HandshakeConfig {
+  HandshakeConfig {
+    bind_addr: Some(bind_addr),
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn sync_client_handshakes_from_bound_address() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let config = bound_config("127.0.0.1:0".parse().unwrap());
+
+  let mut stream = connect_sync("127.0.0.1", server.addr.port(), &config).unwrap();
+  let local = stream.local_addr().unwrap();
+  assert_eq!(local.ip().to_string(), "127.0.0.1");
+  assert_ne!(local.port(), 0);
+
+  let outcome = perform_client_handshake_with_config(&mut stream, 5, &config).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  server.stop();
+}
+
+#[tokio::test]
+async fn async_client_handshakes_from_bound_address() {
+  let server = spawn_server(ServerModel::Async, HandshakeConfig::default()).unwrap();
+  let config = bound_config("127.0.0.1:0".parse().unwrap());
+
+  let mut stream = connect_async("127.0.0.1", server.addr.port(), &config)
+    .await
+    .unwrap();
+  assert_eq!(stream.local_addr().unwrap().ip().to_string(), "127.0.0.1");
+
+  let outcome = perform_async_client_handshake_with_config(&mut stream, 5, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[test]
+fn local_address_in_use_is_reported() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  // Hold a local port so the client cannot bind it
+  let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+  let taken_addr = taken.local_addr().unwrap();
+
+  let error = connect_sync("127.0.0.1", server.addr.port(), &bound_config(taken_addr)).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::LocalAddressInUse(addr) if addr == taken_addr),
+    "{error}"
+  );
+  server.stop();
+}
@@ -0,0 +1,95 @@
+/**
+ * Accept rate limit and BUSY response tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, RateLimit, ServerModel,
+  perform_async_client_handshake_with_config, perform_client_handshake_with_config, spawn_server,
+};
+
+fn one_per_minute(busy_response: bool) -> HandshakeConfig {
+  HandshakeConfig {
+    accept_rate_limit: Some(RateLimit {
+      max_connections: 1,
+      window: Duration::from_secs(60),
+    }),
+    busy_response,
+    ..HandshakeConfig::default()
+  }
+}
+
+fn sync_handshake(server: &tcp_handshake::RunningServer) -> tcp_handshake::Result<i32> {
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default())
+    .map(|outcome| outcome.final_seq)
+}
+
+#[test]
+fn rate_limit_is_parsed() {
+  assert_eq!(
+    RateLimit::parse("100/1s").unwrap(),
+    RateLimit {
+      max_connections: 100,
+      window: Duration::from_secs(1),
+    }
+  );
+  assert_eq!(
+    RateLimit::parse("5/500ms").unwrap().window,
+    Duration::from_millis(500)
+  );
+  for spec in ["100", "0/1s", "10/0s", "ten/1s", "10/1h"] {
+    assert!(RateLimit::parse(spec).is_err(), "{spec}");
+  }
+}
+
+#[test]
+fn throttled_sync_client_sees_server_busy() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, one_per_minute(true)).unwrap();
+    assert_eq!(sync_handshake(&server).unwrap(), 7, "{model}");
+
+    let error = sync_handshake(&server).unwrap_err();
+    assert!(
+      matches!(error, HandshakeError::ServerBusy),
+      "{model}: {error}"
+    );
+    assert_eq!(server.metrics.rate_limited.load(Ordering::Relaxed), 1);
+    server.stop();
+  }
+}
+
+#[tokio::test]
+async fn throttled_async_client_sees_server_busy() {
+  let server = spawn_server(ServerModel::Async, one_per_minute(true)).unwrap();
+  let config = HandshakeConfig::default();
+  let mut outcomes = Vec::new();
+  for _ in 0..2 {
+    let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    outcomes.push(perform_async_client_handshake_with_config(&mut stream, 5, &config).await);
+  }
+  assert_eq!(outcomes[0].as_ref().unwrap().final_seq, 7);
+  assert!(
+    matches!(outcomes[1], Err(HandshakeError::ServerBusy)),
+    "{outcomes:?}"
+  );
+
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[test]
+fn refusal_without_busy_response_is_a_plain_close() {
+  let server = spawn_server(ServerModel::Threaded, one_per_minute(false)).unwrap();
+  assert_eq!(sync_handshake(&server).unwrap(), 7);
+
+  let error = sync_handshake(&server).unwrap_err();
+  assert!(!matches!(error, HandshakeError::ServerBusy), "{error}");
+  assert_eq!(server.metrics.rate_limited.load(Ordering::Relaxed), 1);
+  server.stop();
+}
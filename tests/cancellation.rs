@@ -0,0 +1,54 @@
+/**
+ * Server handshake cancellation tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+use tcp_handshake::{HandshakeConfig, HandshakeError, perform_async_server_handshake};
+
+#[tokio::test]
+async fn cancelling_a_stalled_handshake_aborts_it_promptly() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  // The client connects and never sends HELLO X
+  let _client = TcpStream::connect(listener.local_addr().unwrap())
+    .await
+    .unwrap();
+  let (stream, peer_addr) = listener.accept().await.unwrap();
+
+  let cancel = CancellationToken::new();
+  let canceller = cancel.clone();
+  tokio::spawn(async move {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    canceller.cancel();
+  });
+
+  let started = Instant::now();
+  let result = perform_async_server_handshake(stream, peer_addr, Some(&cancel)).await;
+  assert!(matches!(result, Err(HandshakeError::Aborted)), "{result:?}");
+
+  // Nowhere near the read timeout the handshake would otherwise wait out
+  let read_timeout = HandshakeConfig::default().read_timeout;
+  assert!(
+    started.elapsed() < read_timeout / 4,
+    "{:?}",
+    started.elapsed()
+  );
+}
+
+#[tokio::test]
+async fn a_token_cancelled_up_front_aborts_before_reading() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let _client = TcpStream::connect(listener.local_addr().unwrap())
+    .await
+    .unwrap();
+  let (stream, peer_addr) = listener.accept().await.unwrap();
+
+  let cancel = CancellationToken::new();
+  cancel.cancel();
+  let result = perform_async_server_handshake(stream, peer_addr, Some(&cancel)).await;
+  assert!(matches!(result, Err(HandshakeError::Aborted)), "{result:?}");
+}
@@ -0,0 +1,135 @@
+/**
+ * Capability negotiation tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  Capabilities, EventSink, HandshakeConfig, HandshakeError, HandshakeOutcome, Result,
+  perform_async_client_handshake_on, perform_async_server_handshake_with_config,
+  validate_hello_message,
+};
+
+fn negotiating(supported: Capabilities, required: Capabilities) -> HandshakeConfig {
+  HandshakeConfig {
+    capabilities: Some(supported),
+    required_capabilities: required,
+    ..HandshakeConfig::default()
+  }
+}
+
+async fn handshake(
+  client: &HandshakeConfig,
+  server: &HandshakeConfig,
+) -> (Result<HandshakeOutcome>, Result<HandshakeOutcome>) {
+  let (client_stream, mut server_stream) = duplex(1024);
+  tokio::join!(
+    perform_async_client_handshake_on(client_stream, 5, client),
+    // Dropping the server end on return hangs up on the client, as a socket would
+    async move {
+      perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, server).await
+    },
+  )
+}
+
+#[tokio::test]
+async fn overlapping_sets_agree_on_the_common_subset() {
+  let messages = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&messages);
+  let client = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.message.clone())),
+    ..negotiating(
+      Capabilities::NONCE | Capabilities::CLAIMED_IP | Capabilities::CHUNKED,
+      Capabilities::NONCE,
+    )
+  };
+  let server = negotiating(
+    Capabilities::NONCE | Capabilities::NAMESPACE | Capabilities::CHUNKED,
+    Capabilities::CHUNKED,
+  );
+
+  let (client, server) = handshake(&client, &server).await;
+  let common = Capabilities::NONCE | Capabilities::CHUNKED;
+  assert_eq!(client.unwrap().capabilities, Some(common));
+  assert_eq!(server.unwrap().capabilities, Some(common));
+  assert_eq!(
+    *messages.lock().unwrap(),
+    ["HELLO 5 CAP=13", "HELLO 6 CAP=11", "HELLO 7"]
+  );
+}
+
+#[tokio::test]
+async fn disjoint_sets_fail_on_the_side_that_requires_more() {
+  let client = negotiating(Capabilities::CLAIMED_IP, Capabilities::NONE);
+  let server = negotiating(Capabilities::NONCE, Capabilities::NONCE);
+
+  let (client, server) = handshake(&client, &server).await;
+  match server {
+    Err(HandshakeError::CapabilityMismatch { missing, common }) => {
+      assert_eq!(missing, Capabilities::NONCE);
+      assert_eq!(common, Capabilities::NONE);
+    }
+    other => panic!("expected a capability mismatch, got {other:?}"),
+  }
+  // The server hung up without answering
+  assert!(client.is_err());
+
+  let client = negotiating(Capabilities::CLAIMED_IP, Capabilities::CLAIMED_IP);
+  let server = negotiating(Capabilities::NONCE, Capabilities::NONE);
+  let (client, server) = handshake(&client, &server).await;
+  assert!(matches!(
+    client,
+    Err(HandshakeError::CapabilityMismatch { missing, .. }) if missing == Capabilities::CLAIMED_IP
+  ));
+  assert!(server.is_err());
+}
+
+#[tokio::test]
+async fn peers_that_do_not_negotiate_share_nothing() {
+  // A plain client still completes against a negotiating server with no requirements
+  let (client, server) = handshake(
+    &HandshakeConfig::default(),
+    &negotiating(Capabilities::NONCE, Capabilities::NONE),
+  )
+  .await;
+  assert_eq!(client.unwrap().capabilities, None);
+  assert_eq!(server.unwrap().capabilities, Some(Capabilities::NONE));
+}
+
+#[tokio::test]
+async fn server_may_not_claim_capabilities_that_were_not_offered() {
+  let (client_stream, mut server) = duplex(1024);
+  let config = negotiating(Capabilities::NONCE, Capabilities::NONE);
+  let (client, _) = tokio::join!(
+    perform_async_client_handshake_on(client_stream, 5, &config),
+    async {
+      let mut hello = [0u8; 13];
+      server.read_exact(&mut hello).await.unwrap();
+      server.write_all(b"HELLO 6 CAP=ff").await.unwrap();
+    },
+  );
+  assert!(matches!(client, Err(HandshakeError::ProtocolViolation(_))));
+}
+
+#[test]
+fn grammar_accepts_one_hex_capability_field() {
+  let validated = validate_hello_message("HELLO 5 CAP=1f").unwrap();
+  assert_eq!(validated.capabilities, Some(Capabilities::from_bits(0x1f)));
+
+  for (message, offset, expected) in [
+    ("HELLO 5 CAP=xyz", 12, "hex capability mask"),
+    ("HELLO 5 CAP=1 CAP=2", 14, "no repeated 'CAP' field"),
+  ] {
+    match validate_hello_message(message) {
+      Err(HandshakeError::InvalidMessageFormat {
+        offset: at,
+        expected: wanted,
+        ..
+      }) => assert_eq!((at, wanted), (offset, expected), "{message}"),
+      other => panic!("{message}: {other:?}"),
+    }
+  }
+}
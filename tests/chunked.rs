@@ -0,0 +1,172 @@
+/**
+ * Chunked payload tests over in-memory duplex streams
+ *
+ * Author: Sae-Hwan Park
+ */
+use tokio::io::{AsyncWriteExt, duplex, split};
+
+use tcp_handshake::{
+  CHUNK_HEADER_LEN, CHUNK_MORE, HandshakeConfig, HandshakeError, SessionStats, recv_chunked,
+  run_async_echo_session, send_chunked,
+};
+
+const MIB: usize = 1024 * 1024;
+
+/**
+ * A payload whose bytes depend on their position, so misordered chunks are caught
+ */
+fn payload(len: usize) -> Vec<u8> {
+  (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn chunk_header(flags: u8, len: u32) -> Vec<u8> {
+  let mut header = vec![flags];
+  header.extend_from_slice(&len.to_be_bytes());
+  header
+}
+
+#[tokio::test]
+async fn multi_megabyte_payload_round_trips() {
+  let config = HandshakeConfig::default();
+  let data = payload(5 * MIB + 7);
+  let (mut sender, mut receiver) = duplex(16 * 1024);
+
+  let (sent, received) = tokio::join!(
+    send_chunked(&mut sender, &data, &config),
+    recv_chunked(&mut receiver, &config),
+  );
+  sent.unwrap();
+  assert_eq!(received.unwrap(), data);
+}
+
+#[tokio::test]
+async fn empty_payload_is_one_final_chunk() {
+  let config = HandshakeConfig::default();
+  let (mut sender, mut receiver) = duplex(64);
+
+  send_chunked(&mut sender, &[], &config).await.unwrap();
+  drop(sender);
+  assert_eq!(recv_chunked(&mut receiver, &config).await.unwrap(), b"");
+  assert!(matches!(
+    recv_chunked(&mut receiver, &config).await,
+    Err(HandshakeError::ClientDisconnected)
+  ));
+}
+
+#[tokio::test]
+async fn oversized_payload_is_refused_by_both_ends() {
+  let config = HandshakeConfig {
+    max_chunk_size: 1024,
+    max_chunked_payload: 4096,
+    ..HandshakeConfig::default()
+  };
+  let (mut sender, mut receiver) = duplex(64 * 1024);
+
+  let error = send_chunked(&mut sender, &payload(4097), &config)
+    .await
+    .unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::PayloadTooLarge {
+        size: 4097,
+        max: 4096
+      }
+    ),
+    "{error}"
+  );
+
+  // A peer that keeps sending "more" chunks is cut off once the total passes the max
+  for _ in 0..5 {
+    sender
+      .write_all(&chunk_header(CHUNK_MORE, 1024))
+      .await
+      .unwrap();
+    sender.write_all(&payload(1024)).await.unwrap();
+  }
+  let error = recv_chunked(&mut receiver, &config).await.unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::PayloadTooLarge { size: 5120, .. }),
+    "{error}"
+  );
+}
+
+#[tokio::test]
+async fn malformed_chunk_headers_are_rejected() {
+  let config = HandshakeConfig {
+    max_chunk_size: 1024,
+    ..HandshakeConfig::default()
+  };
+
+  let (mut sender, mut receiver) = duplex(64);
+  sender.write_all(&chunk_header(0, 1025)).await.unwrap();
+  let error = recv_chunked(&mut receiver, &config).await.unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::ProtocolViolation(_)),
+    "{error}"
+  );
+
+  let (mut sender, mut receiver) = duplex(64);
+  sender.write_all(&chunk_header(0x80, 1)).await.unwrap();
+  let error = recv_chunked(&mut receiver, &config).await.unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::ProtocolViolation(_)),
+    "{error}"
+  );
+}
+
+#[tokio::test]
+async fn truncated_payload_is_an_error_not_a_disconnect() {
+  let config = HandshakeConfig::default();
+  let (mut sender, mut receiver) = duplex(64);
+  sender
+    .write_all(&chunk_header(CHUNK_MORE, 3))
+    .await
+    .unwrap();
+  sender.write_all(b"abc").await.unwrap();
+  sender
+    .write_all(&chunk_header(0, 3)[..CHUNK_HEADER_LEN - 1])
+    .await
+    .unwrap();
+  drop(sender);
+
+  let error = recv_chunked(&mut receiver, &config).await.unwrap_err();
+  assert!(matches!(error, HandshakeError::Io(_)), "{error}");
+}
+
+#[tokio::test]
+async fn chunked_echo_session_returns_large_payloads() {
+  let config = HandshakeConfig {
+    echo_session: true,
+    chunked_session: true,
+    max_session_bytes: 16 * MIB as u64,
+    ..HandshakeConfig::default()
+  };
+  let (client, mut server) = duplex(16 * 1024);
+  let server_config = config.clone();
+  let session =
+    tokio::spawn(
+      async move { run_async_echo_session(&mut server, "test peer", &server_config).await },
+    );
+
+  let (mut reader, mut writer) = split(client);
+  for len in [3 * MIB, 10] {
+    let data = payload(len);
+    let (sent, echoed) = tokio::join!(
+      send_chunked(&mut writer, &data, &config),
+      recv_chunked(&mut reader, &config),
+    );
+    sent.unwrap();
+    assert_eq!(echoed.unwrap(), data);
+  }
+  drop((reader, writer));
+
+  let stats = session.await.unwrap().unwrap();
+  assert_eq!(
+    stats,
+    SessionStats {
+      messages: 2,
+      bytes: (3 * MIB + 10) as u64
+    }
+  );
+}
@@ -0,0 +1,182 @@
+/**
+ * Claimed client address tests over loopback, where the real peer is 127.0.0.1
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+use std::thread;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, HandshakeOutcome, Result, format_hello_with_claimed_ip,
+  parse_hello_with_claimed_ip, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_config, perform_client_handshake_with_config,
+  perform_server_handshake_with_config, validate_hello_message, verify_claimed_ip,
+};
+
+const LOOPBACK: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const ELSEWHERE: IpAddr = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+
+fn verifying(strict: bool) -> HandshakeConfig {
+  HandshakeConfig {
+    verify_client_ip: true,
+    strict_client_ip: strict,
+    ..HandshakeConfig::default()
+  }
+}
+
+fn claiming(ip: IpAddr) -> HandshakeConfig {
+  HandshakeConfig {
+    claimed_ip: Some(ip),
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Runs a sync handshake between a client claiming `claimed` and a server using `server_config`
+ */
+fn sync_handshake(claimed: IpAddr, server_config: HandshakeConfig) -> Result<HandshakeOutcome> {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let _ = perform_client_handshake_with_config(&mut stream, 5, &claiming(claimed));
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let result = perform_server_handshake_with_config(&mut stream, &server_config);
+  drop(stream);
+  client.join().unwrap();
+  result
+}
+
+#[test]
+fn grammar_accepts_an_ip_field() {
+  let validated = validate_hello_message("HELLO 5 IP=1.2.3.4").unwrap();
+  assert_eq!(validated.claimed_ip, Some(ELSEWHERE));
+  assert!(validated.has_optional_fields());
+
+  let validated = validate_hello_message("HELLO 5 S=1 IP=::1").unwrap();
+  assert_eq!(validated.claimed_ip, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+}
+
+#[test]
+fn grammar_rejects_a_bad_or_repeated_ip_field() {
+  let error = validate_hello_message("HELLO 5 IP=1.2.3").unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::InvalidMessageFormat {
+        offset: 11,
+        expected: "IP address",
+        ..
+      }
+    ),
+    "{error}"
+  );
+
+  let error = validate_hello_message("HELLO 5 IP=1.2.3.4 IP=1.2.3.4").unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::InvalidMessageFormat {
+        offset: 19,
+        expected: "no repeated 'IP' field",
+        ..
+      }
+    ),
+    "{error}"
+  );
+}
+
+#[test]
+fn claimed_ip_round_trips() {
+  let message = format_hello_with_claimed_ip(5, ELSEWHERE);
+  assert_eq!(message, "HELLO 5 IP=1.2.3.4");
+  assert_eq!(
+    parse_hello_with_claimed_ip(&message).unwrap(),
+    (5, Some(ELSEWHERE))
+  );
+  assert_eq!(parse_hello_with_claimed_ip("HELLO 5").unwrap(), (5, None));
+  assert!(parse_hello_with_claimed_ip("HELLO 5 IP=1.2.3.4 N=1").is_err());
+}
+
+#[test]
+fn verify_matches_ipv4_mapped_addresses() {
+  let mapped = IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped());
+  assert!(verify_claimed_ip(LOOPBACK, LOOPBACK).is_ok());
+  assert!(verify_claimed_ip(LOOPBACK, mapped).is_ok());
+
+  let error = verify_claimed_ip(ELSEWHERE, LOOPBACK).unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::AddressMismatch {
+        claimed: ELSEWHERE,
+        actual: LOOPBACK
+      }
+    ),
+    "{error}"
+  );
+}
+
+#[test]
+fn matching_claim_completes_under_strict_verification() {
+  let outcome = sync_handshake(LOOPBACK, verifying(true)).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+}
+
+#[test]
+fn mismatching_claim_only_warns_by_default() {
+  let outcome = sync_handshake(ELSEWHERE, verifying(false)).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+}
+
+#[test]
+fn mismatching_claim_fails_under_strict_verification() {
+  let error = sync_handshake(ELSEWHERE, verifying(true)).unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::AddressMismatch {
+        claimed: ELSEWHERE,
+        actual: LOOPBACK
+      }
+    ),
+    "{error}"
+  );
+}
+
+#[test]
+fn claim_is_rejected_when_verification_is_off() {
+  let error = sync_handshake(LOOPBACK, HandshakeConfig::default()).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::InvalidMessageFormat { .. }),
+    "{error}"
+  );
+}
+
+#[tokio::test]
+async fn async_server_checks_the_claim_against_the_peer_address() {
+  for (claimed, expect_ok) in [(LOOPBACK, true), (ELSEWHERE, false)] {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = tokio::spawn(async move {
+      let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+      let _ = perform_async_client_handshake_with_config(&mut stream, 5, &claiming(claimed)).await;
+    });
+
+    let (mut stream, peer) = listener.accept().await.unwrap();
+    let result =
+      perform_async_server_handshake_with_config(&mut stream, peer, None, &verifying(true)).await;
+    drop(stream);
+    client.await.unwrap();
+
+    match result {
+      Ok(outcome) => assert!(expect_ok && outcome.final_seq == 7),
+      Err(error) => assert!(
+        !expect_ok && matches!(error, HandshakeError::AddressMismatch { .. }),
+        "{error}"
+      ),
+    }
+  }
+}
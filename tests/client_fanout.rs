@@ -0,0 +1,97 @@
+/**
+ * Fan-out client tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::Write;
+use std::net::TcpListener;
+use std::process::{Command, Output};
+
+use tcp_handshake::{ServerModel, spawn_server};
+
+use common::quiet;
+
+fn fanout(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_client-fanout"))
+    .args(args)
+    .output()
+    .unwrap()
+}
+
+/**
+ * A loopback port with nothing listening on it
+ */
+fn closed_port() -> u16 {
+  TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap()
+    .port()
+}
+
+#[test]
+fn reports_every_target_of_a_healthy_fleet() {
+  let threaded = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  let asynchronous = spawn_server(ServerModel::Async, quiet()).unwrap();
+  let targets = [threaded.addr.to_string(), asynchronous.addr.to_string()];
+
+  let output = fanout(&[&targets[0], &targets[1], "--concurrency", "1"]);
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "{stdout}");
+
+  // Rows follow the order the targets were given in
+  let rows: Vec<&str> = stdout.lines().skip(1).take(2).collect();
+  assert!(rows[0].starts_with(&targets[0]), "{stdout}");
+  assert!(rows[1].starts_with(&targets[1]), "{stdout}");
+  assert!(rows.iter().all(|row| row.contains(" OK ")), "{stdout}");
+  assert!(
+    stdout.contains("2 targets: 2 succeeded, 0 failed"),
+    "{stdout}"
+  );
+
+  threaded.stop();
+  asynchronous.stop();
+}
+
+#[test]
+fn a_failed_target_is_reported_and_fails_the_run() {
+  let server = spawn_server(ServerModel::Async, quiet()).unwrap();
+  let live = server.addr.to_string();
+  let dead = format!("127.0.0.1:{}", closed_port());
+
+  let output = fanout(&[&dead, &live]);
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert_eq!(output.status.code(), Some(1), "{stdout}");
+  assert!(
+    stdout.contains("2 targets: 1 succeeded, 1 failed"),
+    "{stdout}"
+  );
+  let dead_row = stdout.lines().find(|row| row.starts_with(&dead)).unwrap();
+  assert!(dead_row.contains("FAILED"), "{stdout}");
+
+  // --allow-failures keeps the report but exits successfully
+  let output = fanout(&[&dead, &live, "--allow-failures"]);
+  assert!(output.status.success());
+  server.stop();
+}
+
+#[test]
+fn targets_can_come_from_a_file() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  let path = std::env::temp_dir().join(format!("fanout-targets-{}.txt", std::process::id()));
+  let mut file = std::fs::File::create(&path).unwrap();
+  writeln!(file, "# fleet\n\n{}  # primary", server.addr).unwrap();
+
+  let output = fanout(&["--targets-file", path.to_str().unwrap()]);
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(output.status.success(), "{stdout}");
+  assert!(
+    stdout.contains("1 targets: 1 succeeded, 0 failed"),
+    "{stdout}"
+  );
+
+  let _ = std::fs::remove_file(&path);
+  server.stop();
+}
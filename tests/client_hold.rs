@@ -0,0 +1,77 @@
+#![cfg(unix)]
+/**
+ * Client hold tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Runs each client binary with a long `--hold-ms`, sends SIGINT during the
+ * hold and checks that the client ends the hold and exits successfully.
+ */
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{HandshakeConfig, ServerModel, spawn_server};
+
+fn assert_hold_ends_on_sigint(binary: &str) {
+  let server = spawn_server(
+    ServerModel::Threaded,
+    HandshakeConfig {
+      silent: true,
+      ..HandshakeConfig::default()
+    },
+  )
+  .unwrap();
+  let started = Instant::now();
+  let mut child = Command::new(binary)
+    .args(["127.0.0.1", &server.addr.port().to_string(), "5"])
+    .args(["--hold-ms", "60000"])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .expect("client binary runs");
+
+  // The hold announces itself once the handshake is done
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+  let mut line = String::new();
+  while !line.contains("Holding connection open") {
+    line.clear();
+    assert_ne!(
+      stdout.read_line(&mut line).unwrap(),
+      0,
+      "{binary} never held"
+    );
+  }
+
+  // The hold starts watching for Ctrl-C right after announcing itself
+  thread::sleep(Duration::from_millis(200));
+  let status = Command::new("kill")
+    .args(["-INT", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let mut rest = String::new();
+  stdout.read_to_string(&mut rest).unwrap();
+  let status = child.wait().unwrap();
+  assert!(status.success(), "{binary} exited with {status}");
+  assert!(rest.contains("Hold interrupted"), "{rest}");
+  assert!(
+    started.elapsed() < Duration::from_secs(30),
+    "{:?}",
+    started.elapsed()
+  );
+  server.stop();
+}
+
+#[test]
+fn sync_client_ends_its_hold_on_sigint() {
+  assert_hold_ends_on_sigint(env!("CARGO_BIN_EXE_client-sync"));
+}
+
+#[test]
+fn async_client_ends_its_hold_on_sigint() {
+  assert_hold_ends_on_sigint(env!("CARGO_BIN_EXE_client-async"));
+}
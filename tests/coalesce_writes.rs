@@ -0,0 +1,133 @@
+/**
+ * Write coalescing tests for delimited messages and chunked payloads
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, duplex};
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, perform_async_client_handshake_on,
+  perform_async_server_handshake_with_config, send_chunked,
+};
+
+/**
+ * Passes everything through to `inner`, counting the write calls that carried bytes
+ */
+struct CountingStream<S> {
+  inner: S,
+  writes: usize,
+  written: Vec<u8>,
+}
+
+impl<S> CountingStream<S> {
+  fn new(inner: S) -> Self {
+    Self {
+      inner,
+      writes: 0,
+      written: Vec::new(),
+    }
+  }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_read(cx, buf)
+  }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+    if let Poll::Ready(Ok(written)) = result
+      && written > 0
+    {
+      self.writes += 1;
+      self.written.extend_from_slice(&buf[..written]);
+    }
+    result
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_shutdown(cx)
+  }
+}
+
+fn delimited(coalesce_writes: bool) -> HandshakeConfig {
+  HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    coalesce_writes,
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Runs one handshake and returns the client's write calls and bytes sent
+ */
+async fn client_writes(coalesce_writes: bool) -> (usize, Vec<u8>) {
+  let (client_end, mut server_end) = duplex(1024);
+  let mut client = CountingStream::new(client_end);
+  let config = delimited(coalesce_writes);
+  let server_config = delimited(true);
+  let (client_result, server_result) = tokio::join!(
+    perform_async_client_handshake_on(&mut client, 5, &config),
+    perform_async_server_handshake_with_config(&mut server_end, "duplex", None, &server_config),
+  );
+  client_result.unwrap();
+  server_result.unwrap();
+  (client.writes, client.written)
+}
+
+#[test]
+fn coalescing_is_on_by_default() {
+  assert!(HandshakeConfig::default().coalesce_writes);
+}
+
+#[tokio::test]
+async fn delimited_messages_go_out_in_one_write_each() {
+  let (coalesced, coalesced_bytes) = client_writes(true).await;
+  let (split, split_bytes) = client_writes(false).await;
+
+  // HELLO X and HELLO Z, each with its newline
+  assert_eq!(coalesced, 2);
+  assert_eq!(split, 4);
+  assert_eq!(coalesced_bytes, b"HELLO 5\nHELLO 7\n");
+  assert_eq!(split_bytes, coalesced_bytes);
+}
+
+#[tokio::test]
+async fn chunk_headers_go_out_with_their_chunk() {
+  let mut payloads = Vec::new();
+  for coalesce_writes in [true, false] {
+    let config = HandshakeConfig {
+      max_chunk_size: 4,
+      coalesce_writes,
+      ..HandshakeConfig::default()
+    };
+    let mut writer = CountingStream::new(tokio::io::sink());
+    send_chunked(&mut writer, b"12345678", &config)
+      .await
+      .unwrap();
+    payloads.push((writer.writes, writer.written));
+  }
+
+  // Two chunks: one write each when coalescing, a header and a body write each otherwise
+  assert_eq!(payloads[0].0, 2);
+  assert_eq!(payloads[1].0, 4);
+  assert_eq!(payloads[0].1, payloads[1].1);
+}
@@ -0,0 +1,16 @@
+/**
+ * Shared test helpers
+ *
+ * Author: Sae-Hwan Park
+ */
+use tcp_handshake::HandshakeConfig;
+
+/**
+ * The default config without any output, for tests that only check results
+ */
+pub fn quiet() -> HandshakeConfig {
+  HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  }
+}
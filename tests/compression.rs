@@ -0,0 +1,160 @@
+/**
+ * Compressed echo session tests over in-memory duplex streams
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use tokio::io::duplex;
+use tokio::sync::oneshot;
+
+use tcp_handshake::{
+  ByteCounters, Capabilities, Compression, HandshakeConfig, HandshakeError, SessionStats,
+  decompress, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_config, recv_chunked, recv_compressed,
+  run_async_compressed_echo_session, send_chunked, send_compressed,
+};
+
+const PAYLOAD_LEN: usize = 256 * 1024;
+
+fn offering(capabilities: Option<Capabilities>) -> HandshakeConfig {
+  HandshakeConfig {
+    capabilities,
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Handshakes, then sends `data` once through an echo session
+ * Returns the codec the client settled on, the echo, and the bytes the client wrote
+ */
+async fn echo_once(
+  client: Option<Capabilities>,
+  server: Option<Capabilities>,
+  data: &[u8],
+) -> (Compression, Vec<u8>, u64) {
+  let counters = Arc::new(ByteCounters::default());
+  let client_config = HandshakeConfig {
+    byte_counters: Some(Arc::clone(&counters)),
+    ..offering(client)
+  };
+  let server_config = HandshakeConfig {
+    echo_session: true,
+    chunked_session: true,
+    ..offering(server)
+  };
+  let (mut client_stream, mut server_stream) = duplex(64 * 1024);
+  let (ready, server_ready) = oneshot::channel();
+
+  let server = async move {
+    let outcome = perform_async_server_handshake_with_config(
+      &mut server_stream,
+      "duplex",
+      None,
+      &server_config,
+    )
+    .await?;
+    let _ = ready.send(());
+    let compression = Compression::negotiate(outcome.capabilities);
+    run_async_compressed_echo_session(&mut server_stream, "duplex", compression, &server_config)
+      .await
+  };
+  let client = async {
+    let outcome = perform_async_client_handshake_with_config(&mut client_stream, 5, &client_config)
+      .await
+      .unwrap();
+    let compression = Compression::negotiate(outcome.capabilities);
+    // Bytes sent right behind HELLO Z could be read along with it
+    let _ = server_ready.await;
+    let handshake_bytes = counters.sent.load(Ordering::Relaxed);
+    send_compressed(&mut client_stream, data, compression, &client_config)
+      .await
+      .unwrap();
+    let on_wire = counters.sent.load(Ordering::Relaxed) - handshake_bytes;
+    let echoed = recv_compressed(&mut client_stream, compression, &client_config)
+      .await
+      .unwrap();
+    drop(client_stream);
+    (compression, echoed, on_wire)
+  };
+
+  let (stats, result): (tcp_handshake::Result<SessionStats>, _) = tokio::join!(server, client);
+  assert_eq!(stats.unwrap().bytes, data.len() as u64);
+  result
+}
+
+#[tokio::test]
+async fn compressible_payload_round_trips_in_fewer_bytes() {
+  let data = b"HELLO compression ".repeat(PAYLOAD_LEN / 18);
+  let both = Some(Compression::SUPPORTED);
+
+  let (compression, echoed, on_wire) = echo_once(both, both, &data).await;
+  assert_eq!(compression, Compression::Zstd);
+  assert_eq!(echoed, data);
+  assert!(
+    on_wire < data.len() as u64 / 10,
+    "{on_wire} bytes on the wire for a {} byte payload",
+    data.len()
+  );
+}
+
+#[tokio::test]
+async fn gzip_is_used_when_it_is_the_only_common_codec() {
+  let data = vec![b'z'; PAYLOAD_LEN];
+  let (compression, echoed, on_wire) = echo_once(
+    Some(Capabilities::GZIP),
+    Some(Compression::SUPPORTED),
+    &data,
+  )
+  .await;
+  assert_eq!(compression, Compression::Gzip);
+  assert_eq!(echoed, data);
+  assert!(on_wire < data.len() as u64 / 10);
+}
+
+#[tokio::test]
+async fn peers_without_a_common_codec_fall_back_to_plaintext() {
+  let data = vec![b'p'; PAYLOAD_LEN];
+  for (client, server) in [
+    (None, Some(Compression::SUPPORTED)),
+    (Some(Capabilities::GZIP), Some(Capabilities::ZSTD)),
+  ] {
+    let (compression, echoed, on_wire) = echo_once(client, server, &data).await;
+    assert_eq!(compression, Compression::None);
+    assert_eq!(echoed, data);
+    assert!(on_wire > data.len() as u64);
+  }
+}
+
+#[tokio::test]
+async fn plaintext_codec_matches_plain_chunks() {
+  let config = HandshakeConfig::default();
+  let (mut sender, mut receiver) = duplex(64 * 1024);
+  let (sent, received) = tokio::join!(
+    send_compressed(&mut sender, b"as is", Compression::None, &config),
+    recv_chunked(&mut receiver, &config),
+  );
+  sent.unwrap();
+  assert_eq!(received.unwrap(), b"as is");
+
+  let (sent, received) = tokio::join!(
+    send_chunked(&mut sender, b"as is", &config),
+    recv_compressed(&mut receiver, Compression::None, &config),
+  );
+  sent.unwrap();
+  assert_eq!(received.unwrap(), b"as is");
+}
+
+#[test]
+fn decompression_stops_at_the_payload_limit() {
+  let bomb = tcp_handshake::compress(&vec![0; 4 * 1024 * 1024], Compression::Zstd).unwrap();
+  assert!(bomb.len() < 4 * 1024);
+  match decompress(&bomb, Compression::Zstd, 1024 * 1024) {
+    Err(HandshakeError::PayloadTooLarge { max, .. }) => assert_eq!(max, 1024 * 1024),
+    other => panic!("expected PayloadTooLarge, got {other:?}"),
+  }
+
+  let garbage = decompress(b"not gzip", Compression::Gzip, 1024);
+  assert!(matches!(garbage, Err(HandshakeError::ProtocolViolation(_))));
+}
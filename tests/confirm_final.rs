@@ -0,0 +1,98 @@
+/**
+ * Sync client final confirmation read tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MSG_SIZE, ServerModel, perform_client_handshake_with_config,
+  spawn_server,
+};
+
+const CONFIRM_WAIT: Duration = Duration::from_millis(300);
+
+/**
+ * Serves one scripted handshake: replies HELLO X+1, reads HELLO Z, then sends `after_final`
+ * The connection is held open for `hold` before it is closed
+ */
+fn stub_server(after_final: Option<&'static str>, hold: Duration) -> (SocketAddr, JoinHandle<()>) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; MSG_SIZE];
+    let read = stream.read(&mut buffer).unwrap();
+    let seq: i32 = String::from_utf8_lossy(&buffer[..read])
+      .trim_start_matches("HELLO ")
+      .trim()
+      .parse()
+      .unwrap();
+    stream
+      .write_all(format!("HELLO {}", seq + 1).as_bytes())
+      .unwrap();
+    let _ = stream.read(&mut buffer).unwrap();
+    if let Some(reply) = after_final {
+      stream.write_all(reply.as_bytes()).unwrap();
+    }
+    thread::sleep(hold);
+  });
+  (addr, server)
+}
+
+fn confirming_handshake(addr: SocketAddr) -> tcp_handshake::Result<i32> {
+  let config = HandshakeConfig {
+    confirm_final: Some(CONFIRM_WAIT),
+    ..HandshakeConfig::default()
+  };
+  let mut stream = TcpStream::connect(addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 1, &config).map(|outcome| outcome.final_seq)
+}
+
+#[test]
+fn clean_close_confirms_the_handshake() {
+  let (addr, server) = stub_server(None, Duration::ZERO);
+  assert_eq!(confirming_handshake(addr).unwrap(), 3);
+  server.join().unwrap();
+}
+
+#[test]
+fn err_reply_fails_the_handshake() {
+  let (addr, server) = stub_server(Some("ERR final sequence mismatch"), Duration::ZERO);
+  let error = confirming_handshake(addr).unwrap_err();
+  assert!(
+    matches!(&error, HandshakeError::Rejected(reason) if reason == "final sequence mismatch"),
+    "{error}"
+  );
+  server.join().unwrap();
+}
+
+#[test]
+fn silence_until_the_wait_ends_counts_as_acceptance() {
+  let (addr, server) = stub_server(None, CONFIRM_WAIT * 3);
+  assert_eq!(confirming_handshake(addr).unwrap(), 3);
+  server.join().unwrap();
+}
+
+#[test]
+fn unexpected_message_is_a_protocol_violation() {
+  let (addr, server) = stub_server(Some("HELLO 4"), Duration::ZERO);
+  let error = confirming_handshake(addr).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::ProtocolViolation(_)),
+    "{error}"
+  );
+  server.join().unwrap();
+}
+
+#[test]
+fn real_server_confirms_the_handshake() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, HandshakeConfig::default()).unwrap();
+    assert_eq!(confirming_handshake(server.addr).unwrap(), 3, "{model}");
+    server.stop();
+  }
+}
@@ -0,0 +1,50 @@
+/**
+ * Connection phase timing tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  ServerModel, perform_async_client_handshake_to, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_config, spawn_server,
+};
+
+use common::quiet;
+
+#[tokio::test]
+async fn all_three_phases_are_recorded_over_loopback() {
+  let server = spawn_server(ServerModel::Async, quiet()).unwrap();
+  let started = Instant::now();
+
+  let (_stream, outcome) =
+    perform_async_client_handshake_to("localhost", server.addr.port(), 10, &quiet())
+      .await
+      .unwrap();
+  let elapsed = started.elapsed();
+
+  assert_eq!(outcome.final_seq, 12);
+  let phases = outcome.connect_phases.expect("connect phases recorded");
+  // Each phase happened, and together they fit in the time the call took
+  assert!(phases.connect > Duration::ZERO, "{phases:?}");
+  assert!(outcome.duration > Duration::ZERO, "{outcome:?}");
+  assert!(
+    phases.dns + phases.connect + outcome.duration <= elapsed,
+    "{phases:?} {outcome:?} {elapsed:?}"
+  );
+  server.stop();
+}
+
+#[tokio::test]
+async fn handshakes_over_a_caller_stream_have_no_connect_phases() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let config = quiet();
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 10, &config),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &config),
+  );
+  server.unwrap();
+  assert_eq!(client.unwrap().connect_phases, None);
+}
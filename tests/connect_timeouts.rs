@@ -0,0 +1,71 @@
+/**
+ * Async client DNS and connect timeout tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::Arc;
+use std::time::Duration;
+
+use tcp_handshake::{HandshakeConfig, HandshakeError, MockClock, connect_async};
+use tokio::net::TcpListener;
+
+// Reserved by RFC 6761, so it can never resolve
+const UNRESOLVABLE_HOST: &str = "handshake-test.invalid";
+
+#[tokio::test]
+async fn unresolvable_host_fails_in_the_dns_phase() {
+  let config = HandshakeConfig {
+    dns_timeout: Duration::from_secs(3),
+    ..HandshakeConfig::default()
+  };
+
+  let result = connect_async(UNRESOLVABLE_HOST, 80, &config).await;
+  // A resolver without network access may hang instead of answering NXDOMAIN
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::Io(_)) | Err(HandshakeError::DnsTimeout { .. })
+    ),
+    "got {result:?}"
+  );
+}
+
+#[tokio::test]
+async fn slow_lookup_reports_dns_timeout() {
+  // The mock clock never advances, so a zero budget expires as soon as the lookup yields
+  let config = HandshakeConfig {
+    dns_timeout: Duration::ZERO,
+    clock: Arc::new(MockClock::new()),
+    ..HandshakeConfig::default()
+  };
+
+  let result = connect_async(UNRESOLVABLE_HOST, 80, &config).await;
+  match result {
+    Err(HandshakeError::DnsTimeout { host }) => assert_eq!(host, UNRESOLVABLE_HOST),
+    other => panic!("expected DnsTimeout, got {other:?}"),
+  }
+}
+
+#[tokio::test]
+async fn resolved_name_connects_within_budget() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let port = listener.local_addr().unwrap().port();
+
+  let stream = connect_async("localhost", port, &HandshakeConfig::default()).await;
+  // localhost may resolve to ::1 first; the IPv4 listener must still be reached
+  let stream = stream.unwrap();
+  assert_eq!(stream.peer_addr().unwrap().port(), port);
+}
+
+#[tokio::test]
+async fn refused_connect_is_not_a_dns_error() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let port = listener.local_addr().unwrap().port();
+  drop(listener);
+
+  let result = connect_async("127.0.0.1", port, &HandshakeConfig::default()).await;
+  assert!(
+    matches!(result, Err(HandshakeError::Io(_))),
+    "got {result:?}"
+  );
+}
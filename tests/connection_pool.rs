@@ -0,0 +1,81 @@
+/**
+ * Client connection pool tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tcp_handshake::{ConnectionPool, MockClock};
+
+fn addr(port: u16) -> SocketAddr {
+  SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+fn pool(clock: &MockClock, max_idle_per_addr: usize) -> ConnectionPool<&'static str> {
+  ConnectionPool::with_clock(
+    Duration::from_secs(10),
+    max_idle_per_addr,
+    Arc::new(clock.clone()),
+  )
+}
+
+#[test]
+fn checked_in_connection_is_checked_out_again() {
+  let clock = MockClock::new();
+  let pool = pool(&clock, 4);
+  assert_eq!(pool.checkout(addr(8080)), None);
+
+  pool.checkin(addr(8080), "first");
+  pool.checkin(addr(8080), "second");
+  pool.checkin(addr(9090), "other");
+  assert_eq!(pool.idle_count(addr(8080)), 2);
+
+  // The most recently returned connection is reused first
+  assert_eq!(pool.checkout(addr(8080)), Some("second"));
+  assert_eq!(pool.checkout(addr(8080)), Some("first"));
+  assert_eq!(pool.checkout(addr(8080)), None);
+  assert_eq!(pool.checkout(addr(9090)), Some("other"));
+}
+
+#[test]
+fn checkin_beyond_the_idle_limit_is_dropped() {
+  let clock = MockClock::new();
+  let pool = pool(&clock, 1);
+  pool.checkin(addr(8080), "kept");
+  pool.checkin(addr(8080), "dropped");
+  assert_eq!(pool.idle_count(addr(8080)), 1);
+  assert_eq!(pool.checkout(addr(8080)), Some("kept"));
+}
+
+#[test]
+fn idle_connections_are_evicted() {
+  let clock = MockClock::new();
+  let pool = pool(&clock, 4);
+  pool.checkin(addr(8080), "stale");
+  clock.advance(Duration::from_secs(8));
+  pool.checkin(addr(8080), "fresh");
+
+  clock.advance(Duration::from_secs(5));
+  assert_eq!(pool.evict_idle(), 1);
+  assert_eq!(pool.idle_count(addr(8080)), 1);
+
+  clock.advance(Duration::from_secs(10));
+  assert_eq!(pool.evict_idle(), 1);
+  assert_eq!(pool.idle_count(addr(8080)), 0);
+}
+
+#[test]
+fn checkout_skips_expired_connections() {
+  let clock = MockClock::new();
+  let pool = pool(&clock, 4);
+  pool.checkin(addr(8080), "old");
+  clock.advance(Duration::from_secs(11));
+  pool.checkin(addr(8080), "new");
+
+  assert_eq!(pool.checkout(addr(8080)), Some("new"));
+  // The expired one is closed rather than handed out
+  assert_eq!(pool.checkout(addr(8080)), None);
+  assert_eq!(pool.idle_count(addr(8080)), 0);
+}
@@ -0,0 +1,68 @@
+#![cfg(target_os = "linux")]
+/**
+ * Corked server reply tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * With coalescing off every reply is two writes, the message and then its
+ * delimiter, and TCP_NODELAY would send each as its own segment. Corked, the
+ * client's first read has to return the whole framed reply, and it has to
+ * come well inside the 200 ms the kernel holds corked data at most.
+ */
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{Delimiter, HandshakeConfig, ServerModel, ServerProfile, spawn_server};
+
+const HANDSHAKES: usize = 20;
+
+fn corked_server() -> HandshakeConfig {
+  HandshakeConfig {
+    cork_replies: true,
+    coalesce_writes: false,
+    tcp_nodelay: true,
+    delimiter: Delimiter::Byte(b'\n'),
+    silent: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn corked_replies_arrive_in_one_piece() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, corked_server()).unwrap();
+
+    for _ in 0..HANDSHAKES {
+      let mut stream = TcpStream::connect(server.addr).unwrap();
+      stream.set_nodelay(true).unwrap();
+      stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+      stream.write_all(b"HELLO 10\n").unwrap();
+
+      let started = Instant::now();
+      let mut reply = [0u8; 64];
+      let read = stream.read(&mut reply).unwrap();
+      assert_eq!(&reply[..read], b"HELLO 11\n", "{model}");
+      assert!(
+        started.elapsed() < Duration::from_millis(150),
+        "{model}: reply held for {:?}",
+        started.elapsed()
+      );
+      stream.write_all(b"HELLO 12\n").unwrap();
+    }
+
+    server.stop();
+  }
+}
+
+#[test]
+fn throughput_profile_corks_replies() {
+  let mut config = HandshakeConfig::default();
+  assert!(!config.cork_replies);
+  config.apply_profile(ServerProfile::Throughput);
+  assert!(config.cork_replies);
+  config.apply_profile(ServerProfile::Default);
+  assert!(!config.cork_replies);
+}
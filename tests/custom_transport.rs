@@ -0,0 +1,44 @@
+/**
+ * Handshake over a caller-provided transport
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Uses only the public generic API: a `tokio::io::duplex` pipe stands in for
+ * any transport a user might bring, such as a QUIC stream.
+ */
+use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  HandshakeConfig, perform_async_client_handshake_on, perform_async_server_handshake_with_config,
+};
+
+#[tokio::test]
+async fn handshake_runs_over_an_owned_duplex_pipe() {
+  let (client, mut server) = duplex(1024);
+  let config = HandshakeConfig::default();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &config),
+    perform_async_server_handshake_with_config(&mut server, "duplex peer", None, &config),
+  );
+  assert_eq!(client.unwrap().final_seq, 7);
+  assert_eq!(server.unwrap().final_seq, 7);
+}
+
+#[tokio::test]
+async fn borrowed_stream_stays_usable_after_the_handshake() {
+  let (mut client, mut server) = duplex(1024);
+  let config = HandshakeConfig::default();
+
+  let (outcome, _) = tokio::join!(
+    perform_async_client_handshake_on(&mut client, 41, &config),
+    perform_async_server_handshake_with_config(&mut server, "duplex peer", None, &config),
+  );
+  assert_eq!(outcome.unwrap().final_seq, 43);
+
+  // The caller still owns the pipe and decides what happens next
+  client.write_all(b"after").await.unwrap();
+  let mut received = [0u8; 5];
+  server.read_exact(&mut received).await.unwrap();
+  assert_eq!(&received, b"after");
+}
@@ -0,0 +1,116 @@
+/**
+ * Delimited message framing tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::net::TcpStream;
+
+use tcp_handshake::{
+  Delimiter, GrowableBuffer, HandshakeConfig, HandshakeError, MSG_SIZE, ServerModel,
+  perform_client_handshake_with_config, read_until_delimiter, read_until_delimiter_async,
+  spawn_server,
+};
+
+fn delimiters() -> [Delimiter; 3] {
+  [
+    Delimiter::Byte(b'\n'),
+    Delimiter::Bytes(b"\r\n".to_vec()),
+    Delimiter::Byte(b'\0'),
+  ]
+}
+
+#[test]
+fn reads_one_message_per_delimiter() {
+  for delimiter in delimiters() {
+    let mut wire = delimiter.frame("HELLO 1");
+    wire.extend(delimiter.frame("HELLO 2"));
+    let mut stream = &wire[..];
+    let mut pending = GrowableBuffer::default();
+
+    let first = read_until_delimiter(&mut stream, delimiter.as_bytes(), &mut pending).unwrap();
+    assert_eq!(first, b"HELLO 1", "{delimiter:?}");
+    // The second message was read along with the first and is served from `pending`
+    assert!(stream.is_empty());
+    let second = read_until_delimiter(&mut stream, delimiter.as_bytes(), &mut pending).unwrap();
+    assert_eq!(second, b"HELLO 2", "{delimiter:?}");
+    assert!(pending.is_empty());
+  }
+}
+
+#[test]
+fn delimiter_split_across_reads_is_found() {
+  // `chain` hands out each part in a separate read
+  let mut stream = (&b"HELLO 7\r"[..]).chain(&b"\nHELLO"[..]);
+  let mut pending = GrowableBuffer::default();
+
+  let message = read_until_delimiter(&mut stream, b"\r\n", &mut pending).unwrap();
+  assert_eq!(message, b"HELLO 7");
+  assert_eq!(pending.as_slice(), b"HELLO");
+}
+
+#[tokio::test]
+async fn async_delimiter_split_across_reads_is_found() {
+  let mut stream = tokio::io::AsyncReadExt::chain(&b"HELLO 7\r"[..], &b"\n"[..]);
+  let mut pending = GrowableBuffer::default();
+
+  let message = read_until_delimiter_async(&mut stream, b"\r\n", &mut pending)
+    .await
+    .unwrap();
+  assert_eq!(message, b"HELLO 7");
+  assert!(pending.is_empty());
+}
+
+#[test]
+fn missing_delimiter_hits_size_cap() {
+  let wire = [b'A'; MSG_SIZE * 2];
+  let mut stream = &wire[..];
+  let mut pending = GrowableBuffer::default();
+
+  let error = read_until_delimiter(&mut stream, b"\n", &mut pending).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::FrameTooLarge { max: MSG_SIZE }),
+    "{error}"
+  );
+}
+
+#[test]
+fn message_of_exactly_the_size_cap_is_accepted() {
+  let mut wire = vec![b'A'; MSG_SIZE];
+  wire.extend_from_slice(b"\r\n");
+  let mut stream = (&wire[..MSG_SIZE + 1]).chain(&wire[MSG_SIZE + 1..]);
+  let mut pending = GrowableBuffer::default();
+
+  let message = read_until_delimiter(&mut stream, b"\r\n", &mut pending).unwrap();
+  assert_eq!(message.len(), MSG_SIZE);
+}
+
+#[test]
+fn eof_before_delimiter_is_a_disconnect() {
+  let mut stream = &b"HELLO 1"[..];
+  let mut pending = GrowableBuffer::default();
+
+  let error = read_until_delimiter(&mut stream, b"\n", &mut pending).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::ClientDisconnected),
+    "{error}"
+  );
+}
+
+#[test]
+fn handshake_completes_with_each_delimiter() {
+  for model in [ServerModel::Sequential, ServerModel::Async] {
+    for delimiter in delimiters() {
+      let config = HandshakeConfig {
+        delimiter: delimiter.clone(),
+        ..HandshakeConfig::default()
+      };
+      let server = spawn_server(model, config.clone()).unwrap();
+
+      let mut stream = TcpStream::connect(server.addr).unwrap();
+      let outcome = perform_client_handshake_with_config(&mut stream, 10, &config).unwrap();
+      assert_eq!(outcome.final_seq, 12, "{model} {delimiter:?}");
+      server.stop();
+    }
+  }
+}
@@ -0,0 +1,61 @@
+/**
+ * Async server drain timeout tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+use tcp_handshake::{HandshakeConfig, ServerMetrics, ServerOptions, live_config, serve_async};
+
+#[tokio::test]
+async fn a_stuck_connection_is_force_closed_after_the_drain_timeout() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let metrics = Arc::new(ServerMetrics::new());
+  let config = HandshakeConfig {
+    // Far longer than the drain, so only the drain timeout can end the handshake
+    read_timeout: Duration::from_secs(60),
+    drain_timeout: Duration::from_millis(100),
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+
+  let (shutdown, shutdown_requested) = oneshot::channel::<()>();
+  let server = tokio::spawn(serve_async(
+    listener,
+    live_config(config),
+    Arc::clone(&metrics),
+    ServerOptions::default(),
+    async move {
+      let _ = shutdown_requested.await;
+    },
+  ));
+
+  // The peer connects and then never sends HELLO X
+  let _stuck = TcpStream::connect(addr).await.unwrap();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while metrics.snapshot().accepted == 0 {
+    assert!(Instant::now() < deadline, "connection was never accepted");
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+
+  let started = Instant::now();
+  shutdown.send(()).unwrap();
+  let summary = tokio::time::timeout(Duration::from_secs(5), server)
+    .await
+    .expect("shutdown waited on the stuck connection")
+    .unwrap();
+
+  assert_eq!(summary.force_closed, 1);
+  assert_eq!(metrics.force_closed.load(Ordering::Relaxed), 1);
+  assert!(
+    started.elapsed() < Duration::from_secs(5),
+    "{:?}",
+    started.elapsed()
+  );
+}
@@ -0,0 +1,105 @@
+/**
+ * Plaintext and TLS handshakes on the same port
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The server certificate in `tests/fixtures` is self-signed for `localhost`.
+ */
+use std::sync::Arc;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+use tcp_handshake::{
+  DualAcceptor, HandshakeConfig, HandshakeError, HandshakeOutcome,
+  perform_async_client_handshake_on,
+};
+
+const CERT: &[u8] = include_bytes!("fixtures/localhost.crt");
+const KEY: &[u8] = include_bytes!("fixtures/localhost.key");
+
+fn acceptor() -> DualAcceptor {
+  let cert = CertificateDer::from_pem_slice(CERT).unwrap();
+  let key = PrivateKeyDer::from_pem_slice(KEY).unwrap();
+  let config = ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(vec![cert], key)
+    .unwrap();
+  DualAcceptor::new(Arc::new(config))
+}
+
+fn connector() -> TlsConnector {
+  let mut roots = RootCertStore::empty();
+  roots
+    .add(CertificateDer::from_pem_slice(CERT).unwrap())
+    .unwrap();
+  let config = ClientConfig::builder()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+  TlsConnector::from(Arc::new(config))
+}
+
+/**
+ * Serves `connections` handshakes on one port, returning whether each was TLS
+ */
+async fn serve(listener: TcpListener, connections: usize) -> Vec<(bool, HandshakeOutcome)> {
+  let acceptor = acceptor();
+  let config = HandshakeConfig::default();
+  let mut served = Vec::new();
+  for _ in 0..connections {
+    let (stream, peer) = listener.accept().await.unwrap();
+    let (stream, outcome) = acceptor.accept(stream, peer, None, &config).await.unwrap();
+    served.push((stream.is_tls(), outcome));
+  }
+  served
+}
+
+#[tokio::test]
+async fn plaintext_and_tls_clients_share_one_port() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = tokio::spawn(serve(listener, 2));
+  let config = HandshakeConfig::default();
+
+  let plain = TcpStream::connect(addr).await.unwrap();
+  let outcome = perform_async_client_handshake_on(plain, 5, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+
+  let tcp = TcpStream::connect(addr).await.unwrap();
+  let name = ServerName::try_from("localhost").unwrap();
+  let tls = connector().connect(name, tcp).await.unwrap();
+  let outcome = perform_async_client_handshake_on(tls, 41, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 43);
+
+  let served = server.await.unwrap();
+  assert!(!served[0].0);
+  assert_eq!(served[0].1.final_seq, 7);
+  assert!(served[1].0);
+  assert_eq!(served[1].1.final_seq, 43);
+}
+
+#[tokio::test]
+async fn garbage_after_a_tls_record_byte_fails_the_tls_handshake() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = tokio::spawn(async move {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"\x16HELLO 5").await.unwrap();
+    stream
+  });
+
+  let (stream, peer) = listener.accept().await.unwrap();
+  let result = acceptor()
+    .accept(stream, peer, None, &HandshakeConfig::default())
+    .await;
+  let _client = client.await.unwrap();
+  assert!(matches!(result, Err(HandshakeError::Io(_))));
+}
@@ -0,0 +1,76 @@
+/**
+ * Echoed sequence detection tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use tcp_handshake::{
+  HandshakeError, perform_async_client_handshake_with_config, perform_client_handshake_with_config,
+};
+
+use common::quiet;
+
+/**
+ * A fake server that answers the first message with `reply`, whatever it was
+ */
+fn fake_server(reply: &'static [u8]) -> (SocketAddr, JoinHandle<()>) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; 64];
+    let _ = stream.read(&mut buffer).unwrap();
+    stream.write_all(reply).unwrap();
+  });
+  (addr, server)
+}
+
+#[test]
+fn a_server_echoing_hello_x_is_detected() {
+  let (addr, server) = fake_server(b"HELLO 5");
+  let mut stream = TcpStream::connect(addr).unwrap();
+
+  let result = perform_client_handshake_with_config(&mut stream, 5, &quiet());
+  assert!(
+    matches!(result, Err(HandshakeError::EchoDetected { seq: 5 })),
+    "{result:?}"
+  );
+  server.join().unwrap();
+}
+
+#[test]
+fn any_other_wrong_reply_is_a_sequence_mismatch() {
+  let (addr, server) = fake_server(b"HELLO 9");
+  let mut stream = TcpStream::connect(addr).unwrap();
+
+  let result = perform_client_handshake_with_config(&mut stream, 5, &quiet());
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::SequenceMismatch {
+        expected: 6,
+        received: 9
+      })
+    ),
+    "{result:?}"
+  );
+  server.join().unwrap();
+}
+
+#[tokio::test]
+async fn the_async_client_detects_an_echo_too() {
+  let (addr, server) = fake_server(b"HELLO 5");
+  let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+  let result = perform_async_client_handshake_with_config(&mut stream, 5, &quiet()).await;
+  assert!(
+    matches!(result, Err(HandshakeError::EchoDetected { seq: 5 })),
+    "{result:?}"
+  );
+  server.join().unwrap();
+}
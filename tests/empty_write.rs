@@ -0,0 +1,34 @@
+/**
+ * Empty message write tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tcp_handshake::{HandshakeError, write_message_to_async_stream, write_message_to_stream};
+
+fn is_empty_message_error(error: &HandshakeError) -> bool {
+  matches!(
+    error,
+    HandshakeError::InvalidMessageFormat { message, offset: 0, .. } if message.is_empty()
+  )
+}
+
+#[test]
+fn sync_write_rejects_empty_message() {
+  let mut written = Vec::new();
+  let error = write_message_to_stream(&mut written, "").unwrap_err();
+  assert!(is_empty_message_error(&error), "{error}");
+  assert!(written.is_empty());
+
+  write_message_to_stream(&mut written, "HELLO 5").unwrap();
+  assert_eq!(written, b"HELLO 5");
+}
+
+#[tokio::test]
+async fn async_write_rejects_empty_message() {
+  let mut written = Vec::new();
+  let error = write_message_to_async_stream(&mut written, "")
+    .await
+    .unwrap_err();
+  assert!(is_empty_message_error(&error), "{error}");
+  assert!(written.is_empty());
+}
@@ -0,0 +1,90 @@
+/**
+ * Handshake event callback tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::{Arc, Mutex};
+
+use tcp_handshake::{
+  Direction, EventSink, HandshakeConfig, HandshakeEvent,
+  perform_async_client_handshake_with_config, perform_async_server_handshake_with_config,
+};
+
+/**
+ * Config whose events are appended to the returned Vec
+ */
+fn collecting_config() -> (HandshakeConfig, Arc<Mutex<Vec<HandshakeEvent>>>) {
+  let events = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&events);
+  let config = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.clone())),
+    ..HandshakeConfig::default()
+  };
+  (config, events)
+}
+
+fn summary(events: &Mutex<Vec<HandshakeEvent>>) -> Vec<(u8, Direction, String)> {
+  let events = events.lock().unwrap();
+  assert!(
+    events
+      .windows(2)
+      .all(|pair| pair[0].timestamp <= pair[1].timestamp),
+    "timestamps go backwards: {events:?}"
+  );
+  events
+    .iter()
+    .map(|event| (event.step, event.direction, event.message.clone()))
+    .collect()
+}
+
+#[tokio::test]
+async fn both_sides_report_every_step_in_order() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let (client_config, client_events) = collecting_config();
+  let (server_config, server_events) = collecting_config();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 10, &client_config),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &server_config),
+  );
+  client.unwrap();
+  server.unwrap();
+
+  assert_eq!(
+    summary(&client_events),
+    vec![
+      (1, Direction::Outbound, "HELLO 10".to_string()),
+      (2, Direction::Inbound, "HELLO 11".to_string()),
+      (3, Direction::Outbound, "HELLO 12".to_string()),
+    ]
+  );
+  assert_eq!(
+    summary(&server_events),
+    vec![
+      (1, Direction::Inbound, "HELLO 10".to_string()),
+      (2, Direction::Outbound, "HELLO 11".to_string()),
+      (3, Direction::Inbound, "HELLO 12".to_string()),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn failed_step_is_not_reported() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let (server_config, server_events) = collecting_config();
+
+  let client =
+    async { tcp_handshake::write_message_to_async_stream(&mut client_stream, "HELLO x").await };
+  let (written, server) = tokio::join!(
+    client,
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &server_config),
+  );
+  written.unwrap();
+  assert!(server.is_err());
+
+  // The malformed message was still received, so step 1 is reported
+  assert_eq!(
+    summary(&server_events),
+    vec![(1, Direction::Inbound, "HELLO x".to_string())]
+  );
+}
@@ -0,0 +1,78 @@
+/**
+ * Per-connection message limit tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tokio::io::{AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ServerMetrics, perform_async_client_handshake_on,
+  perform_async_server_handshake_with_config, run_async_mux_session,
+};
+
+fn capped(max: u64, metrics: &ServerMetrics) -> HandshakeConfig {
+  metrics.instrument(&HandshakeConfig {
+    max_connection_messages: Some(max),
+    ..HandshakeConfig::default()
+  })
+}
+
+#[tokio::test]
+async fn pipelined_hellos_over_the_cap_abort_the_connection() {
+  let metrics = ServerMetrics::new();
+  let config = HandshakeConfig {
+    multiplex: true,
+    ..capped(8, &metrics)
+  };
+  let (mut peer, mut server) = duplex(64 * 1024);
+
+  // Dozens of HELLOs in one burst, each opening a new stream
+  let flood: String = (1..=40).map(|id| format!("HELLO {id} S={id}\n")).collect();
+  let (_, result) = tokio::join!(
+    async move {
+      // The server may hang up before reading everything
+      let _ = peer.write_all(flood.as_bytes()).await;
+      peer
+    },
+    run_async_mux_session(&mut server, "flooder", &config),
+  );
+
+  match result {
+    Err(HandshakeError::ProtocolViolation(reason)) => {
+      assert_eq!(reason, "more than 8 messages on one connection");
+    }
+    other => panic!("expected a protocol violation, got {other:?}"),
+  }
+  assert_eq!(metrics.snapshot().excess_messages, 1);
+}
+
+#[tokio::test]
+async fn strict_handshake_fits_a_cap_of_two() {
+  let metrics = ServerMetrics::new();
+  let server_config = capped(2, &metrics);
+  let client_config = HandshakeConfig::default();
+  let (client, mut server) = duplex(1024);
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &client_config),
+    perform_async_server_handshake_with_config(&mut server, "duplex", None, &server_config),
+  );
+  assert_eq!(client.unwrap().final_seq, 7);
+  assert_eq!(server.unwrap().final_seq, 7);
+  assert_eq!(metrics.snapshot().excess_messages, 0);
+}
+
+#[tokio::test]
+async fn strict_handshake_fails_on_the_read_past_the_cap() {
+  let metrics = ServerMetrics::new();
+  let server_config = capped(1, &metrics);
+  let client_config = HandshakeConfig::default();
+  let (client, mut server) = duplex(1024);
+
+  let (_, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &client_config),
+    perform_async_server_handshake_with_config(&mut server, "duplex", None, &server_config),
+  );
+  assert!(matches!(server, Err(HandshakeError::ProtocolViolation(_))));
+  assert_eq!(metrics.snapshot().excess_messages, 1);
+}
@@ -0,0 +1,163 @@
+/**
+ * Exit code tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io;
+use std::net::TcpListener;
+use std::process::Command;
+
+use tcp_handshake::{Capabilities, HandshakeError};
+
+fn io_error(kind: io::ErrorKind) -> HandshakeError {
+  HandshakeError::Io(io::Error::from(kind))
+}
+
+#[test]
+fn every_variant_maps_to_its_code() {
+  let cases = [
+    (io_error(io::ErrorKind::PermissionDenied), 1),
+    (io_error(io::ErrorKind::TimedOut), 2),
+    (io_error(io::ErrorKind::WouldBlock), 2),
+    (HandshakeError::Timeout, 2),
+    (HandshakeError::FirstByteTimeout, 2),
+    (
+      HandshakeError::DnsTimeout {
+        host: "example.com".to_string(),
+      },
+      2,
+    ),
+    (
+      HandshakeError::SequenceMismatch {
+        expected: 6,
+        received: 9,
+      },
+      3,
+    ),
+    (HandshakeError::EchoDetected { seq: 5 }, 3),
+    (io_error(io::ErrorKind::ConnectionRefused), 4),
+    (HandshakeError::ServerBusy, 5),
+    (HandshakeError::ServerOverloaded, 5),
+    (HandshakeError::Rejected("warming up".to_string()), 5),
+    (
+      HandshakeError::InvalidMessageFormat {
+        message: "HI".to_string(),
+        offset: 0,
+        expected: "'HELLO'",
+      },
+      6,
+    ),
+    (
+      HandshakeError::MalformedSequence {
+        value: "x".to_string(),
+        offset: 6,
+        expected: "32-bit integer",
+      },
+      6,
+    ),
+    (HandshakeError::SequenceOverflow { seq: i32::MAX }, 6),
+    (HandshakeError::ProtocolViolation("extra".to_string()), 6),
+    (
+      HandshakeError::SessionLimitExceeded {
+        limit: "messages",
+        value: 2,
+        max: 1,
+      },
+      6,
+    ),
+    (HandshakeError::PayloadTooLarge { size: 2, max: 1 }, 6),
+    (HandshakeError::FrameTooLarge { max: 64 }, 6),
+    (
+      HandshakeError::NonceMismatch {
+        expected: 1,
+        received: None,
+      },
+      7,
+    ),
+    (
+      HandshakeError::AddressMismatch {
+        claimed: "192.0.2.1".parse().unwrap(),
+        actual: "127.0.0.1".parse().unwrap(),
+      },
+      7,
+    ),
+    (
+      HandshakeError::CapabilityMismatch {
+        missing: Capabilities::NONCE,
+        common: Capabilities::NONE,
+      },
+      7,
+    ),
+    (HandshakeError::ClientDisconnected, 8),
+    (io_error(io::ErrorKind::ConnectionReset), 8),
+    (io_error(io::ErrorKind::BrokenPipe), 8),
+    (io_error(io::ErrorKind::UnexpectedEof), 8),
+    (HandshakeError::Socks5("auth refused".to_string()), 9),
+    (
+      HandshakeError::LocalAddressInUse("127.0.0.1:9000".parse().unwrap()),
+      10,
+    ),
+    (HandshakeError::PortInUse(9000), 10),
+    (
+      HandshakeError::SourcePortsExhausted {
+        first: 9000,
+        last: 9001,
+      },
+      10,
+    ),
+    (io_error(io::ErrorKind::AddrInUse), 10),
+    (HandshakeError::Aborted, 11),
+    (HandshakeError::InvalidSequenceNumber("x".to_string()), 12),
+    (HandshakeError::InvalidPort("x".to_string()), 12),
+    (HandshakeError::InvalidArguments("x".to_string()), 12),
+    (HandshakeError::InvalidConfigFile("x".to_string()), 12),
+    (
+      HandshakeError::InvalidResponseTemplate {
+        template: "x".to_string(),
+        reason: "no {seq}".to_string(),
+      },
+      12,
+    ),
+    (
+      HandshakeError::InvalidTranscript {
+        line: 1,
+        reason: "x".to_string(),
+      },
+      12,
+    ),
+  ];
+
+  for (error, code) in cases {
+    assert_eq!(error.exit_code(), code, "{error:?}");
+  }
+}
+
+#[test]
+fn client_exits_with_connection_refused() {
+  // Bind and release a port so nothing is listening on it
+  let port = TcpListener::bind("127.0.0.1:0")
+    .unwrap()
+    .local_addr()
+    .unwrap()
+    .port();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_client-sync"))
+    .args(["127.0.0.1", &port.to_string(), "10"])
+    .output()
+    .unwrap();
+  assert_eq!(
+    output.status.code(),
+    Some(4),
+    "{}",
+    String::from_utf8_lossy(&output.stderr)
+  );
+}
+
+#[test]
+fn bad_arguments_exit_with_a_usage_code() {
+  let output = Command::new(env!("CARGO_BIN_EXE_client-sync"))
+    .args(["127.0.0.1", "not-a-port", "10"])
+    .output()
+    .unwrap();
+  assert_eq!(output.status.code(), Some(12));
+}
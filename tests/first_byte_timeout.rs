@@ -0,0 +1,79 @@
+/**
+ * First byte timeout tests with a peer that connects and stays silent
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  EventSink, HandshakeConfig, HandshakeError, HandshakeEvent,
+  perform_async_server_handshake_with_config, perform_client_handshake_with_config,
+  perform_server_handshake_with_config,
+};
+
+fn impatient_config() -> HandshakeConfig {
+  HandshakeConfig {
+    first_byte_timeout: Some(Duration::from_millis(100)),
+    read_timeout: Duration::from_secs(5),
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn sync_server_sheds_a_silent_peer() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let _silent = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+  let (mut stream, _) = listener.accept().unwrap();
+
+  let started = Instant::now();
+  let error = perform_server_handshake_with_config(&mut stream, &impatient_config()).unwrap_err();
+  assert!(matches!(error, HandshakeError::FirstByteTimeout), "{error}");
+  assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn async_server_sheds_a_silent_peer() {
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let _silent = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+    .await
+    .unwrap();
+  let (mut stream, peer) = listener.accept().await.unwrap();
+
+  let started = Instant::now();
+  let error =
+    perform_async_server_handshake_with_config(&mut stream, peer, None, &impatient_config())
+      .await
+      .unwrap_err();
+  assert!(matches!(error, HandshakeError::FirstByteTimeout), "{error}");
+  assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn active_peer_gets_the_full_read_timeout_after_its_first_byte() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    perform_server_handshake_with_config(&mut stream, &impatient_config())
+  });
+
+  // The client is slower than the first byte timeout only after HELLO X
+  let mut stream = TcpStream::connect(addr).unwrap();
+  let outcome = perform_client_handshake_with_config(
+    &mut stream,
+    5,
+    &HandshakeConfig {
+      on_event: EventSink::new(|event: &HandshakeEvent| {
+        if event.step == 2 {
+          thread::sleep(Duration::from_millis(300));
+        }
+      }),
+      ..HandshakeConfig::default()
+    },
+  )
+  .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  assert_eq!(server.join().unwrap().unwrap().final_seq, 7);
+}
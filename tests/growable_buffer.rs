@@ -0,0 +1,54 @@
+/**
+ * Growable framed read buffer tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Cursor;
+
+use tcp_handshake::{DEFAULT_RETAINED_CAPACITY, GrowableBuffer, read_until_delimiter};
+
+#[test]
+fn buffer_shrinks_after_a_large_message() {
+  let mut buffer = GrowableBuffer::with_max_retained(128);
+  buffer.extend_from_slice(&[b'x'; 4096]);
+  assert!(buffer.capacity() >= 4096);
+
+  // Still holding more than the cap, so nothing is released yet
+  buffer.consume(1024);
+  assert!(buffer.capacity() >= 4096);
+  assert_eq!(buffer.len(), 3072);
+
+  buffer.consume(3072);
+  assert!(buffer.is_empty());
+  assert!(buffer.capacity() <= 128, "{}", buffer.capacity());
+}
+
+#[test]
+fn unread_bytes_survive_compaction() {
+  let mut buffer = GrowableBuffer::with_max_retained(16);
+  buffer.extend_from_slice(b"HELLO 1\nHELLO 2\n");
+  buffer.consume(8);
+  assert_eq!(buffer.as_slice(), b"HELLO 2\n");
+
+  // Appending reuses the consumed space and keeps the unread bytes in order
+  buffer.extend_from_slice(b"HELLO 3\n");
+  assert_eq!(buffer.as_slice(), b"HELLO 2\nHELLO 3\n");
+  assert_eq!(buffer.take_all(), b"HELLO 2\nHELLO 3\n");
+  assert!(buffer.is_empty());
+}
+
+#[test]
+fn long_stream_of_small_messages_stays_within_the_cap() {
+  let stream: Vec<u8> = (0..1000)
+    .flat_map(|seq| format!("HELLO {seq}\n").into_bytes())
+    .collect();
+  let mut stream = Cursor::new(stream);
+  let mut pending = GrowableBuffer::default();
+
+  for seq in 0..1000 {
+    let message = read_until_delimiter(&mut stream, b"\n", &mut pending).unwrap();
+    assert_eq!(message, format!("HELLO {seq}").as_bytes());
+    assert!(pending.capacity() <= DEFAULT_RETAINED_CAPACITY);
+  }
+  assert!(pending.is_empty());
+}
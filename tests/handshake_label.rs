@@ -0,0 +1,71 @@
+/**
+ * Handshake label propagation tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::{Arc, Mutex};
+
+use tcp_handshake::{
+  EventSink, HandshakeConfig, HandshakeEvent, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_config,
+};
+
+/**
+ * Config tagged with `label` whose events are appended to the returned Vec
+ */
+fn labelled_config(label: Option<&str>) -> (HandshakeConfig, Arc<Mutex<Vec<HandshakeEvent>>>) {
+  let events = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&events);
+  let config = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.clone())),
+    label: label.map(str::to_string),
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  (config, events)
+}
+
+fn labels(events: &Mutex<Vec<HandshakeEvent>>) -> Vec<Option<String>> {
+  events
+    .lock()
+    .unwrap()
+    .iter()
+    .map(|event| event.label.clone())
+    .collect()
+}
+
+#[tokio::test]
+async fn each_side_tags_its_events_and_outcome() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let (client_config, client_events) = labelled_config(Some("scenario-7"));
+  let (server_config, server_events) = labelled_config(Some("shard-2"));
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 10, &client_config),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &server_config),
+  );
+
+  assert_eq!(client.unwrap().label.as_deref(), Some("scenario-7"));
+  assert_eq!(server.unwrap().label.as_deref(), Some("shard-2"));
+  assert_eq!(
+    labels(&client_events),
+    vec![Some("scenario-7".to_string()); 3]
+  );
+  assert_eq!(labels(&server_events), vec![Some("shard-2".to_string()); 3]);
+}
+
+#[tokio::test]
+async fn unlabelled_handshakes_carry_no_label() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let (client_config, client_events) = labelled_config(None);
+  let (server_config, _) = labelled_config(Some("shard-2"));
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 10, &client_config),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &server_config),
+  );
+  server.unwrap();
+
+  assert_eq!(client.unwrap().label, None);
+  assert_eq!(labels(&client_events), vec![None; 3]);
+}
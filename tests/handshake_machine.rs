@@ -0,0 +1,461 @@
+/**
+ * Sans-io handshake machine tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Every test drives `HandshakeMachine` with byte slices only; no socket is
+ * opened. The failure tests cover each way the protocol can go wrong.
+ */
+mod common;
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use tcp_handshake::{
+  Capabilities, Delimiter, HandshakeConfig, HandshakeError, HandshakeMachine, HandshakeOutcome,
+  MachineOutput, Role,
+};
+
+use common::quiet;
+
+fn line_framed() -> HandshakeConfig {
+  HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    ..quiet()
+  }
+}
+
+fn client(initial_seq: i32, config: &HandshakeConfig) -> HandshakeMachine<'_> {
+  HandshakeMachine::new(Role::Client { initial_seq }, Cow::Borrowed(config))
+}
+
+fn server(config: &HandshakeConfig) -> HandshakeMachine<'_> {
+  HandshakeMachine::new(Role::Server, Cow::Borrowed(config))
+}
+
+fn expect_send(machine: &mut HandshakeMachine) -> Vec<u8> {
+  match machine.poll_output().unwrap() {
+    MachineOutput::Send(bytes) => bytes,
+    other => panic!("expected bytes to send, got {other:?}"),
+  }
+}
+
+fn expect_complete(machine: &mut HandshakeMachine) -> HandshakeOutcome {
+  match machine.poll_output().unwrap() {
+    MachineOutput::Complete(outcome) => outcome,
+    other => panic!("expected completion, got {other:?}"),
+  }
+}
+
+/**
+ * Feeds `input` and returns the error the next poll fails with
+ */
+fn fails_with(machine: &mut HandshakeMachine, input: &[u8]) -> HandshakeError {
+  machine.handle_input(input);
+  let error = machine.poll_output().unwrap_err();
+  assert_eq!(machine.next_step(), None);
+  error
+}
+
+/**
+ * Polls `from` once, passing anything it sends to `to`
+ * Returns whether it made progress, and its outcome once complete
+ */
+fn pass(
+  from: &mut HandshakeMachine,
+  to: &mut HandshakeMachine,
+) -> (bool, Option<HandshakeOutcome>) {
+  match from.poll_output().unwrap() {
+    MachineOutput::Send(bytes) => {
+      to.handle_input(&bytes);
+      (true, None)
+    }
+    MachineOutput::NeedInput => (false, None),
+    MachineOutput::Complete(outcome) => (true, Some(outcome)),
+  }
+}
+
+/**
+ * Runs a client machine against a server machine, passing each one's output to the other
+ */
+fn run_pair(
+  client: &mut HandshakeMachine,
+  server: &mut HandshakeMachine,
+) -> (HandshakeOutcome, HandshakeOutcome) {
+  let mut client_outcome = None;
+  let mut server_outcome = None;
+  while client_outcome.is_none() || server_outcome.is_none() {
+    let mut progressed = false;
+    if client_outcome.is_none() {
+      let (moved, outcome) = pass(client, server);
+      progressed |= moved;
+      client_outcome = outcome;
+    }
+    if server_outcome.is_none() {
+      let (moved, outcome) = pass(server, client);
+      progressed |= moved;
+      server_outcome = outcome;
+    }
+    assert!(progressed, "both machines are waiting");
+  }
+  (client_outcome.unwrap(), server_outcome.unwrap())
+}
+
+#[test]
+fn client_runs_the_handshake_from_byte_slices() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+
+  assert_eq!(machine.next_step(), Some(1));
+  assert_eq!(expect_send(&mut machine), b"HELLO 10");
+  assert_eq!(machine.poll_output().unwrap(), MachineOutput::NeedInput);
+  assert!(machine.wants_input());
+
+  machine.handle_input(b"HELLO 11");
+  assert_eq!(expect_send(&mut machine), b"HELLO 12");
+
+  let outcome = expect_complete(&mut machine);
+  assert_eq!(outcome.initial_seq, 10);
+  assert_eq!(outcome.final_seq, 12);
+  assert_eq!(outcome.rtt, std::time::Duration::ZERO);
+  assert_eq!(machine.next_step(), None);
+  assert_eq!(machine.outcome(), Some(outcome));
+}
+
+#[test]
+fn server_runs_the_handshake_from_byte_slices() {
+  let config = quiet();
+  let mut machine = server(&config);
+
+  assert_eq!(machine.poll_output().unwrap(), MachineOutput::NeedInput);
+  machine.handle_input(b"HELLO 41");
+  assert_eq!(expect_send(&mut machine), b"HELLO 42");
+  assert_eq!(machine.poll_output().unwrap(), MachineOutput::NeedInput);
+  machine.handle_input(b"HELLO 43");
+
+  let outcome = expect_complete(&mut machine);
+  assert_eq!(outcome.initial_seq, 41);
+  assert_eq!(outcome.final_seq, 43);
+}
+
+#[test]
+fn delimited_input_is_reassembled_from_any_split() {
+  let config = line_framed();
+  let reply = b"HELLO 11\n";
+
+  for split in 1..reply.len() {
+    let mut machine = client(10, &config);
+    assert_eq!(expect_send(&mut machine), b"HELLO 10\n");
+
+    machine.handle_input(&reply[..split]);
+    assert_eq!(machine.poll_output().unwrap(), MachineOutput::NeedInput);
+    machine.handle_input(&reply[split..]);
+    assert_eq!(expect_send(&mut machine), b"HELLO 12\n");
+    assert_eq!(expect_complete(&mut machine).final_seq, 12);
+  }
+}
+
+#[test]
+fn two_machines_agree_on_every_option() {
+  let config = HandshakeConfig {
+    require_nonce: true,
+    namespace: Some("APP".to_string()),
+    capabilities: Some(Capabilities::NONCE | Capabilities::GZIP),
+    ..line_framed()
+  };
+  let server_config = HandshakeConfig {
+    capabilities: Some(Capabilities::GZIP | Capabilities::ZSTD),
+    ..config.clone()
+  };
+  let mut client = client(1000, &config);
+  let mut server = server(&server_config);
+
+  let (client_outcome, server_outcome) = run_pair(&mut client, &mut server);
+  assert_eq!(client_outcome.final_seq, 1002);
+  assert_eq!(server_outcome.final_seq, 1002);
+  assert_eq!(client_outcome.capabilities, Some(Capabilities::GZIP));
+  assert_eq!(client_outcome.negotiated, server_outcome.negotiated);
+  assert!(server_outcome.negotiated.uses_nonce);
+}
+
+#[test]
+fn an_empty_first_message_starts_at_the_reply() {
+  let config = HandshakeConfig {
+    raw_first_message: Some(String::new()),
+    ..quiet()
+  };
+  let mut machine = client(10, &config);
+  assert_eq!(machine.next_step(), Some(2));
+  assert_eq!(machine.poll_output().unwrap(), MachineOutput::NeedInput);
+  machine.handle_input(b"HELLO 11");
+  assert_eq!(expect_send(&mut machine), b"HELLO 12");
+}
+
+#[test]
+fn the_message_layer_skips_framing() {
+  let config = line_framed();
+  let mut machine = client(7, &config);
+  assert_eq!(machine.poll_message().as_deref(), Some("HELLO 7"));
+  assert_eq!(machine.poll_message(), None);
+  machine.handle_message("HELLO 8").unwrap();
+  assert_eq!(machine.poll_message().as_deref(), Some("HELLO 9"));
+  assert_eq!(machine.outcome().unwrap().final_seq, 9);
+}
+
+#[test]
+fn client_rejects_a_wrong_sequence() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 99"),
+    HandshakeError::SequenceMismatch {
+      expected: 11,
+      received: 99
+    }
+  ));
+}
+
+#[test]
+fn client_rejects_an_echo() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 10"),
+    HandshakeError::EchoDetected { seq: 10 }
+  ));
+}
+
+#[test]
+fn server_rejects_hello_x_with_no_next_sequence() {
+  let config = quiet();
+  let mut machine = server(&config);
+  let hello = format!("HELLO {}", i32::MAX);
+  assert!(matches!(
+    fails_with(&mut machine, hello.as_bytes()),
+    HandshakeError::SequenceOverflow { seq: i32::MAX }
+  ));
+  assert!(machine.poll_output().is_err());
+}
+
+#[test]
+fn client_rejects_hello_y_with_no_next_sequence() {
+  let config = quiet();
+  let mut machine = client(i32::MAX - 1, &config);
+  expect_send(&mut machine);
+  let hello = format!("HELLO {}", i32::MAX);
+  assert!(matches!(
+    fails_with(&mut machine, hello.as_bytes()),
+    HandshakeError::SequenceOverflow { seq: i32::MAX }
+  ));
+  assert_eq!(machine.outcome(), None);
+}
+
+#[test]
+fn client_reports_a_busy_or_overloaded_server() {
+  let config = quiet();
+  for (reply, busy) in [(&b"BUSY"[..], true), (&b"OVERLOADED"[..], false)] {
+    let mut machine = client(10, &config);
+    expect_send(&mut machine);
+    match fails_with(&mut machine, reply) {
+      HandshakeError::ServerBusy => assert!(busy),
+      HandshakeError::ServerOverloaded => assert!(!busy),
+      other => panic!("unexpected error {other:?}"),
+    }
+  }
+}
+
+#[test]
+fn malformed_messages_are_rejected_on_both_sides() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  assert!(matches!(
+    fails_with(&mut machine, b"HI 11"),
+    HandshakeError::InvalidMessageFormat { .. }
+  ));
+
+  let mut machine = server(&config);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO eleven"),
+    HandshakeError::MalformedSequence { .. }
+  ));
+}
+
+#[test]
+fn a_message_outside_the_namespace_is_a_violation() {
+  let config = HandshakeConfig {
+    namespace: Some("APP".to_string()),
+    ..quiet()
+  };
+  let mut machine = client(10, &config);
+  assert_eq!(expect_send(&mut machine), b"APP:HELLO 10");
+  assert!(matches!(
+    fails_with(&mut machine, b"OTHER:HELLO 11"),
+    HandshakeError::ProtocolViolation(_)
+  ));
+}
+
+#[test]
+fn server_rejects_a_final_hello_without_its_nonce() {
+  let config = HandshakeConfig {
+    require_nonce: true,
+    ..quiet()
+  };
+  let mut machine = server(&config);
+  machine.handle_input(b"HELLO 5");
+  let reply = String::from_utf8(expect_send(&mut machine)).unwrap();
+  assert!(reply.starts_with("HELLO 6 N="), "{reply}");
+
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 7 N=1"),
+    HandshakeError::NonceMismatch {
+      received: Some(1),
+      ..
+    }
+  ));
+}
+
+#[test]
+fn server_logs_a_wrong_final_sequence_but_completes() {
+  let config = quiet();
+  let mut machine = server(&config);
+  machine.handle_input(b"HELLO 5");
+  expect_send(&mut machine);
+  machine.handle_input(b"HELLO 50");
+  assert_eq!(expect_complete(&mut machine).final_seq, 50);
+}
+
+#[test]
+fn missing_required_capabilities_fail_on_both_sides() {
+  let config = HandshakeConfig {
+    capabilities: Some(Capabilities::GZIP),
+    required_capabilities: Capabilities::GZIP,
+    ..quiet()
+  };
+  let mut machine = client(10, &config);
+  assert_eq!(expect_send(&mut machine), b"HELLO 10 CAP=20");
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 11 CAP=0"),
+    HandshakeError::CapabilityMismatch { .. }
+  ));
+
+  let mut machine = server(&config);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 10 CAP=40"),
+    HandshakeError::CapabilityMismatch { .. }
+  ));
+}
+
+#[test]
+fn client_rejects_capabilities_it_never_offered() {
+  let config = HandshakeConfig {
+    capabilities: Some(Capabilities::GZIP),
+    ..quiet()
+  };
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 11 CAP=60"),
+    HandshakeError::ProtocolViolation(_)
+  ));
+}
+
+#[test]
+fn server_checks_a_claimed_address_against_the_peer() {
+  let config = HandshakeConfig {
+    verify_client_ip: true,
+    strict_client_ip: true,
+    ..quiet()
+  };
+  let peer: IpAddr = "10.0.0.1".parse().unwrap();
+
+  let mut machine = server(&config).with_peer_ip(peer);
+  machine.handle_input(b"HELLO 5 IP=10.0.0.1");
+  assert_eq!(expect_send(&mut machine), b"HELLO 6");
+
+  let mut machine = server(&config).with_peer_ip(peer);
+  assert!(matches!(
+    fails_with(&mut machine, b"HELLO 5 IP=10.0.0.2"),
+    HandshakeError::AddressMismatch { .. }
+  ));
+}
+
+#[test]
+fn a_frame_without_its_delimiter_is_too_large() {
+  let config = line_framed();
+  let mut machine = server(&config);
+  assert!(matches!(
+    fails_with(&mut machine, &[b'A'; 100]),
+    HandshakeError::FrameTooLarge { max: 64 }
+  ));
+}
+
+#[test]
+fn an_undelimited_message_must_fit_one_buffer() {
+  let config = quiet();
+  let mut machine = server(&config);
+  assert!(matches!(
+    fails_with(&mut machine, &[b'A'; 65]),
+    HandshakeError::ProtocolViolation(_)
+  ));
+}
+
+#[test]
+fn a_peer_that_closes_early_is_a_disconnect() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  machine.handle_eof();
+  assert!(matches!(
+    machine.poll_output(),
+    Err(HandshakeError::ClientDisconnected)
+  ));
+
+  // Input that arrived before the close is still handled
+  let mut machine = server(&config);
+  machine.handle_input(b"HELLO 5");
+  machine.handle_eof();
+  assert_eq!(expect_send(&mut machine), b"HELLO 6");
+  assert!(matches!(
+    machine.poll_output(),
+    Err(HandshakeError::ClientDisconnected)
+  ));
+}
+
+#[test]
+fn a_message_out_of_turn_is_a_violation() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  assert!(matches!(
+    machine.handle_message("HELLO 11"),
+    Err(HandshakeError::ProtocolViolation(_))
+  ));
+
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  machine.handle_input(b"HELLO 11");
+  expect_send(&mut machine);
+  expect_complete(&mut machine);
+  assert!(matches!(
+    machine.handle_message("HELLO 13"),
+    Err(HandshakeError::ProtocolViolation(_))
+  ));
+}
+
+#[test]
+fn a_failed_machine_stays_failed() {
+  let config = quiet();
+  let mut machine = client(10, &config);
+  expect_send(&mut machine);
+  fails_with(&mut machine, b"HELLO 99");
+
+  machine.handle_input(b"HELLO 11");
+  assert!(matches!(
+    machine.poll_output(),
+    Err(HandshakeError::ProtocolViolation(_))
+  ));
+  assert_eq!(machine.poll_message(), None);
+  assert_eq!(machine.outcome(), None);
+}
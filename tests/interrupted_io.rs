@@ -0,0 +1,75 @@
+/**
+ * EINTR handling tests for the sync read/write helpers
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{self, ErrorKind, Read, Write};
+
+use tcp_handshake::{
+  HandshakeError, MAX_INTERRUPTED_RETRIES, read_message_from_stream, write_message_to_stream,
+};
+
+/**
+ * Fails with `Interrupted` a fixed number of times before behaving normally
+ */
+struct Interrupting {
+  interruptions: u32,
+  data: &'static [u8],
+  written: Vec<u8>,
+}
+
+impl Interrupting {
+  fn new(interruptions: u32, data: &'static [u8]) -> Self {
+    Self {
+      interruptions,
+      data,
+      written: Vec::new(),
+    }
+  }
+
+  fn interrupt(&mut self) -> io::Result<()> {
+    if self.interruptions > 0 {
+      self.interruptions -= 1;
+      return Err(ErrorKind::Interrupted.into());
+    }
+    Ok(())
+  }
+}
+
+impl Read for Interrupting {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.interrupt()?;
+    self.data.read(buf)
+  }
+}
+
+impl Write for Interrupting {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.interrupt()?;
+    self.written.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn read_retries_after_interruption() {
+  let mut stream = Interrupting::new(1, b"HELLO 6");
+  assert_eq!(read_message_from_stream(&mut stream).unwrap(), "HELLO 6");
+}
+
+#[test]
+fn write_retries_after_interruption() {
+  let mut stream = Interrupting::new(1, b"");
+  write_message_to_stream(&mut stream, "HELLO 5").unwrap();
+  assert_eq!(stream.written, b"HELLO 5");
+}
+
+#[test]
+fn read_gives_up_after_retry_limit() {
+  let mut stream = Interrupting::new(MAX_INTERRUPTED_RETRIES + 1, b"HELLO 6");
+  let err = read_message_from_stream(&mut stream).unwrap_err();
+  assert!(matches!(err, HandshakeError::Io(e) if e.kind() == ErrorKind::Interrupted));
+}
@@ -0,0 +1,61 @@
+/**
+ * SO_LINGER configuration tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket2::SockRef;
+use tcp_handshake::{
+  HandshakeConfig, ServerModel, apply_linger, perform_client_handshake_with_config, spawn_server,
+};
+
+fn linger_config(linger: Option<Duration>) -> HandshakeConfig {
+  HandshakeConfig {
+    linger,
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn client_handshake_sets_linger_on_its_socket() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+
+  let config = linger_config(Some(Duration::from_secs(2)));
+  perform_client_handshake_with_config(&mut stream, 1, &config).unwrap();
+
+  let linger = SockRef::from(&stream).linger().unwrap();
+  assert_eq!(linger, Some(Duration::from_secs(2)));
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn zero_linger_requests_abortive_close() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let stream = TcpStream::connect(server.addr).unwrap();
+
+  apply_linger(&stream, &linger_config(Some(Duration::ZERO))).unwrap();
+
+  let linger = SockRef::from(&stream).linger().unwrap();
+  assert_eq!(linger, Some(Duration::ZERO));
+  drop(stream);
+  server.stop();
+}
+
+#[tokio::test]
+async fn default_config_leaves_linger_unset() {
+  let server = spawn_server(ServerModel::Async, HandshakeConfig::default()).unwrap();
+  let stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+
+  apply_linger(&stream, &HandshakeConfig::default()).unwrap();
+
+  let linger = SockRef::from(&stream).linger().unwrap();
+  assert_eq!(linger, None);
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
@@ -0,0 +1,118 @@
+#![cfg(unix)]
+/**
+ * Inherited listening socket tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::{BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use socket2::{Domain, SockRef, Socket, Type};
+use tcp_handshake::{
+  HandshakeError, create_async_listener_from_fd, create_listener_from_fd,
+  perform_async_server_handshake, perform_client_handshake_with_config, perform_server_handshake,
+};
+
+use common::quiet;
+
+fn handshake_with(addr: SocketAddr) -> i32 {
+  let mut stream = TcpStream::connect(addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 10, &quiet())
+    .unwrap()
+    .final_seq
+}
+
+#[test]
+fn handshakes_through_an_inherited_listener() {
+  let bound = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = bound.local_addr().unwrap();
+
+  let listener = create_listener_from_fd(bound.into_raw_fd()).unwrap();
+  assert_eq!(listener.local_addr().unwrap(), addr);
+  let server = thread::spawn(move || {
+    let (stream, _) = listener.accept().unwrap();
+    perform_server_handshake(stream).unwrap()
+  });
+
+  assert_eq!(handshake_with(addr), 12);
+  assert_eq!(server.join().unwrap().final_seq, 12);
+}
+
+#[tokio::test]
+async fn handshakes_through_an_inherited_async_listener() {
+  let bound = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = bound.local_addr().unwrap();
+
+  let listener = create_async_listener_from_fd(bound.into_raw_fd()).unwrap();
+  let server = tokio::spawn(async move {
+    let (stream, peer) = listener.accept().await.unwrap();
+    perform_async_server_handshake(stream, peer, None)
+      .await
+      .unwrap()
+  });
+
+  let client = tokio::task::spawn_blocking(move || handshake_with(addr));
+  assert_eq!(client.await.unwrap(), 12);
+  assert_eq!(server.await.unwrap().final_seq, 12);
+}
+
+#[test]
+fn sockets_that_are_not_listening_are_refused() {
+  // Bound but never listened on
+  let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+  socket
+    .bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+    .unwrap();
+  let result = create_listener_from_fd(socket.as_raw_fd());
+  assert!(
+    matches!(result, Err(HandshakeError::InvalidArguments(_))),
+    "{result:?}"
+  );
+
+  // Not TCP at all
+  let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+  let result = create_listener_from_fd(udp.as_raw_fd());
+  assert!(
+    matches!(result, Err(HandshakeError::InvalidArguments(_))),
+    "{result:?}"
+  );
+}
+
+#[test]
+fn server_binary_serves_the_fd_it_is_given() {
+  let bound = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = bound.local_addr().unwrap();
+  // The child only inherits descriptors that are not close-on-exec
+  SockRef::from(&bound).set_cloexec(false).unwrap();
+
+  let mut child = Command::new(env!("CARGO_BIN_EXE_server-threaded"))
+    .args(["--fd", &bound.as_raw_fd().to_string()])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .expect("server binary runs");
+  drop(bound);
+
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+  let mut line = String::new();
+  stdout.read_line(&mut line).unwrap();
+  assert!(line.contains(&format!("Listening on {addr}")), "{line}");
+
+  assert_eq!(handshake_with(addr), 12);
+
+  let status = Command::new("kill")
+    .args(["-INT", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+  let mut rest = String::new();
+  stdout.read_to_string(&mut rest).unwrap();
+  assert!(child.wait().unwrap().success());
+  assert!(rest.contains("\"succeeded\":1,"), "{rest}");
+}
@@ -0,0 +1,55 @@
+#![cfg(unix)]
+/**
+ * Degraded stdout logging tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::pipe;
+use std::process::Command;
+
+use tcp_handshake::{ServerModel, spawn_server};
+
+use common::quiet;
+
+fn client_sync(port: u16) -> Command {
+  let mut command = Command::new(env!("CARGO_BIN_EXE_client-sync"));
+  command.args(["127.0.0.1", &port.to_string(), "10"]);
+  command
+}
+
+#[test]
+fn handshake_completes_with_stdout_closed() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+
+  // Nobody will ever read what the client writes to stdout
+  let (reader, writer) = pipe().unwrap();
+  drop(reader);
+
+  let output = client_sync(server.addr.port())
+    .stdout(writer)
+    .output()
+    .unwrap();
+  let stderr = String::from_utf8_lossy(&output.stderr);
+
+  assert!(output.status.success(), "{stderr}");
+  assert!(stderr.contains("Round-trip time"), "{stderr}");
+  assert!(
+    stderr.contains("could not be written to stdout"),
+    "{stderr}"
+  );
+  server.stop();
+}
+
+#[test]
+fn readable_stdout_is_not_degraded() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  let output = client_sync(server.addr.port()).output().unwrap();
+  let stderr = String::from_utf8_lossy(&output.stderr);
+
+  assert!(output.status.success(), "{stderr}");
+  assert_eq!(String::from_utf8_lossy(&output.stdout), "HELLO 11\n");
+  assert!(!stderr.contains("could not be written"), "{stderr}");
+  server.stop();
+}
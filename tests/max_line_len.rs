@@ -0,0 +1,83 @@
+/**
+ * Delimited message length cap tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, HandshakeError, MSG_SIZE, perform_async_server_handshake_with_config,
+};
+
+const MAX_LINE_LEN: usize = 1024;
+
+/**
+ * A peer that sends `A` forever and never a newline, counting what was read from it
+ */
+#[derive(Default)]
+struct EndlessLine {
+  sent: usize,
+}
+
+impl AsyncRead for EndlessLine {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let len = buf.remaining();
+    buf.put_slice(&vec![b'A'; len]);
+    self.sent += len;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for EndlessLine {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+#[test]
+fn default_cap_is_one_message_buffer() {
+  assert_eq!(HandshakeConfig::default().max_line_len, MSG_SIZE);
+}
+
+#[tokio::test]
+async fn endless_line_is_cut_off_at_the_cap() {
+  let config = HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    max_line_len: MAX_LINE_LEN,
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  let mut peer = EndlessLine::default();
+
+  let error = perform_async_server_handshake_with_config(&mut peer, "endless", None, &config)
+    .await
+    .unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::FrameTooLarge { max: MAX_LINE_LEN }),
+    "{error}"
+  );
+
+  // Gave up within one read of the cap instead of buffering the whole stream
+  assert!(peer.sent >= MAX_LINE_LEN, "{}", peer.sent);
+  assert!(peer.sent <= MAX_LINE_LEN + 1 + MSG_SIZE, "{}", peer.sent);
+}
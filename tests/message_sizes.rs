@@ -0,0 +1,56 @@
+/**
+ * Received message size histogram tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Write;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  HandshakeConfig, MESSAGE_SIZE_BUCKETS, MSG_SIZE, MessageSizeHistogram, ServerModel,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+#[test]
+fn lengths_land_in_their_buckets() {
+  let histogram = MessageSizeHistogram::default();
+  for len in [0, 7, 8, 15, 16, 31, 32, 63, 64, 500] {
+    histogram.record(len);
+  }
+  assert_eq!(histogram.counts(), [2, 2, 2, 2, 2]);
+}
+
+#[test]
+fn server_records_every_received_message() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, HandshakeConfig::default()).unwrap();
+
+    // HELLO 1 and HELLO 3 are 7 bytes each
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 1, &HandshakeConfig::default()).unwrap();
+
+    // A padded 20-byte first message, then the 8-byte HELLO 12
+    let padded = HandshakeConfig {
+      raw_first_message: Some(format!("{:<20}", "HELLO 10")),
+      ..HandshakeConfig::default()
+    };
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 10, &padded).unwrap();
+
+    // A message that fills the whole buffer
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream.write_all(&[b'A'; MSG_SIZE]).unwrap();
+
+    let expected: [u64; MESSAGE_SIZE_BUCKETS] = [2, 1, 1, 0, 1];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.metrics.snapshot().message_sizes != expected && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(server.metrics.snapshot().message_sizes, expected, "{model}");
+
+    drop(stream);
+    server.stop();
+  }
+}
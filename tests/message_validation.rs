@@ -0,0 +1,164 @@
+/**
+ * HELLO message grammar tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tcp_handshake::{
+  HandshakeError, ValidatedMessage, parse_hello_message, parse_hello_with_stream_id,
+  validate_hello_message,
+};
+
+fn hello(seq: i32, stream_id: Option<u32>) -> ValidatedMessage {
+  ValidatedMessage {
+    verb: "HELLO".to_string(),
+    seq,
+    stream_id,
+    nonce: None,
+    claimed_ip: None,
+    capabilities: None,
+  }
+}
+
+#[test]
+fn accepts_plain_message() {
+  assert_eq!(validate_hello_message("HELLO 5").unwrap(), hello(5, None));
+}
+
+#[test]
+fn accepts_stream_id() {
+  assert_eq!(
+    validate_hello_message("HELLO 5 S=3").unwrap(),
+    hello(5, Some(3))
+  );
+}
+
+#[test]
+fn accepts_nonce_alongside_stream_id() {
+  let validated = validate_hello_message("HELLO 6 N=18446744073709551615 S=2").unwrap();
+  assert_eq!(validated.nonce, Some(u64::MAX));
+  assert_eq!(validated.stream_id, Some(2));
+  assert!(validated.has_optional_fields());
+}
+
+#[test]
+fn accepts_sequence_bounds_and_extra_whitespace() {
+  let cases = [
+    ("HELLO -7", hello(-7, None)),
+    ("HELLO 0 S=0", hello(0, Some(0))),
+    ("HELLO 2147483647", hello(i32::MAX, None)),
+    (
+      "HELLO -2147483648 S=4294967295",
+      hello(i32::MIN, Some(u32::MAX)),
+    ),
+    ("  HELLO\t5   S=3 \n", hello(5, Some(3))),
+  ];
+  for (message, expected) in cases {
+    assert_eq!(
+      validate_hello_message(message).unwrap(),
+      expected,
+      "{message:?}"
+    );
+  }
+}
+
+/**
+ * Asserts that `message` fails at `offset` expecting `expected`
+ */
+fn assert_fails_at(message: &str, offset: usize, expected: &str) {
+  match validate_hello_message(message) {
+    Err(HandshakeError::InvalidMessageFormat {
+      offset: actual_offset,
+      expected: actual_expected,
+      ..
+    }) => {
+      assert_eq!(actual_offset, offset, "offset for {message:?}");
+      assert_eq!(actual_expected, expected, "hint for {message:?}");
+    }
+    other => panic!("{message:?}: expected InvalidMessageFormat, got {other:?}"),
+  }
+}
+
+#[test]
+fn reports_offset_of_bad_verb() {
+  assert_fails_at("", 0, "'HELLO'");
+  assert_fails_at("   ", 0, "'HELLO'");
+  assert_fails_at("hello 5", 0, "'HELLO'");
+  assert_fails_at("HI 5", 0, "'HELLO'");
+  assert_fails_at("5 HELLO", 0, "'HELLO'");
+  assert_fails_at("  HELLOO 5", 2, "'HELLO'");
+}
+
+#[test]
+fn reports_offset_of_bad_sequence_number() {
+  assert_fails_at("HELLO", 5, "sequence number");
+  assert_fails_at("HELLO   ", 5, "sequence number");
+}
+
+#[test]
+fn a_sequence_that_is_not_an_i32_is_a_malformed_sequence() {
+  for (message, token, at) in [
+    ("HELLO x", "x", 6),
+    ("HELLO 5.0", "5.0", 6),
+    ("HELLO 2147483648", "2147483648", 6),
+    ("HELLO\t\tabc S=1", "abc", 7),
+  ] {
+    match validate_hello_message(message) {
+      Err(HandshakeError::MalformedSequence {
+        value,
+        offset,
+        expected,
+      }) => {
+        assert_eq!(value, token, "{message:?}");
+        assert_eq!(offset, at, "{message:?}");
+        assert_eq!(expected, "32-bit integer", "{message:?}");
+      }
+      other => panic!("{message:?}: {other:?}"),
+    }
+  }
+}
+
+#[test]
+fn reports_offset_of_bad_fields() {
+  assert_fails_at("HELLO 5 extra", 8, "KEY=value field");
+  assert_fails_at("HELLO 5 X=1", 8, "known field 'S', 'N', 'IP' or 'CAP'");
+  assert_fails_at("HELLO 5 s=1", 8, "known field 'S', 'N', 'IP' or 'CAP'");
+  assert_fails_at("HELLO 5 S=1 X=2", 12, "known field 'S', 'N', 'IP' or 'CAP'");
+  assert_fails_at("HELLO 5 S=", 10, "unsigned stream ID");
+  assert_fails_at("HELLO 5 S=-1", 10, "unsigned stream ID");
+  assert_fails_at("HELLO 5  S=abc", 11, "unsigned stream ID");
+  assert_fails_at("HELLO 5 S=1 S=2", 12, "no repeated 'S' field");
+  assert_fails_at("HELLO 5 N=x", 10, "unsigned nonce");
+  assert_fails_at("HELLO 5 N=1 N=1", 12, "no repeated 'N' field");
+}
+
+#[test]
+fn display_names_offset_and_hint() {
+  let err = validate_hello_message("HELLO").unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "Invalid message format: expected sequence number at offset 5 in 'HELLO'"
+  );
+  let err = validate_hello_message("HELLO x").unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "Malformed sequence number 'x': expected 32-bit integer at offset 6"
+  );
+}
+
+#[test]
+fn parse_hello_message_stays_strict() {
+  assert_eq!(parse_hello_message("HELLO 5").unwrap(), 5);
+  assert!(matches!(
+    parse_hello_message("HELLO 5 S=3"),
+    Err(HandshakeError::InvalidMessageFormat { offset: 8, .. })
+  ));
+}
+
+#[test]
+fn parse_with_stream_id_matches_validation() {
+  assert_eq!(parse_hello_with_stream_id("HELLO 5").unwrap(), (5, None));
+  assert_eq!(
+    parse_hello_with_stream_id("HELLO 5 S=3").unwrap(),
+    (5, Some(3))
+  );
+}
@@ -0,0 +1,64 @@
+/**
+ * Mock clock timeout tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::duplex;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MockClock, perform_async_client_handshake_on,
+  perform_async_server_handshake_with_config,
+};
+
+#[tokio::test]
+async fn advancing_past_read_timeout_times_out_without_waiting() {
+  let clock = Arc::new(MockClock::new());
+  let config = HandshakeConfig {
+    read_timeout: Duration::from_secs(3600),
+    clock: clock.clone(),
+    ..HandshakeConfig::default()
+  };
+
+  // The peer never sends HELLO X
+  let (_client, mut server) = duplex(64);
+  let started = Instant::now();
+  let handshake = tokio::spawn(async move {
+    perform_async_server_handshake_with_config(&mut server, "silent peer", None, &config).await
+  });
+
+  // Jump the clock past the timeout until the parked read notices
+  while !handshake.is_finished() {
+    tokio::task::yield_now().await;
+    clock.advance(Duration::from_secs(3601));
+  }
+
+  let result = handshake.await.unwrap();
+  assert!(matches!(result, Err(HandshakeError::Timeout)), "{result:?}");
+  assert!(
+    started.elapsed() < Duration::from_secs(1),
+    "{:?}",
+    started.elapsed()
+  );
+}
+
+#[tokio::test]
+async fn a_clock_short_of_the_timeout_lets_the_handshake_finish() {
+  let clock = Arc::new(MockClock::new());
+  let config = HandshakeConfig {
+    read_timeout: Duration::from_secs(10),
+    clock: clock.clone(),
+    ..HandshakeConfig::default()
+  };
+
+  let (client, mut server) = duplex(64);
+  clock.advance(Duration::from_secs(9));
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &config),
+    perform_async_server_handshake_with_config(&mut server, "duplex peer", None, &config),
+  );
+  assert_eq!(client.unwrap().final_seq, 7);
+  assert_eq!(server.unwrap().final_seq, 7);
+}
@@ -0,0 +1,113 @@
+/**
+ * MockPeer examples pinning down the wire behavior of the client and server
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Run with `cargo test --features testing`.
+ */
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MockPeer, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_config,
+};
+
+#[tokio::test]
+async fn client_follows_the_script() {
+  let (mut stream, peer) = MockPeer::new()
+    .expect("HELLO 5")
+    .send("HELLO 6")
+    .expect("HELLO 7")
+    .expect_close()
+    .spawn();
+
+  let outcome =
+    perform_async_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default())
+      .await
+      .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  drop(stream);
+  peer.verify().await;
+}
+
+#[tokio::test]
+async fn client_rejects_a_wrong_reply() {
+  // A server that answers HELLO 99 instead of HELLO 6
+  let (mut stream, peer) = MockPeer::new()
+    .expect("HELLO 5")
+    .send("HELLO 99")
+    .expect_close()
+    .spawn();
+
+  let error =
+    perform_async_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default())
+      .await
+      .unwrap_err();
+  assert!(
+    matches!(
+      error,
+      HandshakeError::SequenceMismatch {
+        expected: 6,
+        received: 99
+      }
+    ),
+    "{error}"
+  );
+  // The client sent nothing after the bad reply
+  drop(stream);
+  peer.verify().await;
+}
+
+#[tokio::test]
+async fn server_follows_the_script() {
+  let (mut stream, peer) = MockPeer::new()
+    .send("HELLO 5")
+    .expect("HELLO 6")
+    .send("HELLO 7")
+    .spawn();
+
+  let outcome = perform_async_server_handshake_with_config(
+    &mut stream,
+    "mock peer",
+    None,
+    &HandshakeConfig::default(),
+  )
+  .await
+  .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  peer.verify().await;
+}
+
+#[tokio::test]
+async fn deviation_names_the_step() {
+  // This script wrongly expects the server to skip a sequence number
+  let (mut stream, peer) = MockPeer::new()
+    .send("HELLO 5")
+    .expect("HELLO 7")
+    .send("HELLO 8")
+    .spawn();
+
+  let config = HandshakeConfig::default();
+  let server = perform_async_server_handshake_with_config(&mut stream, "mock peer", None, &config);
+  let (server, deviation) = tokio::join!(server, peer.finish());
+
+  let deviation = deviation.unwrap_err().to_string();
+  assert!(
+    deviation.contains("step 2: expected 'HELLO 7', received 'HELLO 6'"),
+    "{deviation}"
+  );
+  // The peer hung up instead of sending HELLO Z
+  assert!(server.is_err());
+}
+
+#[tokio::test]
+#[should_panic(expected = "expected the connection to close, received 'HELLO 7'")]
+async fn verify_fails_the_test_on_a_deviation() {
+  let (mut stream, peer) = MockPeer::new()
+    .expect("HELLO 5")
+    .send("HELLO 6")
+    .expect_close()
+    .spawn();
+
+  let _ =
+    perform_async_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default()).await;
+  peer.verify().await;
+}
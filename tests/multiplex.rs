@@ -0,0 +1,96 @@
+/**
+ * Multiplexed handshake tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::ErrorKind;
+
+use tcp_handshake::{HandshakeConfig, HandshakeError, MuxStats, run_async_mux_session};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[tokio::test]
+async fn two_handshakes_share_one_socket() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  let server = tokio::spawn(async move {
+    let (mut stream, peer_addr) = listener.accept().await.unwrap();
+    run_async_mux_session(&mut stream, peer_addr, &HandshakeConfig::default()).await
+  });
+
+  let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+  let mut replies = BufReader::new(reader).lines();
+
+  // Open both streams before finishing either one
+  writer.write_all(b"HELLO 5 S=1\n").await.unwrap();
+  assert_eq!(replies.next_line().await.unwrap().unwrap(), "HELLO 6 S=1");
+  writer.write_all(b"HELLO 40 S=2\n").await.unwrap();
+  assert_eq!(replies.next_line().await.unwrap().unwrap(), "HELLO 41 S=2");
+
+  // Finish them in one write to exercise several messages per read
+  writer
+    .write_all(b"HELLO 42 S=2\nHELLO 7 S=1\n")
+    .await
+    .unwrap();
+  writer.shutdown().await.unwrap();
+
+  let stats = server.await.unwrap().unwrap();
+  assert_eq!(
+    stats,
+    MuxStats {
+      completed: 2,
+      incomplete: 0,
+    }
+  );
+}
+
+/**
+ * Runs one tagged handshake, optionally skipping the final HELLO, then resets the connection
+ */
+async fn reset_after(send_final: bool) -> Result<MuxStats, HandshakeError> {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  let server = tokio::spawn(async move {
+    let (mut stream, peer_addr) = listener.accept().await.unwrap();
+    run_async_mux_session(&mut stream, peer_addr, &HandshakeConfig::default()).await
+  });
+
+  let mut client = BufReader::new(TcpStream::connect(addr).await.unwrap());
+  client.write_all(b"HELLO 5 S=1\n").await.unwrap();
+  let mut reply = String::new();
+  client.read_line(&mut reply).await.unwrap();
+  assert_eq!(reply, "HELLO 6 S=1\n");
+  if send_final {
+    client.write_all(b"HELLO 7 S=1\n").await.unwrap();
+  }
+
+  // Closing with a zero linger sends RST instead of FIN
+  let client = client.into_inner();
+  client.set_zero_linger().unwrap();
+  drop(client);
+
+  server.await.unwrap()
+}
+
+#[tokio::test]
+async fn reset_after_final_hello_completes_cleanly() {
+  let stats = reset_after(true).await.unwrap();
+  assert_eq!(
+    stats,
+    MuxStats {
+      completed: 1,
+      incomplete: 0,
+    }
+  );
+}
+
+#[tokio::test]
+async fn reset_before_final_hello_is_an_error() {
+  let result = reset_after(false).await;
+  assert!(
+    matches!(result, Err(HandshakeError::Io(ref e)) if e.kind() == ErrorKind::ConnectionReset),
+    "got {result:?}"
+  );
+}
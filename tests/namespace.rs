@@ -0,0 +1,110 @@
+/**
+ * Message namespace tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tcp_handshake::{
+  EventSink, HandshakeConfig, HandshakeError, HandshakeOutcome, Result, ServerModel,
+  perform_async_client_handshake_with_config, perform_client_handshake_with_config,
+  perform_server_handshake_with_config, spawn_server,
+};
+
+fn namespaced(namespace: Option<&str>) -> HandshakeConfig {
+  HandshakeConfig {
+    namespace: namespace.map(str::to_string),
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Runs one sync handshake between a client and a server in their namespaces
+ * Returns the client's and the server's results
+ */
+fn handshake(
+  client: Option<&str>,
+  server: Option<&str>,
+) -> (Result<HandshakeOutcome>, Result<HandshakeOutcome>) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server_config = namespaced(server);
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    perform_server_handshake_with_config(&mut stream, &server_config)
+  });
+
+  let mut stream = TcpStream::connect(addr).unwrap();
+  let client = perform_client_handshake_with_config(&mut stream, 5, &namespaced(client));
+  drop(stream);
+  (client, server.join().unwrap())
+}
+
+#[test]
+fn matching_namespaces_complete_the_handshake() {
+  let (client, server) = handshake(Some("MYAPP"), Some("MYAPP"));
+  assert_eq!(client.unwrap().final_seq, 7);
+  assert_eq!(server.unwrap().final_seq, 7);
+
+  // Every message on the wire carries the namespace
+  let messages = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&messages);
+  let config = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.message.clone())),
+    ..namespaced(Some("MYAPP"))
+  };
+  let server = spawn_server(ServerModel::Sequential, namespaced(Some("MYAPP"))).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 5, &config).unwrap();
+  server.stop();
+  assert_eq!(
+    *messages.lock().unwrap(),
+    ["MYAPP:HELLO 5", "MYAPP:HELLO 6", "MYAPP:HELLO 7"]
+  );
+}
+
+#[tokio::test]
+async fn async_ends_agree_on_the_namespace() {
+  let server = spawn_server(ServerModel::Async, namespaced(Some("MYAPP"))).unwrap();
+  let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+  let outcome =
+    perform_async_client_handshake_with_config(&mut stream, 5, &namespaced(Some("MYAPP")))
+      .await
+      .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[test]
+fn mismatched_namespace_is_rejected() {
+  let (client, server) = handshake(Some("OTHER"), Some("MYAPP"));
+  assert!(client.is_err());
+  let error = server.unwrap_err();
+  assert!(
+    matches!(&error, HandshakeError::ProtocolViolation(reason) if reason.contains("namespace 'MYAPP'")),
+    "{error}"
+  );
+}
+
+#[test]
+fn absent_namespace_is_rejected() {
+  // A plain client is a stray on a namespaced server
+  let (client, server) = handshake(None, Some("MYAPP"));
+  assert!(client.is_err());
+  let error = server.unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::ProtocolViolation(_)),
+    "{error}"
+  );
+
+  // A namespaced client rejects a reply from a plain server; the server cannot parse the prefix
+  let (client, server) = handshake(Some("MYAPP"), None);
+  assert!(server.is_err());
+  assert!(client.is_err());
+}
@@ -0,0 +1,84 @@
+/**
+ * Negotiated parameter reporting tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tokio::io::duplex;
+
+use tcp_handshake::{
+  Capabilities, HandshakeConfig, HandshakeOutcome, Negotiated, Preamble, Result,
+  perform_async_client_handshake_on, perform_async_server_handshake_with_config,
+};
+
+async fn handshake(
+  client: &HandshakeConfig,
+  server: &HandshakeConfig,
+) -> (Result<HandshakeOutcome>, Result<HandshakeOutcome>) {
+  let (client_stream, mut server_stream) = duplex(1024);
+  tokio::join!(
+    perform_async_client_handshake_on(client_stream, 5, client),
+    async move {
+      perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, server).await
+    },
+  )
+}
+
+#[tokio::test]
+async fn both_sides_report_the_intersection() {
+  let client = HandshakeConfig {
+    capabilities: Some(Capabilities::NONCE | Capabilities::PREAMBLE | Capabilities::CHUNKED),
+    preamble: Some(Preamble::default()),
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  let server = HandshakeConfig {
+    capabilities: Some(Capabilities::NONCE | Capabilities::PREAMBLE | Capabilities::NAMESPACE),
+    preamble: Some(Preamble::default()),
+    require_nonce: true,
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+
+  let (client, server) = handshake(&client, &server).await;
+  let expected = Negotiated {
+    features: Capabilities::NONCE | Capabilities::PREAMBLE,
+    uses_nonce: true,
+    uses_preamble: true,
+  };
+  assert_eq!(client.unwrap().negotiated, expected);
+  assert_eq!(server.unwrap().negotiated, expected);
+}
+
+#[tokio::test]
+async fn plain_handshakes_negotiate_nothing() {
+  let config = HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+
+  let (client, server) = handshake(&config, &config).await;
+  assert_eq!(client.unwrap().negotiated, Negotiated::default());
+  assert_eq!(server.unwrap().negotiated, Negotiated::default());
+}
+
+#[tokio::test]
+async fn disjoint_sets_share_no_features() {
+  let client = HandshakeConfig {
+    capabilities: Some(Capabilities::NONCE | Capabilities::CHUNKED),
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  let server = HandshakeConfig {
+    capabilities: Some(Capabilities::CLAIMED_IP),
+    require_nonce: true,
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+
+  let (client, server) = handshake(&client, &server).await;
+  let client = client.unwrap().negotiated;
+  let server = server.unwrap().negotiated;
+  assert_eq!(client.features, Capabilities::NONE);
+  assert_eq!(server.features, Capabilities::NONE);
+  assert!(client.uses_nonce && server.uses_nonce);
+}
@@ -0,0 +1,77 @@
+/**
+ * Sync handshake tests over non-blocking sockets
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MSG_SIZE, perform_client_handshake_with_config,
+};
+
+/**
+ * Accepts one client and answers its HELLO after `delay`, or never if `None`
+ * The connection stays open until the client's final message or close
+ */
+fn slow_server(delay: Option<Duration>) -> (SocketAddr, JoinHandle<()>) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; MSG_SIZE];
+    let read = stream.read(&mut buffer).unwrap();
+    let seq: i32 = String::from_utf8_lossy(&buffer[..read])
+      .trim_start_matches("HELLO ")
+      .parse()
+      .unwrap();
+    if let Some(delay) = delay {
+      thread::sleep(delay);
+      stream
+        .write_all(format!("HELLO {}", seq + 1).as_bytes())
+        .unwrap();
+    }
+    let _ = stream.read(&mut buffer);
+  });
+  (addr, server)
+}
+
+fn non_blocking_client(addr: SocketAddr) -> TcpStream {
+  let stream = TcpStream::connect(addr).unwrap();
+  stream.set_nonblocking(true).unwrap();
+  stream
+}
+
+#[test]
+fn non_blocking_socket_waits_for_a_late_reply() {
+  let (addr, server) = slow_server(Some(Duration::from_millis(100)));
+  let mut stream = non_blocking_client(addr);
+
+  let outcome =
+    perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default()).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  assert!(outcome.rtt >= Duration::from_millis(100));
+  drop(stream);
+  server.join().unwrap();
+}
+
+#[test]
+fn non_blocking_socket_times_out_at_the_deadline() {
+  let (addr, server) = slow_server(None);
+  let mut stream = non_blocking_client(addr);
+  let config = HandshakeConfig {
+    read_timeout: Duration::from_millis(200),
+    ..HandshakeConfig::default()
+  };
+
+  let started = Instant::now();
+  let error = perform_client_handshake_with_config(&mut stream, 5, &config).unwrap_err();
+  let waited = started.elapsed();
+  assert!(matches!(error, HandshakeError::Timeout), "{error}");
+  assert!(waited >= Duration::from_millis(200), "{waited:?}");
+  assert!(waited < Duration::from_secs(2), "{waited:?}");
+  drop(stream);
+  server.join().unwrap();
+}
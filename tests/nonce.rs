@@ -0,0 +1,113 @@
+/**
+ * Server-issued nonce tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, HandshakeOutcome, Result, parse_hello_with_nonce,
+  perform_async_client_handshake_with_config, perform_async_server_handshake_with_config,
+  read_message_from_async_stream, write_message_to_async_stream,
+};
+use tokio::io::DuplexStream;
+
+fn nonce_config() -> HandshakeConfig {
+  HandshakeConfig {
+    require_nonce: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Runs a nonce-requiring server against a hand-written client
+ * `final_message` builds HELLO Z from the nonce the server issued
+ */
+async fn handshake_with_final(
+  final_message: impl FnOnce(u64) -> String,
+) -> Result<HandshakeOutcome> {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let config = nonce_config();
+
+  let client = async move |stream: &mut DuplexStream| {
+    write_message_to_async_stream(stream, "HELLO 5")
+      .await
+      .unwrap();
+    let reply = read_message_from_async_stream(stream).await.unwrap();
+    let (seq, nonce) = parse_hello_with_nonce(&reply).unwrap();
+    assert_eq!(seq, 6);
+    let nonce = nonce.expect("server issues a nonce");
+    write_message_to_async_stream(stream, &final_message(nonce))
+      .await
+      .unwrap();
+  };
+
+  let ((), server) = tokio::join!(
+    client(&mut client_stream),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &config),
+  );
+  server
+}
+
+#[tokio::test]
+async fn echoed_nonce_completes_handshake() {
+  let outcome = handshake_with_final(|nonce| format!("HELLO 7 N={nonce}"))
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+}
+
+#[tokio::test]
+async fn missing_nonce_is_rejected() {
+  let result = handshake_with_final(|_| "HELLO 7".to_string()).await;
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::NonceMismatch { received: None, .. })
+    ),
+    "got {result:?}"
+  );
+}
+
+#[tokio::test]
+async fn wrong_nonce_is_rejected() {
+  let result = handshake_with_final(|nonce| format!("HELLO 7 N={}", nonce.wrapping_add(1))).await;
+  match result {
+    Err(HandshakeError::NonceMismatch { expected, received }) => {
+      assert_eq!(received, Some(expected.wrapping_add(1)));
+    }
+    other => panic!("expected NonceMismatch, got {other:?}"),
+  }
+}
+
+#[tokio::test]
+async fn library_client_echoes_nonce() {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let server_config = nonce_config();
+  let client_config = HandshakeConfig::default();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 10, &client_config),
+    perform_async_server_handshake_with_config(&mut server_stream, "duplex", None, &server_config),
+  );
+  assert_eq!(client.unwrap().final_seq, 12);
+  assert_eq!(server.unwrap().final_seq, 12);
+}
+
+#[test]
+fn mismatch_display_names_both_nonces() {
+  let missing = HandshakeError::NonceMismatch {
+    expected: 9,
+    received: None,
+  };
+  assert_eq!(
+    missing.to_string(),
+    "Nonce mismatch: expected N=9, received no nonce"
+  );
+  let wrong = HandshakeError::NonceMismatch {
+    expected: 9,
+    received: Some(3),
+  };
+  assert_eq!(
+    wrong.to_string(),
+    "Nonce mismatch: expected N=9, received N=3"
+  );
+}
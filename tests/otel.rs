@@ -0,0 +1,117 @@
+/**
+ * OpenTelemetry export tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use opentelemetry::Value;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+
+use tcp_handshake::{
+  HandshakeError, OtelConfig, OtelGuard, ServerModel, install_otel, install_otel_provider,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+use common::quiet;
+
+/**
+ * Keeps every exported span for the test to inspect
+ */
+#[derive(Debug, Clone, Default)]
+struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+impl SpanExporter for Collected {
+  async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+    self.0.lock().unwrap().extend(batch);
+    Ok(())
+  }
+}
+
+/**
+ * Installs the collecting exporter once; the subscriber is global to the test binary
+ */
+fn collected() -> &'static Collected {
+  static INSTALLED: OnceLock<(Collected, OtelGuard)> = OnceLock::new();
+  let (collected, _) = INSTALLED.get_or_init(|| {
+    let collected = Collected::default();
+    let provider = SdkTracerProvider::builder()
+      .with_simple_exporter(collected.clone())
+      .build();
+    (collected, install_otel_provider(provider).unwrap())
+  });
+  collected
+}
+
+fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+  span
+    .attributes
+    .iter()
+    .find(|attribute| attribute.key.as_str() == key)
+    .map(|attribute| &attribute.value)
+}
+
+#[test]
+fn each_step_is_a_child_of_the_connection_span() {
+  let collected = collected();
+  for model in [ServerModel::Threaded, ServerModel::Async] {
+    let server = spawn_server(model, quiet()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let peer = stream.local_addr().unwrap().to_string();
+    perform_client_handshake_with_config(&mut stream, 10, &quiet()).unwrap();
+    drop(stream);
+
+    // The connection span is exported once the handler returns
+    let is_connection = |span: &SpanData| {
+      span.name == "connection" && attribute(span, "peer") == Some(&Value::from(peer.clone()))
+    };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !collected.0.lock().unwrap().iter().any(is_connection) && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(10));
+    }
+    server.stop();
+
+    let spans = collected.0.lock().unwrap();
+    let connection = spans
+      .iter()
+      .find(|span| is_connection(span))
+      .unwrap_or_else(|| panic!("{model}: no connection span for {peer}"));
+    let trace_id = connection.span_context.trace_id();
+    assert_eq!(
+      attribute(connection, "trace_id"),
+      Some(&Value::from(trace_id.to_string())),
+      "{model}"
+    );
+
+    let mut steps: Vec<_> = spans
+      .iter()
+      .filter(|span| span.parent_span_id == connection.span_context.span_id())
+      .collect();
+    steps.sort_by_key(|span| span.start_time);
+    assert_eq!(steps.len(), 3, "{model}");
+    for (step, span) in (1..).zip(&steps) {
+      assert_eq!(span.name, "handshake_step", "{model}");
+      assert_eq!(span.span_context.trace_id(), trace_id, "{model}");
+      assert_eq!(attribute(span, "step"), Some(&Value::I64(step)), "{model}");
+      assert!(attribute(span, "duration_us").is_some(), "{model}");
+    }
+  }
+}
+
+#[test]
+fn a_malformed_endpoint_is_rejected() {
+  let config = OtelConfig {
+    endpoint: "not a url".to_string(),
+    ..OtelConfig::default()
+  };
+  assert!(matches!(
+    install_otel(&config),
+    Err(HandshakeError::InvalidArguments(_))
+  ));
+}
@@ -0,0 +1,98 @@
+/**
+ * Global concurrent handshake limit tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  ConcurrencyLimit, HandshakeConfig, HandshakeError, ServerModel, ServerOptions,
+  perform_async_client_handshake_with_config, perform_client_handshake_with_config,
+  spawn_server_with_options,
+};
+
+fn one_at_a_time() -> ServerOptions {
+  ServerOptions {
+    max_concurrent_handshakes: Some(1),
+    ..ServerOptions::default()
+  }
+}
+
+fn sync_handshake(server: &tcp_handshake::RunningServer) -> tcp_handshake::Result<i32> {
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default())
+    .map(|outcome| outcome.final_seq)
+}
+
+#[test]
+fn permits_are_returned_when_dropped() {
+  let limit = Arc::new(ConcurrencyLimit::new(2));
+  let first = limit.try_acquire().unwrap();
+  let _second = limit.try_acquire().unwrap();
+  assert!(limit.try_acquire().is_none());
+  assert_eq!(limit.active(), 2);
+
+  drop(first);
+  assert_eq!(limit.active(), 1);
+  assert!(limit.try_acquire().is_some());
+}
+
+#[test]
+fn saturated_server_answers_overloaded() {
+  for model in [ServerModel::ThreadPool, ServerModel::Async] {
+    let server =
+      spawn_server_with_options(model, HandshakeConfig::default(), one_at_a_time()).unwrap();
+
+    // A silent client holds the only slot
+    let held = TcpStream::connect(server.addr).unwrap();
+    let error = sync_handshake(&server).unwrap_err();
+    assert!(
+      matches!(error, HandshakeError::ServerOverloaded),
+      "{model}: {error}"
+    );
+    assert_eq!(server.metrics.overloaded.load(Ordering::Relaxed), 1);
+
+    // Once it leaves, the slot is free again
+    drop(held);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let final_seq = loop {
+      match sync_handshake(&server) {
+        Ok(final_seq) => break final_seq,
+        Err(HandshakeError::ServerOverloaded) if Instant::now() < deadline => {
+          thread::sleep(Duration::from_millis(20));
+        }
+        Err(e) => panic!("{model}: {e}"),
+      }
+    };
+    assert_eq!(final_seq, 7, "{model}");
+    server.stop();
+  }
+}
+
+#[tokio::test]
+async fn async_client_sees_overloaded() {
+  let server = spawn_server_with_options(
+    ServerModel::Async,
+    HandshakeConfig::default(),
+    one_at_a_time(),
+  )
+  .unwrap();
+  let held = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+
+  let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+  let result =
+    perform_async_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default()).await;
+  assert!(
+    matches!(result, Err(HandshakeError::ServerOverloaded)),
+    "{result:?}"
+  );
+
+  drop(held);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
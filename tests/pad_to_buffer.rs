@@ -0,0 +1,58 @@
+/**
+ * Null padding tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, MSG_SIZE, ServerModel, perform_client_handshake_with_config,
+  spawn_server, write_message_to_stream_with_config,
+};
+
+fn padding() -> HandshakeConfig {
+  HandshakeConfig {
+    pad_to_buffer: true,
+    silent: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn pads_a_message_to_the_buffer_size() {
+  let mut written = Vec::new();
+  write_message_to_stream_with_config(&mut written, "HELLO 5", &padding()).unwrap();
+  assert_eq!(written.len(), MSG_SIZE);
+  assert_eq!(&written[..7], b"HELLO 5");
+  assert!(written[7..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn delimited_messages_are_not_padded() {
+  let config = HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    ..padding()
+  };
+  let mut written = Vec::new();
+  write_message_to_stream_with_config(&mut written, "HELLO 5", &config).unwrap();
+  assert_eq!(written, b"HELLO 5\n");
+}
+
+#[test]
+fn padding_client_completes_against_a_non_padding_server() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(
+      model,
+      HandshakeConfig {
+        silent: true,
+        ..HandshakeConfig::default()
+      },
+    )
+    .unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let outcome = perform_client_handshake_with_config(&mut stream, 10, &padding()).unwrap();
+    assert_eq!(outcome.final_seq, 12, "{model}");
+    drop(stream);
+    server.stop();
+  }
+}
@@ -0,0 +1,94 @@
+/**
+ * In-place HELLO parsing tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+
+use tcp_handshake::{
+  HandshakeConfig, ServerModel, ServerProfile, parse_hello_message, parse_hello_message_bytes,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+const INPUTS: &[&str] = &[
+  "HELLO 0",
+  "HELLO 42",
+  "HELLO +7",
+  "HELLO -7",
+  "HELLO 2147483647",
+  "HELLO -2147483648",
+  "HELLO 000123",
+  "  HELLO\t5 \r\n",
+  "HELLO\n\n9",
+  "HELLO\u{a0}5",
+  "HELLO 5 \u{a0}",
+  "HELLO\u{b}5",
+  "HELLO 2147483648",
+  "HELLO -2147483649",
+  "HELLO 99999999999999999999",
+  "HELLO",
+  "HELLO ",
+  "HELLO +",
+  "HELLO -",
+  "HELLO +-1",
+  "HELLO 1 2",
+  "HELLO 1a",
+  "HELLO ٣",
+  "hello 1",
+  "HELO 1",
+  "HELLOX 1",
+  "BYE 1",
+  "",
+  "   ",
+];
+
+#[test]
+fn bytes_and_string_parsers_agree() {
+  for input in INPUTS {
+    let from_str = parse_hello_message(input);
+    let from_bytes = parse_hello_message_bytes(input.as_bytes());
+    assert_eq!(
+      format!("{from_bytes:?}"),
+      format!("{from_str:?}"),
+      "{input:?}"
+    );
+  }
+}
+
+#[test]
+fn invalid_utf8_is_rejected_like_its_lossy_decoding() {
+  for input in [&b"HELLO \xff1"[..], b"\xffHELLO 1", b"HELLO 1\xff"] {
+    let from_str = parse_hello_message(&String::from_utf8_lossy(input));
+    let from_bytes = parse_hello_message_bytes(input);
+    assert!(from_bytes.is_err(), "{input:?}");
+    assert_eq!(
+      format!("{from_bytes:?}"),
+      format!("{from_str:?}"),
+      "{input:?}"
+    );
+  }
+}
+
+#[test]
+fn throughput_profile_parses_in_place() {
+  let mut config = HandshakeConfig::default();
+  assert!(!config.parse_in_place);
+  config.apply_profile(ServerProfile::Throughput);
+  assert!(config.parse_in_place);
+  config.apply_profile(ServerProfile::Default);
+  assert!(!config.parse_in_place);
+
+  for model in ServerModel::ALL {
+    let server_config = HandshakeConfig {
+      parse_in_place: true,
+      silent: true,
+      ..HandshakeConfig::default()
+    };
+    let server = spawn_server(model, server_config).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let outcome =
+      perform_client_handshake_with_config(&mut stream, 10, &HandshakeConfig::default()).unwrap();
+    assert_eq!(outcome.final_seq, 12, "{model}");
+    server.stop();
+  }
+}
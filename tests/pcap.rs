@@ -0,0 +1,178 @@
+/**
+ * Pcap trace writer tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use tcp_handshake::{
+  Direction, EventSink, HandshakeConfig, HandshakeError, PCAP_LINKTYPE_RAW, PCAP_MAGIC, PcapWriter,
+  ServerModel, perform_client_handshake_with_config, spawn_server,
+};
+
+/**
+ * One parsed record: source, destination, TCP sequence and acknowledgement, payload
+ */
+#[derive(Debug)]
+struct Segment {
+  src: SocketAddr,
+  dst: SocketAddr,
+  seq: u32,
+  ack: u32,
+  payload: Vec<u8>,
+}
+
+fn u16_be(bytes: &[u8]) -> u16 {
+  u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn u32_be(bytes: &[u8]) -> u32 {
+  u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+  u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+/**
+ * Ones' complement sum; a region holding a correct checksum sums to 0xffff
+ */
+fn ones_complement_sum(bytes: &[u8]) -> u16 {
+  let mut sum: u32 = bytes
+    .chunks(2)
+    .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+    .sum();
+  while sum > 0xffff {
+    sum = (sum & 0xffff) + (sum >> 16);
+  }
+  sum as u16
+}
+
+/**
+ * Parses a classic little-endian pcap of raw IP packets, checking every header and checksum
+ */
+fn parse_pcap(bytes: &[u8]) -> Vec<Segment> {
+  assert_eq!(u32_le(&bytes[0..]), PCAP_MAGIC);
+  assert_eq!(bytes[4..8], [2, 0, 4, 0]);
+  assert_eq!(u32_le(&bytes[20..]), PCAP_LINKTYPE_RAW);
+
+  let mut segments = Vec::new();
+  let mut rest = &bytes[24..];
+  while !rest.is_empty() {
+    let captured = u32_le(&rest[8..]) as usize;
+    assert_eq!(captured, u32_le(&rest[12..]) as usize);
+    assert!(u32_le(&rest[4..]) < 1_000_000);
+    let packet = &rest[16..16 + captured];
+    rest = &rest[16 + captured..];
+
+    let (src_ip, dst_ip, mut pseudo, tcp) = match packet[0] >> 4 {
+      4 => {
+        let header_len = usize::from(packet[0] & 0x0f) * 4;
+        assert_eq!(usize::from(u16_be(&packet[2..])), packet.len());
+        assert_eq!(packet[9], 6);
+        assert_eq!(ones_complement_sum(&packet[..header_len]), 0xffff);
+        let src: [u8; 4] = packet[12..16].try_into().unwrap();
+        let dst: [u8; 4] = packet[16..20].try_into().unwrap();
+        let tcp = &packet[header_len..];
+        let mut pseudo = [&src[..], &dst[..], &[0, 6]].concat();
+        pseudo.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+        (src.into(), dst.into(), pseudo, tcp)
+      }
+      6 => {
+        assert_eq!(usize::from(u16_be(&packet[4..])), packet.len() - 40);
+        assert_eq!(packet[6], 6);
+        let src: [u8; 16] = packet[8..24].try_into().unwrap();
+        let dst: [u8; 16] = packet[24..40].try_into().unwrap();
+        let tcp = &packet[40..];
+        let mut pseudo = [&src[..], &dst[..]].concat();
+        pseudo.extend_from_slice(&(tcp.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, 6]);
+        (src.into(), dst.into(), pseudo, tcp)
+      }
+      version => panic!("unexpected IP version {version}"),
+    };
+    pseudo.extend_from_slice(tcp);
+    assert_eq!(ones_complement_sum(&pseudo), 0xffff, "TCP checksum");
+    assert_eq!(tcp[13], 0x18, "PSH|ACK");
+
+    let data_offset = usize::from(tcp[12] >> 4) * 4;
+    segments.push(Segment {
+      src: SocketAddr::new(src_ip, u16_be(&tcp[0..])),
+      dst: SocketAddr::new(dst_ip, u16_be(&tcp[2..])),
+      seq: u32_be(&tcp[4..]),
+      ack: u32_be(&tcp[8..]),
+      payload: tcp[data_offset..].to_vec(),
+    });
+  }
+  segments
+}
+
+#[test]
+fn recorded_handshake_is_a_valid_pcap() {
+  let events = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&events);
+  let config = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.clone())),
+    ..HandshakeConfig::default()
+  };
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  perform_client_handshake_with_config(&mut stream, 5, &config).unwrap();
+  let (local, remote) = (stream.local_addr().unwrap(), server.addr);
+  server.stop();
+
+  let mut writer = PcapWriter::new(Vec::new(), local, remote).unwrap();
+  for event in events.lock().unwrap().iter() {
+    writer.write_event(event).unwrap();
+  }
+  let segments = parse_pcap(&writer.into_inner().unwrap());
+
+  let payloads: Vec<_> = segments
+    .iter()
+    .map(|segment| String::from_utf8_lossy(&segment.payload).into_owned())
+    .collect();
+  assert_eq!(payloads, ["HELLO 5", "HELLO 6", "HELLO 7"]);
+  assert_eq!((segments[0].src, segments[0].dst), (local, remote));
+  assert_eq!((segments[1].src, segments[1].dst), (remote, local));
+
+  // Sequence numbers advance by the bytes each side sent and acknowledge the other side
+  assert_eq!((segments[0].seq, segments[0].ack), (1, 1));
+  assert_eq!((segments[1].seq, segments[1].ack), (1, 8));
+  assert_eq!((segments[2].seq, segments[2].ack), (8, 8));
+}
+
+#[test]
+fn ipv6_endpoints_are_written() {
+  let local: SocketAddr = "[::1]:40000".parse().unwrap();
+  let remote: SocketAddr = "[::1]:8080".parse().unwrap();
+  let mut writer = PcapWriter::new(Vec::new(), local, remote).unwrap();
+  let now = SystemTime::now();
+  writer
+    .write_message(Direction::Outbound, b"HELLO 1", now)
+    .unwrap();
+  writer
+    .write_message(Direction::Inbound, b"HELLO 2", now)
+    .unwrap();
+
+  let segments = parse_pcap(&writer.into_inner().unwrap());
+  assert_eq!(segments.len(), 2);
+  assert_eq!((segments[1].src, segments[1].dst), (remote, local));
+  assert_eq!(segments[1].payload, b"HELLO 2");
+}
+
+#[test]
+fn mixed_ip_versions_are_rejected() {
+  let error = PcapWriter::new(
+    Vec::new(),
+    "127.0.0.1:40000".parse().unwrap(),
+    "[::1]:8080".parse().unwrap(),
+  )
+  .err()
+  .unwrap();
+  assert!(
+    matches!(error, HandshakeError::InvalidArguments(_)),
+    "{error}"
+  );
+}
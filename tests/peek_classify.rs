@@ -0,0 +1,122 @@
+/**
+ * Protocol classification tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{Protocol, classify_prefix, peek_classify, peek_classify_async};
+
+// A TLS 1.2 record header opening a ClientHello
+const CLIENT_HELLO: &[u8] = &[0x16, 0x03, 0x01, 0x00, 0xf1, 0x01];
+
+/**
+ * Accepts one connection whose peer writes each of `parts` in turn
+ */
+fn accepted(parts: &'static [&'static [u8]]) -> TcpStream {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  thread::spawn(move || {
+    let mut client = TcpStream::connect(addr).unwrap();
+    for part in parts {
+      client.write_all(part).unwrap();
+      thread::sleep(Duration::from_millis(20));
+    }
+    // Keep the connection open while the server classifies and reads it
+    thread::sleep(Duration::from_millis(200));
+  });
+  let (stream, _) = listener.accept().unwrap();
+  stream
+    .set_read_timeout(Some(Duration::from_secs(5)))
+    .unwrap();
+  stream
+}
+
+/**
+ * Classifies the connection, then checks that its first bytes are still there to read
+ */
+fn classify(parts: &'static [&'static [u8]]) -> Protocol {
+  let mut stream = accepted(parts);
+  let protocol = peek_classify(&stream).unwrap();
+  let sent = parts.concat();
+  if !sent.is_empty() {
+    let mut first = vec![0u8; sent.len().min(2)];
+    stream.read_exact(&mut first).unwrap();
+    assert_eq!(first, sent[..first.len()]);
+  }
+  protocol
+}
+
+#[test]
+fn ping_is_a_health_check() {
+  assert_eq!(classify(&[b"PING\n"]), Protocol::Ping);
+}
+
+#[test]
+fn proxy_is_a_proxy_header() {
+  assert_eq!(
+    classify(&[b"PROXY TCP4 10.0.0.1 10.0.0.2 1000 80\r\nHELLO 5"]),
+    Protocol::Proxy
+  );
+}
+
+#[test]
+fn hello_is_a_plaintext_handshake() {
+  assert_eq!(classify(&[b"HELLO 5"]), Protocol::Hello);
+}
+
+#[test]
+fn a_client_hello_is_tls() {
+  assert_eq!(classify(&[CLIENT_HELLO]), Protocol::Tls);
+}
+
+#[test]
+fn anything_else_is_unknown() {
+  assert_eq!(classify(&[b"GET / HTTP/1.1\r\n"]), Protocol::Unknown);
+}
+
+#[test]
+fn a_peer_closing_without_a_byte_is_unknown() {
+  assert_eq!(classify(&[]), Protocol::Unknown);
+}
+
+#[test]
+fn a_split_prefix_is_peeked_until_it_decides() {
+  assert_eq!(classify(&[b"P", b"ING\n"]), Protocol::Ping);
+  assert_eq!(classify(&[b"PR", b"OXY TCP4"]), Protocol::Proxy);
+}
+
+#[test]
+fn prefixes_too_short_to_tell_are_undecided() {
+  assert_eq!(classify_prefix(b""), None);
+  assert_eq!(classify_prefix(b"P"), None);
+  assert_eq!(classify_prefix(b"HEL"), None);
+  assert_eq!(classify_prefix(&[0x16]), None);
+  assert_eq!(classify_prefix(b"PX"), Some(Protocol::Unknown));
+}
+
+#[tokio::test]
+async fn async_peek_classifies_each_protocol() {
+  let cases: [(&'static [&'static [u8]], Protocol); 6] = [
+    (&[b"PING\n"], Protocol::Ping),
+    (&[b"PROXY UNKNOWN\r\n"], Protocol::Proxy),
+    (&[b"HELLO 5"], Protocol::Hello),
+    (&[CLIENT_HELLO], Protocol::Tls),
+    (&[b"SSH-2.0"], Protocol::Unknown),
+    (&[b"P", b"ROXY "], Protocol::Proxy),
+  ];
+  for (parts, expected) in cases {
+    let stream = accepted(parts);
+    stream.set_nonblocking(true).unwrap();
+    let stream = tokio::net::TcpStream::from_std(stream).unwrap();
+    assert_eq!(peek_classify_async(&stream).await.unwrap(), expected);
+
+    // Nothing was consumed
+    let mut first = [0u8; 1];
+    stream.peek(&mut first).await.unwrap();
+    assert_eq!(first[0], parts[0][0]);
+  }
+}
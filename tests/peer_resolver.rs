@@ -0,0 +1,109 @@
+/**
+ * Custom peer address resolver tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::Arc;
+
+use tcp_handshake::{
+  DefaultPeerResolver, HandshakeConfig, PeerInfo, PeerResolver, ProxyHeader, ServerModel,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+/**
+ * Stands in for a NAT table: every socket peer port is its own client in 203.0.113.0/24
+ */
+#[derive(Debug)]
+struct NatResolver;
+
+impl PeerResolver for NatResolver {
+  fn resolve(&self, peer: &PeerInfo) -> SocketAddr {
+    let host = (peer.socket_peer.port() % 250) as u8 + 1;
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(203, 0, 113, host)), 4000)
+  }
+}
+
+fn resolved_ip(peer: &TcpStream) -> IpAddr {
+  NatResolver
+    .resolve(&PeerInfo::accepted(peer.local_addr().unwrap(), None))
+    .ip()
+}
+
+#[test]
+fn default_resolver_prefers_the_proxy_source() {
+  let socket_peer: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+  let source: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+  let mut peer = PeerInfo::accepted(socket_peer, None);
+  assert_eq!(DefaultPeerResolver.resolve(&peer), socket_peer);
+
+  peer.proxy = Some(ProxyHeader::Tcp4 {
+    source,
+    destination: "198.51.100.1:8080".parse().unwrap(),
+  });
+  assert_eq!(DefaultPeerResolver.resolve(&peer), source);
+
+  // UNKNOWN names nobody, so the socket peer stands
+  peer.proxy = Some(ProxyHeader::Unknown);
+  assert_eq!(DefaultPeerResolver.resolve(&peer), socket_peer);
+}
+
+#[test]
+fn per_source_limits_count_resolved_clients() {
+  for model in [ServerModel::Threaded, ServerModel::Async] {
+    let config = HandshakeConfig {
+      max_connections_per_source: Some(1),
+      peer_resolver: Arc::new(NatResolver),
+      silent: true,
+      ..HandshakeConfig::default()
+    };
+    let server = spawn_server(model, config).unwrap();
+
+    // Both come from 127.0.0.1, but the resolver sees two clients
+    let held = TcpStream::connect(server.addr).unwrap();
+    let mut second = TcpStream::connect(server.addr).unwrap();
+    assert_ne!(resolved_ip(&held), resolved_ip(&second), "{model}");
+    let outcome =
+      perform_client_handshake_with_config(&mut second, 10, &HandshakeConfig::default()).unwrap();
+    assert_eq!(outcome.final_seq, 12, "{model}");
+
+    assert_eq!(server.metrics.snapshot().per_source_rejected, 0, "{model}");
+    drop(held);
+    server.stop();
+  }
+}
+
+#[test]
+fn claimed_addresses_are_checked_against_the_resolved_client() {
+  for model in ServerModel::ALL {
+    let config = HandshakeConfig {
+      verify_client_ip: true,
+      strict_client_ip: true,
+      peer_resolver: Arc::new(NatResolver),
+      silent: true,
+      ..HandshakeConfig::default()
+    };
+    let server = spawn_server(model, config).unwrap();
+
+    // Claiming the NAT address passes where the socket peer would not
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let client = HandshakeConfig {
+      claimed_ip: Some(resolved_ip(&stream)),
+      ..HandshakeConfig::default()
+    };
+    perform_client_handshake_with_config(&mut stream, 10, &client)
+      .unwrap_or_else(|e| panic!("{model}: {e}"));
+
+    // Claiming the real loopback address no longer matches and is refused
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let client = HandshakeConfig {
+      claimed_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+      ..HandshakeConfig::default()
+    };
+    assert!(
+      perform_client_handshake_with_config(&mut stream, 10, &client).is_err(),
+      "{model}"
+    );
+    server.stop();
+  }
+}
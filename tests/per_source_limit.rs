@@ -0,0 +1,84 @@
+/**
+ * Per-source connection limit tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tcp_handshake::{
+  HandshakeConfig, ServerMetrics, ServerOptions, SourceLimiter, live_config, serve_async,
+};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+
+const MAX_PER_SOURCE: usize = 3;
+
+#[test]
+fn limiter_refuses_source_at_cap() {
+  let limiter = Arc::new(SourceLimiter::new(Some(MAX_PER_SOURCE)));
+  let source = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+  let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+
+  let guards: Vec<_> = (0..MAX_PER_SOURCE)
+    .map(|_| limiter.try_acquire(source).expect("below the cap"))
+    .collect();
+  assert!(limiter.try_acquire(source).is_none());
+  // Other sources have their own budget
+  assert!(limiter.try_acquire(other).is_some());
+
+  // Releasing one connection makes room for the next
+  drop(guards);
+  assert_eq!(limiter.active(source), 0);
+  assert!(limiter.try_acquire(source).is_some());
+}
+
+#[tokio::test]
+async fn server_closes_connection_over_the_cap() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let config = HandshakeConfig {
+    max_connections_per_source: Some(MAX_PER_SOURCE),
+    ..HandshakeConfig::default()
+  };
+  let metrics = Arc::new(ServerMetrics::new());
+  let (stop, stopped) = oneshot::channel::<()>();
+  let server = tokio::spawn(serve_async(
+    listener,
+    live_config(config),
+    Arc::clone(&metrics),
+    ServerOptions::default(),
+    async {
+      let _ = stopped.await;
+    },
+  ));
+
+  // Hold N connections open mid-handshake, then open one more
+  let mut held = Vec::new();
+  for _ in 0..MAX_PER_SOURCE {
+    held.push(TcpStream::connect(addr).await.unwrap());
+  }
+  let mut extra = TcpStream::connect(addr).await.unwrap();
+
+  // The extra connection is closed without a reply
+  let mut buffer = [0u8; 8];
+  let read = tokio::time::timeout(Duration::from_secs(2), extra.read(&mut buffer))
+    .await
+    .expect("server closes the extra connection");
+  assert!(matches!(read, Ok(0) | Err(_)));
+  assert_eq!(metrics.snapshot().per_source_rejected, 1);
+
+  // Handshakes that fail still release their slot
+  drop(held);
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  let mut again = TcpStream::connect(addr).await.unwrap();
+  let idle = tokio::time::timeout(Duration::from_millis(200), again.read(&mut buffer)).await;
+  assert!(idle.is_err(), "connection should be waiting for HELLO");
+  assert_eq!(metrics.snapshot().per_source_rejected, 1);
+
+  drop(again);
+  let _ = stop.send(());
+  server.await.unwrap();
+}
@@ -0,0 +1,167 @@
+/**
+ * Persistent connection tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  Delimiter, HANDSHAKES_PER_CONNECTION_BUCKETS, HandshakeConfig, HandshakeCountHistogram,
+  RunningServer, ServerModel, perform_client_handshake_with_config, spawn_server,
+};
+
+fn persistent() -> HandshakeConfig {
+  HandshakeConfig {
+    persistent: true,
+    silent: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Waits for the server to notice closed connections and record them
+ */
+fn wait_for_connections(
+  server: &RunningServer,
+  expected: [u64; HANDSHAKES_PER_CONNECTION_BUCKETS],
+) {
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while server.metrics.handshakes_per_connection.counts() != expected && Instant::now() < deadline {
+    thread::sleep(Duration::from_millis(10));
+  }
+}
+
+#[test]
+fn counts_land_in_their_buckets() {
+  let histogram = HandshakeCountHistogram::default();
+  for handshakes in [0, 1, 2, 4, 5, 9, 10, 99, 100, 5000] {
+    histogram.record(handshakes);
+  }
+  assert_eq!(histogram.counts(), [2, 2, 2, 2, 2]);
+}
+
+#[test]
+fn three_handshakes_share_one_socket() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, persistent()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+
+    // Every handshake starts over, so the same HELLO X works each time
+    let client = HandshakeConfig {
+      silent: true,
+      ..HandshakeConfig::default()
+    };
+    for _ in 0..3 {
+      let outcome = perform_client_handshake_with_config(&mut stream, 100, &client).unwrap();
+      assert_eq!(outcome.initial_seq, 100, "{model}");
+      assert_eq!(outcome.final_seq, 102, "{model}");
+    }
+
+    drop(stream);
+    let expected = [0, 1, 0, 0, 0];
+    wait_for_connections(&server, expected);
+    assert_eq!(
+      server.metrics.handshakes_per_connection.counts(),
+      expected,
+      "{model}"
+    );
+    assert_eq!(
+      server.metrics.snapshot().handshakes_per_connection,
+      expected
+    );
+    server.stop();
+  }
+}
+
+#[test]
+fn framed_handshakes_can_be_pipelined() {
+  let config = HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    ..persistent()
+  };
+  for model in [ServerModel::Threaded, ServerModel::Async] {
+    let server = spawn_server(model, config.clone()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream
+      .set_read_timeout(Some(Duration::from_secs(5)))
+      .unwrap();
+
+    // The next HELLO X rides along with each HELLO Z
+    let mut reply = [0u8; 16];
+    stream.write_all(b"HELLO 1\n").unwrap();
+    let read = stream.read(&mut reply).unwrap();
+    assert_eq!(&reply[..read], b"HELLO 2\n", "{model}");
+    stream.write_all(b"HELLO 3\nHELLO 50\n").unwrap();
+    let read = stream.read(&mut reply).unwrap();
+    assert_eq!(&reply[..read], b"HELLO 51\n", "{model}");
+    stream.write_all(b"HELLO 52\n").unwrap();
+
+    drop(stream);
+    let expected = [0, 1, 0, 0, 0];
+    wait_for_connections(&server, expected);
+    assert_eq!(
+      server.metrics.handshakes_per_connection.counts(),
+      expected,
+      "{model}"
+    );
+    server.stop();
+  }
+}
+
+#[test]
+fn without_persistent_the_second_handshake_fails() {
+  let server = spawn_server(
+    ServerModel::Threaded,
+    HandshakeConfig {
+      silent: true,
+      ..HandshakeConfig::default()
+    },
+  )
+  .unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  stream
+    .set_read_timeout(Some(Duration::from_secs(5)))
+    .unwrap();
+  let client = HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  perform_client_handshake_with_config(&mut stream, 7, &client).unwrap();
+  assert!(perform_client_handshake_with_config(&mut stream, 7, &client).is_err());
+
+  // Only persistent connections are counted
+  assert_eq!(
+    server.metrics.handshakes_per_connection.counts(),
+    [0; HANDSHAKES_PER_CONNECTION_BUCKETS]
+  );
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn client_binary_runs_every_handshake_on_one_connection() {
+  let server = spawn_server(ServerModel::Async, persistent()).unwrap();
+  let output = Command::new(env!("CARGO_BIN_EXE_client-persistent"))
+    .args([
+      "127.0.0.1",
+      &server.addr.port().to_string(),
+      "3",
+      "--initial-seq",
+      "40",
+    ])
+    .output()
+    .unwrap();
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(output.status.success(), "{stderr}");
+  assert!(stderr.contains("[handshake 3/3]"), "{stderr}");
+  assert!(stderr.contains("Completed 3 handshakes"), "{stderr}");
+
+  let expected = [0, 1, 0, 0, 0];
+  wait_for_connections(&server, expected);
+  assert_eq!(server.metrics.handshakes_per_connection.counts(), expected);
+  server.stop();
+}
@@ -0,0 +1,55 @@
+/**
+ * Bounded worker pool shutdown tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{BoundedWorkerPool, ServerMetrics};
+
+fn sleeping_pool(finished: Arc<AtomicBool>) -> BoundedWorkerPool<Duration> {
+  BoundedWorkerPool::new(1, 4, Arc::new(ServerMetrics::new()), move |work| {
+    thread::sleep(work);
+    finished.store(true, Ordering::SeqCst);
+  })
+}
+
+#[test]
+fn wait_idle_returns_once_jobs_finish() {
+  let finished = Arc::new(AtomicBool::new(false));
+  let pool = sleeping_pool(Arc::clone(&finished));
+  pool.try_submit(Duration::from_millis(100)).unwrap();
+
+  assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+  assert!(finished.load(Ordering::SeqCst));
+  assert_eq!(pool.outstanding(), 0);
+}
+
+#[test]
+fn shutdown_waits_for_long_job_up_to_timeout() {
+  let finished = Arc::new(AtomicBool::new(false));
+  let pool = sleeping_pool(Arc::clone(&finished));
+  pool.try_submit(Duration::from_secs(2)).unwrap();
+
+  let started = Instant::now();
+  let drained = pool.shutdown(Duration::from_millis(300));
+  let waited = started.elapsed();
+
+  assert!(!drained);
+  assert!(!finished.load(Ordering::SeqCst));
+  assert!(waited >= Duration::from_millis(300), "waited {waited:?}");
+  assert!(waited < Duration::from_secs(2), "waited {waited:?}");
+}
+
+#[test]
+fn shutdown_drains_jobs_that_finish_in_time() {
+  let finished = Arc::new(AtomicBool::new(false));
+  let pool = sleeping_pool(Arc::clone(&finished));
+  pool.try_submit(Duration::from_millis(100)).unwrap();
+
+  assert!(pool.shutdown(Duration::from_secs(5)));
+  assert!(finished.load(Ordering::SeqCst));
+}
@@ -0,0 +1,38 @@
+/**
+ * Listener port conflict tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpListener;
+
+use tcp_handshake::{HandshakeError, create_async_listener, create_listener};
+
+fn taken_port() -> (TcpListener, u16) {
+  let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+  let port = listener.local_addr().unwrap().port();
+  (listener, port)
+}
+
+#[test]
+fn binding_a_taken_port_reports_port_in_use() {
+  let (_held, port) = taken_port();
+  match create_listener(port) {
+    Err(HandshakeError::PortInUse(reported)) => assert_eq!(reported, port),
+    other => panic!("expected PortInUse, got {other:?}"),
+  }
+}
+
+#[tokio::test]
+async fn async_binding_a_taken_port_reports_port_in_use() {
+  let (_held, port) = taken_port();
+  let error = create_async_listener(port).await.unwrap_err();
+  assert!(matches!(error, HandshakeError::PortInUse(reported) if reported == port));
+
+  // The message points at the fix rather than the errno
+  let message = error.to_string();
+  assert!(
+    message.contains(&format!("Port {port} is already in use")),
+    "{message}"
+  );
+  assert!(message.contains("another port"), "{message}");
+}
@@ -0,0 +1,108 @@
+/**
+ * Preamble exchange tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use tokio::io::{AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, HandshakeError, Preamble, perform_async_client_handshake_on,
+  perform_async_server_handshake_with_config, perform_client_handshake_with_config,
+  perform_server_handshake_with_config,
+};
+
+fn with_preamble() -> HandshakeConfig {
+  HandshakeConfig {
+    preamble: Some(Preamble::default()),
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn sync_handshake_runs_after_the_preamble() {
+  let config = with_preamble();
+  let client_config = config.clone();
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 5, &client_config)
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let outcome = perform_server_handshake_with_config(&mut stream, &config).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  assert_eq!(client.join().unwrap().unwrap().final_seq, 7);
+}
+
+#[tokio::test]
+async fn async_handshake_runs_after_the_preamble() {
+  let (client, mut server) = duplex(1024);
+  let config = with_preamble();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &config),
+    perform_async_server_handshake_with_config(&mut server, "duplex peer", None, &config),
+  );
+  assert_eq!(client.unwrap().final_seq, 7);
+  assert_eq!(server.unwrap().final_seq, 7);
+}
+
+#[tokio::test]
+async fn custom_tokens_work_with_a_delimiter() {
+  let (client, mut server) = duplex(1024);
+  let config = HandshakeConfig {
+    preamble: Some(Preamble {
+      greeting: "220 ready".to_string(),
+      reply: "GO".to_string(),
+    }),
+    delimiter: Delimiter::Byte(b'\n'),
+    ..HandshakeConfig::default()
+  };
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_on(client, 10, &config),
+    perform_async_server_handshake_with_config(&mut server, "duplex peer", None, &config),
+  );
+  assert_eq!(client.unwrap().final_seq, 12);
+  assert_eq!(server.unwrap().final_seq, 12);
+}
+
+#[test]
+fn server_rejects_a_wrong_reply() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut greeting = [0u8; 5];
+    stream.read_exact(&mut greeting).unwrap();
+    assert_eq!(&greeting, b"READY");
+    stream.write_all(b"BEGIN").unwrap();
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let result = perform_server_handshake_with_config(&mut stream, &with_preamble());
+  client.join().unwrap();
+  match result {
+    Err(HandshakeError::ProtocolViolation(message)) => {
+      assert_eq!(message, "expected preamble 'START', received 'BEGIN'");
+    }
+    other => panic!("expected a protocol violation, got {other:?}"),
+  }
+}
+
+#[tokio::test]
+async fn client_rejects_a_server_without_the_preamble() {
+  let (client, mut server) = duplex(1024);
+  let config = with_preamble();
+  let (client, _) = tokio::join!(
+    perform_async_client_handshake_on(client, 5, &config),
+    // A server without the preamble never greets; this one jumps straight to a reply
+    server.write_all(b"HELLO 6"),
+  );
+  assert!(matches!(client, Err(HandshakeError::ProtocolViolation(_))));
+}
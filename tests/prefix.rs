@@ -0,0 +1,107 @@
+/**
+ * Server handshake with a pre-read prefix tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MSG_SIZE, perform_async_client_handshake_with_config,
+  perform_async_server_handshake_with_prefix, perform_client_handshake,
+  perform_server_handshake_with_prefix,
+};
+
+/**
+ * Runs a sync client against a server that consumes `consumed` bytes before the handshake
+ * `None` consumes whatever the first read returns
+ */
+fn sync_handshake_after_reading(consumed: Option<usize>) -> (i32, i32) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let stream = TcpStream::connect(addr).unwrap();
+    perform_client_handshake(stream, 20).unwrap()
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let mut first = [0u8; MSG_SIZE];
+  let prefix = match consumed {
+    Some(len) => {
+      stream.read_exact(&mut first[..len]).unwrap();
+      &first[..len]
+    }
+    None => {
+      let len = stream.read(&mut first).unwrap();
+      &first[..len]
+    }
+  };
+  let outcome =
+    perform_server_handshake_with_prefix(&mut stream, prefix, &HandshakeConfig::default()).unwrap();
+
+  client.join().unwrap();
+  (outcome.initial_seq, outcome.final_seq)
+}
+
+#[test]
+fn sync_split_prefix_is_completed_from_socket() {
+  assert_eq!(sync_handshake_after_reading(Some(3)), (20, 22));
+}
+
+#[test]
+fn sync_whole_prefix_is_not_read_again() {
+  assert_eq!(sync_handshake_after_reading(None), (20, 22));
+}
+
+#[test]
+fn oversized_prefix_is_rejected() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+  let (mut stream, _) = listener.accept().unwrap();
+
+  let prefix = [b' '; MSG_SIZE + 1];
+  let result =
+    perform_server_handshake_with_prefix(&mut stream, &prefix, &HandshakeConfig::default());
+  assert!(
+    matches!(result, Err(HandshakeError::ProtocolViolation(_))),
+    "got {result:?}"
+  );
+}
+
+/**
+ * Async handshake where the server was handed `prefix` and the client sends only `rest`
+ */
+async fn async_handshake_with(prefix: &[u8], rest: &str) -> (i32, i32) {
+  let (mut client_stream, mut server_stream) = tokio::io::duplex(256);
+  let client_config = HandshakeConfig {
+    raw_first_message: Some(rest.to_string()),
+    ..HandshakeConfig::default()
+  };
+  let server_config = HandshakeConfig::default();
+
+  let (client, server) = tokio::join!(
+    perform_async_client_handshake_with_config(&mut client_stream, 30, &client_config),
+    perform_async_server_handshake_with_prefix(
+      &mut server_stream,
+      prefix,
+      "duplex",
+      None,
+      &server_config,
+    ),
+  );
+  client.unwrap();
+  let outcome = server.unwrap();
+  (outcome.initial_seq, outcome.final_seq)
+}
+
+#[tokio::test]
+async fn async_split_prefix_is_completed_from_stream() {
+  assert_eq!(async_handshake_with(b"HELLO ", "30").await, (30, 32));
+}
+
+#[tokio::test]
+async fn async_whole_prefix_is_not_read_again() {
+  // The client's raw first message is empty, so nothing extra reaches the server
+  assert_eq!(async_handshake_with(b"HELLO 30", "").await, (30, 32));
+}
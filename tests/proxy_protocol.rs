@@ -0,0 +1,122 @@
+/**
+ * PROXY protocol v1 header tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ProxyHeader, parse_proxy_header, read_proxy_header_from_stream,
+};
+
+/**
+ * A connected pair of loopback sockets: the client end and the server end
+ */
+fn socket_pair() -> (TcpStream, TcpStream) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+  let (server, _) = listener.accept().unwrap();
+  (client, server)
+}
+
+#[test]
+fn parses_a_tcp4_header() {
+  let header = parse_proxy_header("PROXY TCP4 192.0.2.1 198.51.100.1 56324 8080\r\n").unwrap();
+  assert_eq!(
+    header,
+    ProxyHeader::Tcp4 {
+      source: "192.0.2.1:56324".parse().unwrap(),
+      destination: "198.51.100.1:8080".parse().unwrap(),
+    }
+  );
+  assert_eq!(header.source(), Some("192.0.2.1:56324".parse().unwrap()));
+}
+
+#[test]
+fn parses_a_tcp6_header() {
+  let header = parse_proxy_header("PROXY TCP6 2001:db8::1 2001:db8::2 56324 8080\r\n").unwrap();
+  let source: SocketAddr = "[2001:db8::1]:56324".parse().unwrap();
+  assert_eq!(
+    header,
+    ProxyHeader::Tcp6 {
+      source,
+      destination: "[2001:db8::2]:8080".parse().unwrap(),
+    }
+  );
+  assert_eq!(header.source(), Some(source));
+}
+
+#[test]
+fn parses_an_unknown_header() {
+  let header = parse_proxy_header("PROXY UNKNOWN\r\n").unwrap();
+  assert_eq!(header, ProxyHeader::Unknown);
+  assert_eq!(header.source(), None);
+}
+
+#[test]
+fn rejects_malformed_headers() {
+  for line in [
+    "HELLO 5",
+    "PROXY UDP4 192.0.2.1 198.51.100.1 56324 8080",
+    "PROXY TCP4 192.0.2.1 198.51.100.1 56324",
+    "PROXY TCP4 2001:db8::1 198.51.100.1 56324 8080",
+    "PROXY TCP6 192.0.2.1 2001:db8::2 56324 8080",
+    "PROXY TCP4 192.0.2.1 198.51.100.1 70000 8080",
+  ] {
+    assert!(
+      matches!(
+        parse_proxy_header(line),
+        Err(HandshakeError::ProtocolViolation(_))
+      ),
+      "{line}"
+    );
+  }
+}
+
+#[test]
+fn reading_the_header_leaves_the_hello_unread() {
+  let (mut client, mut server) = socket_pair();
+  client
+    .write_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 8080\r\nHELLO 5")
+    .unwrap();
+
+  let header = read_proxy_header_from_stream(&mut server, &HandshakeConfig::default()).unwrap();
+  assert_eq!(header.source(), Some("192.0.2.1:56324".parse().unwrap()));
+
+  let mut hello = [0u8; 7];
+  server.read_exact(&mut hello).unwrap();
+  assert_eq!(&hello, b"HELLO 5");
+}
+
+#[test]
+fn a_silent_peer_times_out_after_the_configured_read_timeout() {
+  let (_client, mut server) = socket_pair();
+  let config = HandshakeConfig {
+    read_timeout: Duration::from_millis(100),
+    ..HandshakeConfig::default()
+  };
+
+  let started = Instant::now();
+  let result = read_proxy_header_from_stream(&mut server, &config);
+  assert!(matches!(result, Err(HandshakeError::Timeout)), "{result:?}");
+  assert!(
+    started.elapsed() < Duration::from_secs(2),
+    "{:?}",
+    started.elapsed()
+  );
+}
+
+#[test]
+fn a_close_mid_header_is_a_disconnect() {
+  let (mut client, mut server) = socket_pair();
+  client.write_all(b"PROXY TCP4 192.0.2.1").unwrap();
+  drop(client);
+
+  let result = read_proxy_header_from_stream(&mut server, &HandshakeConfig::default());
+  assert!(
+    matches!(result, Err(HandshakeError::ClientDisconnected)),
+    "{result:?}"
+  );
+}
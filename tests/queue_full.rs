@@ -0,0 +1,84 @@
+/**
+ * Thread pool queue backpressure tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use tcp_handshake::{
+  HandshakeConfig, ServerMetrics, ServerOptions, live_config, serve_threadpool,
+  stop_blocking_server,
+};
+
+/**
+ * Polls `ready` until it holds, failing the test after a few seconds
+ */
+fn wait_until(what: &str, ready: impl Fn() -> bool) {
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while !ready() {
+    assert!(Instant::now() < deadline, "timed out waiting for {what}");
+    thread::sleep(Duration::from_millis(10));
+  }
+}
+
+#[test]
+fn a_full_queue_closes_the_overflow_connection() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let metrics = Arc::new(ServerMetrics::new());
+  let stop = CancellationToken::new();
+  let config = live_config(HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  });
+
+  // One worker and room for one connection in the queue
+  let options = ServerOptions {
+    workers: Some(1),
+    queue_capacity: 1,
+    ..ServerOptions::default()
+  };
+  let server = {
+    let metrics = Arc::clone(&metrics);
+    let stop = stop.clone();
+    thread::spawn(move || serve_threadpool(listener, config, metrics, options, &stop))
+  };
+
+  // The first client holds the only worker by never sending HELLO X
+  let blocker = TcpStream::connect(addr).unwrap();
+  wait_until("the worker to take the first connection", || {
+    let snapshot = metrics.snapshot();
+    snapshot.accepted == 1 && snapshot.queue_depth == 0
+  });
+
+  // The second waits in the queue
+  let queued = TcpStream::connect(addr).unwrap();
+  wait_until("the second connection to be queued", || {
+    metrics.snapshot().queue_depth == 1
+  });
+
+  // The third finds the queue full and is closed without a reply
+  let mut overflow = TcpStream::connect(addr).unwrap();
+  overflow
+    .set_read_timeout(Some(Duration::from_secs(5)))
+    .unwrap();
+  let mut reply = Vec::new();
+  overflow.read_to_end(&mut reply).unwrap();
+  assert!(reply.is_empty(), "{reply:?}");
+
+  let snapshot = metrics.snapshot();
+  assert_eq!(snapshot.rejected_queue_full, 1);
+  assert_eq!(snapshot.queue_depth, 1);
+  assert!(snapshot.to_string().contains("queue_depth=1"), "{snapshot}");
+
+  drop(blocker);
+  drop(queued);
+  stop_blocking_server(&stop, addr);
+  assert!(server.join().unwrap());
+}
@@ -0,0 +1,54 @@
+/**
+ * Seeded random initial sequence tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tcp_handshake::{
+  HandshakeConfig, MAX_RANDOM_INITIAL_SEQ, ServerModel, perform_client_handshake_with_config,
+  random_initial_seq, spawn_server,
+};
+
+const SEED: u64 = 0x5eed;
+
+fn seeded_sequences(count: usize) -> Vec<i32> {
+  let mut rng = StdRng::seed_from_u64(SEED);
+  (0..count).map(|_| random_initial_seq(&mut rng)).collect()
+}
+
+#[test]
+fn same_seed_gives_same_sequences() {
+  let first = seeded_sequences(16);
+  assert_eq!(first, seeded_sequences(16));
+  assert!(
+    first
+      .iter()
+      .all(|seq| (0..=MAX_RANDOM_INITIAL_SEQ).contains(seq))
+  );
+  assert!(first.iter().any(|seq| *seq != first[0]));
+
+  let mut other = StdRng::seed_from_u64(SEED + 1);
+  assert_ne!(random_initial_seq(&mut other), first[0]);
+}
+
+#[test]
+fn seeded_sequence_chain_is_reproducible() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  for _ in 0..2 {
+    let initial_seq = random_initial_seq(&mut StdRng::seed_from_u64(SEED));
+    assert_eq!(initial_seq, seeded_sequences(1)[0]);
+
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let outcome =
+      perform_client_handshake_with_config(&mut stream, initial_seq, &HandshakeConfig::default())
+        .unwrap();
+    assert_eq!(
+      (outcome.initial_seq, outcome.final_seq),
+      (initial_seq, initial_seq + 2)
+    );
+  }
+  server.stop();
+}
@@ -0,0 +1,87 @@
+/**
+ * Oversized reply detection tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, MSG_SIZE, perform_async_client_handshake_with_config,
+  perform_client_handshake_with_config,
+};
+
+const INITIAL_SEQ: i32 = 5;
+
+/**
+ * Answers one HELLO with a valid reply padded far past `MSG_SIZE` in a single write
+ */
+fn spawn_flooding_server() -> (SocketAddr, JoinHandle<()>) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; MSG_SIZE];
+    let _ = stream.read(&mut buffer).unwrap();
+    let reply = format!("HELLO {:<width$}", INITIAL_SEQ + 1, width = MSG_SIZE * 4);
+    stream.write_all(reply.as_bytes()).unwrap();
+    // Swallow the final HELLO, if any, until the client hangs up
+    while matches!(stream.read(&mut buffer), Ok(read) if read > 0) {}
+  });
+  (addr, server)
+}
+
+fn overflow_config() -> HandshakeConfig {
+  HandshakeConfig {
+    detect_read_overflow: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn sync_client_rejects_flooded_reply() {
+  let (addr, server) = spawn_flooding_server();
+  let mut stream = TcpStream::connect(addr).unwrap();
+
+  let result = perform_client_handshake_with_config(&mut stream, INITIAL_SEQ, &overflow_config());
+  assert!(
+    matches!(result, Err(HandshakeError::ProtocolViolation(ref reason)) if reason.contains("exceeds")),
+    "got {result:?}"
+  );
+
+  drop(stream);
+  server.join().unwrap();
+}
+
+#[test]
+fn sync_client_ignores_overflow_by_default() {
+  let (addr, server) = spawn_flooding_server();
+  let mut stream = TcpStream::connect(addr).unwrap();
+
+  let outcome =
+    perform_client_handshake_with_config(&mut stream, INITIAL_SEQ, &HandshakeConfig::default())
+      .expect("the first buffer holds a valid reply");
+  assert_eq!(outcome.final_seq, INITIAL_SEQ + 2);
+
+  drop(stream);
+  server.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_client_rejects_flooded_reply() {
+  let (addr, server) = spawn_flooding_server();
+  let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+
+  let result =
+    perform_async_client_handshake_with_config(&mut stream, INITIAL_SEQ, &overflow_config()).await;
+  assert!(
+    matches!(result, Err(HandshakeError::ProtocolViolation(_))),
+    "got {result:?}"
+  );
+
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.join().unwrap())
+    .await
+    .unwrap();
+}
@@ -0,0 +1,98 @@
+#![cfg(unix)]
+/**
+ * Readiness socket tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{
+  HandshakeConfig, NOT_READY_REPLY, READY_REPLY, ServerMetrics, ServerModel, live_config,
+  serve_ready_socket, serve_ready_socket_blocking, spawn_server,
+};
+
+const WARMUP: Duration = Duration::from_millis(300);
+
+fn socket_path(name: &str) -> PathBuf {
+  std::env::temp_dir().join(format!(
+    "handshake-ready-{}-{name}.sock",
+    std::process::id()
+  ))
+}
+
+fn check(path: &Path) -> String {
+  let mut stream = UnixStream::connect(path).unwrap();
+  let mut reply = String::new();
+  stream.read_to_string(&mut reply).unwrap();
+  reply.trim_end().to_string()
+}
+
+#[test]
+fn readiness_follows_warmup_and_draining() {
+  for model in ServerModel::ALL {
+    let config = HandshakeConfig {
+      warmup: Some(WARMUP),
+      silent: true,
+      ..HandshakeConfig::default()
+    };
+    let server = spawn_server(model, config).unwrap();
+    let path = socket_path(&model.to_string());
+    let live = live_config(HandshakeConfig::default());
+    serve_ready_socket_blocking(&path, live, Arc::clone(&server.metrics)).unwrap();
+
+    // Bound, but still warming up
+    assert_eq!(check(&path), NOT_READY_REPLY, "{model}");
+
+    thread::sleep(WARMUP);
+    assert_eq!(check(&path), READY_REPLY, "{model}");
+
+    // Once stopped the server is draining or done, never ready again
+    let metrics = Arc::clone(&server.metrics);
+    server.stop();
+    assert!(metrics.snapshot().draining, "{model}");
+    assert_eq!(check(&path), NOT_READY_REPLY, "{model}");
+    let _ = std::fs::remove_file(&path);
+  }
+}
+
+#[tokio::test]
+async fn not_ready_before_the_accept_loop_starts() {
+  let path = socket_path("async");
+  let metrics = Arc::new(ServerMetrics::new());
+  serve_ready_socket(
+    &path,
+    live_config(HandshakeConfig::default()),
+    Arc::clone(&metrics),
+  )
+  .unwrap();
+
+  let reply = tokio::task::spawn_blocking({
+    let path = path.clone();
+    move || check(&path)
+  })
+  .await
+  .unwrap();
+  assert_eq!(reply, NOT_READY_REPLY);
+  let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn a_stale_socket_file_is_replaced() {
+  let path = socket_path("stale");
+  let metrics = Arc::new(ServerMetrics::new());
+  for _ in 0..2 {
+    serve_ready_socket_blocking(
+      &path,
+      live_config(HandshakeConfig::default()),
+      Arc::clone(&metrics),
+    )
+    .unwrap();
+  }
+  assert_eq!(check(&path), NOT_READY_REPLY);
+  let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,97 @@
+#![cfg(unix)]
+/**
+ * Config file and SIGHUP reload tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::SIGHUP;
+use tcp_handshake::{
+  ConfigFile, HandshakeConfig, RateLimit, ServerMetrics, ServerOptions, load_live_config,
+  serve_async,
+};
+
+#[test]
+fn config_file_is_parsed_and_applied() {
+  let file = ConfigFile::parse(
+    r#"
+      read_timeout = "2s"
+      first_byte_timeout = "250ms"
+      rate_limit = "10/1s"
+      max_per_source = 4
+    "#,
+  )
+  .unwrap();
+  let config = file.apply(&HandshakeConfig::default());
+  assert_eq!(config.read_timeout, Duration::from_secs(2));
+  assert_eq!(config.first_byte_timeout, Some(Duration::from_millis(250)));
+  assert_eq!(
+    config.accept_rate_limit,
+    Some(RateLimit {
+      max_connections: 10,
+      window: Duration::from_secs(1),
+    })
+  );
+  assert_eq!(config.max_connections_per_source, Some(4));
+  // Settings missing from the file keep the command line value
+  assert_eq!(
+    config.connection_timeout,
+    HandshakeConfig::default().connection_timeout
+  );
+
+  assert!(ConfigFile::parse("read_timeout = \"soon\"").is_err());
+  assert!(ConfigFile::parse("bind_address = \"0.0.0.0\"").is_err());
+}
+
+/**
+ * Connects without sending anything and returns how long the server took to hang up
+ */
+fn silent_connection_lifetime(addr: std::net::SocketAddr) -> Duration {
+  let mut stream = TcpStream::connect(addr).unwrap();
+  stream
+    .set_read_timeout(Some(Duration::from_secs(10)))
+    .unwrap();
+  let started = Instant::now();
+  let _ = stream.read(&mut [0u8; 64]);
+  started.elapsed()
+}
+
+#[test]
+fn sighup_reload_changes_the_next_handshake() {
+  let path = std::env::temp_dir().join(format!("handshake-reload-{}.toml", std::process::id()));
+  std::fs::write(&path, "first_byte_timeout = \"5s\"\n").unwrap();
+  let live = load_live_config(HandshakeConfig::default(), Some(path.clone())).unwrap();
+
+  let runtime = tokio::runtime::Runtime::new().unwrap();
+  let listener = runtime
+    .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+    .unwrap();
+  let addr = listener.local_addr().unwrap();
+  let server = runtime.spawn(serve_async(
+    listener,
+    Arc::clone(&live),
+    Arc::new(ServerMetrics::new()),
+    ServerOptions::default(),
+    std::future::pending(),
+  ));
+
+  std::fs::write(&path, "first_byte_timeout = \"100ms\"\n").unwrap();
+  signal_hook::low_level::raise(SIGHUP).unwrap();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  while live.load().first_byte_timeout != Some(Duration::from_millis(100)) {
+    assert!(Instant::now() < deadline, "config was not reloaded");
+    thread::sleep(Duration::from_millis(10));
+  }
+
+  // A silent client is now dropped after 100ms instead of 5s
+  let lifetime = silent_connection_lifetime(addr);
+  assert!(lifetime < Duration::from_secs(2), "{lifetime:?}");
+
+  server.abort();
+  let _ = std::fs::remove_file(&path);
+}
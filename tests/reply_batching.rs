@@ -0,0 +1,91 @@
+/**
+ * Batched reply tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, ReplyStrategy, perform_async_server_handshake_with_config,
+};
+
+/**
+ * Hands out a whole client transcript on the first read and records every write
+ */
+struct ScriptedPeer {
+  input: Vec<u8>,
+  writes: usize,
+  written: Vec<u8>,
+}
+
+impl AsyncRead for ScriptedPeer {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    _: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let len = self.input.len().min(buf.remaining());
+    let input: Vec<u8> = self.input.drain(..len).collect();
+    buf.put_slice(&input);
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for ScriptedPeer {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    _: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    self.writes += 1;
+    self.written.extend_from_slice(buf);
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+/**
+ * Serves one line-framed handshake with `reply_strategy`, writing the delimiter separately
+ */
+async fn serve(reply_strategy: ReplyStrategy) -> ScriptedPeer {
+  let config = HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    coalesce_writes: false,
+    reply_strategy,
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  let mut peer = ScriptedPeer {
+    input: b"HELLO 5\nHELLO 7\n".to_vec(),
+    writes: 0,
+    written: Vec::new(),
+  };
+  let outcome = perform_async_server_handshake_with_config(&mut peer, "peer", None, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  peer
+}
+
+#[tokio::test]
+async fn a_batched_reply_is_the_same_bytes_in_fewer_writes() {
+  let immediate = serve(ReplyStrategy::Immediate).await;
+  let batched = serve(ReplyStrategy::batched(Duration::from_millis(1))).await;
+
+  assert_eq!(immediate.written, b"HELLO 6\n");
+  assert_eq!(batched.written, immediate.written);
+  assert_eq!(immediate.writes, 2);
+  assert_eq!(batched.writes, 1);
+}
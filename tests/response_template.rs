@@ -0,0 +1,77 @@
+/**
+ * Server response template tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpStream;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, HandshakeOutcome, ResponseTemplate, Result, ServerModel,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+fn handshake_against(template: &str) -> Result<HandshakeOutcome> {
+  let config = HandshakeConfig {
+    response_template: Some(ResponseTemplate::parse(template).unwrap()),
+    ..HandshakeConfig::default()
+  };
+  let server = spawn_server(ServerModel::Threaded, config).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  let outcome = perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default());
+  server.stop();
+  outcome
+}
+
+#[test]
+fn placeholders_are_substituted() {
+  let template = ResponseTemplate::parse("HELLO {seq} V=1").unwrap();
+  assert_eq!(template.render(6, None), "HELLO 6 V=1");
+  assert!(!template.uses_nonce());
+
+  let template = ResponseTemplate::parse("{{HELLO}} {seq} N={nonce}").unwrap();
+  assert_eq!(template.render(6, Some(42)), "{HELLO} 6 N=42");
+  assert!(template.uses_nonce());
+}
+
+#[test]
+fn invalid_templates_are_rejected_when_parsed() {
+  for (template, reason) in [
+    ("HELLO", "missing '{seq}'"),
+    ("HELLO {sequence}", "unknown placeholder '{sequence}'"),
+    ("HELLO {seq", "unclosed '{'"),
+    ("HELLO {seq} }", "unmatched '}'"),
+  ] {
+    match ResponseTemplate::parse(template) {
+      Err(HandshakeError::InvalidResponseTemplate {
+        template: rejected,
+        reason: actual,
+      }) => {
+        assert_eq!(rejected, template);
+        assert_eq!(actual, reason, "{template}");
+      }
+      other => panic!("{template}: expected a template error, got {other:?}"),
+    }
+  }
+}
+
+#[test]
+fn client_accepts_reply_with_extra_whitespace() {
+  let outcome = handshake_against("HELLO   {seq}").unwrap();
+  assert_eq!(outcome.final_seq, 7);
+}
+
+#[test]
+fn nonce_placeholder_must_be_echoed() {
+  // The client parses the templated nonce and echoes it in HELLO Z
+  let outcome = handshake_against("HELLO {seq} N={nonce}").unwrap();
+  assert_eq!(outcome.final_seq, 7);
+}
+
+#[test]
+fn client_rejects_reply_with_unknown_field() {
+  let error = handshake_against("HELLO {seq} V=2").unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::InvalidMessageFormat { .. }),
+    "{error}"
+  );
+}
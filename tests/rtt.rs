@@ -0,0 +1,67 @@
+/**
+ * Client round-trip time tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::net::TcpStream;
+use std::process::Command;
+use std::time::Duration;
+
+use tcp_handshake::{
+  ServerModel, perform_async_client_handshake_with_config, perform_client_handshake_with_config,
+  spawn_server,
+};
+
+use common::quiet;
+
+#[test]
+fn sync_client_measures_the_round_trip() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+
+  let outcome = perform_client_handshake_with_config(&mut stream, 10, &quiet()).unwrap();
+  assert!(outcome.rtt > Duration::ZERO, "{:?}", outcome.rtt);
+  // The round trip is part of the handshake
+  assert!(outcome.rtt <= outcome.duration, "{outcome:?}");
+
+  drop(stream);
+  server.stop();
+}
+
+#[tokio::test]
+async fn async_client_measures_the_round_trip() {
+  let server = spawn_server(ServerModel::Async, quiet()).unwrap();
+  let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+
+  let outcome = perform_async_client_handshake_with_config(&mut stream, 10, &quiet())
+    .await
+    .unwrap();
+  assert!(outcome.rtt > Duration::ZERO, "{:?}", outcome.rtt);
+  assert!(outcome.rtt <= outcome.duration, "{outcome:?}");
+
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn both_clients_report_the_round_trip_on_stderr() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  for binary in [
+    env!("CARGO_BIN_EXE_client-sync"),
+    env!("CARGO_BIN_EXE_client-async"),
+  ] {
+    let output = Command::new(binary)
+      .args(["127.0.0.1", &server.addr.port().to_string(), "10"])
+      .output()
+      .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{binary}: {stderr}");
+    assert!(stderr.contains("Round-trip time: "), "{binary}: {stderr}");
+    assert!(!stdout.contains("Round-trip time"), "{binary}: {stdout}");
+  }
+  server.stop();
+}
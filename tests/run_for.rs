@@ -0,0 +1,79 @@
+/**
+ * Server --run-for tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Runs the real server binaries on an ephemeral port and checks that each
+ * one shuts itself down through the normal path once its run time is up.
+ */
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use tcp_handshake::parse_duration;
+
+const RUN_FOR: Duration = Duration::from_millis(500);
+// Covers process startup and the shutdown itself
+const GRACE: Duration = Duration::from_secs(5);
+
+fn assert_exits_within_window(binary: &str) {
+  let started = Instant::now();
+  let output = Command::new(binary)
+    .args(["0", "--run-for", "500ms"])
+    .stdin(Stdio::null())
+    .output()
+    .expect("server binary runs");
+  let elapsed = started.elapsed();
+
+  assert!(
+    output.status.success(),
+    "{binary} exited with {}",
+    output.status
+  );
+  assert!(elapsed >= RUN_FOR, "{binary} exited after {elapsed:?}");
+  assert!(
+    elapsed < RUN_FOR + GRACE,
+    "{binary} exited after {elapsed:?}"
+  );
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  assert!(stdout.contains("Run time of 500ms elapsed"), "{stdout}");
+  // Exactly one shutdown report, as a JSON line
+  let reports: Vec<&str> = stdout
+    .lines()
+    .filter(|line| line.starts_with("{\"event\":\"server_stopped\""))
+    .collect();
+  assert_eq!(reports.len(), 1, "{stdout}");
+  assert!(reports[0].contains("\"accepted\":0,"), "{stdout}");
+  assert!(reports[0].contains("\"clean\":true"), "{stdout}");
+}
+
+#[test]
+fn sequential_server_exits_after_run_time() {
+  assert_exits_within_window(env!("CARGO_BIN_EXE_server-sequential"));
+}
+
+#[test]
+fn threaded_server_exits_after_run_time() {
+  assert_exits_within_window(env!("CARGO_BIN_EXE_server-threaded"));
+}
+
+#[test]
+fn threadpool_server_exits_after_run_time() {
+  assert_exits_within_window(env!("CARGO_BIN_EXE_server-threadpool"));
+}
+
+#[test]
+fn async_server_exits_after_run_time() {
+  assert_exits_within_window(env!("CARGO_BIN_EXE_server-async"));
+}
+
+#[test]
+fn parses_run_time_units() {
+  assert_eq!(parse_duration("10s").unwrap(), Duration::from_secs(10));
+  assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+  assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+  assert_eq!(parse_duration("7").unwrap(), Duration::from_secs(7));
+  for invalid in ["", "s", "10h", "-1s", "1.5s"] {
+    assert!(parse_duration(invalid).is_err(), "{invalid:?}");
+  }
+}
@@ -0,0 +1,72 @@
+/**
+ * Async accept loop tests for a runtime that shuts down mid-accept
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::net::TcpListener as StdTcpListener;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{ServerMetrics, ServerOptions, live_config, serve_async};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+use common::quiet;
+
+#[test]
+fn loop_returns_when_the_listener_runtime_shuts_down() {
+  let listener_runtime = Runtime::new().unwrap();
+  let listener = listener_runtime
+    .block_on(TcpListener::bind("127.0.0.1:0"))
+    .unwrap();
+  let metrics = Arc::new(ServerMetrics::new());
+
+  // The loop runs on its own runtime and is never asked to stop
+  let (done, finished) = mpsc::channel();
+  let loop_metrics = Arc::clone(&metrics);
+  thread::spawn(move || {
+    let summary = Runtime::new().unwrap().block_on(serve_async(
+      listener,
+      live_config(quiet()),
+      loop_metrics,
+      ServerOptions::default(),
+      std::future::pending(),
+    ));
+    let _ = done.send(summary);
+  });
+
+  // Let the loop park in accept, then take the listener's runtime away
+  thread::sleep(Duration::from_millis(100));
+  drop(listener_runtime);
+
+  let summary = finished
+    .recv_timeout(Duration::from_secs(5))
+    .expect("accept loop kept running after its listener's runtime shut down");
+  assert_eq!(summary.accepted, 0);
+  assert_eq!(summary.force_closed, 0);
+  assert_eq!(metrics.snapshot().accepted, 0);
+}
+
+#[test]
+fn shutting_down_the_serving_runtime_closes_the_listener() {
+  let runtime = Runtime::new().unwrap();
+  let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+  let addr = listener.local_addr().unwrap();
+  runtime.spawn(serve_async(
+    listener,
+    live_config(quiet()),
+    Arc::new(ServerMetrics::new()),
+    ServerOptions::default(),
+    std::future::pending(),
+  ));
+
+  thread::sleep(Duration::from_millis(100));
+  runtime.shutdown_timeout(Duration::from_secs(5));
+
+  // The dropped loop released its port
+  StdTcpListener::bind(addr).expect("listener outlived the runtime");
+}
@@ -0,0 +1,100 @@
+/**
+ * Sequence policy tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, IncrementPolicy, SequencePolicy, ServerModel,
+  perform_client_handshake_with_config, perform_server_handshake_with_config, spawn_server,
+};
+
+use common::quiet;
+
+/**
+ * Steps by two instead of one
+ */
+#[derive(Debug)]
+struct PlusTwo;
+
+impl SequencePolicy for PlusTwo {
+  fn next(&self, prev: i32) -> Option<i32> {
+    prev.checked_add(2)
+  }
+}
+
+fn plus_two() -> HandshakeConfig {
+  HandshakeConfig {
+    sequence_policy: Arc::new(PlusTwo),
+    ..quiet()
+  }
+}
+
+#[test]
+fn both_sides_stepping_by_two_complete() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, plus_two()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    let outcome = perform_client_handshake_with_config(&mut stream, 10, &plus_two()).unwrap();
+    assert_eq!(outcome.initial_seq, 10, "{model}");
+    assert_eq!(outcome.final_seq, 14, "{model}");
+    drop(stream);
+    server.stop();
+  }
+}
+
+#[test]
+fn a_client_with_the_default_policy_rejects_a_plus_two_server() {
+  let server = spawn_server(ServerModel::Async, plus_two()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  let result = perform_client_handshake_with_config(&mut stream, 10, &quiet());
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::SequenceMismatch {
+        expected: 11,
+        received: 12
+      })
+    ),
+    "{result:?}"
+  );
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn the_default_policy_has_no_next_sequence_after_i32_max() {
+  assert_eq!(IncrementPolicy.next(i32::MAX - 1), Some(i32::MAX));
+  assert_eq!(IncrementPolicy.next(i32::MAX), None);
+  assert_eq!(PlusTwo.next(i32::MAX - 1), None);
+}
+
+#[test]
+fn a_server_rejects_hello_x_at_i32_max() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+      .write_all(format!("HELLO {}", i32::MAX).as_bytes())
+      .unwrap();
+    stream
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let result = perform_server_handshake_with_config(&mut stream, &quiet());
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::SequenceOverflow { seq: i32::MAX })
+    ),
+    "{result:?}"
+  );
+  drop(client.join().unwrap());
+}
@@ -0,0 +1,73 @@
+/**
+ * Echo session limit tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  Delimiter, HandshakeConfig, HandshakeError, Result, SessionStats, run_async_echo_session,
+};
+
+/**
+ * Sends `messages` newline-framed into an echo session and returns how it ended
+ * along with everything echoed back
+ */
+async fn run_session(messages: &[&str], config: HandshakeConfig) -> (Result<SessionStats>, String) {
+  let (mut client, mut server) = duplex(4096);
+  for message in messages {
+    client
+      .write_all(format!("{message}\n").as_bytes())
+      .await
+      .unwrap();
+  }
+
+  let ended = run_async_echo_session(&mut server, "duplex peer", &config).await;
+  drop(server);
+  let mut echoed = String::new();
+  client.read_to_string(&mut echoed).await.unwrap();
+  (ended, echoed)
+}
+
+fn session_config() -> HandshakeConfig {
+  HandshakeConfig {
+    delimiter: Delimiter::Byte(b'\n'),
+    silent: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+#[tokio::test]
+async fn the_message_limit_ends_the_session() {
+  let config = HandshakeConfig {
+    max_session_messages: 2,
+    ..session_config()
+  };
+  let (ended, echoed) = run_session(&["one", "two", "three"], config).await;
+
+  match ended {
+    Err(HandshakeError::SessionLimitExceeded { limit, value, max }) => {
+      assert_eq!((limit, value, max), ("messages", 3, 2));
+    }
+    other => panic!("expected SessionLimitExceeded, got {other:?}"),
+  }
+  // Messages within the limit were echoed; the one past it was not
+  assert_eq!(echoed, "one\ntwo\n");
+}
+
+#[tokio::test]
+async fn the_byte_limit_ends_the_session() {
+  let config = HandshakeConfig {
+    max_session_bytes: 10,
+    ..session_config()
+  };
+  let (ended, echoed) = run_session(&["hello", "world", "again"], config).await;
+
+  match ended {
+    Err(HandshakeError::SessionLimitExceeded { limit, value, max }) => {
+      assert_eq!((limit, value, max), ("bytes", 15, 10));
+    }
+    other => panic!("expected SessionLimitExceeded, got {other:?}"),
+  }
+  assert_eq!(echoed, "hello\nworld\n");
+}
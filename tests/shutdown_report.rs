@@ -0,0 +1,104 @@
+/**
+ * Shutdown report tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Write;
+use std::net::TcpStream;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{
+  HandshakeConfig, ReportOnExit, RunReport, ServerMetrics, ServerModel,
+  perform_client_handshake_with_config, spawn_server,
+};
+
+/**
+ * A report guard that collects every emitted report
+ */
+fn collecting_guard(metrics: &Arc<ServerMetrics>) -> (ReportOnExit, Arc<Mutex<Vec<String>>>) {
+  let lines = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&lines);
+  let guard = ReportOnExit::new(Arc::clone(metrics), move |report| {
+    sink.lock().unwrap().push(report.to_string());
+  });
+  (guard, lines)
+}
+
+#[test]
+fn report_accounts_for_a_short_run() {
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, HandshakeConfig::default()).unwrap();
+    let (guard, lines) = collecting_guard(&server.metrics);
+
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    stream.write_all(b"GOODBYE").unwrap();
+    drop(stream);
+
+    // The threaded server leaves handler threads running after it stops
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while RunReport::from_metrics(&server.metrics).failed == 0 {
+      assert!(
+        Instant::now() < deadline,
+        "{model}: second connection never finished"
+      );
+      thread::sleep(Duration::from_millis(10));
+    }
+
+    let metrics = Arc::clone(&server.metrics);
+    server.stop();
+    drop(guard);
+
+    let report = RunReport::from_metrics(&metrics);
+    assert_eq!(report.accepted, 2, "{model}");
+    assert_eq!(report.succeeded, 1, "{model}");
+    assert_eq!(report.failed, 1, "{model}");
+    assert_eq!(report.timed_out, 0, "{model}");
+    assert!(report.peak_concurrency >= 1, "{model}");
+    // HELLO 5, HELLO 7 and GOODBYE in; HELLO 6 out
+    assert_eq!(report.bytes_received, 21, "{model}");
+    assert_eq!(report.bytes_sent, 7, "{model}");
+    assert!(report.is_clean(), "{model}");
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines.len(), 1, "{model}");
+    assert!(
+      lines[0].starts_with("{\"event\":\"server_stopped\",\"uptime_ms\":"),
+      "{}",
+      lines[0]
+    );
+    assert!(
+      lines[0].contains("\"succeeded\":1,\"failed\":1,\"timed_out\":0,"),
+      "{}",
+      lines[0]
+    );
+    assert!(
+      lines[0].ends_with("\"force_closed\":0,\"clean\":true}"),
+      "{}",
+      lines[0]
+    );
+  }
+}
+
+#[test]
+fn report_is_emitted_once_even_when_the_run_fails() {
+  let metrics = Arc::new(ServerMetrics::new());
+  let (guard, lines) = collecting_guard(&metrics);
+  guard.emit();
+  guard.emit();
+  drop(guard);
+  assert_eq!(lines.lock().unwrap().len(), 1);
+
+  // A server thread that panics still reports on the way out
+  let (guard, lines) = collecting_guard(&metrics);
+  let result = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+    let _guard = guard;
+    panic!("accept loop failed");
+  }));
+  assert!(result.is_err());
+  assert_eq!(lines.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,84 @@
+#![cfg(unix)]
+/**
+ * Ctrl-C shutdown tests for the server binaries
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Runs each real server binary, completes one handshake against it, sends
+ * SIGINT and checks that the server stops accepting, prints its run report
+ * and exits successfully.
+ */
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+use tcp_handshake::{HandshakeConfig, perform_client_handshake_with_config};
+
+fn assert_exits_cleanly_on_sigint(binary: &str) {
+  let mut child = Command::new(binary)
+    .arg("0")
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .expect("server binary runs");
+
+  // The handler is installed before the loop starts, and the listener announces its port
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+  let mut line = String::new();
+  while !line.to_lowercase().contains("listening on") {
+    line.clear();
+    assert_ne!(
+      stdout.read_line(&mut line).unwrap(),
+      0,
+      "{binary} never listened"
+    );
+  }
+  let port = line.trim().rsplit(':').next().unwrap();
+
+  let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+  let client = HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  perform_client_handshake_with_config(&mut stream, 10, &client).unwrap();
+  drop(stream);
+
+  let status = Command::new("kill")
+    .args(["-INT", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let mut rest = String::new();
+  stdout.read_to_string(&mut rest).unwrap();
+  let output = child.wait_with_output().unwrap();
+  assert!(
+    output.status.success(),
+    "{binary} exited with {}",
+    output.status
+  );
+  assert!(rest.contains("Received Ctrl-C"), "{rest}");
+  assert!(rest.contains("\"event\":\"server_stopped\""), "{rest}");
+  assert!(rest.contains("\"succeeded\":1,"), "{rest}");
+}
+
+#[test]
+fn sequential_server_exits_cleanly_on_sigint() {
+  assert_exits_cleanly_on_sigint(env!("CARGO_BIN_EXE_server-sequential"));
+}
+
+#[test]
+fn threaded_server_exits_cleanly_on_sigint() {
+  assert_exits_cleanly_on_sigint(env!("CARGO_BIN_EXE_server-threaded"));
+}
+
+#[test]
+fn threadpool_server_exits_cleanly_on_sigint() {
+  assert_exits_cleanly_on_sigint(env!("CARGO_BIN_EXE_server-threadpool"));
+}
+
+#[test]
+fn async_server_exits_cleanly_on_sigint() {
+  assert_exits_cleanly_on_sigint(env!("CARGO_BIN_EXE_server-async"));
+}
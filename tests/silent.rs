@@ -0,0 +1,97 @@
+/**
+ * Silent mode tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * The test harness captures `println!` inside tests, so the handshakes run in
+ * a child copy of this test binary with capturing off, and the parent checks
+ * what the child actually wrote to its stdout and stderr.
+ */
+use std::env;
+use std::io::Write;
+use std::net::TcpStream;
+use std::process::{self, Command};
+
+use tcp_handshake::{
+  HandshakeConfig, ServerModel, perform_client_handshake_with_config, spawn_server,
+};
+
+// Set in the child process to the value of `silent` it should run with
+const CHILD_ENV: &str = "TCP_HANDSHAKE_SILENT_CHILD";
+
+/**
+ * Runs `handshakes_in_child` in a child process, returning its stdout and stderr
+ */
+fn run_child(silent: bool) -> (String, String) {
+  let output = Command::new(env::current_exe().unwrap())
+    .args([
+      "--exact",
+      "handshakes_in_child",
+      "--nocapture",
+      "--test-threads=1",
+    ])
+    .env(CHILD_ENV, silent.to_string())
+    .output()
+    .expect("test binary runs");
+  assert!(
+    output.status.success(),
+    "child exited with {}",
+    output.status
+  );
+  (
+    String::from_utf8_lossy(&output.stdout).into_owned(),
+    String::from_utf8_lossy(&output.stderr).into_owned(),
+  )
+}
+
+/**
+ * Only does anything when started by `run_child`; exits before the harness reports the result
+ */
+#[test]
+fn handshakes_in_child() {
+  let Ok(silent) = env::var(CHILD_ENV) else {
+    return;
+  };
+  let config = HandshakeConfig {
+    silent: silent == "true",
+    ..HandshakeConfig::default()
+  };
+
+  for model in ServerModel::ALL {
+    let server = spawn_server(model, config.clone()).unwrap();
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 5, &config).unwrap();
+
+    // A rejected first message exercises the error paths too
+    let rejected = HandshakeConfig {
+      raw_first_message: Some("BOGUS".to_string()),
+      ..config.clone()
+    };
+    let mut stream = TcpStream::connect(server.addr).unwrap();
+    assert!(perform_client_handshake_with_config(&mut stream, 5, &rejected).is_err());
+    server.stop();
+  }
+
+  std::io::stdout().flush().unwrap();
+  process::exit(0);
+}
+
+#[test]
+fn silent_mode_writes_nothing() {
+  let (stdout, stderr) = run_child(true);
+  // The harness announces the child test; nothing may follow it
+  let (_, after) = stdout
+    .split_once("test handshakes_in_child ... ")
+    .expect("child test announced");
+  assert_eq!(after, "", "{stdout}");
+  assert_eq!(stderr, "");
+}
+
+#[test]
+fn default_mode_still_prints() {
+  let (stdout, _) = run_child(false);
+  assert!(
+    stdout.contains("Handshake completed successfully"),
+    "{stdout}"
+  );
+}
@@ -0,0 +1,249 @@
+/**
+ * SOCKS5 tunneling tests against an in-process proxy stub
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ServerModel, Socks5Credentials, Socks5Proxy, connect_async,
+  connect_sync, perform_async_client_handshake_with_config, perform_client_handshake_with_config,
+  spawn_server,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, copy_bidirectional};
+use tokio::net::{TcpListener, TcpStream};
+
+/**
+ * How the stub proxy behaves
+ */
+#[derive(Clone, Copy)]
+struct StubPolicy {
+  // Username and password the proxy demands, if any
+  credentials: Option<(&'static str, &'static str)>,
+  // Reply code for CONNECT; anything but 0 refuses it
+  connect_reply: u8,
+}
+
+const OPEN: StubPolicy = StubPolicy {
+  credentials: None,
+  connect_reply: 0,
+};
+
+/**
+ * Minimal SOCKS5 proxy that records each CONNECT target as `host:port`
+ */
+async fn stub_proxy(policy: StubPolicy) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let targets = Arc::new(Mutex::new(Vec::new()));
+  let recorded = Arc::clone(&targets);
+
+  tokio::spawn(async move {
+    while let Ok((client, _)) = listener.accept().await {
+      let targets = Arc::clone(&targets);
+      tokio::spawn(async move {
+        let _ = serve_proxy_client(client, policy, targets).await;
+      });
+    }
+  });
+  (addr, recorded)
+}
+
+async fn serve_proxy_client(
+  mut client: TcpStream,
+  policy: StubPolicy,
+  targets: Arc<Mutex<Vec<String>>>,
+) -> std::io::Result<()> {
+  // Greeting: version, method count, methods
+  let mut header = [0u8; 2];
+  client.read_exact(&mut header).await?;
+  let mut methods = vec![0u8; header[1] as usize];
+  client.read_exact(&mut methods).await?;
+
+  match policy.credentials {
+    Some((username, password)) => {
+      if !methods.contains(&0x02) {
+        return client.write_all(&[0x05, 0xff]).await;
+      }
+      client.write_all(&[0x05, 0x02]).await?;
+      let mut version_and_len = [0u8; 2];
+      client.read_exact(&mut version_and_len).await?;
+      let mut user = vec![0u8; version_and_len[1] as usize];
+      client.read_exact(&mut user).await?;
+      let mut len = [0u8; 1];
+      client.read_exact(&mut len).await?;
+      let mut pass = vec![0u8; len[0] as usize];
+      client.read_exact(&mut pass).await?;
+      if user != username.as_bytes() || pass != password.as_bytes() {
+        return client.write_all(&[0x01, 0x01]).await;
+      }
+      client.write_all(&[0x01, 0x00]).await?;
+    }
+    None => client.write_all(&[0x05, 0x00]).await?,
+  }
+
+  // CONNECT request: version, command, reserved, address type, address, port
+  let mut request = [0u8; 4];
+  client.read_exact(&mut request).await?;
+  let host = match request[3] {
+    0x01 => {
+      let mut ip = [0u8; 4];
+      client.read_exact(&mut ip).await?;
+      std::net::Ipv4Addr::from(ip).to_string()
+    }
+    0x03 => {
+      let mut len = [0u8; 1];
+      client.read_exact(&mut len).await?;
+      let mut name = vec![0u8; len[0] as usize];
+      client.read_exact(&mut name).await?;
+      String::from_utf8_lossy(&name).into_owned()
+    }
+    _ => {
+      let mut ip = [0u8; 16];
+      client.read_exact(&mut ip).await?;
+      std::net::Ipv6Addr::from(ip).to_string()
+    }
+  };
+  let port = client.read_u16().await?;
+  targets.lock().unwrap().push(format!("{host}:{port}"));
+
+  let refused = [0x05, policy.connect_reply, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+  if policy.connect_reply != 0 {
+    return client.write_all(&refused).await;
+  }
+  let mut upstream = TcpStream::connect((host.as_str(), port)).await?;
+  client
+    .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0, 0])
+    .await?;
+  copy_bidirectional(&mut client, &mut upstream).await?;
+  Ok(())
+}
+
+fn proxied_config(proxy: SocketAddr, credentials: Option<(&str, &str)>) -> HandshakeConfig {
+  HandshakeConfig {
+    socks5_proxy: Some(Socks5Proxy {
+      host: proxy.ip().to_string(),
+      port: proxy.port(),
+      credentials: credentials.map(|(username, password)| Socks5Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+      }),
+    }),
+    ..HandshakeConfig::default()
+  }
+}
+
+#[test]
+fn proxy_spec_is_parsed() {
+  let proxy = Socks5Proxy::parse("alice:s3cret@[::1]:1080").unwrap();
+  assert_eq!(proxy.host, "::1");
+  assert_eq!(proxy.port, 1080);
+  assert_eq!(proxy.credentials.as_ref().unwrap().username, "alice");
+  assert_eq!(proxy.to_string(), "[::1]:1080");
+  // The password never shows up in debug output
+  assert!(!format!("{proxy:?}").contains("s3cret"));
+
+  assert_eq!(
+    Socks5Proxy::parse("proxy.local:9050").unwrap().host,
+    "proxy.local"
+  );
+  assert!(Socks5Proxy::parse("proxy.local").is_err());
+  assert!(Socks5Proxy::parse("alice@proxy.local:9050").is_err());
+}
+
+#[tokio::test]
+async fn async_handshake_runs_through_open_proxy() {
+  let server = spawn_server(ServerModel::Async, HandshakeConfig::default()).unwrap();
+  let (proxy, targets) = stub_proxy(OPEN).await;
+  let config = proxied_config(proxy, None);
+
+  // The host name is handed to the proxy unresolved
+  let mut stream = connect_async("localhost", server.addr.port(), &config)
+    .await
+    .unwrap();
+  let outcome = perform_async_client_handshake_with_config(&mut stream, 8, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 10);
+  assert_eq!(
+    *targets.lock().unwrap(),
+    [format!("localhost:{}", server.addr.port())]
+  );
+
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_handshake_authenticates_with_username_and_password() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let policy = StubPolicy {
+    credentials: Some(("alice", "s3cret")),
+    ..OPEN
+  };
+  let (proxy, targets) = stub_proxy(policy).await;
+  let config = proxied_config(proxy, Some(("alice", "s3cret")));
+
+  let port = server.addr.port();
+  let outcome = tokio::task::spawn_blocking(move || {
+    let mut stream = connect_sync("127.0.0.1", port, &config)?;
+    perform_client_handshake_with_config(&mut stream, 8, &config)
+  })
+  .await
+  .unwrap()
+  .unwrap();
+  assert_eq!(outcome.final_seq, 10);
+  assert_eq!(*targets.lock().unwrap(), [format!("127.0.0.1:{port}")]);
+
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn wrong_password_is_reported() {
+  let policy = StubPolicy {
+    credentials: Some(("alice", "s3cret")),
+    ..OPEN
+  };
+  let (proxy, targets) = stub_proxy(policy).await;
+
+  let error = connect_async(
+    "127.0.0.1",
+    9,
+    &proxied_config(proxy, Some(("alice", "guess"))),
+  )
+  .await
+  .unwrap_err();
+  assert!(
+    matches!(&error, HandshakeError::Socks5(reason) if reason.contains("username or password")),
+    "{error}"
+  );
+  assert!(targets.lock().unwrap().is_empty());
+
+  // Without credentials the proxy accepts none of the offered methods
+  let error = connect_async("127.0.0.1", 9, &proxied_config(proxy, None))
+    .await
+    .unwrap_err();
+  assert!(matches!(error, HandshakeError::Socks5(_)), "{error}");
+}
+
+#[tokio::test]
+async fn refused_connect_is_reported() {
+  let policy = StubPolicy {
+    connect_reply: 0x05,
+    ..OPEN
+  };
+  let (proxy, _) = stub_proxy(policy).await;
+
+  let error = connect_async("127.0.0.1", 9, &proxied_config(proxy, None))
+    .await
+    .unwrap_err();
+  assert!(
+    matches!(&error, HandshakeError::Socks5(reason) if reason.contains("connection refused")),
+    "{error}"
+  );
+}
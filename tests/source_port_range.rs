@@ -0,0 +1,86 @@
+/**
+ * Client source port range tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::net::TcpListener;
+use std::ops::RangeInclusive;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ServerModel, connect_async, connect_sync,
+  perform_async_client_handshake_with_config, perform_client_handshake_with_config, spawn_server,
+};
+
+fn ranged_config(range: RangeInclusive<u16>) -> HandshakeConfig {
+  HandshakeConfig {
+    bind_addr: Some("127.0.0.1:0".parse().unwrap()),
+    source_port_range: Some(range),
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Holds a loopback port; the ports right after it make up the test range
+ */
+fn taken_port() -> (TcpListener, u16) {
+  let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+  let port = taken.local_addr().unwrap().port();
+  (taken, port)
+}
+
+#[test]
+fn sync_client_skips_a_taken_port() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let (_taken, first) = taken_port();
+  let config = ranged_config(first..=first + 5);
+
+  let mut stream = connect_sync("127.0.0.1", server.addr.port(), &config).unwrap();
+  let source = stream.local_addr().unwrap().port();
+  assert!(
+    source > first && source <= first + 5,
+    "source port {source}"
+  );
+
+  let outcome = perform_client_handshake_with_config(&mut stream, 5, &config).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  server.stop();
+}
+
+#[tokio::test]
+async fn async_client_skips_a_taken_port() {
+  let server = spawn_server(ServerModel::Async, HandshakeConfig::default()).unwrap();
+  let (_taken, first) = taken_port();
+  let config = ranged_config(first..=first + 5);
+
+  let mut stream = connect_async("127.0.0.1", server.addr.port(), &config)
+    .await
+    .unwrap();
+  let source = stream.local_addr().unwrap().port();
+  assert!(
+    source > first && source <= first + 5,
+    "source port {source}"
+  );
+
+  let outcome = perform_async_client_handshake_with_config(&mut stream, 5, &config)
+    .await
+    .unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  drop(stream);
+  tokio::task::spawn_blocking(move || server.stop())
+    .await
+    .unwrap();
+}
+
+#[test]
+fn exhausted_range_is_reported() {
+  let server = spawn_server(ServerModel::Sequential, HandshakeConfig::default()).unwrap();
+  let (_taken, port) = taken_port();
+
+  let error =
+    connect_sync("127.0.0.1", server.addr.port(), &ranged_config(port..=port)).unwrap_err();
+  assert!(
+    matches!(error, HandshakeError::SourcePortsExhausted { first, last } if first == port && last == port),
+    "{error}"
+  );
+  server.stop();
+}
@@ -0,0 +1,66 @@
+#![cfg(unix)]
+/**
+ * SIGUSR1 state dump tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Runs the real server binaries, signals them once they listen and checks
+ * that the dump reaches stderr while the server keeps running to the end of
+ * its run time.
+ */
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Stdio};
+
+fn assert_dumps_on_sigusr1(binary: &str) {
+  let mut child = Command::new(binary)
+    .args(["0", "--run-for", "1s"])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .expect("server binary runs");
+
+  // The handler is installed before the listener announces itself
+  let mut stdout = BufReader::new(child.stdout.take().unwrap());
+  let mut line = String::new();
+  while !line.to_lowercase().contains("listening on") {
+    line.clear();
+    assert_ne!(
+      stdout.read_line(&mut line).unwrap(),
+      0,
+      "{binary} never listened"
+    );
+  }
+  let status = Command::new("kill")
+    .args(["-USR1", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let mut rest = String::new();
+  stdout.read_to_string(&mut rest).unwrap();
+  let output = child.wait_with_output().unwrap();
+  assert!(
+    output.status.success(),
+    "{binary} exited with {}",
+    output.status
+  );
+  // Still served out its run time after the dump
+  assert!(rest.contains("Run time of 1s elapsed"), "{rest}");
+
+  let stderr = String::from_utf8_lossy(&output.stderr);
+  assert!(stderr.contains("=== State dump ==="), "{stderr}");
+  assert!(stderr.contains("config: HandshakeConfig {"), "{stderr}");
+  assert!(stderr.contains("metrics: accepted=0 "), "{stderr}");
+  assert!(stderr.contains("=== End of state dump ==="), "{stderr}");
+}
+
+#[test]
+fn async_server_dumps_state_on_sigusr1() {
+  assert_dumps_on_sigusr1(env!("CARGO_BIN_EXE_server-async"));
+}
+
+#[test]
+fn threaded_server_dumps_state_on_sigusr1() {
+  assert_dumps_on_sigusr1(env!("CARGO_BIN_EXE_server-threaded"));
+}
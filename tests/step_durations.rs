@@ -0,0 +1,90 @@
+/**
+ * Per-step handshake duration tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{
+  EventSink, HandshakeConfig, HandshakeOutcome, ServerMetrics,
+  perform_client_handshake_with_config, perform_server_handshake_with_config,
+};
+
+// Slack for work between steps, such as parsing and logging
+const TOLERANCE: Duration = Duration::from_millis(50);
+
+fn assert_steps_add_up(outcome: &HandshakeOutcome) {
+  assert!(
+    outcome.step_durations.iter().all(|step| !step.is_zero()),
+    "unpopulated step: {outcome:?}"
+  );
+  let sum: Duration = outcome.step_durations.iter().sum();
+  assert!(
+    sum <= outcome.duration,
+    "steps exceed the total: {outcome:?}"
+  );
+  assert!(
+    outcome.duration - sum < TOLERANCE,
+    "steps miss part of the total: {outcome:?}"
+  );
+}
+
+#[test]
+fn every_step_is_timed_and_the_steps_add_up() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 5, &HandshakeConfig::default()).unwrap()
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let server = perform_server_handshake_with_config(&mut stream, &HandshakeConfig::default());
+  assert_steps_add_up(&server.unwrap());
+  assert_steps_add_up(&client.join().unwrap());
+}
+
+#[test]
+fn slow_client_shows_up_in_the_receive_steps() {
+  let elapsed = Arc::new(Mutex::new(Vec::new()));
+  let sink = Arc::clone(&elapsed);
+  let config = HandshakeConfig {
+    on_event: EventSink::new(move |event| sink.lock().unwrap().push(event.elapsed)),
+    ..HandshakeConfig::default()
+  };
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    stream.write_all(b"HELLO 5").unwrap();
+    let mut reply = [0u8; 7];
+    stream.read_exact(&mut reply).unwrap();
+    thread::sleep(Duration::from_millis(60));
+    stream.write_all(b"HELLO 7").unwrap();
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let outcome = perform_server_handshake_with_config(&mut stream, &config).unwrap();
+  client.join().unwrap();
+
+  let [hello_x, hello_y, hello_z] = outcome.step_durations;
+  assert!(hello_x >= Duration::from_millis(100), "{outcome:?}");
+  assert!(hello_y < TOLERANCE, "{outcome:?}");
+  assert!(hello_z >= Duration::from_millis(60), "{outcome:?}");
+  assert_steps_add_up(&outcome);
+  // Each event carries the duration of its step
+  assert_eq!(*elapsed.lock().unwrap(), outcome.step_durations);
+
+  let metrics = ServerMetrics::new();
+  metrics.record_step_durations(&outcome);
+  let snapshot = metrics.snapshot();
+  // Buckets are <1ms, <10ms, <100ms, <1000ms and >=1000ms
+  assert_eq!(snapshot.step_durations[0], [0, 0, 0, 1, 0]);
+  assert_eq!(snapshot.step_durations[1][..2].iter().sum::<u64>(), 1);
+  assert_eq!(snapshot.step_durations[2], [0, 0, 1, 0, 0]);
+}
@@ -0,0 +1,127 @@
+/**
+ * Step-by-step handshake tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tcp_handshake::{Handshake, HandshakeError, Role, ServerModel, StepStatus, spawn_server};
+
+use common::quiet;
+
+#[test]
+fn steps_through_a_full_handshake() {
+  let server = spawn_server(ServerModel::Threaded, quiet()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  stream
+    .set_read_timeout(Some(Duration::from_secs(5)))
+    .unwrap();
+
+  let mut handshake = Handshake::new(Role::Client { initial_seq: 10 }, quiet()).unwrap();
+
+  // Step 1 writes HELLO 10
+  assert_eq!(handshake.next_step(), Some(1));
+  assert!(handshake.wants_write());
+  assert!(matches!(
+    handshake.poll_step(&mut stream),
+    StepStatus::Pending
+  ));
+
+  // Step 2 reads and checks HELLO 11
+  assert_eq!(handshake.next_step(), Some(2));
+  assert!(!handshake.wants_write());
+  assert!(matches!(
+    handshake.poll_step(&mut stream),
+    StepStatus::Pending
+  ));
+
+  // Step 3 writes HELLO 12 and completes
+  assert_eq!(handshake.next_step(), Some(3));
+  assert!(handshake.wants_write());
+  let outcome = match handshake.poll_step(&mut stream) {
+    StepStatus::Complete(outcome) => outcome,
+    other => panic!("expected completion, got {other:?}"),
+  };
+  assert_eq!(outcome.initial_seq, 10);
+  assert_eq!(outcome.final_seq, 12);
+  assert_eq!(handshake.next_step(), None);
+
+  // A finished handshake has nothing left to do
+  assert!(matches!(
+    handshake.poll_step(&mut stream),
+    StepStatus::Error(HandshakeError::ProtocolViolation(_))
+  ));
+
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn steps_over_a_non_blocking_stream() {
+  let server = spawn_server(ServerModel::Async, quiet()).unwrap();
+  let mut stream = TcpStream::connect(server.addr).unwrap();
+  stream.set_nonblocking(true).unwrap();
+
+  let mut handshake = Handshake::new(Role::Client { initial_seq: 500 }, quiet()).unwrap();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let outcome = loop {
+    match handshake.poll_step(&mut stream) {
+      StepStatus::Pending => {
+        assert!(Instant::now() < deadline, "handshake stalled");
+        // A real event loop would wait for readiness here
+        thread::sleep(Duration::from_millis(1));
+      }
+      StepStatus::Complete(outcome) => break outcome,
+      StepStatus::Error(e) => panic!("handshake failed: {e}"),
+    }
+  };
+  assert_eq!(outcome.final_seq, 502);
+
+  drop(stream);
+  server.stop();
+}
+
+#[test]
+fn a_wrong_reply_ends_in_an_error() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let peer = thread::spawn(move || {
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buffer = [0u8; 64];
+    let _ = stream.read(&mut buffer).unwrap();
+    stream.write_all(b"HELLO 99").unwrap();
+  });
+
+  let mut stream = TcpStream::connect(addr).unwrap();
+  stream
+    .set_read_timeout(Some(Duration::from_secs(5)))
+    .unwrap();
+  let mut handshake = Handshake::new(Role::Client { initial_seq: 10 }, quiet()).unwrap();
+  assert!(matches!(
+    handshake.poll_step(&mut stream),
+    StepStatus::Pending
+  ));
+  assert!(matches!(
+    handshake.poll_step(&mut stream),
+    StepStatus::Error(HandshakeError::SequenceMismatch {
+      expected: 11,
+      received: 99
+    })
+  ));
+  assert_eq!(handshake.next_step(), None);
+
+  peer.join().unwrap();
+}
+
+#[test]
+fn the_server_role_is_not_supported_yet() {
+  assert!(matches!(
+    Handshake::new(Role::Server, quiet()),
+    Err(HandshakeError::InvalidArguments(_))
+  ));
+}
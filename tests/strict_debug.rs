@@ -0,0 +1,90 @@
+/**
+ * Runtime protocol invariant tests
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Test builds have debug assertions on, so a violated invariant panics.
+ */
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use tcp_handshake::{
+  HandshakeConfig, HandshakeError, ResponseTemplate, perform_client_handshake_with_config,
+  perform_server_handshake_with_config,
+};
+
+fn strict() -> HandshakeConfig {
+  HandshakeConfig {
+    strict_debug: true,
+    ..HandshakeConfig::default()
+  }
+}
+
+/**
+ * Serves one handshake with `config` on this thread while `client` runs on another
+ */
+fn serve_one(config: HandshakeConfig, client: impl FnOnce(TcpStream) + Send + 'static) {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  thread::spawn(move || client(TcpStream::connect(addr).unwrap()));
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let _ = perform_server_handshake_with_config(&mut stream, &config);
+}
+
+#[test]
+fn conforming_handshake_passes_every_check() {
+  let config = HandshakeConfig {
+    require_nonce: true,
+    namespace: Some("MYAPP".to_string()),
+    ..strict()
+  };
+  let client_config = config.clone();
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    perform_client_handshake_with_config(&mut stream, 5, &client_config)
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let outcome = perform_server_handshake_with_config(&mut stream, &config).unwrap();
+  assert_eq!(outcome.final_seq, 7);
+  assert_eq!(client.join().unwrap().unwrap().final_seq, 7);
+}
+
+#[test]
+fn a_peer_sequence_near_i32_max_is_an_error_not_a_violation() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let addr = listener.local_addr().unwrap();
+  let client = thread::spawn(move || {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let _ = stream.write_all(format!("HELLO {}", i32::MAX - 1).as_bytes());
+    stream
+  });
+
+  let (mut stream, _) = listener.accept().unwrap();
+  let result = perform_server_handshake_with_config(&mut stream, &strict());
+  assert!(
+    matches!(
+      result,
+      Err(HandshakeError::SequenceOverflow { seq: i32::MAX })
+    ),
+    "{result:?}"
+  );
+  drop(client.join().unwrap());
+}
+
+#[test]
+#[should_panic(expected = "sent 'HELLO 60' does not parse back as HELLO 6")]
+fn server_catches_a_reply_that_does_not_parse_back() {
+  // A template typo that glues a digit onto the sequence number
+  let config = HandshakeConfig {
+    response_template: Some(ResponseTemplate::parse("HELLO {seq}0").unwrap()),
+    ..strict()
+  };
+  serve_one(config, |mut stream| {
+    let _ = stream.write_all(b"HELLO 5");
+  });
+}
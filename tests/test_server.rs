@@ -0,0 +1,46 @@
+/**
+ * TestServer example for downstream crates
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Run with `cargo test --features testing`.
+ */
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+
+use tcp_handshake::{
+  HandshakeConfig, TestServer, perform_async_client_handshake_with_config, perform_client_handshake,
+};
+
+#[test]
+fn sync_client_completes_handshake() {
+  let server = TestServer::start().unwrap();
+
+  let stream = TcpStream::connect(server.addr()).unwrap();
+  let outcome = perform_client_handshake(stream, 41).unwrap();
+
+  assert_eq!(outcome.final_seq, 43);
+}
+
+#[tokio::test]
+async fn async_client_completes_handshake() {
+  let server = TestServer::start().unwrap();
+
+  let mut stream = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+  let config = HandshakeConfig::default();
+  let outcome = perform_async_client_handshake_with_config(&mut stream, 7, &config)
+    .await
+    .unwrap();
+
+  assert_eq!(outcome.final_seq, 9);
+  assert_eq!(server.metrics().accepted.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn dropping_the_handle_stops_the_server() {
+  let server = TestServer::start().unwrap();
+  let addr = server.addr();
+  drop(server);
+
+  assert!(TcpStream::connect(addr).is_err());
+}
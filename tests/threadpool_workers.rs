@@ -0,0 +1,73 @@
+/**
+ * Thread pool worker count tests
+ *
+ * Author: Sae-Hwan Park
+ */
+mod common;
+
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{
+  ServerModel, ServerOptions, perform_client_handshake_with_config, spawn_server_with_options,
+};
+
+use common::quiet;
+
+/**
+ * Connects a slow client and then a prompt one, returning the order their handshakes completed
+ * The slow client waits before sending HELLO X, holding whichever worker took it
+ */
+fn completion_order(addr: SocketAddr) -> Vec<&'static str> {
+  let completed = Arc::new(Mutex::new(Vec::new()));
+  let clients: Vec<_> = [
+    ("slow", Duration::from_millis(300)),
+    ("prompt", Duration::ZERO),
+  ]
+  .into_iter()
+  .map(|(name, delay)| {
+    // Connected here, so the server accepts the slow client first
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let completed = Arc::clone(&completed);
+    thread::spawn(move || {
+      thread::sleep(delay);
+      perform_client_handshake_with_config(&mut stream, 1, &quiet()).unwrap();
+      completed.lock().unwrap().push(name);
+    })
+  })
+  .collect();
+  for client in clients {
+    client.join().unwrap();
+  }
+  Arc::try_unwrap(completed).unwrap().into_inner().unwrap()
+}
+
+#[test]
+fn one_worker_completes_connections_in_accept_order() {
+  let options = ServerOptions {
+    workers: Some(1),
+    ..ServerOptions::default()
+  };
+  let server = spawn_server_with_options(ServerModel::ThreadPool, quiet(), options).unwrap();
+
+  // The prompt client waits in the queue until the slow one is done
+  assert_eq!(completion_order(server.addr), ["slow", "prompt"]);
+
+  server.stop();
+}
+
+#[test]
+fn many_workers_let_later_connections_finish_first() {
+  let options = ServerOptions {
+    workers: Some(4),
+    ..ServerOptions::default()
+  };
+  let server = spawn_server_with_options(ServerModel::ThreadPool, quiet(), options).unwrap();
+
+  // Another worker serves the prompt client while the slow one holds the first
+  assert_eq!(completion_order(server.addr), ["prompt", "slow"]);
+
+  server.stop();
+}
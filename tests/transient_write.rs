@@ -0,0 +1,104 @@
+/**
+ * Transient async write retry tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::{self, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tcp_handshake::config::DEFAULT_WRITE_RETRIES;
+use tcp_handshake::{HandshakeError, is_transient, write_message_to_async_stream};
+use tokio::io::AsyncWrite;
+
+/**
+ * Fails the first writes with the given errors, then accepts everything
+ */
+struct FlakyWriter {
+  failures: Vec<ErrorKind>,
+  attempts: usize,
+  written: Vec<u8>,
+}
+
+impl FlakyWriter {
+  fn new(failures: Vec<ErrorKind>) -> Self {
+    Self {
+      failures,
+      attempts: 0,
+      written: Vec::new(),
+    }
+  }
+}
+
+impl AsyncWrite for FlakyWriter {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    _: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    self.attempts += 1;
+    if !self.failures.is_empty() {
+      let kind = self.failures.remove(0);
+      return Poll::Ready(Err(kind.into()));
+    }
+    self.written.extend_from_slice(buf);
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+#[test]
+fn classifies_transient_and_permanent_errors() {
+  for kind in [
+    ErrorKind::WouldBlock,
+    ErrorKind::Interrupted,
+    ErrorKind::TimedOut,
+  ] {
+    assert!(is_transient(&kind.into()), "{kind:?}");
+  }
+  for kind in [
+    ErrorKind::BrokenPipe,
+    ErrorKind::ConnectionReset,
+    ErrorKind::NotConnected,
+  ] {
+    assert!(!is_transient(&kind.into()), "{kind:?}");
+  }
+}
+
+#[tokio::test]
+async fn retries_transient_error_once() {
+  let mut writer = FlakyWriter::new(vec![ErrorKind::WouldBlock]);
+  write_message_to_async_stream(&mut writer, "HELLO 5")
+    .await
+    .unwrap();
+  assert_eq!(writer.attempts, 2);
+  assert_eq!(writer.written, b"HELLO 5");
+}
+
+#[tokio::test]
+async fn permanent_error_fails_immediately() {
+  let mut writer = FlakyWriter::new(vec![ErrorKind::BrokenPipe]);
+  let err = write_message_to_async_stream(&mut writer, "HELLO 5")
+    .await
+    .unwrap_err();
+  assert!(matches!(err, HandshakeError::Io(e) if e.kind() == ErrorKind::BrokenPipe));
+  assert_eq!(writer.attempts, 1);
+}
+
+#[tokio::test]
+async fn gives_up_after_retry_limit() {
+  let failures = vec![ErrorKind::WouldBlock; DEFAULT_WRITE_RETRIES as usize + 1];
+  let mut writer = FlakyWriter::new(failures);
+  let err = write_message_to_async_stream(&mut writer, "HELLO 5")
+    .await
+    .unwrap_err();
+  assert!(matches!(err, HandshakeError::Io(e) if e.kind() == ErrorKind::WouldBlock));
+  assert_eq!(writer.attempts, DEFAULT_WRITE_RETRIES as usize + 1);
+}
@@ -0,0 +1,260 @@
+/**
+ * Protocol test vector suite
+ *
+ * Author: Sae-Hwan Park
+ *
+ * Runs every JSON vector in tests/vectors through the parser it names and
+ * compares the outcome, written out as JSON, with the vector's `expect`. See
+ * tests/vectors/README.md for the format.
+ */
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, duplex};
+
+use tcp_handshake::{
+  Capabilities, HandshakeConfig, HandshakeError, parse_hello_message, parse_hello_message_bytes,
+  parse_hello_with_capabilities, parse_hello_with_claimed_ip, parse_hello_with_nonce,
+  parse_hello_with_stream_id, perform_async_client_handshake_on, require_capabilities,
+  validate_hello_message, validate_nonce, verify_claimed_ip,
+};
+
+// Every error a message can be answered with; each needs at least one vector
+const MESSAGE_ERRORS: [&str; 9] = [
+  "InvalidMessageFormat",
+  "MalformedSequence",
+  "SequenceMismatch",
+  "EchoDetected",
+  "NonceMismatch",
+  "AddressMismatch",
+  "CapabilityMismatch",
+  "ServerBusy",
+  "ServerOverloaded",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Vector {
+  name: String,
+  parser: String,
+  input: Option<String>,
+  // Raw bytes for inputs that are not valid UTF-8
+  input_hex: Option<String>,
+  // `reply`: the X the client sent
+  initial_seq: Option<i32>,
+  // `final_nonce`: the nonce the server issued
+  issued_nonce: Option<u64>,
+  // `claimed_ip`: the address the client connected from
+  peer: Option<IpAddr>,
+  // `capabilities`: features that must be in the parsed set
+  required: Option<String>,
+  expect: Value,
+}
+
+impl Vector {
+  fn bytes(&self) -> Vec<u8> {
+    match (&self.input, &self.input_hex) {
+      (Some(input), None) => input.as_bytes().to_vec(),
+      (None, Some(hex)) => (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("input_hex is hex"))
+        .collect(),
+      _ => panic!("{}: needs exactly one of input and input_hex", self.name),
+    }
+  }
+}
+
+/**
+ * A parsed message as the vectors spell it: `seq` plus whichever fields were present
+ */
+fn fields(
+  seq: i32,
+  stream_id: Option<u32>,
+  nonce: Option<u64>,
+  claimed_ip: Option<IpAddr>,
+  capabilities: Option<Capabilities>,
+) -> Value {
+  let mut object = Map::new();
+  object.insert("seq".into(), json!(seq));
+  if let Some(stream_id) = stream_id {
+    object.insert("stream_id".into(), json!(stream_id));
+  }
+  if let Some(nonce) = nonce {
+    object.insert("nonce".into(), json!(nonce));
+  }
+  if let Some(ip) = claimed_ip {
+    object.insert("claimed_ip".into(), json!(ip.to_string()));
+  }
+  if let Some(capabilities) = capabilities {
+    object.insert("capabilities".into(), json!(capabilities.to_string()));
+  }
+  Value::Object(object)
+}
+
+/**
+ * An error as the vectors spell it: its variant name and the fields a port can reproduce
+ */
+fn error_value(error: &HandshakeError) -> Value {
+  match error {
+    HandshakeError::InvalidMessageFormat {
+      offset, expected, ..
+    } => json!({ "error": "InvalidMessageFormat", "offset": offset, "expected": expected }),
+    HandshakeError::MalformedSequence {
+      value,
+      offset,
+      expected,
+    } => json!({
+      "error": "MalformedSequence",
+      "value": value,
+      "offset": offset,
+      "expected": expected,
+    }),
+    HandshakeError::SequenceMismatch { expected, received } => {
+      json!({ "error": "SequenceMismatch", "expected": expected, "received": received })
+    }
+    HandshakeError::EchoDetected { seq } => json!({ "error": "EchoDetected", "seq": seq }),
+    HandshakeError::NonceMismatch { expected, received } => {
+      json!({ "error": "NonceMismatch", "expected": expected, "received": received })
+    }
+    HandshakeError::AddressMismatch { claimed, actual } => json!({
+      "error": "AddressMismatch",
+      "claimed": claimed.to_string(),
+      "actual": actual.to_string(),
+    }),
+    HandshakeError::CapabilityMismatch { missing, common } => json!({
+      "error": "CapabilityMismatch",
+      "missing": missing.to_string(),
+      "common": common.to_string(),
+    }),
+    HandshakeError::ServerBusy => json!({ "error": "ServerBusy" }),
+    HandshakeError::ServerOverloaded => json!({ "error": "ServerOverloaded" }),
+    other => panic!("no vector form for {other:?}"),
+  }
+}
+
+fn outcome(result: tcp_handshake::Result<Value>) -> Value {
+  result.unwrap_or_else(|e| error_value(&e))
+}
+
+/**
+ * Runs the client against a peer that answers HELLO X with `reply`
+ */
+async fn client_reply(initial_seq: i32, reply: &[u8]) -> Value {
+  let (client_end, mut server_end) = duplex(1024);
+  let server = async {
+    let mut hello = [0u8; 64];
+    let _ = server_end.read(&mut hello).await;
+    let _ = server_end.write_all(reply).await;
+    let mut rest = Vec::new();
+    let _ = server_end.read_to_end(&mut rest).await;
+  };
+  let config = HandshakeConfig {
+    silent: true,
+    ..HandshakeConfig::default()
+  };
+  let client = async {
+    let result = perform_async_client_handshake_on(client_end, initial_seq, &config).await;
+    // HELLO Z is Y + 1, so the reply's sequence is one less
+    result.map(|outcome| fields(outcome.final_seq - 1, None, None, None, None))
+  };
+  let (result, ()) = tokio::join!(client, server);
+  outcome(result)
+}
+
+async fn run(vector: &Vector) -> Value {
+  let bytes = vector.bytes();
+  let message = String::from_utf8_lossy(&bytes);
+  match vector.parser.as_str() {
+    "hello" => {
+      let from_text =
+        outcome(parse_hello_message(&message).map(|seq| fields(seq, None, None, None, None)));
+      let from_bytes =
+        outcome(parse_hello_message_bytes(&bytes).map(|seq| fields(seq, None, None, None, None)));
+      assert_eq!(
+        from_text, from_bytes,
+        "{}: bytes parser disagrees",
+        vector.name
+      );
+      from_text
+    }
+    "validate" => outcome(
+      validate_hello_message(&message)
+        .map(|m| fields(m.seq, m.stream_id, m.nonce, m.claimed_ip, m.capabilities)),
+    ),
+    "stream_id" => outcome(
+      parse_hello_with_stream_id(&message).map(|(seq, id)| fields(seq, id, None, None, None)),
+    ),
+    "nonce" => outcome(
+      parse_hello_with_nonce(&message).map(|(seq, nonce)| fields(seq, None, nonce, None, None)),
+    ),
+    "final_nonce" => {
+      let issued = vector.issued_nonce.expect("final_nonce needs issued_nonce");
+      outcome(parse_hello_with_nonce(&message).and_then(|(seq, nonce)| {
+        validate_nonce(issued, nonce)?;
+        Ok(fields(seq, None, nonce, None, None))
+      }))
+    }
+    "claimed_ip" => outcome(parse_hello_with_claimed_ip(&message).and_then(|(seq, ip)| {
+      if let (Some(claimed), Some(peer)) = (ip, vector.peer) {
+        verify_claimed_ip(claimed, peer)?;
+      }
+      Ok(fields(seq, None, None, ip, None))
+    })),
+    "capabilities" => outcome(
+      parse_hello_with_capabilities(&message).and_then(|(seq, caps)| {
+        if let Some(required) = &vector.required {
+          require_capabilities(required.parse()?, caps.unwrap_or_default())?;
+        }
+        Ok(fields(seq, None, None, None, caps))
+      }),
+    ),
+    "reply" => {
+      let initial_seq = vector.initial_seq.expect("reply needs initial_seq");
+      client_reply(initial_seq, &bytes).await
+    }
+    other => panic!("{}: unknown parser '{other}'", vector.name),
+  }
+}
+
+#[tokio::test]
+async fn every_vector_matches() {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/vectors");
+  let mut paths: Vec<_> = fs::read_dir(&dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+    .collect();
+  paths.sort();
+  assert!(!paths.is_empty(), "no vectors in {}", dir.display());
+
+  let mut failures = Vec::new();
+  let mut seen_errors = Vec::new();
+  for path in &paths {
+    let file = path.file_name().unwrap().to_string_lossy();
+    let vectors: Vec<Vector> = serde_json::from_str(&fs::read_to_string(path).unwrap())
+      .unwrap_or_else(|e| panic!("{file}: {e}"));
+    for vector in &vectors {
+      let actual = run(vector).await;
+      if actual != vector.expect {
+        failures.push(format!(
+          "{file}: {}\n  expected {}\n  got      {actual}",
+          vector.name, vector.expect
+        ));
+      }
+      if let Some(error) = vector.expect.get("error").and_then(Value::as_str) {
+        seen_errors.push(error.to_string());
+      }
+    }
+  }
+
+  assert!(failures.is_empty(), "{}", failures.join("\n"));
+  for error in MESSAGE_ERRORS {
+    assert!(
+      seen_errors.iter().any(|seen| seen == error),
+      "no vector expects {error}"
+    );
+  }
+}
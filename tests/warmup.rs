@@ -0,0 +1,41 @@
+/**
+ * Startup warmup tests
+ *
+ * Author: Sae-Hwan Park
+ */
+use std::io::Read;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use tcp_handshake::{HandshakeConfig, ServerModel, perform_client_handshake, spawn_server};
+
+const WARMUP: Duration = Duration::from_millis(300);
+
+#[test]
+fn connections_during_warmup_are_refused() {
+  for model in ServerModel::ALL {
+    let config = HandshakeConfig {
+      warmup: Some(WARMUP),
+      ..HandshakeConfig::default()
+    };
+    let server = spawn_server(model, config).unwrap();
+
+    // Closed without a reply while warming up
+    let mut early = TcpStream::connect(server.addr).unwrap();
+    early
+      .set_read_timeout(Some(Duration::from_secs(2)))
+      .unwrap();
+    let mut buffer = [0u8; 8];
+    assert!(matches!(early.read(&mut buffer), Ok(0) | Err(_)), "{model}");
+
+    // Handled normally once the warmup has elapsed
+    thread::sleep(WARMUP);
+    let late = TcpStream::connect(server.addr).unwrap();
+    let outcome = perform_client_handshake(late, 10).unwrap();
+    assert_eq!(outcome.final_seq, 12, "{model}");
+
+    assert_eq!(server.metrics.snapshot().rejected_warmup, 1, "{model}");
+    server.stop();
+  }
+}